@@ -0,0 +1,129 @@
+//! One-time initialization of the app defaults from the platform config directory.
+
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+#[cfg(not(any(target_os = "ios", target_os = "android")))]
+use directories::ProjectDirs;
+use persistent_config_core::{PERSISTENT_CONFIGS, PersistentConfigParameters};
+
+/// Initializer of the parameters shared by all the configs of an application.
+///
+/// Call [`init`](Self::init) once at startup, before loading any config: types deriving
+/// `Persistent` without a `config_dir` are then saved in the platform config directory of the
+/// application, such as `~/.config/<application>` on Linux, and types without any
+/// registration are registered on first use.
+///
+/// On mobile platforms, where each app has its own storage, the configs are saved in:
+///
+/// - iOS: the `Documents` directory of the app sandbox
+/// - Android: the internal files directory of the app, as returned by `Context.getFilesDir()`
+///   through the Android context of [`ndk-context`](https://docs.rs/ndk-context), or
+///   `/data/data/<package>/files` if the context was not initialized
+///
+/// # Example
+///
+/// ```no_run
+/// # use persistent_config::prelude::*;
+/// # fn main() -> anyhow::Result<()> {
+/// let config_dir = PersistentConfigApp::init_with(
+///     "Example",
+///     "my_app",
+///     PersistentConfigParameters {
+///         save_format: SaveFormat::YAML,
+///         panic_on_error: true,
+///         ..Default::default()
+///     },
+/// )?;
+/// println!("Configs are saved in {:?}", config_dir);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PersistentConfigApp;
+
+impl PersistentConfigApp {
+    /// Sets the app defaults to the default parameters, in the config directory of the
+    /// application.
+    ///
+    /// Returns the config directory.
+    ///
+    /// # Errors
+    /// Returns an error if no home directory could be found for the current user.
+    pub fn init(organization: &str, application: &str) -> Result<PathBuf> {
+        Self::init_with(organization, application, PersistentConfigParameters::default())
+    }
+
+    /// Sets the app defaults to `params`, in the config directory of the application.
+    ///
+    /// The `config_dir` of `params` is replaced and its `file_name` should be left empty, so
+    /// each type is saved under its own name. Calling it again replaces the app defaults, but
+    /// types already registered keep their parameters.
+    ///
+    /// Returns the config directory.
+    ///
+    /// # Errors
+    /// Returns an error if no home directory could be found for the current user.
+    pub fn init_with(organization: &str, application: &str, params: PersistentConfigParameters) -> Result<PathBuf> {
+        let config_dir = platform_config_dir(organization, application)?;
+
+        PERSISTENT_CONFIGS.set_app_defaults(PersistentConfigParameters {
+            config_dir: config_dir.to_string_lossy().into_owned(),
+            ..params
+        });
+        Ok(config_dir)
+    }
+}
+
+/// Returns the config directory of the application on desktop platforms.
+#[cfg(not(any(target_os = "ios", target_os = "android")))]
+fn platform_config_dir(organization: &str, application: &str) -> Result<PathBuf> {
+    let project_dirs = ProjectDirs::from("", organization, application)
+        .ok_or_else(|| anyhow!("No config directory found for the application {:?}", application))?;
+    Ok(project_dirs.config_dir().to_path_buf())
+}
+
+/// Returns the `Documents` directory of the app sandbox, the home directory of an iOS app.
+#[cfg(target_os = "ios")]
+fn platform_config_dir(_organization: &str, application: &str) -> Result<PathBuf> {
+    let home = std::env::var_os("HOME")
+        .ok_or_else(|| anyhow!("No config directory found for the application {:?}", application))?;
+    Ok(PathBuf::from(home).join("Documents"))
+}
+
+/// Returns the internal files directory of the Android app.
+#[cfg(target_os = "android")]
+fn platform_config_dir(_organization: &str, application: &str) -> Result<PathBuf> {
+    // The context is only set by the native activity glue, or by the app itself
+    match std::panic::catch_unwind(ndk_context::android_context) {
+        Ok(context) => android_files_dir(context),
+        Err(_) => {
+            let cmdline = std::fs::read("/proc/self/cmdline")?;
+            // The process of a service may be named `<package>:<service>`
+            let process = cmdline.split(|&byte| byte == 0).next().unwrap_or_default();
+            let package = String::from_utf8_lossy(process);
+            let package = package.split(':').next().unwrap_or_default();
+            if package.is_empty() {
+                return Err(anyhow!("No config directory found for the application {:?}", application));
+            }
+            Ok(PathBuf::from("/data/data").join(package).join("files"))
+        }
+    }
+}
+
+/// Returns the directory returned by `getFilesDir()` on the Android `context`.
+#[cfg(target_os = "android")]
+fn android_files_dir(context: ndk_context::AndroidContext) -> Result<PathBuf> {
+    use jni::objects::{JObject, JString};
+
+    // SAFETY: the pointers of the Android context are valid as long as the app runs
+    let vm = unsafe { jni::JavaVM::from_raw(context.vm().cast())? };
+    let mut env = vm.attach_current_thread()?;
+    let context = unsafe { JObject::from_raw(context.context().cast()) };
+    let files_dir = env.call_method(&context, "getFilesDir", "()Ljava/io/File;", &[])?.l()?;
+    let path = env
+        .call_method(&files_dir, "getAbsolutePath", "()Ljava/lang/String;", &[])?
+        .l()?;
+    let path: String = env.get_string(&JString::from(path))?.into();
+    Ok(PathBuf::from(path))
+}
@@ -0,0 +1,65 @@
+//! Audit log of the saves, written when `audit_log` is set in the parameters.
+//!
+//! Every save appends a JSON line to `audit.log`, next to the config file:
+//!
+//! ```json
+//! {"saved_at":"2025-01-31T12:00:00Z","type":"app::Config","changed":["name"],"pid":4242,"user":"alice"}
+//! ```
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::document;
+use crate::envelope;
+
+/// Name of the audit log file, in the config directory.
+const AUDIT_LOG: &str = "audit.log";
+
+/// A line of the audit log.
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    saved_at: String,
+    #[serde(rename = "type")]
+    type_name: &'a str,
+    changed: Vec<String>,
+    pid: u32,
+    user: String,
+}
+
+/// Appends the save of `T` from `previous` to `saved` to the audit log next to `file_path`.
+///
+/// `previous` is the document found in the file before the save, `None` if there was none.
+/// Envelopes are ignored, only the changes of the payload are recorded.
+pub(crate) fn record<T>(file_path: &Path, previous: Option<Value>, saved: &Value) -> Result<()> {
+    let previous = previous.map_or_else(|| Value::Object(Map::new()), envelope::payload);
+    let entry = AuditEntry {
+        saved_at: envelope::format_timestamp(SystemTime::now()),
+        type_name: std::any::type_name::<T>(),
+        changed: document::changed_paths(&previous, &envelope::payload(saved.clone())),
+        pid: std::process::id(),
+        user: current_user(),
+    };
+
+    let mut line = serde_json::to_string(&entry)?;
+    line.push('\n');
+    let log_path = file_path.with_file_name(AUDIT_LOG);
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("Config saved, but the audit log {:?} could not be written", log_path))
+}
+
+/// Returns the name of the user running the process, from the environment.
+fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_owned())
+}
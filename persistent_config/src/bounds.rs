@@ -0,0 +1,48 @@
+//! Traits required from the types deriving `Persistent`, bounding the generated impl so a
+//! missing implementation is reported once, on the struct, with a message saying what to add.
+
+use std::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+/// Implemented by the types that can be saved.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` must implement `Serialize` to derive `Persistent`",
+    label = "`Serialize` is not implemented for `{Self}`",
+    note = "add `#[derive(Serialize)]` from serde, or use `#[persistent_config]` which adds it"
+)]
+pub trait PersistentSerialize: Serialize {}
+
+impl<T: Serialize + ?Sized> PersistentSerialize for T {}
+
+/// Implemented by the types that can be loaded.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` must implement `Deserialize` to derive `Persistent`",
+    label = "`Deserialize` is not implemented for `{Self}`",
+    note = "add `#[derive(Deserialize)]` from serde, or use `#[persistent_config]` which adds it; \
+            the type can't borrow from the config file, it must implement `for<'de> Deserialize<'de>`"
+)]
+pub trait PersistentDeserialize: for<'de> Deserialize<'de> {}
+
+impl<T: for<'de> Deserialize<'de>> PersistentDeserialize for T {}
+
+/// Implemented by the types with a value to use when the config file does not exist yet.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` must implement `Default` to derive `Persistent`",
+    label = "`Default` is not implemented for `{Self}`",
+    note = "the default value is used when the config file does not exist yet, add `#[derive(Default)]` \
+            or implement `Default`"
+)]
+pub trait PersistentDefault: Default {}
+
+impl<T: Default> PersistentDefault for T {}
+
+/// Implemented by the types that can be shown in the errors and logs.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` must implement `Debug` to derive `Persistent`",
+    label = "`Debug` is not implemented for `{Self}`",
+    note = "add `#[derive(Debug)]` or implement `Debug`"
+)]
+pub trait PersistentDebug: Debug {}
+
+impl<T: Debug + ?Sized> PersistentDebug for T {}
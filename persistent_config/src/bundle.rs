@@ -0,0 +1,171 @@
+//! Bundles of the config files of all the registered types, in a single tar archive.
+//!
+//! A bundle holds a `manifest.json` listing its configs, and the file of each config as
+//! `configs/<type name>/<file name>`. Configs are matched by type name when the bundle is
+//! imported, so they are restored at the paths they are registered with on the importing
+//! machine, whatever their paths on the exporting one.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, anyhow, bail};
+use persistent_config_core::{PERSISTENT_CONFIGS, SaveFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::{config_file_path, envelope};
+
+/// Version of the bundle layout written by [`export_bundle`](crate::export_bundle).
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Path of the manifest in a bundle.
+const MANIFEST: &str = "manifest.json";
+
+/// List of the configs of a bundle, stored as `manifest.json` at its root.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    /// Version of the bundle layout, [`BUNDLE_VERSION`] for the bundles written by this
+    /// version of the crate.
+    pub version: u32,
+    /// Time the bundle was made at, in RFC 3339 format.
+    pub created_at: String,
+    /// Configs in the bundle.
+    pub configs: Vec<BundleEntry>,
+}
+
+/// A config in a bundle, see [`BundleManifest`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleEntry {
+    /// Name of the config type, as given by [`std::any::type_name`].
+    pub type_name: String,
+    /// Path of the config file in the bundle.
+    pub path: String,
+    /// Format of the config file.
+    pub save_format: SaveFormat,
+}
+
+/// Writes the existing config files of the registered types to a bundle at `path`.
+pub(crate) fn export(path: &Path) -> Result<BundleManifest> {
+    let mut configs = Vec::new();
+    let mut files = Vec::new();
+    for registration in PERSISTENT_CONFIGS.registrations() {
+        let file_path = config_file_path(&registration.params);
+        let content = match std::fs::read(&file_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(anyhow::Error::new(e).context(format!("Failed to read {:?}", file_path))),
+        };
+        let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+        let entry = BundleEntry {
+            type_name: registration.type_name.to_owned(),
+            path: format!("configs/{}/{}", entry_dir(registration.type_name), file_name),
+            save_format: registration.params.save_format,
+        };
+        files.push((entry.path.clone(), content));
+        configs.push(entry);
+    }
+
+    let manifest = BundleManifest {
+        version: BUNDLE_VERSION,
+        created_at: envelope::format_timestamp(SystemTime::now()),
+        configs,
+    };
+    let mut builder = tar::Builder::new(File::create(path)?);
+    append(&mut builder, MANIFEST, &serde_json::to_vec_pretty(&manifest)?)?;
+    for (entry_path, content) in &files {
+        append(&mut builder, entry_path, content)?;
+    }
+    builder.into_inner()?.sync_all()?;
+    Ok(manifest)
+}
+
+/// Restores the configs of the bundle at `path` whose type is registered, replacing their
+/// config files.
+///
+/// Returns the manifest of the bundle, listing the configs restored.
+pub(crate) fn import(path: &Path) -> Result<BundleManifest> {
+    let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut archive = tar::Archive::new(File::open(path)?);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_string_lossy().into_owned();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        files.insert(entry_path, content);
+    }
+
+    let manifest = files
+        .get(MANIFEST)
+        .ok_or_else(|| anyhow!("{:?} is not a config bundle, it has no {}", path, MANIFEST))?;
+    let mut manifest: BundleManifest =
+        serde_json::from_slice(manifest).with_context(|| format!("Invalid manifest in {:?}", path))?;
+    if manifest.version > BUNDLE_VERSION {
+        bail!(
+            "{:?} is a bundle of version {}, this version of the crate reads up to version {}",
+            path,
+            manifest.version,
+            BUNDLE_VERSION
+        );
+    }
+
+    // Every file is checked to be in the bundle before any config is replaced
+    let registrations = PERSISTENT_CONFIGS.registrations();
+    let mut restored = Vec::new();
+    for entry in manifest.configs {
+        let Some(registration) = registrations
+            .iter()
+            .find(|registration| registration.type_name == entry.type_name)
+        else {
+            continue;
+        };
+        let content = files
+            .remove(&entry.path)
+            .ok_or_else(|| anyhow!("The bundle {:?} is missing {}", path, entry.path))?;
+        restored.push((entry, config_file_path(&registration.params), content));
+    }
+    for (_, file_path, content) in &restored {
+        replace(file_path, content).with_context(|| format!("Failed to restore {:?}", file_path))?;
+    }
+
+    manifest.configs = restored.into_iter().map(|(entry, ..)| entry).collect();
+    Ok(manifest)
+}
+
+/// Returns the directory of the configs of `type_name` in a bundle, the characters that are
+/// not allowed in file names on some platforms replaced by `_`.
+fn entry_dir(type_name: &str) -> String {
+    type_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Appends a file holding `content` at `entry_path` to the bundle.
+fn append(builder: &mut tar::Builder<File>, entry_path: &str, content: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs());
+    header.set_cksum();
+    builder.append_data(&mut header, entry_path, content)?;
+    Ok(())
+}
+
+/// Writes `content` to a temporary file renamed over `file_path`, so a failed import never
+/// leaves a truncated config behind.
+fn replace(file_path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(parent) = file_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut tmp_path = file_path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    if let Err(e) = std::fs::write(&tmp_path, content) {
+        _ = std::fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    std::fs::rename(&tmp_path, file_path)?;
+    Ok(())
+}
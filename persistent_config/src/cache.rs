@@ -0,0 +1,83 @@
+//! In-process cache of loaded configurations.
+//!
+//! The last value loaded from disk is stored in [`PERSISTENT_CONFIGS`] together with
+//! a stamp of the file it was read from. As long as the file is unchanged, the cached
+//! value is returned instead of reading and parsing the file again.
+//!
+//! The stamp of the file when the type was last loaded or saved is kept as well, to tell
+//! whether the file was changed by someone else since.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use persistent_config_core::PERSISTENT_CONFIGS;
+
+/// Extension slot holding the cached value of a type.
+const CACHE_SLOT: &str = "cache";
+
+/// Extension slot holding the stamp of the file when a type was last loaded or saved.
+const SYNCED_SLOT: &str = "synced";
+
+/// Identifies the state of a file on disk.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FileStamp {
+    /// Path of the file.
+    path: PathBuf,
+    /// Last modification time, if supported by the platform.
+    modified: Option<SystemTime>,
+    /// Size of the file in bytes.
+    len: u64,
+}
+
+impl FileStamp {
+    /// Returns the stamp of the file at `path`, or `None` if the file can't be accessed.
+    pub(crate) fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            path: path.to_path_buf(),
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Stamp of the file when it was last loaded or saved, `None` if it didn't exist.
+struct Synced(Option<FileStamp>);
+
+/// Cached value of a type, along with the stamp of the file it was loaded from.
+struct CachedValue<T> {
+    stamp: FileStamp,
+    value: T,
+}
+
+/// Returns a clone of the cached value of `T`, if it was loaded from a file matching `stamp`.
+pub(crate) fn get<T: Clone + Send + Sync + 'static>(stamp: &FileStamp) -> Option<T> {
+    let cached = PERSISTENT_CONFIGS.get_extension::<T, CachedValue<T>>(CACHE_SLOT)?;
+    (cached.stamp == *stamp).then(|| cached.value.clone())
+}
+
+/// Stores the value of `T` loaded from the file identified by `stamp`.
+pub(crate) fn store<T: Send + Sync + 'static>(stamp: FileStamp, value: T) {
+    PERSISTENT_CONFIGS.add_extension::<T, _>(CACHE_SLOT, CachedValue { stamp, value });
+}
+
+/// Drops the cached value of `T`.
+pub(crate) fn invalidate<T: 'static>() {
+    PERSISTENT_CONFIGS.remove_extension::<T>(CACHE_SLOT);
+}
+
+/// Records `stamp` as the state of the file of `T` when it was last loaded or saved.
+pub(crate) fn mark_synced<T: 'static>(stamp: Option<FileStamp>) {
+    PERSISTENT_CONFIGS.add_extension::<T, _>(SYNCED_SLOT, Synced(stamp));
+}
+
+/// Returns whether the file at `path` changed since `T` was last loaded or saved.
+///
+/// If `T` was never loaded or saved, the file is stale as soon as it exists.
+pub(crate) fn is_stale<T: 'static>(path: &Path) -> bool {
+    let current = FileStamp::of(path);
+    match PERSISTENT_CONFIGS.get_extension::<T, Synced>(SYNCED_SLOT) {
+        Some(synced) => synced.0 != current,
+        None => current.is_some(),
+    }
+}
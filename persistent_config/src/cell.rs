@@ -0,0 +1,96 @@
+//! Thread-safe shared handle around a persistent configuration.
+//!
+//! [`PersistentCell`] wraps a configuration in an `Arc<RwLock<T>>`, so a single
+//! instance can be cloned and shared between threads. Changes made through
+//! [`PersistentCell::update`] are saved to disk right after the closure returns.
+
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+
+use anyhow::Result;
+
+use crate::PersistentConfig;
+
+/// Thread-safe, cloneable handle to a persistent configuration.
+///
+/// Every clone points to the same underlying value, so updates made from one
+/// thread are visible to all the others.
+///
+/// # Example
+///
+/// ```no_run
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct MyConfig { count: u32 }
+/// # impl PersistentConfigBuilder for MyConfig {}
+/// # fn main() -> anyhow::Result<()> {
+/// MyConfig::default().default_save_config(false)?;
+/// let config = PersistentCell::<MyConfig>::load()?;
+///
+/// let shared = config.clone();
+/// std::thread::spawn(move || shared.update(|c| c.count += 1)).join().unwrap()?;
+///
+/// println!("count: {}", config.read().count);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct PersistentCell<T> {
+    /// Shared configuration value.
+    inner: Arc<RwLock<T>>,
+}
+
+impl<T> Clone for PersistentCell<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: PersistentConfig> PersistentCell<T> {
+    /// Wraps an existing configuration value.
+    ///
+    /// The value is not saved or loaded; the type must still be registered with
+    /// `config_builder` or `default_save_config` before calling [`update`](Self::update).
+    pub fn new(value: T) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(value)),
+        }
+    }
+
+    /// Creates a cell holding the configuration loaded from persistent storage.
+    ///
+    /// The type must be registered before calling this function.
+    pub fn load() -> Result<Self> {
+        let mut value = T::default();
+        value.load()?;
+        Ok(Self::new(value))
+    }
+
+    /// Acquires shared read access to the configuration.
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Applies `f` to the configuration and saves it to persistent storage.
+    ///
+    /// The write lock is held while saving, so concurrent updates are written in order.
+    /// If saving fails, the in-memory change is kept and the error is returned. If `f`
+    /// panics, the cell remains usable with the value as `f` left it, call
+    /// [`reload`](Self::reload) to go back to the stored configuration.
+    pub fn update<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut T),
+    {
+        let mut guard = self.inner.write().unwrap_or_else(PoisonError::into_inner);
+        f(&mut guard);
+        guard.save()
+    }
+
+    /// Replaces the in-memory configuration with the one stored on disk.
+    pub fn reload(&self) -> Result<()> {
+        let mut guard = self.inner.write().unwrap_or_else(PoisonError::into_inner);
+        guard.load()
+    }
+}
@@ -0,0 +1,411 @@
+//! Large `Vec` fields split into chunk files, loaded on first access, with
+//! `#[persistent(chunked)]` on a [`Chunked`] field.
+//!
+//! The config file only holds the length and chunk size of such a field: its items are
+//! stored as JSON files, one per chunk, in `<config file stem>.data/<key>/` next to the
+//! config file. Loading the config reads none of them, each chunk is read the first time one
+//! of its items is accessed, so a config holding a huge index loads in no time and only pays
+//! for the parts it uses. Saving writes the chunks changed since they were loaded.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+use anyhow::{Context, Result, bail};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::sidecar::Sidecar;
+
+/// Number of items per chunk of [`Chunked::default`].
+const DEFAULT_CHUNK_SIZE: usize = 10_000;
+
+/// A list of items stored in chunk files, read lazily, for the fields marked with
+/// `#[persistent(chunked)]`.
+///
+/// The items are accessed by chunk, or one by one: the chunk holding an item is read from
+/// its file the first time, then kept in memory until [`unload`](Self::unload) is called.
+/// Every chunk holds [`chunk_size`](Self::chunk_size) items, except the last one.
+///
+/// Clones share the chunk files, and the chunks already read.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+/// # #[persistent(config_dir = "/tmp/persistent_config_doc_chunked", namespace = "")]
+/// struct Index {
+///     #[persistent(chunked)]
+///     entries: Chunked<u64>,
+/// }
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let index = Index {
+///     entries: Chunked::from_vec((0..25).collect(), 10),
+/// };
+/// index.save()?;
+///
+/// let mut index = Index::default();
+/// index.load()?;
+/// assert_eq!(index.entries.len(), 25);
+/// // Reads the third chunk only
+/// assert_eq!(index.entries.get(24)?, Some(&24));
+/// # std::fs::remove_dir_all("/tmp/persistent_config_doc_chunked")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Chunked<T> {
+    /// Number of items per chunk.
+    chunk_size: usize,
+    /// Number of items.
+    len: usize,
+    /// Chunks, in order.
+    chunks: Vec<Arc<Chunk<T>>>,
+    /// Directory of the chunk files, unset until the chunks are loaded or saved.
+    source: Arc<Mutex<Option<PathBuf>>>,
+}
+
+/// A chunk of a [`Chunked`] list.
+struct Chunk<T> {
+    /// Items of the chunk, once read or set.
+    items: OnceLock<Vec<T>>,
+    /// Whether the items differ from the chunk file.
+    dirty: AtomicBool,
+}
+
+impl<T> Chunk<T> {
+    /// Returns a chunk to be read from its file.
+    fn unloaded() -> Self {
+        Self {
+            items: OnceLock::new(),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns a chunk holding `items`, not saved yet.
+    fn new(items: Vec<T>) -> Self {
+        Self {
+            items: OnceLock::from(items),
+            dirty: AtomicBool::new(true),
+        }
+    }
+}
+
+impl<T: Clone> Clone for Chunk<T> {
+    fn clone(&self) -> Self {
+        Self {
+            items: self.items.clone(),
+            dirty: AtomicBool::new(self.dirty.load(Ordering::Acquire)),
+        }
+    }
+}
+
+impl<T> Chunked<T> {
+    /// Returns an empty list, split in chunks of `chunk_size` items.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn new(chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "The chunk size must be at least 1");
+        Self {
+            chunk_size,
+            len: 0,
+            chunks: Vec::new(),
+            source: Arc::default(),
+        }
+    }
+
+    /// Returns a list holding `items`, split in chunks of `chunk_size` items.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn from_vec(items: Vec<T>, chunk_size: usize) -> Self {
+        let mut chunked = Self::new(chunk_size);
+        chunked.len = items.len();
+        let mut items = items.into_iter();
+        while items.len() > 0 {
+            let chunk = items.by_ref().take(chunk_size).collect();
+            chunked.chunks.push(Arc::new(Chunk::new(chunk)));
+        }
+        chunked
+    }
+
+    /// Returns the number of items.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the list holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of items per chunk.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Returns the number of chunks.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Frees the chunks read from their file and not changed since, to be read again when
+    /// they are accessed.
+    pub fn unload(&mut self) {
+        if self.source().is_none() {
+            return;
+        }
+        for chunk in &mut self.chunks {
+            if !chunk.dirty.load(Ordering::Acquire) {
+                *chunk = Arc::new(Chunk::unloaded());
+            }
+        }
+    }
+
+    /// Returns the directory of the chunk files, if known.
+    fn source(&self) -> Option<PathBuf> {
+        self.source.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Returns the number of items of the chunk at `index`.
+    fn expected_len(&self, index: usize) -> usize {
+        self.chunk_size.min(self.len - index * self.chunk_size)
+    }
+}
+
+impl<T: DeserializeOwned> Chunked<T> {
+    /// Returns the items of the chunk at `index`, reading its file if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `index` is out of range, or if the chunk file can't be read or
+    /// doesn't hold the expected number of items.
+    pub fn chunk(&self, index: usize) -> Result<&[T]> {
+        let Some(chunk) = self.chunks.get(index) else {
+            bail!(
+                "Chunk {} out of range, the list has {} chunks",
+                index,
+                self.chunks.len()
+            );
+        };
+        if let Some(items) = chunk.items.get() {
+            return Ok(items);
+        }
+        let items = self.read_chunk(index)?;
+        Ok(chunk.items.get_or_init(|| items))
+    }
+
+    /// Returns the item at `index`, or `None` if it is out of range, reading the file of its
+    /// chunk if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chunk file can't be read.
+    pub fn get(&self, index: usize) -> Result<Option<&T>> {
+        if index >= self.len {
+            return Ok(None);
+        }
+        Ok(self.chunk(index / self.chunk_size)?.get(index % self.chunk_size))
+    }
+
+    /// Returns an iterator over the chunks, read as they are reached.
+    pub fn chunks(&self) -> impl Iterator<Item = Result<&[T]>> {
+        (0..self.chunks.len()).map(|index| self.chunk(index))
+    }
+
+    /// Returns all the items, reading every chunk.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a chunk file can't be read.
+    pub fn to_vec(&self) -> Result<Vec<T>>
+    where
+        T: Clone,
+    {
+        let mut items = Vec::with_capacity(self.len);
+        for chunk in self.chunks() {
+            items.extend_from_slice(chunk?);
+        }
+        Ok(items)
+    }
+
+    /// Reads the file of the chunk at `index`.
+    fn read_chunk(&self, index: usize) -> Result<Vec<T>> {
+        let Some(source) = self.source() else {
+            bail!("Chunk {} has no file to be read from, load the config first", index);
+        };
+        let path = chunk_path(&source, index);
+        let file = File::open(&path).with_context(|| format!("Failed to open the chunk file {:?}", path))?;
+        let items: Vec<T> = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Failed to read the chunk file {:?}", path))?;
+        if items.len() != self.expected_len(index) {
+            bail!(
+                "The chunk file {:?} holds {} items instead of {}",
+                path,
+                items.len(),
+                self.expected_len(index)
+            );
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Clone + DeserializeOwned> Chunked<T> {
+    /// Returns the items of the chunk at `index` to change them, reading its file if needed.
+    ///
+    /// The chunk is written by the next save.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `index` is out of range, or if the chunk file can't be read.
+    pub fn chunk_mut(&mut self, index: usize) -> Result<&mut [T]> {
+        self.chunk(index)?;
+        let chunk = Arc::make_mut(&mut self.chunks[index]);
+        chunk.dirty.store(true, Ordering::Release);
+        Ok(chunk.items.get_mut().map_or(&mut [], Vec::as_mut_slice))
+    }
+
+    /// Appends an item, reading the file of the last chunk if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file of the last chunk can't be read.
+    pub fn push(&mut self, item: T) -> Result<()> {
+        if self.len.is_multiple_of(self.chunk_size) {
+            self.chunks
+                .push(Arc::new(Chunk::new(Vec::with_capacity(self.chunk_size))));
+        }
+        let last = self.chunks.len() - 1;
+        self.chunk(last)?;
+        let chunk = Arc::make_mut(&mut self.chunks[last]);
+        chunk.dirty.store(true, Ordering::Release);
+        if let Some(items) = chunk.items.get_mut() {
+            items.push(item);
+        }
+        self.len += 1;
+        Ok(())
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Sidecar for Chunked<T> {
+    fn read_from(&mut self, path: PathBuf) {
+        *self.source.lock().unwrap_or_else(PoisonError::into_inner) = Some(path);
+    }
+
+    /// Writes the chunk files to the `dir` directory.
+    ///
+    /// Only the changed chunks are written to the directory they were read from, every chunk
+    /// is written to another one. Chunk files left from a longer list are removed.
+    fn write_to(&self, dir: &Path) -> Result<()> {
+        let source = self.source();
+        let same_dir = source.as_deref() == Some(dir);
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create the chunk directory {:?}", dir))?;
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            if same_dir && !chunk.dirty.load(Ordering::Acquire) {
+                continue;
+            }
+            let path = chunk_path(dir, index);
+            let temp_path = path.with_extension("json.tmp");
+            let mut writer = BufWriter::new(
+                File::create(&temp_path).with_context(|| format!("Failed to create the chunk file {:?}", temp_path))?,
+            );
+            serde_json::to_writer(&mut writer, self.chunk(index)?)?;
+            writer.flush()?;
+            std::fs::rename(&temp_path, &path).with_context(|| format!("Failed to write the chunk file {:?}", path))?;
+        }
+
+        for entry in std::fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            let stale = path.extension().is_some_and(|extension| extension == "json")
+                && path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str()?.parse::<usize>().ok())
+                    .is_some_and(|index| index >= self.chunks.len());
+            if stale {
+                std::fs::remove_file(&path).with_context(|| format!("Failed to remove the chunk file {:?}", path))?;
+            }
+        }
+
+        if same_dir || source.is_none() {
+            for chunk in &self.chunks {
+                chunk.dirty.store(false, Ordering::Release);
+            }
+            *self.source.lock().unwrap_or_else(PoisonError::into_inner) = Some(dir.to_owned());
+        }
+        Ok(())
+    }
+}
+
+impl<T> Default for Chunked<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHUNK_SIZE)
+    }
+}
+
+impl<T> Clone for Chunked<T> {
+    fn clone(&self) -> Self {
+        Self {
+            chunk_size: self.chunk_size,
+            len: self.len,
+            chunks: self.chunks.clone(),
+            source: self.source.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Chunked<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chunked")
+            .field("len", &self.len)
+            .field("chunk_size", &self.chunk_size)
+            .field("source", &self.source())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Layout of a [`Chunked`] list in the config file.
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    /// Number of items.
+    len: usize,
+    /// Number of items per chunk.
+    chunk_size: usize,
+}
+
+impl<T> Serialize for Chunked<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Manifest {
+            len: self.len,
+            chunk_size: self.chunk_size,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Chunked<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let manifest = Manifest::deserialize(deserializer)?;
+        if manifest.chunk_size == 0 {
+            return Err(serde::de::Error::custom("the chunk size must be at least 1"));
+        }
+        let mut chunked = Self::new(manifest.chunk_size);
+        chunked.len = manifest.len;
+        chunked.chunks = (0..manifest.len.div_ceil(manifest.chunk_size))
+            .map(|_| Arc::new(Chunk::unloaded()))
+            .collect();
+        Ok(chunked)
+    }
+}
+
+/// Returns the path of the file of the chunk at `index` in `dir`.
+fn chunk_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("{}.json", index))
+}
@@ -0,0 +1,145 @@
+//! Checks of the value constraints set on fields with `#[persistent(min = ..., max = ...,
+//! regex = "...", one_of(...))]`.
+//!
+//! The derive generates a `validate` method calling them, run by load once the config is
+//! read and by save before the config is written. A failed check returns
+//! [`PersistentConfigError::InvalidValue`] naming the on-disk key of the field.
+
+use std::fmt::Display;
+
+use anyhow::Result;
+use persistent_config_core::PersistentConfigError;
+
+/// Field types whose value can be constrained.
+///
+/// An `Option` is checked only when it holds a value.
+pub trait Constrained {
+    /// Type of the value compared with the constraints.
+    type Value: ?Sized;
+
+    /// Returns the value to check, if any.
+    fn constrained(&self) -> Option<&Self::Value>;
+}
+
+macro_rules! constrained_as_self {
+    ($($ty:ty),*) => {
+        $(
+            impl Constrained for $ty {
+                type Value = $ty;
+
+                fn constrained(&self) -> Option<&$ty> {
+                    Some(self)
+                }
+            }
+        )*
+    };
+}
+
+constrained_as_self!(
+    i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64, char, str
+);
+
+impl Constrained for String {
+    type Value = str;
+
+    fn constrained(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl<T: Constrained> Constrained for Option<T> {
+    type Value = T::Value;
+
+    fn constrained(&self) -> Option<&T::Value> {
+        self.as_ref().and_then(Constrained::constrained)
+    }
+}
+
+/// Returns the error reporting the invalid value of `field`.
+fn invalid(field: &str, message: String) -> anyhow::Error {
+    PersistentConfigError::InvalidValue {
+        field: field.to_owned(),
+        message,
+    }
+    .into()
+}
+
+/// Checks that the value of `field` is at least `min`.
+pub fn min<F>(field: &str, value: &F, min: F::Value) -> Result<()>
+where
+    F: Constrained + ?Sized,
+    F::Value: PartialOrd + Display + Sized,
+{
+    match value.constrained() {
+        Some(value) if *value < min => Err(invalid(field, format!("{} is less than the minimum {}", value, min))),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the value of `field` is at most `max`.
+pub fn max<F>(field: &str, value: &F, max: F::Value) -> Result<()>
+where
+    F: Constrained + ?Sized,
+    F::Value: PartialOrd + Display + Sized,
+{
+    match value.constrained() {
+        Some(value) if *value > max => Err(invalid(field, format!("{} is greater than the maximum {}", value, max))),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the value of `field` is one of `allowed`.
+pub fn one_of<F>(field: &str, value: &F, allowed: &[&F::Value]) -> Result<()>
+where
+    F: Constrained + ?Sized,
+    F::Value: PartialEq + Display,
+{
+    match value.constrained() {
+        Some(value) if !allowed.contains(&value) => Err(invalid(
+            field,
+            format!(
+                "{:?} is not one of {}",
+                value.to_string(),
+                allowed
+                    .iter()
+                    .map(|allowed| format!("{:?}", allowed.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the value of `field` matches `pattern`.
+///
+/// The patterns are compiled once, and cached for the life of the process.
+#[cfg(feature = "regex")]
+pub fn regex<F>(field: &str, value: &F, pattern: &'static str) -> Result<()>
+where
+    F: Constrained + ?Sized,
+    F::Value: AsRef<str>,
+{
+    use std::collections::HashMap;
+    use std::sync::{LazyLock, Mutex, PoisonError};
+
+    static PATTERNS: LazyLock<Mutex<HashMap<&'static str, regex::Regex>>> = LazyLock::new(Default::default);
+
+    let Some(value) = value.constrained() else {
+        return Ok(());
+    };
+    let mut patterns = PATTERNS.lock().unwrap_or_else(PoisonError::into_inner);
+    if !patterns.contains_key(pattern) {
+        let regex = regex::Regex::new(pattern)
+            .map_err(|e| invalid(field, format!("the pattern {:?} is invalid: {}", pattern, e)))?;
+        patterns.insert(pattern, regex);
+    }
+    if patterns[pattern].is_match(value.as_ref()) {
+        Ok(())
+    } else {
+        Err(invalid(
+            field,
+            format!("{:?} does not match the pattern {:?}", value.as_ref(), pattern),
+        ))
+    }
+}
@@ -0,0 +1,42 @@
+//! Fields stored in the config file of their own type, set with
+//! `#[persistent(flatten_from = "...")]`.
+//!
+//! Such a field holds another persistent config, for example the section of a library in an
+//! app-level struct. It is never written to the file of the struct holding it: it is loaded
+//! and saved with the registration of its own type.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::{PersistentConfig, PersistentConfigBuilder};
+
+/// Loads the config of type `F` from its own file, and puts it under `key` in `document`.
+///
+/// The error policy of `F` applies: with `panic_on_error` unset, a config that can't be
+/// loaded is replaced by its default value.
+pub fn load_field<F: PersistentConfigBuilder>(document: &mut Value, key: &str) -> Result<()> {
+    let mut field = F::default();
+    field
+        .load()
+        .with_context(|| format!("Failed to load `{}` from its own config file", key))?;
+    if let Value::Object(map) = document {
+        map.insert(key.to_owned(), serde_json::to_value(&field)?);
+    }
+    Ok(())
+}
+
+/// Saves `field` to its own file.
+pub fn save_field<F: PersistentConfigBuilder>(field: &F, key: &str) -> Result<()> {
+    field
+        .save()
+        .with_context(|| format!("Failed to save `{}` to its own config file", key))
+}
+
+/// Removes the `keys` stored in their own file from `document`.
+pub(crate) fn strip(document: &mut Value, keys: &[&str]) {
+    if let Value::Object(map) = document {
+        for key in keys {
+            map.shift_remove(*key);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Machine-readable reference of the fields of a config, returned by
+//! [`PersistentConfigBuilder::describe`].
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::PersistentConfigBuilder;
+use crate::document::{self, Direction};
+
+/// Description of a field of a config, for help output or generated documentation.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct MyConfig { port: u16 }
+/// # impl PersistentConfigBuilder for MyConfig {}
+/// for field in MyConfig::describe() {
+///     println!("{} = {}  {}", field.key, field.default, field.docs);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldDescription {
+    /// Key of the field in the config file.
+    pub key: String,
+    /// Rust type of the field, empty if unknown.
+    pub ty: String,
+    /// Doc comment of the field, empty if none.
+    pub docs: String,
+    /// Default value of the field, `"***"` for redacted fields.
+    pub default: Value,
+    /// Environment variable overriding the field, if any.
+    pub env: Option<String>,
+    /// Constraints on the value, as written in the attributes, such as `min = 1`.
+    pub constraints: Vec<String>,
+}
+
+/// Returns the default value of `T` as it is saved, with the redacted fields masked.
+pub fn defaults<T: PersistentConfigBuilder>() -> Value {
+    let mut defaults = serde_json::to_value(T::default()).unwrap_or_default();
+    defaults = document::rename_keys(defaults, T::field_renames(), Direction::ToDisk);
+    document::redact(&mut defaults, T::redacted_fields());
+    defaults
+}
+
+/// Describes the fields of `T` found in its default value, with their key and default only.
+pub(crate) fn from_defaults<T: PersistentConfigBuilder>() -> Vec<FieldDescription> {
+    let Value::Object(map) = defaults::<T>() else {
+        return Vec::new();
+    };
+    map.into_iter()
+        .map(|(key, default)| FieldDescription {
+            key,
+            ty: String::new(),
+            docs: String::new(),
+            default,
+            env: None,
+            constraints: Vec::new(),
+        })
+        .collect()
+}
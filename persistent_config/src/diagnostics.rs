@@ -0,0 +1,160 @@
+//! Self-diagnostics of the config file and its directory.
+
+use std::fmt::{self, Display};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Result;
+use persistent_config_core::SaveFormat;
+
+use crate::envelope::format_timestamp;
+
+/// Report on the config file of a type, returned by
+/// [`diagnose`](crate::PersistentConfig::diagnose).
+///
+/// Its [`Display`] output is meant to be dumped as is in bug reports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics {
+    /// Path of the config file.
+    pub file_path: PathBuf,
+    /// Format the config file is saved in.
+    pub save_format: SaveFormat,
+    /// Whether the config directory exists.
+    pub dir_exists: bool,
+    /// Whether new files can be created in the config directory, or in its closest existing
+    /// parent if it does not exist yet.
+    pub dir_writable: bool,
+    /// Whether the config file exists.
+    pub file_exists: bool,
+    /// Size of the config file in bytes.
+    pub size: Option<u64>,
+    /// Last modification time of the config file.
+    pub modified: Option<SystemTime>,
+    /// Whether the config file is marked read-only.
+    pub readonly: Option<bool>,
+    /// Unix permission bits of the config file.
+    pub mode: Option<u32>,
+    /// Error returned when loading the config file, if it exists and could not be loaded.
+    pub load_error: Option<String>,
+    /// Problems found, in plain words. Empty if the config can be loaded and saved, a config
+    /// file not created yet is not a problem.
+    pub problems: Vec<String>,
+}
+
+impl Diagnostics {
+    /// Returns whether no problem was found.
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "file: {:?} ({:?})", self.file_path, self.save_format)?;
+        writeln!(
+            f,
+            "directory exists: {}, writable: {}",
+            self.dir_exists, self.dir_writable
+        )?;
+        if self.file_exists {
+            write!(f, "file size: {} bytes", self.size.unwrap_or_default())?;
+            if let Some(modified) = self.modified {
+                write!(f, ", modified: {}", format_timestamp(modified))?;
+            }
+            if let Some(readonly) = self.readonly {
+                write!(f, ", read-only: {}", readonly)?;
+            }
+            if let Some(mode) = self.mode {
+                write!(f, ", mode: {:o}", mode & 0o7777)?;
+            }
+            writeln!(f)?;
+        } else {
+            writeln!(f, "file exists: false")?;
+        }
+        if self.problems.is_empty() {
+            write!(f, "no problem found")
+        } else {
+            write!(f, "problems:")?;
+            self.problems
+                .iter()
+                .try_for_each(|problem| write!(f, "\n- {}", problem))
+        }
+    }
+}
+
+/// Diagnoses the config file at `file_path`, saved in `save_format`.
+///
+/// `load` is called to check that an existing regular file can be loaded.
+pub(crate) fn diagnose(
+    file_path: PathBuf,
+    save_format: SaveFormat,
+    load: impl FnOnce(&Path) -> Result<()>,
+) -> Diagnostics {
+    let mut problems = Vec::new();
+
+    let dir = file_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    // A missing directory is not a problem, it is created on the first save
+    let dir_exists = dir.is_dir();
+    let dir_writable = dir.ancestors().find(|dir| dir.is_dir()).is_some_and(is_writable);
+    if !dir_writable {
+        problems.push("the config directory is not writable, saves will fail".to_string());
+    }
+
+    let metadata = std::fs::metadata(&file_path).ok();
+    let file_exists = metadata.is_some();
+    let mut load_error = None;
+    match &metadata {
+        Some(metadata) if !metadata.is_file() => {
+            problems.push("the config file is not a regular file, it was not loaded".to_string());
+        }
+        Some(_) => {
+            if let Err(e) = load(&file_path) {
+                problems.push(format!("the config file can't be loaded: {:#}", e));
+                load_error = Some(format!("{:#}", e));
+            }
+        }
+        None => {}
+    }
+
+    let mut tmp_path = file_path.clone().into_os_string();
+    tmp_path.push(".tmp");
+    if Path::new(&tmp_path).exists() {
+        problems.push(format!("{:?} was left behind by an interrupted save", tmp_path));
+    }
+
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.as_ref().map(|metadata| metadata.permissions().mode())
+    };
+    #[cfg(not(unix))]
+    let mode = None;
+
+    Diagnostics {
+        file_path,
+        save_format,
+        dir_exists,
+        dir_writable,
+        file_exists,
+        size: metadata.as_ref().map(|metadata| metadata.len()),
+        modified: metadata.as_ref().and_then(|metadata| metadata.modified().ok()),
+        readonly: metadata.as_ref().map(|metadata| metadata.permissions().readonly()),
+        mode,
+        load_error,
+        problems,
+    }
+}
+
+/// Returns whether a file can be created in `dir`, by creating and removing a probe file.
+fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".persistent_config_probe_{}", std::process::id()));
+    let created = OpenOptions::new().write(true).create_new(true).open(&probe).is_ok();
+    if created {
+        _ = std::fs::remove_file(&probe);
+    }
+    created
+}
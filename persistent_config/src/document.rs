@@ -0,0 +1,286 @@
+//! Intermediate document model used to transform config files.
+//!
+//! When a type needs its on-disk layout to differ from its serde representation (for
+//! example with `#[persistent(rename_all = "...")]` or `#[persistent(alias = "...")]`), it is converted to a
+//! [`serde_json::Value`] which is transformed before being written, and the other way
+//! around when loading.
+
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use serde_json::{Map, Value};
+
+/// Direction of a transformation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Direction {
+    /// From the serde representation to the file.
+    ToDisk,
+    /// From the file to the serde representation.
+    FromDisk,
+}
+
+/// Renames the top level keys of `document` according to `renames`.
+///
+/// `renames` holds `(field name, on-disk key)` pairs, keys without a pair are kept as they are.
+pub(crate) fn rename_keys(document: Value, renames: &[(&str, &str)], direction: Direction) -> Value {
+    let Value::Object(map) = document else {
+        return document;
+    };
+
+    let map = map
+        .into_iter()
+        .map(|(key, value)| {
+            let renamed = renames.iter().find_map(|(field, on_disk)| match direction {
+                Direction::ToDisk => (*field == key).then_some(*on_disk),
+                Direction::FromDisk => (*on_disk == key).then_some(*field),
+            });
+            (renamed.map_or(key, str::to_owned), value)
+        })
+        .collect::<Map<_, _>>();
+    Value::Object(map)
+}
+
+/// Moves the values saved under an alias to the key they belong to.
+///
+/// `aliases` holds `(old key, key)` pairs. The value of `old key` is kept only if `key` is
+/// missing, in any case `old key` is removed so it doesn't reach the deserializer.
+pub(crate) fn resolve_aliases(document: Value, aliases: &[(&str, &str)]) -> Value {
+    let Value::Object(mut map) = document else {
+        return document;
+    };
+
+    for (alias, key) in aliases {
+        if let Some(value) = map.remove(*alias)
+            && !map.contains_key(*key)
+        {
+            map.insert((*key).to_owned(), value);
+        }
+    }
+    Value::Object(map)
+}
+
+/// Replaces the top level `keys` of `existing` with their value in `document`.
+///
+/// `existing` is replaced by an empty map if it is not one, and every key must be present in
+/// `document`.
+pub(crate) fn patch(existing: Value, document: &Value, keys: &[&str]) -> Result<Value> {
+    let mut map = match existing {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    };
+
+    for key in keys {
+        let Some(value) = document.get(*key) else {
+            return Err(anyhow!(
+                "`{}` is not a field of the serialized config (unknown, or skipped when serializing)",
+                key
+            ));
+        };
+        map.insert((*key).to_owned(), value.clone());
+    }
+    Ok(Value::Object(map))
+}
+
+/// Prefix of the values read from a file by the fields marked `#[persistent(from_file)]`.
+const FILE_PREFIX: &str = "file:";
+
+/// Replaces the `file:<path>` values of `keys` by the content of the referenced files.
+///
+/// Relative paths are resolved from `base_dir`. A single trailing newline is removed from
+/// the content, as most tools writing secrets add one.
+pub(crate) fn resolve_file_refs(document: Value, keys: &[&str], base_dir: &Path) -> Result<Value> {
+    let Value::Object(mut map) = document else {
+        return Ok(document);
+    };
+
+    for key in keys {
+        let Some(path) = map
+            .get(*key)
+            .and_then(Value::as_str)
+            .and_then(|value| value.strip_prefix(FILE_PREFIX))
+        else {
+            continue;
+        };
+        let path = base_dir.join(path);
+        // Not a missing config file, the io::Error is not kept so `load` doesn't take it for one
+        let mut content = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Failed to read the file {:?} referenced by `{}`: {}", path, key, e))?;
+        if content.ends_with('\n') {
+            content.pop();
+            if content.ends_with('\r') {
+                content.pop();
+            }
+        }
+        map.insert((*key).to_owned(), Value::String(content));
+    }
+    Ok(Value::Object(map))
+}
+
+/// Keeps the `file:<path>` values of `keys` found in `previous`, instead of the values read
+/// from the files.
+///
+/// This way saving never writes the content of a referenced file into the config file.
+pub(crate) fn keep_file_refs(document: &mut Value, previous: &Value, keys: &[&str]) {
+    let (Value::Object(map), Value::Object(previous)) = (document, previous) else {
+        return;
+    };
+
+    for key in keys {
+        if let Some(reference) = previous
+            .get(*key)
+            .filter(|value| value.as_str().is_some_and(|value| value.starts_with(FILE_PREFIX)))
+        {
+            map.insert((*key).to_owned(), reference.clone());
+        }
+    }
+}
+
+/// Replacement of the values of the fields marked `#[persistent(redact)]`.
+pub(crate) const REDACTED: &str = "***";
+
+/// Removes the top level keys of `document` holding the same value in `defaults`.
+pub(crate) fn strip_defaults(document: &mut Value, defaults: &Value) {
+    let (Value::Object(map), Value::Object(defaults)) = (document, defaults) else {
+        return;
+    };
+    map.retain(|key, value| defaults.get(key) != Some(value));
+}
+
+/// Adds the top level keys of `defaults` missing from `document`.
+pub(crate) fn fill_missing(document: &mut Value, defaults: Value) {
+    let (Value::Object(map), Value::Object(defaults)) = (document, defaults) else {
+        return;
+    };
+    for (key, value) in defaults {
+        map.entry(key).or_insert(value);
+    }
+}
+
+/// Removes the top level `keys` of `document`, the fields computed at runtime that are never
+/// read from nor written to the config file.
+pub(crate) fn strip_computed(document: &mut Value, keys: &[&str]) {
+    if let Value::Object(map) = document {
+        for key in keys {
+            map.shift_remove(*key);
+        }
+    }
+}
+
+/// Replaces the values of the top level `keys` of `document` by [`REDACTED`].
+pub(crate) fn redact(document: &mut Value, keys: &[&str]) {
+    let Value::Object(map) = document else {
+        return;
+    };
+
+    for key in keys {
+        if let Some(value) = map.get_mut(*key) {
+            *value = Value::String(REDACTED.to_owned());
+        }
+    }
+}
+
+/// Returns the non-empty strings found in the values of the top level `keys` of `document`.
+pub(crate) fn redacted_strings(document: &Value, keys: &[&str]) -> Vec<String> {
+    fn collect(value: &Value, strings: &mut Vec<String>) {
+        match value {
+            Value::String(string) if !string.is_empty() => strings.push(string.clone()),
+            Value::Array(values) => values.iter().for_each(|value| collect(value, strings)),
+            Value::Object(map) => map.values().for_each(|value| collect(value, strings)),
+            _ => {}
+        }
+    }
+
+    let mut strings = Vec::new();
+    for key in keys {
+        if let Some(value) = document.get(*key) {
+            collect(value, &mut strings);
+        }
+    }
+    strings
+}
+
+/// Replaces the occurrences of `strings` in the message of `error` by [`REDACTED`].
+///
+/// The error is returned as is if it doesn't quote any of them.
+pub(crate) fn scrub(error: anyhow::Error, strings: &[String]) -> anyhow::Error {
+    let message = format!("{:#}", error);
+    if !strings.iter().any(|string| message.contains(string.as_str())) {
+        return error;
+    }
+    let message = strings
+        .iter()
+        .fold(message, |message, string| message.replace(string.as_str(), REDACTED));
+    anyhow!(message)
+}
+
+/// Removes `null` values from maps, recursively.
+///
+/// TOML has no null value: when serializing a struct, `None` fields are skipped, but a
+/// `null` inside a [`Value`] is an error.
+pub(crate) fn strip_nulls(document: &mut Value) {
+    match document {
+        Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+/// Sorts the keys of maps in alphabetical order, recursively.
+pub(crate) fn sort_keys(document: &mut Value) {
+    match document {
+        Value::Object(map) => {
+            map.sort_keys();
+            map.values_mut().for_each(sort_keys);
+        }
+        Value::Array(values) => values.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+/// Returns the paths of the values that differ between `old` and `new`, in the order of `new`.
+///
+/// Paths are keys joined with `.`, with array indices as keys (`servers.0.host`). Values
+/// only present in `old` are reported after the others. Maps are compared key by key, any
+/// other change of value is reported at the path of the value.
+pub(crate) fn changed_paths(old: &Value, new: &Value) -> Vec<String> {
+    let mut paths = Vec::new();
+    diff(old, new, &mut String::new(), &mut paths);
+    paths
+}
+
+/// Appends the paths of the differences between `old` and `new` below `path` to `paths`.
+fn diff(old: &Value, new: &Value, path: &mut String, paths: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, value) in new {
+                with_key(path, key, |path| {
+                    diff(old.get(key).unwrap_or(&Value::Null), value, path, paths)
+                });
+            }
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                with_key(path, key, |path| paths.push(path.clone()));
+            }
+        }
+        (Value::Array(old), Value::Array(new)) if old.len() == new.len() => {
+            for (index, (old, new)) in old.iter().zip(new).enumerate() {
+                with_key(path, &index.to_string(), |path| diff(old, new, path, paths));
+            }
+        }
+        (old, new) if old != new => paths.push(path.clone()),
+        _ => {}
+    }
+}
+
+/// Calls `f` with `key` appended to `path`.
+fn with_key(path: &mut String, key: &str, f: impl FnOnce(&mut String)) {
+    let len = path.len();
+    if !path.is_empty() {
+        path.push('.');
+    }
+    path.push_str(key);
+    f(path);
+    path.truncate(len);
+}
@@ -0,0 +1,43 @@
+//! Default configs embedded in the program, with `#[persistent(embedded_default = "...")]`.
+//!
+//! The embedded file is written in the on-disk layout, like the config file itself, so the
+//! shipped defaults can be maintained by people who don't read Rust. It stands for the
+//! default value of the type when the config file doesn't exist.
+
+use anyhow::{Context, Result};
+use persistent_config_core::{PersistentConfigParameters, SaveFormat};
+use serde_json::Value;
+
+use crate::document::{self, Direction};
+use crate::{PersistentConfigBuilder, from_document, properties, serializer};
+
+/// Parses the default config embedded in `T`, if any.
+pub(crate) fn parse<T: PersistentConfigBuilder>(params: &PersistentConfigParameters) -> Option<Result<T>> {
+    let (content, save_format) = T::embedded_default()?;
+    let parsed = parse_document(content, save_format, params).and_then(|document| {
+        let document = document::rename_keys(document, T::field_renames(), Direction::FromDisk);
+        let document = document::resolve_aliases(document, T::field_aliases());
+        from_document(document, save_format)
+    });
+    Some(parsed.context("Failed to parse the embedded default config"))
+}
+
+/// Returns `T` parsed from its embedded default config, or its default value if it has none.
+pub(crate) fn default<T: PersistentConfigBuilder>(params: &PersistentConfigParameters) -> Result<T> {
+    parse(params).unwrap_or_else(|| Ok(T::default()))
+}
+
+/// Parses `content` in `save_format` to a document.
+pub(crate) fn parse_document(mut content: &[u8], save_format: SaveFormat, params: &PersistentConfigParameters) -> Result<Value> {
+    if save_format != SaveFormat::CBOR {
+        content = serializer::strip_bom(content);
+    }
+    match save_format {
+        SaveFormat::JSON => Ok(serde_json::from_slice(content)?),
+        SaveFormat::TOML => Ok(toml::from_str(std::str::from_utf8(content)?)?),
+        SaveFormat::YAML => serializer::from_yaml(serde_yaml::Deserializer::from_slice(content), &params.serializer),
+        SaveFormat::Properties => properties::parse(std::str::from_utf8(content)?),
+        SaveFormat::CBOR => serializer::from_cbor(content),
+        SaveFormat::HCL => serializer::from_hcl(std::str::from_utf8(content)?),
+    }
+}
@@ -0,0 +1,41 @@
+//! Overrides of single fields by environment variables, set with `#[persistent(env = "...")]`.
+
+use std::env::VarError;
+
+use anyhow::{Result, anyhow};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::document;
+
+/// Reads the environment variable `var` as a value of type `T`.
+///
+/// The value is parsed as JSON first, so numbers, booleans, `null` and arrays keep their
+/// type, and is otherwise taken as a plain string: `PORT=8080` gives a `u16`, `NAME=8080`
+/// gives a `String`.
+///
+/// Returns `Ok(None)` if the variable is not set. With `redact`, the value is left out of the
+/// error reported when it is invalid.
+pub fn env_override<T: DeserializeOwned>(var: &str, redact: bool) -> Result<Option<T>> {
+    let raw = match std::env::var(var) {
+        Ok(raw) => raw,
+        Err(VarError::NotPresent) => return Ok(None),
+        Err(e) => return Err(anyhow!("Invalid environment variable {}: {}", var, e)),
+    };
+
+    serde_json::from_str(&raw)
+        .or_else(|_| T::deserialize(Value::String(raw.clone())))
+        .map(Some)
+        .map_err(|e| {
+            if redact {
+                // The deserialization error may quote the value
+                anyhow!(
+                    "Invalid value {:?} for environment variable {}",
+                    document::REDACTED,
+                    var
+                )
+            } else {
+                anyhow!("Invalid value {:?} for environment variable {}: {}", raw, var, e)
+            }
+        })
+}
@@ -0,0 +1,118 @@
+//! Envelope wrapped around the saved payload.
+//!
+//! With [`EnvelopeOptions`] set in the parameters, the config file holds the struct under a
+//! `payload` key, next to the metadata describing who saved it and when:
+//!
+//! ```toml
+//! schema_version = 2
+//! app_version = "1.4.0"
+//! saved_at = "2025-01-31T12:00:00Z"
+//!
+//! [payload]
+//! name = "example"
+//! ```
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use persistent_config_core::{EnvelopeOptions, PersistentConfigError};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Key holding the struct inside the envelope.
+const PAYLOAD_KEY: &str = "payload";
+
+/// Metadata read from the envelope of a config file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvelopeMetadata {
+    /// Version of the config schema the file was saved with.
+    pub schema_version: u32,
+    /// Version of the application that saved the file.
+    pub app_version: String,
+    /// When the file was saved, as an RFC 3339 UTC timestamp.
+    pub saved_at: String,
+}
+
+/// Wraps `payload` in an envelope built from `options`, timestamped now.
+pub(crate) fn wrap(payload: Value, options: &EnvelopeOptions) -> Value {
+    let mut envelope = Map::new();
+    envelope.insert("schema_version".to_owned(), options.schema_version.into());
+    envelope.insert("app_version".to_owned(), options.app_version.clone().into());
+    envelope.insert("saved_at".to_owned(), format_timestamp(SystemTime::now()).into());
+    envelope.insert(PAYLOAD_KEY.to_owned(), payload);
+    Value::Object(envelope)
+}
+
+/// Splits the document read from `path` into its payload and its metadata.
+///
+/// Documents without envelope are returned as they are if `accept_legacy` is set in
+/// `options`, and rejected with [`PersistentConfigError::MissingEnvelope`] otherwise.
+pub(crate) fn unwrap(
+    document: Value,
+    options: &EnvelopeOptions,
+    path: &Path,
+) -> Result<(Value, Option<EnvelopeMetadata>)> {
+    match split(document) {
+        Ok((payload, metadata)) => Ok((payload, Some(metadata))),
+        Err(document) if options.accept_legacy => Ok((document, None)),
+        Err(_) => Err(PersistentConfigError::MissingEnvelope { path: path.to_owned() }.into()),
+    }
+}
+
+/// Returns the metadata of `document`, if it is an envelope.
+pub(crate) fn metadata(document: Value) -> Option<EnvelopeMetadata> {
+    split(document).ok().map(|(_, metadata)| metadata)
+}
+
+/// Returns the payload of `document` if it is an envelope, or `document` itself.
+pub(crate) fn payload(document: Value) -> Value {
+    split(document).map_or_else(|document| document, |(payload, _)| payload)
+}
+
+/// Splits an envelope into its payload and its metadata, or gives the document back if it
+/// isn't one.
+fn split(document: Value) -> Result<(Value, EnvelopeMetadata), Value> {
+    let Value::Object(mut map) = document else {
+        return Err(document);
+    };
+    let Some(payload) = map.remove(PAYLOAD_KEY) else {
+        return Err(Value::Object(map));
+    };
+
+    match EnvelopeMetadata::deserialize(&Value::Object(map.clone())) {
+        Ok(metadata) => Ok((payload, metadata)),
+        Err(_) => {
+            // A struct with a `payload` field, not an envelope
+            map.insert(PAYLOAD_KEY.to_owned(), payload);
+            Err(Value::Object(map))
+        }
+    }
+}
+
+/// Formats `time` as an RFC 3339 UTC timestamp, with a precision of one second.
+pub(crate) fn format_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
@@ -0,0 +1,157 @@
+//! Per-type callbacks invoked by save and load.
+//!
+//! Hooks are stored in [`PERSISTENT_CONFIGS`] extension slots, next to the registered
+//! parameters of the type.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use persistent_config_core::PERSISTENT_CONFIGS;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::document;
+use crate::progress::{LoadEvent, Observer};
+
+/// Extension slot holding the first run hook of a type.
+const FIRST_RUN_SLOT: &str = "first_run";
+
+/// Extension slot holding the upgrade hook of a type.
+const UPGRADE_SLOT: &str = "upgrade";
+
+/// Extension slot holding the load event hook of a type.
+const LOAD_EVENTS_SLOT: &str = "load_events";
+
+/// Extension slot holding the field change hooks of a type.
+const FIELD_CHANGES_SLOT: &str = "field_changes";
+
+/// Callback invoked by `load` when the config file does not exist yet.
+struct FirstRunHook<T>(Box<dyn Fn(&mut T) + Send + Sync>);
+
+/// Sets the first run hook of `T`, replacing any previous one.
+pub(crate) fn set_first_run<T: 'static>(hook: impl Fn(&mut T) + Send + Sync + 'static) {
+    PERSISTENT_CONFIGS.add_extension::<T, _>(FIRST_RUN_SLOT, FirstRunHook::<T>(Box::new(hook)));
+}
+
+/// Updates `value` with the first run hook of `T`.
+///
+/// Returns `false` if no hook is set.
+pub(crate) fn first_run<T: 'static>(value: &mut T) -> bool {
+    let Some(hook) = PERSISTENT_CONFIGS.get_extension::<T, FirstRunHook<T>>(FIRST_RUN_SLOT) else {
+        return false;
+    };
+    (hook.0)(value);
+    true
+}
+
+/// Callback invoked by `load` with the version of the app that saved the config file, the
+/// current version and the loaded config.
+type UpgradeFn<T> = dyn Fn(Option<&str>, &str, &mut T) + Send + Sync;
+
+/// Callback invoked by `load` when the config file was saved by another version of the app.
+struct UpgradeHook<T>(Box<UpgradeFn<T>>);
+
+/// Sets the upgrade hook of `T`, replacing any previous one.
+pub(crate) fn set_upgrade<T: 'static>(hook: impl Fn(Option<&str>, &str, &mut T) + Send + Sync + 'static) {
+    PERSISTENT_CONFIGS.add_extension::<T, _>(UPGRADE_SLOT, UpgradeHook::<T>(Box::new(hook)));
+}
+
+/// Updates `value`, saved by the app in `old_version`, with the upgrade hook of `T`.
+///
+/// Returns `false` if no hook is set.
+pub(crate) fn upgrade<T: 'static>(old_version: Option<&str>, new_version: &str, value: &mut T) -> bool {
+    let Some(hook) = PERSISTENT_CONFIGS.get_extension::<T, UpgradeHook<T>>(UPGRADE_SLOT) else {
+        return false;
+    };
+    (hook.0)(old_version, new_version, value);
+    true
+}
+
+/// Callback receiving the load events of a type.
+struct LoadEventsHook(Observer);
+
+/// Sets the load event hook of `T`, replacing any previous one.
+pub(crate) fn set_load_events<T: 'static>(hook: impl Fn(&LoadEvent) + Send + Sync + 'static) {
+    PERSISTENT_CONFIGS.add_extension::<T, _>(LOAD_EVENTS_SLOT, LoadEventsHook(Arc::new(hook)));
+}
+
+/// Returns the load event hook of `T`, if set.
+pub(crate) fn load_events<T: 'static>() -> Option<Observer> {
+    let hook = PERSISTENT_CONFIGS.get_extension::<T, LoadEventsHook>(LOAD_EVENTS_SLOT)?;
+    Some(hook.0.clone())
+}
+
+/// Callback invoked with the old and new value of a field.
+type FieldChangeFn = dyn Fn(&Value, &Value) + Send + Sync;
+
+/// Callbacks invoked when the value of a field changes, with the last value they were
+/// compared with.
+#[derive(Default)]
+struct FieldChangeHooks {
+    /// Callbacks, by path of the field they watch.
+    hooks: Vec<(String, Arc<FieldChangeFn>)>,
+    /// Serialized value of the last config loaded or saved.
+    last: Option<Value>,
+}
+
+/// Adds a hook of `T` invoked when the field at `path` changes.
+pub(crate) fn add_field_change<T: 'static>(path: &str, hook: impl Fn(&Value, &Value) + Send + Sync + 'static) {
+    let hooks = PERSISTENT_CONFIGS
+        .get_or_add_extension::<T, _>(FIELD_CHANGES_SLOT, Mutex::<FieldChangeHooks>::default)
+        .expect("the field changes slot holds field change hooks");
+    let mut hooks = hooks.lock().unwrap_or_else(PoisonError::into_inner);
+    hooks.hooks.push((path.to_owned(), Arc::new(hook)));
+}
+
+/// Invokes the field change hooks of `T` whose field differs between `value` and the last
+/// config loaded or saved.
+///
+/// The first config seen is only recorded.
+pub(crate) fn field_changes<T: Serialize + 'static>(value: &T) {
+    let Some(hooks) = PERSISTENT_CONFIGS.get_extension::<T, Mutex<FieldChangeHooks>>(FIELD_CHANGES_SLOT) else {
+        return;
+    };
+    let Ok(new) = serde_json::to_value(value) else {
+        return;
+    };
+
+    let (old, triggered) = {
+        let mut hooks = hooks.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(old) = hooks.last.replace(new.clone()) else {
+            return;
+        };
+        let changed = document::changed_paths(&old, &new);
+        let triggered: Vec<_> = hooks
+            .hooks
+            .iter()
+            .filter(|(path, _)| changed.iter().any(|changed| overlaps(path, changed)))
+            .map(|(path, hook)| (path.clone(), hook.clone()))
+            .collect();
+        (old, triggered)
+    };
+
+    // Invoked without the lock, a hook may load or save the config
+    for (path, hook) in triggered {
+        let pointer = pointer(&path);
+        hook(
+            old.pointer(&pointer).unwrap_or(&Value::Null),
+            new.pointer(&pointer).unwrap_or(&Value::Null),
+        );
+    }
+}
+
+/// Returns `true` if one of the dot separated paths `a` and `b` is inside the other.
+fn overlaps(a: &str, b: &str) -> bool {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    long.strip_prefix(short)
+        .is_some_and(|rest| rest.is_empty() || short.is_empty() || rest.starts_with('.'))
+}
+
+/// Returns the JSON pointer of the dot separated `path`.
+fn pointer(path: &str) -> String {
+    if path.is_empty() {
+        return String::new();
+    }
+    path.split('.')
+        .map(|key| format!("/{}", key.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
@@ -0,0 +1,297 @@
+//! `@include` directives splicing other files into the config.
+//!
+//! With `includes` set in the parameters, any map of the config file may name other files
+//! under an `@include` key, as a path or a list of paths relative to the including file:
+//!
+//! ```toml
+//! "@include" = ["servers.toml", "secrets.json"]
+//! name = "example"
+//! ```
+//!
+//! The included documents are merged in order, then the keys of the including map are merged
+//! over them. By default maps are merged key by key and any other value is replaced, the
+//! `merge` parameter sets other strategies for maps and lists. When saving, the
+//! directive is kept and only the values differing from the included ones are written.
+//!
+//! The top-level map may also name a base profile under an `extends` key. The profile is the
+//! file of that name in the same directory, with the extension of the config file when the
+//! name has none. It is merged under the config the same way, and may extend another one:
+//!
+//! ```toml
+//! extends = "ci_runner"
+//! name = "runner-7"
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow, bail};
+use persistent_config_core::{ArrayMerge, MapMerge, MergeStrategy};
+use serde_json::{Map, Value};
+
+/// Key of the include directive.
+const INCLUDE_KEY: &str = "@include";
+
+/// Key of the base profile, in the top-level map.
+const EXTENDS_KEY: &str = "extends";
+
+/// Resolves the include directives of `document`, read from a file in `base_dir`.
+///
+/// `read` reads the document of an included file. `stack` holds the files being included,
+/// to detect cycles.
+pub(crate) fn resolve(
+    document: Value,
+    base_dir: &Path,
+    read: &dyn Fn(&Path) -> Result<Value>,
+    strategy: &MergeStrategy,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Value> {
+    let Value::Object(map) = document else {
+        return Ok(document);
+    };
+
+    let mut resolved = Map::new();
+    let mut paths = Vec::new();
+    for (key, value) in map {
+        if key == INCLUDE_KEY {
+            paths = include_paths(&value)?;
+        } else {
+            resolved.insert(key, resolve(value, base_dir, read, strategy, stack)?);
+        }
+    }
+    if paths.is_empty() {
+        return Ok(Value::Object(resolved));
+    }
+
+    let mut merged = included(&paths, base_dir, read, strategy, stack)?;
+    merge(&mut merged, resolved, strategy);
+    Ok(Value::Object(merged))
+}
+
+/// Merges `document`, read from `file_path`, over the base profile it extends, if any.
+///
+/// The include directives of the profiles are resolved, the ones of `document` are left to
+/// [`resolve`]. `stack` holds the files being included, to detect cycles.
+pub(crate) fn extend(
+    document: Value,
+    file_path: &Path,
+    read: &dyn Fn(&Path) -> Result<Value>,
+    strategy: &MergeStrategy,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Value> {
+    let Value::Object(mut map) = document else {
+        return Ok(document);
+    };
+    let Some(profile) = map.shift_remove(EXTENDS_KEY) else {
+        return Ok(Value::Object(map));
+    };
+
+    let mut merged = base_profile(&profile, file_path, read, strategy, stack)?;
+    merge(&mut merged, map, strategy);
+    Ok(Value::Object(merged))
+}
+
+/// Removes from `document` the values equal to the ones of the base profile extended by
+/// `previous`, and puts the `extends` key of `previous` back.
+///
+/// `previous` is the document read from `file_path` before saving, `document` the one about
+/// to be saved.
+pub(crate) fn keep_extends(
+    document: &mut Value,
+    previous: &Value,
+    file_path: &Path,
+    read: &dyn Fn(&Path) -> Result<Value>,
+    strategy: &MergeStrategy,
+) -> Result<()> {
+    let (Value::Object(map), Some(profile)) = (document, previous.get(EXTENDS_KEY)) else {
+        return Ok(());
+    };
+
+    let base = base_profile(profile, file_path, read, strategy, &mut Vec::new())?;
+    strip_included(map, &base, strategy);
+    map.shift_insert(0, EXTENDS_KEY.to_owned(), profile.clone());
+    Ok(())
+}
+
+/// Reads the base profile named by `profile` in the directory of `file_path`, with its own
+/// base profiles and include directives resolved.
+fn base_profile(
+    profile: &Value,
+    file_path: &Path,
+    read: &dyn Fn(&Path) -> Result<Value>,
+    strategy: &MergeStrategy,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Map<String, Value>> {
+    let Value::String(profile) = profile else {
+        bail!("`{}` must be the name of a profile", EXTENDS_KEY);
+    };
+    let path = match file_path.extension() {
+        Some(extension) if Path::new(profile).extension() != Some(extension) => {
+            file_path.with_file_name(format!("{}.{}", profile, extension.to_string_lossy()))
+        }
+        _ => file_path.with_file_name(profile),
+    };
+    let canonical = std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+    if stack.contains(&canonical) {
+        bail!("Profile cycle: {:?} is already being extended", path);
+    }
+
+    // Not a missing config file, the io::Error is not kept so `load` doesn't take it for one
+    let document = read(&path).map_err(|e| anyhow!("Failed to read the base profile {:?}: {:#}", path, e))?;
+    stack.push(canonical);
+    let document = extend(document, &path, read, strategy, stack)?;
+    let document = resolve(document, path.parent().unwrap_or(Path::new("")), read, strategy, stack)?;
+    stack.pop();
+
+    match document {
+        Value::Object(map) => Ok(map),
+        _ => bail!("The base profile {:?} does not hold a map", path),
+    }
+}
+
+/// Removes from `document` the values equal to the ones included by `previous`, and puts
+/// the include directives of `previous` back.
+///
+/// `previous` is the document read from the file in `base_dir` before saving, `document` the
+/// one about to be saved.
+pub(crate) fn keep(
+    document: &mut Value,
+    previous: &Value,
+    base_dir: &Path,
+    read: &dyn Fn(&Path) -> Result<Value>,
+    strategy: &MergeStrategy,
+) -> Result<()> {
+    let (Value::Object(map), Value::Object(previous)) = (document, previous) else {
+        return Ok(());
+    };
+
+    for (key, previous) in previous {
+        if let Some(value) = map.get_mut(key) {
+            keep(value, previous, base_dir, read, strategy)?;
+        }
+    }
+
+    if let Some(directive) = previous.get(INCLUDE_KEY) {
+        let paths = include_paths(directive)?;
+        let base = included(&paths, base_dir, read, strategy, &mut Vec::new())?;
+        strip_included(map, &base, strategy);
+        map.shift_insert(0, INCLUDE_KEY.to_owned(), directive.clone());
+    }
+    Ok(())
+}
+
+/// Reads the included files and merges them in order.
+fn included(
+    paths: &[String],
+    base_dir: &Path,
+    read: &dyn Fn(&Path) -> Result<Value>,
+    strategy: &MergeStrategy,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Map<String, Value>> {
+    let mut merged = Map::new();
+    for path in paths {
+        let path = base_dir.join(path);
+        let canonical = std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if stack.contains(&canonical) {
+            bail!("Include cycle: {:?} is already being included", path);
+        }
+
+        // Not a missing config file, the io::Error is not kept so `load` doesn't take it for one
+        let document = read(&path).map_err(|e| anyhow!("Failed to read the included file {:?}: {:#}", path, e))?;
+        stack.push(canonical);
+        let document = resolve(document, path.parent().unwrap_or(Path::new("")), read, strategy, stack)?;
+        stack.pop();
+
+        match document {
+            Value::Object(map) => merge(&mut merged, map, strategy),
+            _ => bail!("The included file {:?} does not hold a map", path),
+        }
+    }
+    Ok(merged)
+}
+
+/// Returns the paths named by an include directive.
+fn include_paths(directive: &Value) -> Result<Vec<String>> {
+    match directive {
+        Value::String(path) => Ok(vec![path.clone()]),
+        Value::Array(paths) => paths
+            .iter()
+            .map(|path| path.as_str().map(str::to_owned))
+            .collect::<Option<_>>()
+            .ok_or_else(|| anyhow!("`{}` must be a path or a list of paths", INCLUDE_KEY)),
+        _ => bail!("`{}` must be a path or a list of paths", INCLUDE_KEY),
+    }
+}
+
+/// Merges `map` over `base` with the given strategy.
+fn merge(base: &mut Map<String, Value>, map: Map<String, Value>, strategy: &MergeStrategy) {
+    for (key, value) in map {
+        match base.get_mut(&key) {
+            Some(base) => merge_value(base, value, strategy),
+            None => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Merges `value` over `base` with the given strategy.
+fn merge_value(base: &mut Value, value: Value, strategy: &MergeStrategy) {
+    match (base, value) {
+        (Value::Object(base), Value::Object(map)) if strategy.maps == MapMerge::Recursive => merge(base, map, strategy),
+        (Value::Array(base), Value::Array(items)) => match &strategy.arrays {
+            ArrayMerge::Replace => *base = items,
+            ArrayMerge::Append => base.extend(items),
+            ArrayMerge::Keyed(key) => {
+                for item in items {
+                    let existing = item
+                        .get(key)
+                        .and_then(|id| base.iter_mut().find(|base| base.get(key) == Some(id)));
+                    match existing {
+                        Some(existing) => merge_value(existing, item, strategy),
+                        None => base.push(item),
+                    }
+                }
+            }
+        },
+        (base, value) => *base = value,
+    }
+}
+
+/// Removes from `map` the values merged from `base`, recursively.
+fn strip_included(map: &mut Map<String, Value>, base: &Map<String, Value>, strategy: &MergeStrategy) {
+    for (key, base) in base {
+        if let Some(value) = map.get_mut(key)
+            && strip_value(value, base, strategy)
+        {
+            map.shift_remove(key);
+        }
+    }
+}
+
+/// Removes from `value` the parts merged from `base`, and returns whether nothing else is
+/// left in it.
+fn strip_value(value: &mut Value, base: &Value, strategy: &MergeStrategy) -> bool {
+    if value == base {
+        return true;
+    }
+    match (value, base) {
+        (Value::Object(map), Value::Object(base)) if strategy.maps == MapMerge::Recursive => {
+            strip_included(map, base, strategy);
+            map.is_empty()
+        }
+        (Value::Array(items), Value::Array(base)) => match &strategy.arrays {
+            ArrayMerge::Replace => false,
+            // Items removed from the base can't be written, the whole list is kept then
+            ArrayMerge::Append if items.starts_with(base) => {
+                items.drain(..base.len());
+                items.is_empty()
+            }
+            ArrayMerge::Append => false,
+            ArrayMerge::Keyed(_) => {
+                items.retain(|item| !base.contains(item));
+                items.is_empty()
+            }
+        },
+        _ => false,
+    }
+}
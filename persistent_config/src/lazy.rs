@@ -0,0 +1,266 @@
+//! Heavy fields stored in a file of their own, loaded on first access, with
+//! `#[persistent(lazy)]` on a [`Lazy`] field.
+//!
+//! The value of such a field is stored as JSON in `<config file stem>.data/<key>.json` next
+//! to the config file, which only holds a `"sidecar"` placeholder. Loading the config doesn't
+//! read it: the sidecar file is read the first time the value is accessed, so a small config
+//! holding megabytes of cached data still loads at once.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+use anyhow::{Context, Result, bail};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::sidecar::Sidecar;
+
+/// Placeholder written to the config file for a [`Lazy`] field.
+const PLACEHOLDER: &str = "sidecar";
+
+/// A value stored in a sidecar file, read on first access, for the fields marked with
+/// `#[persistent(lazy)]`.
+///
+/// A missing sidecar file reads as the default value. A value written inline in the config
+/// file, such as one saved before the field was made lazy, is read as it is, and moved to
+/// the sidecar file by the next save.
+///
+/// Clones share the sidecar file, and the value once read.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # use std::collections::HashMap;
+/// #[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+/// # #[persistent(config_dir = "/tmp/persistent_config_doc_lazy", namespace = "")]
+/// struct Settings {
+///     theme: String,
+///     #[persistent(lazy)]
+///     thumbnails: Lazy<HashMap<String, Vec<u8>>>,
+/// }
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut settings = Settings::default();
+/// settings.thumbnails.get_mut()?.insert("logo.png".to_owned(), vec![0x89, 0x50]);
+/// settings.save()?;
+///
+/// let mut settings = Settings::default();
+/// settings.load()?;
+/// assert!(!settings.thumbnails.is_loaded());
+/// assert_eq!(settings.thumbnails.get()?["logo.png"], [0x89, 0x50]);
+/// # std::fs::remove_dir_all("/tmp/persistent_config_doc_lazy")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Lazy<T> {
+    /// The value, once read or set.
+    value: Arc<LazyValue<T>>,
+    /// Path of the sidecar file, unset until the value is loaded or saved.
+    source: Arc<Mutex<Option<PathBuf>>>,
+}
+
+/// The value of a [`Lazy`] field.
+struct LazyValue<T> {
+    /// The value, once read or set.
+    value: OnceLock<T>,
+    /// Whether the value differs from the sidecar file.
+    dirty: AtomicBool,
+}
+
+impl<T: Clone> Clone for LazyValue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            dirty: AtomicBool::new(self.dirty.load(Ordering::Acquire)),
+        }
+    }
+}
+
+impl<T> Lazy<T> {
+    /// Returns a field holding `value`, not saved yet.
+    pub fn new(value: T) -> Self {
+        Self::with_value(Some(value))
+    }
+
+    /// Returns `true` if the value is in memory, read from its file or set.
+    pub fn is_loaded(&self) -> bool {
+        self.value.value.get().is_some()
+    }
+
+    /// Frees the value read from its file and not changed since, to be read again when it
+    /// is accessed.
+    pub fn unload(&mut self) {
+        if self.source().is_some() && !self.value.dirty.load(Ordering::Acquire) {
+            self.value = Arc::new(LazyValue {
+                value: OnceLock::new(),
+                dirty: AtomicBool::new(false),
+            });
+        }
+    }
+
+    /// Returns a field holding `value`, or to be read from its file if `None`.
+    fn with_value(value: Option<T>) -> Self {
+        Self {
+            value: Arc::new(LazyValue {
+                dirty: AtomicBool::new(value.is_some()),
+                value: value.map_or_else(OnceLock::new, OnceLock::from),
+            }),
+            source: Arc::default(),
+        }
+    }
+
+    /// Returns the path of the sidecar file, if known.
+    fn source(&self) -> Option<PathBuf> {
+        self.source.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+}
+
+impl<T: Default + DeserializeOwned> Lazy<T> {
+    /// Returns the value, reading the sidecar file if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sidecar file exists but can't be read.
+    pub fn get(&self) -> Result<&T> {
+        if let Some(value) = self.value.value.get() {
+            return Ok(value);
+        }
+        let value = self.read()?;
+        Ok(self.value.value.get_or_init(|| value))
+    }
+
+    /// Returns the value to change it, reading the sidecar file if needed.
+    ///
+    /// The value is written by the next save.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sidecar file exists but can't be read.
+    pub fn get_mut(&mut self) -> Result<&mut T>
+    where
+        T: Clone,
+    {
+        self.get()?;
+        let value = Arc::make_mut(&mut self.value);
+        value.dirty.store(true, Ordering::Release);
+        Ok(value.value.get_mut().expect("the value was just read"))
+    }
+
+    /// Replaces the value, without reading the sidecar file.
+    pub fn set(&mut self, value: T) {
+        self.value = Arc::new(LazyValue {
+            value: OnceLock::from(value),
+            dirty: AtomicBool::new(true),
+        });
+    }
+
+    /// Reads the sidecar file.
+    fn read(&self) -> Result<T> {
+        let Some(path) = self.source() else {
+            bail!("The value has no file to be read from, load the config first");
+        };
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(T::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to open the sidecar file {:?}", path)),
+        };
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Failed to read the sidecar file {:?}", path))
+    }
+}
+
+impl<T> Sidecar for Lazy<T>
+where
+    T: Default + Serialize + DeserializeOwned,
+{
+    fn read_from(&mut self, path: PathBuf) {
+        *self.source.lock().unwrap_or_else(PoisonError::into_inner) = Some(sidecar_path(path));
+    }
+
+    /// Writes the value to the `<path>.json` file, unless it was read from it and not
+    /// changed since.
+    fn write_to(&self, path: &Path) -> Result<()> {
+        let path = sidecar_path(path.to_owned());
+        let source = self.source();
+        let same_file = source.as_deref() == Some(path.as_path());
+        if same_file && !self.value.dirty.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create the sidecar directory {:?}", dir))?;
+        }
+        let temp_path = path.with_extension("json.tmp");
+        let mut writer = BufWriter::new(
+            File::create(&temp_path).with_context(|| format!("Failed to create the sidecar file {:?}", temp_path))?,
+        );
+        serde_json::to_writer(&mut writer, self.get()?)?;
+        writer.flush()?;
+        std::fs::rename(&temp_path, &path).with_context(|| format!("Failed to write the sidecar file {:?}", path))?;
+
+        if same_file || source.is_none() {
+            self.value.dirty.store(false, Ordering::Release);
+            *self.source.lock().unwrap_or_else(PoisonError::into_inner) = Some(path);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Default> Default for Lazy<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> Clone for Lazy<T> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            source: self.source.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Lazy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value.value.get() {
+            Some(value) => f.debug_tuple("Lazy").field(value).finish(),
+            None => f
+                .debug_tuple("Lazy")
+                .field(&format_args!("<{:?}>", self.source()))
+                .finish(),
+        }
+    }
+}
+
+impl<T> Serialize for Lazy<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(PLACEHOLDER)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Lazy<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::String(placeholder) if placeholder == PLACEHOLDER => Ok(Self::with_value(None)),
+            inline => serde_json::from_value(inline)
+                .map(|value| Self::with_value(Some(value)))
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// Returns the path of the sidecar file of the field at `path`.
+fn sidecar_path(path: PathBuf) -> PathBuf {
+    let mut path = path.into_os_string();
+    path.push(".json");
+    path.into()
+}
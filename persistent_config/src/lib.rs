@@ -3,18 +3,189 @@
 //! Persistent configuration trait and helpers.
 //!
 //! This module provides traits and helpers for saving and loading configuration
-//! structs to disk using various formats (JSON, TOML, YAML). It builds on the
+//! structs to disk using various formats (JSON, TOML, YAML, Java properties, CBOR, and HCL for loading). It builds on the
 //! core types from `persistent_config_core` and provides a builder pattern for
 //! configuring persistence parameters.
+//!
+//! # Cargo features
+//!
+//! - `derive`: enables the `Persistent` derive macro and the `#[persistent_config]` attribute macro.
+//! - `zeroize`: wipes the intermediate buffers used while saving and loading, and enables
+//!   the `#[persistent(zeroize)]` field attribute for sensitive values.
+//! - `ownership`: applies the `owner` and `group` parameters to the config file, and checks
+//!   its owner with the `permission_check` parameter (Unix only).
+//! - `cbor`: enables the [`SaveFormat::CBOR`] binary format.
+//! - `hcl`: enables loading [`SaveFormat::HCL`] files, saving them is not supported.
+//! - `directories`: enables `PersistentConfigApp`, saving all configs in the platform config
+//!   directory of the application, or in the app storage on iOS and Android.
+//! - `metrics`: records counters and histograms of the saves and loads (count, failures, bytes
+//!   written, durations) through the [`metrics`](https://docs.rs/metrics) facade, named
+//!   `persistent_config_*` and labelled with the config type.
+//! - `regex`: enables the `#[persistent(regex = "...")]` field constraint.
+//! - `dialoguer`: asks for the fields marked with `#[persistent(prompt = "...")]` in the
+//!   terminal when the config file does not exist yet.
+//! - `mmap`: loads the binary formats (CBOR) straight from the memory-mapped file, instead of
+//!   reading it through a buffer, for large persisted caches.
+//! - `bundle`: enables `export_bundle` and `import_bundle`, packing the config files of all
+//!   the registered types in a single tar archive to back up the settings or move them to
+//!   another machine.
+//! - `vault`: enables `VaultResolver`, resolving the `vault://` secret references from the KV
+//!   secrets engine of HashiCorp Vault.
+//! - `sops`: loads and saves the config files encrypted with SOPS, with the `sops` parameter,
+//!   by running the `sops` command. Saving is only supported on Unix.
+//! - `gpg`: signs the config files and verifies their signature, with the `signature`
+//!   parameter, by running the `gpg` command.
+//! - `templates`: renders the string values of the config files as MiniJinja templates, with
+//!   the `templates` parameter.
+//! - `axum`: enables `ConfigExtractor` and `refresh_config`, serving a `PersistentCell` as
+//!   the state of an [axum](https://docs.rs/axum) router and reloading it from a route.
+//! - `egui`: enables `show_settings_ui`, drawing a settings panel of the config with
+//!   [egui](https://docs.rs/egui) and saving it when a field is changed.
+//! - `bevy`: enables `PersistentResourcePlugin`, keeping a config in a Bevy resource saved
+//!   when it changes.
+//!
+//! # Network access
+//!
+//! The crate only reads and writes local files: it never opens a network connection itself.
+//! The `metrics` feature doesn't send anything either, it only reports to the recorder
+//! installed by the application, and the `vault` feature sends its requests through the HTTP
+//! client of the application. The `axum` feature only provides handlers, served by the
+//! application.
+//!
+//! The [`SecretResolver`]s, such as the Vault one, may reach a remote secrets store when a
+//! config is loaded. `set_offline(true)` stops calling them: the loads reuse the secrets
+//! resolved before, instead of hanging on an unreachable store.
+//!
+//! The `sops` and `gpg` features run the `sops` and `gpg` commands, which may access the
+//! network depending on their configuration: `sops` reaches the key management service
+//! holding the keys of the file (AWS KMS, GCP KMS, Azure Key Vault or HashiCorp Vault), and
+//! `gpg` may fetch missing keys from a keyserver. Config files encrypted with `age` or PGP
+//! keys, and signatures verified against a local `keyring`, need no network access.
 
 use std::fmt::Debug;
 use std::fs::{File, OpenOptions};
-use std::io::{Write, read_to_string};
-use std::path::PathBuf;
+#[cfg(not(feature = "zeroize"))]
+use std::io::{BufReader, read_to_string};
+use std::io::{Read, Write};
+use std::panic::Location;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use anyhow::Result;
-use persistent_config_core::{PERSISTENT_CONFIGS, PersistentConfigParameters, SaveFormat};
+use anyhow::{Context, Result};
+use persistent_config_core::{
+    Durability, PERSISTENT_CONFIGS, PersistentConfigError, PersistentConfigParameters, SaveFormat,
+};
+#[doc(hidden)]
+pub use serde;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "zeroize")]
+pub use zeroize;
+
+#[cfg(feature = "directories")]
+mod app;
+mod audit;
+mod bounds;
+#[cfg(feature = "bundle")]
+mod bundle;
+mod cache;
+mod cell;
+mod chunked;
+pub mod constraints;
+mod delegate;
+mod describe;
+mod diagnostics;
+mod document;
+mod embedded;
+mod env;
+mod envelope;
+mod hooks;
+mod include;
+mod lazy;
+mod links;
+mod lock;
+#[cfg(all(unix, feature = "ownership"))]
+mod ownership;
+mod permissions;
+#[cfg(feature = "bevy")]
+mod plugin;
+mod preview;
+mod progress;
+#[cfg(feature = "dialoguer")]
+mod prompt;
+mod properties;
+mod provenance;
+mod recovery;
+mod references;
+mod registered;
+mod report;
+mod secrets;
+mod serializer;
+#[cfg(feature = "egui")]
+mod settings;
+mod sidecar;
+mod signature;
+mod snapshots;
+mod sops;
+mod startup;
+mod telemetry;
+mod template;
+#[cfg(feature = "templates")]
+mod templates;
+pub mod testing;
+mod throttle;
+mod timeout;
+mod values;
+#[cfg(feature = "vault")]
+mod vault;
+mod watch;
+#[cfg(feature = "axum")]
+mod web;
+#[cfg(feature = "zeroize")]
+mod zeroizing;
+
+#[cfg(feature = "directories")]
+pub use app::PersistentConfigApp;
+#[cfg(feature = "bundle")]
+pub use bundle::{BUNDLE_VERSION, BundleEntry, BundleManifest};
+use cache::FileStamp;
+pub use cell::PersistentCell;
+pub use chunked::Chunked;
+pub use describe::FieldDescription;
+pub use diagnostics::Diagnostics;
+use document::Direction;
+pub use envelope::EnvelopeMetadata;
+pub use lazy::Lazy;
+use lock::FileLock;
+#[cfg(feature = "bevy")]
+pub use plugin::{PersistentResourcePlugin, ResourceLoadOutcome};
+pub use preview::SavePreview;
+pub use progress::LoadEvent;
+use progress::ProgressReader;
+pub use recovery::LoadOutcome;
+pub use registered::Registered;
+pub use report::SaveReport;
+pub use secrets::SecretResolver;
+pub use startup::ConfigType;
+pub use values::{ByteSize, ExpandablePath, HumanDuration, ParseValueError};
+#[cfg(feature = "vault")]
+pub use vault::{VaultRequest, VaultResolver};
+pub use watch::WatchHandle;
+#[cfg(feature = "axum")]
+pub use web::{ConfigExtractor, refresh_config};
+
+/// Items used by the code generated by the `Persistent` derive, not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use anyhow;
+    pub use serde_json;
+
+    pub use crate::bounds::{PersistentDebug, PersistentDefault, PersistentDeserialize, PersistentSerialize};
+    pub use crate::constraints;
+    pub use crate::delegate::{load_field, save_field};
+    pub use crate::describe::defaults;
+    pub use crate::env::env_override;
+    pub use crate::sidecar::{attach as attach_sidecar, save as save_sidecar};
+}
 
 /// Prelude for convenient imports.
 ///
@@ -22,9 +193,24 @@ use serde::{Deserialize, Serialize};
 pub mod prelude {
     pub use persistent_config_core::*;
     #[cfg(feature = "derive")]
-    pub use persistent_config_macros::Persistent;
+    pub use persistent_config_macros::{Persistent, persistent_config};
 
-    pub use crate::{PersistentConfig, PersistentConfigBuilder};
+    #[cfg(feature = "directories")]
+    pub use crate::PersistentConfigApp;
+    #[cfg(feature = "bundle")]
+    pub use crate::{BundleEntry, BundleManifest, export_bundle, import_bundle};
+    #[cfg(feature = "templates")]
+    pub use crate::set_template_var;
+    #[cfg(feature = "bevy")]
+    pub use crate::{PersistentResourcePlugin, ResourceLoadOutcome};
+    #[cfg(feature = "axum")]
+    pub use crate::{ConfigExtractor, refresh_config};
+    pub use crate::{
+        ByteSize, Chunked, ConfigType, Diagnostics, EnvelopeMetadata, ExpandablePath, FieldDescription, HumanDuration,
+        Lazy, LoadEvent, LoadOutcome, PersistentCell, PersistentConfig, PersistentConfigBuilder, Registered,
+        SavePreview, SaveReport, SecretResolver, WatchHandle, flush_all, freeze, is_offline, load_all_ordered,
+        set_offline, set_secret_resolver,
+    };
 }
 
 /// Trait for building persistent configuration parameters for a type.
@@ -37,9 +223,10 @@ pub trait PersistentConfigBuilder: Sized + Default + Serialize + for<'de> Deseri
     ///
     /// # Parameters
     ///
-    /// * `config_dir` - Optional directory path where the config file will be stored. Defaults to `./`.
+    /// * `config_dir` - Optional directory path where the config file will be stored. Defaults to the
+    ///   directory of the app defaults if set (see [`PersistentConfigDB::set_app_defaults`](persistent_config_core::PersistentConfigDB::set_app_defaults)), else `./.config`.
     /// * `file_name` - Optional name for the config file (without extension). Defaults to the type name.
-    /// * `save_format` - Format used for serialization (JSON, TOML, or YAML).
+    /// * `save_format` - Format used for serialization (JSON, TOML, YAML, properties, CBOR or HCL).
     /// * `panic_on_error` - If true, panics on load/save errors. If false, falls back to defaults.
     ///
     /// # Returns
@@ -49,15 +236,24 @@ pub trait PersistentConfigBuilder: Sized + Default + Serialize + for<'de> Deseri
     ///
     /// # Example
     ///
-    /// ```
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig {}
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
     /// let my_config = MyConfig::default();
     /// my_config.config_builder(
     ///     Some("./some_dir".to_string()),
     ///     Some("some_name".to_string()),
-    ///     SaveFormat::TOML, /// Serialization format
-    ///     false,            /// Panic on error, false means it will use default values on error
+    ///     SaveFormat::TOML, // Serialization format
+    ///     false,            // Panic on error, false means it will use default values on error
     /// )?;
+    /// # Ok(())
+    /// # }
     /// ```
+    #[track_caller]
     fn config_builder(
         &self,
         config_dir: Option<impl AsRef<str>>,
@@ -65,31 +261,64 @@ pub trait PersistentConfigBuilder: Sized + Default + Serialize + for<'de> Deseri
         save_format: SaveFormat,
         panic_on_error: bool,
     ) -> Result<()> {
-        let config_dir = config_dir.map_or_else(|| "./.config".to_string(), |dir| dir.as_ref().to_string());
-        let file_name = file_name.map_or_else(
-            || std::any::type_name::<Self>().split("::").last().unwrap().to_owned(),
-            |name| name.as_ref().to_string(),
-        );
+        let config_dir = config_dir.map_or_else(default_config_dir, |dir| dir.as_ref().to_string());
+        let file_name = file_name.map_or_else(default_file_name::<Self>, |name| name.as_ref().to_string());
 
         let config_params = PersistentConfigParameters {
             config_dir,
             file_name,
             save_format,
             panic_on_error,
+            ..Default::default()
         };
-        _ = PERSISTENT_CONFIGS.add_config::<Self>(config_params);
-        Ok(())
+        PERSISTENT_CONFIGS.add_config::<Self>(config_params)
+    }
+
+    /// Configures persistent storage from a full set of parameters.
+    ///
+    /// Use this when you need options not covered by `config_builder`, such as
+    /// [`Durability`]. An empty `config_dir` defaults to the directory of the app defaults, or
+    /// `./.config` without them, and an empty
+    /// `file_name` defaults to the type name.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the configuration was registered successfully
+    /// * `Err` if there was a problem registering the configuration
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig {}
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// let my_config = MyConfig::default();
+    /// my_config.config_with_parameters(PersistentConfigParameters {
+    ///     durability: Durability::Fsync,
+    ///     ..Default::default()
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn config_with_parameters(&self, params: PersistentConfigParameters) -> Result<()> {
+        PERSISTENT_CONFIGS.add_config::<Self>(complete_parameters::<Self>(params))
     }
 
     /// Configures persistent storage with default parameters.
     ///
     /// This function provides a simplified way to set up configuration persistence with default values.
     /// It uses the current directory for storage and the type name as the file name, with TOML as the format.
+    /// When app defaults are set (see [`PersistentConfigDB::set_app_defaults`](persistent_config_core::PersistentConfigDB::set_app_defaults)), their directory and
+    /// format are used instead.
     ///
     /// # Parameters
     ///
     /// * `panic_on_error` - If true, panics on load/save errors. If false, falls back to default values.
-    /// In this case, data may be lost if the program exits without saving successfully.
+    ///   In this case, data may be lost if the program exits without saving successfully.
     ///
     /// # Returns
     ///
@@ -98,21 +327,585 @@ pub trait PersistentConfigBuilder: Sized + Default + Serialize + for<'de> Deseri
     ///
     /// # Example
     ///
-    /// ```
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig {}
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
     /// let my_config = MyConfig::default();
     /// my_config.default_save_config(false)?;
+    /// # Ok(())
+    /// # }
     /// ```
+    #[track_caller]
     fn default_save_config(&self, panic_on_error: bool) -> Result<()> {
+        let save_format = PERSISTENT_CONFIGS
+            .app_defaults()
+            .map_or_else(SaveFormat::default, |defaults| defaults.save_format);
         let config_params = PersistentConfigParameters {
             panic_on_error,
-            file_name: std::any::type_name::<Self>().split("::").last().unwrap().to_owned(),
-            config_dir: "./.config".to_string(),
-            save_format: SaveFormat::default(),
+            file_name: default_file_name::<Self>(),
+            config_dir: default_config_dir(),
+            save_format,
+            ..Default::default()
+        };
+
+        PERSISTENT_CONFIGS.add_config::<Self>(config_params)
+    }
+
+    /// Registers the type with `params`, like
+    /// [`config_with_parameters`](Self::config_with_parameters), and returns its
+    /// [`Registered`] handle.
+    ///
+    /// The handle is an optional helper, loading and saving through it works as through
+    /// [`load`](PersistentConfig::load) and [`save`](PersistentConfig::save).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Registered<Self>)` if the configuration was registered successfully
+    /// * `Err` if the registration of the type is frozen
+    #[track_caller]
+    fn register(params: PersistentConfigParameters) -> Result<Registered<Self>> {
+        PERSISTENT_CONFIGS.add_config::<Self>(complete_parameters::<Self>(params))?;
+        Ok(Registered::new())
+    }
+
+    /// Returns the [`Registered`] handle of the type, registering it with its
+    /// [`derived_parameters`](Self::derived_parameters) or the app defaults first if needed,
+    /// as done by [`load`](PersistentConfig::load).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PersistentConfigError::NotRegistered`] if the type is not registered and
+    /// has no parameters to be registered with.
+    #[track_caller]
+    fn registered() -> Result<Registered<Self>> {
+        registered_params::<Self>()?;
+        Ok(Registered::new())
+    }
+
+    /// Registers a callback invoked by `load` when the config file does not exist yet.
+    ///
+    /// Instead of reporting the missing file, `load` resets the instance to its default
+    /// value and passes it to `hook`, which can run an interactive setup, import values
+    /// from a legacy location, and so on. The file is not written until `save` is called.
+    ///
+    /// Registering a new hook replaces the previous one.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { username: String }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut my_config = MyConfig::default();
+    /// my_config.default_save_config(false)?;
+    /// my_config.on_first_run(|config| {
+    ///     config.username = std::env::var("USER").unwrap_or_default();
+    /// });
+    /// my_config.load()?;
+    /// my_config.save()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn on_first_run<F>(&self, hook: F)
+    where
+        F: Fn(&mut Self) + Send + Sync + 'static,
+    {
+        hooks::set_first_run::<Self>(hook);
+    }
+
+    /// Registers a callback invoked by `load` when the config file was saved by another
+    /// version of the app, to run one-time fix-ups such as clearing caches or resetting
+    /// experimental flags.
+    ///
+    /// Requires an envelope in the parameters: the `app_version` of the file, `None` for a
+    /// file saved without envelope, is compared with the one of the
+    /// [`EnvelopeOptions`]. When they differ, the hook receives both versions and the loaded
+    /// config, which is saved right away with the current version, so the hook runs once.
+    ///
+    /// Registering a new hook replaces the previous one.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { experimental_renderer: bool }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut my_config = MyConfig::default();
+    /// my_config.config_with_parameters(PersistentConfigParameters {
+    ///     envelope: Some(EnvelopeOptions {
+    ///         schema_version: 1,
+    ///         app_version: env!("CARGO_PKG_VERSION").to_string(),
+    ///         accept_legacy: true,
+    ///     }),
+    ///     ..Default::default()
+    /// })?;
+    /// my_config.on_upgrade(|old_version, new_version, config| {
+    ///     println!("Upgraded from {:?} to {}", old_version, new_version);
+    ///     config.experimental_renderer = false;
+    /// });
+    /// my_config.load()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn on_upgrade<F>(&self, hook: F)
+    where
+        F: Fn(Option<&str>, &str, &mut Self) + Send + Sync + 'static,
+    {
+        hooks::set_upgrade::<Self>(hook);
+    }
+
+    /// Registers a callback receiving the progress of the loads of the type, to show a
+    /// progress indicator while a large file loads.
+    ///
+    /// Each load reports [`LoadEvent::Started`] with the size of the file, then
+    /// [`LoadEvent::BytesRead`] as it is read, [`LoadEvent::Parsed`] once deserialized and
+    /// [`LoadEvent::Done`]. The callback runs on the loading thread, or on the IO thread when
+    /// a `timeout` is set: it should hand the events over to the UI rather than block.
+    ///
+    /// Registering a new hook replaces the previous one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { username: String }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut my_config = MyConfig::default();
+    /// my_config.default_save_config(false)?;
+    /// my_config.on_load_event(|event| match event {
+    ///     LoadEvent::Started { size: Some(size), .. } => println!("Loading {} bytes", size),
+    ///     LoadEvent::BytesRead(read) => println!("{} bytes read", read),
+    ///     _ => {}
+    /// });
+    /// my_config.load()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn on_load_event<F>(&self, hook: F)
+    where
+        F: Fn(&LoadEvent) + Send + Sync + 'static,
+    {
+        hooks::set_load_events::<Self>(hook);
+    }
+
+    /// Registers a callback invoked with the old and new value of the field at `path` when it
+    /// changes, so a subsystem can react to the setting it cares about.
+    ///
+    /// `path` is the name of the field in the serialized struct, with the fields of nested
+    /// structs and the array indices joined with `.`, such as `server.port`. The callback of a
+    /// struct is invoked when any of its fields changes.
+    ///
+    /// The values are compared with the last config loaded or saved in this process, by
+    /// [`load`](PersistentConfig::load), [`load_cached`](PersistentConfig::load_cached),
+    /// [`save`](PersistentConfig::save), [`update`](PersistentConfig::update) and the reloads
+    /// of [`shared_watch`](PersistentConfig::shared_watch). The first config seen is only
+    /// recorded. The callbacks run on the thread that loaded or saved the config.
+    ///
+    /// Callbacks are added to the previous ones.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { log_level: String }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let dir = std::env::temp_dir().join("persistent_config_doc_on_change");
+    /// # std::fs::create_dir_all(&dir)?;
+    /// # std::fs::write(dir.join("MyConfig.toml"), "log_level = \"info\"")?;
+    /// let mut my_config = MyConfig::default();
+    /// my_config.config_with_parameters(PersistentConfigParameters {
+    ///     config_dir: dir.to_string_lossy().into_owned(),
+    ///     file_name: "MyConfig".to_string(),
+    ///     ..Default::default()
+    /// })?;
+    /// my_config.on_change("log_level", |old, new| {
+    ///     println!("Log level changed from {} to {}", old, new);
+    /// });
+    /// my_config.load()?;
+    /// my_config.log_level = "debug".to_string();
+    /// my_config.save()?; // Prints `Log level changed from "info" to "debug"`
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn on_change<F>(&self, path: &str, callback: F)
+    where
+        F: Fn(&serde_json::Value, &serde_json::Value) + Send + Sync + 'static,
+    {
+        hooks::add_field_change::<Self>(path, callback);
+    }
+
+    /// Declares the type to be loaded by [`load_all_ordered`], after the types of
+    /// `depends_on`, and hands the loaded value over to `on_load`.
+    ///
+    /// A config computing values from the others, in [`after_load`](Self::after_load) for
+    /// instance, can then rely on them being loaded first. Each type is loaded as with
+    /// [`load`](PersistentConfig::load), under its own error policy.
+    ///
+    /// Declaring the type again replaces its dependencies and callback, the type keeping its
+    /// place among the types without dependencies between them.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # use std::sync::OnceLock;
+    /// # #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+    /// # struct Database { url: String }
+    /// # impl PersistentConfigBuilder for Database {}
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct Cache { size: u32 }
+    /// # impl PersistentConfigBuilder for Cache {}
+    /// static DATABASE: OnceLock<Database> = OnceLock::new();
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// Database::default().default_save_config(false)?;
+    /// Cache::default().default_save_config(false)?;
+    ///
+    /// Cache::default().load_on_startup(&[ConfigType::of::<Database>()], |cache| {
+    ///     println!("Cache of {} entries for {}", cache.size, DATABASE.get().unwrap().url);
+    /// });
+    /// Database::default().load_on_startup(&[], |database| {
+    ///     _ = DATABASE.set(database);
+    /// });
+    ///
+    /// load_all_ordered()?; // Loads Database, then Cache
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn load_on_startup<F>(&self, depends_on: &[ConfigType], on_load: F)
+    where
+        F: Fn(Self) + Send + Sync + 'static,
+    {
+        let load = move || {
+            let mut value = Self::default();
+            value.load()?;
+            on_load(value);
+            Ok(())
+        };
+        startup::declare(ConfigType::of::<Self>(), depends_on, std::sync::Arc::new(load));
+    }
+
+    /// Exposes the type under `name`, so the string values of the other configs can reference
+    /// its values as `${name.field}`.
+    ///
+    /// The references are resolved when the other configs load, by loading the type under its
+    /// own parameters, a missing file giving the default value. A value made of a single
+    /// reference takes the referenced value with its type, nested values are reached with
+    /// `${name.field.key}`. Saving writes the references back as long as the values they
+    /// resolve to are unchanged, so the values are never duplicated in the files.
+    ///
+    /// References to names that are not exposed are left as they are. Exposing another type
+    /// under the same name replaces it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct PathsConfig { data_dir: String }
+    /// # impl PersistentConfigBuilder for PathsConfig {}
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct CacheConfig { location: String }
+    /// # impl PersistentConfigBuilder for CacheConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let dir = std::env::temp_dir().join("persistent_config_doc_expose_as");
+    /// # let params = PersistentConfigParameters {
+    /// #     config_dir: dir.to_string_lossy().into_owned(),
+    /// #     save_format: SaveFormat::JSON,
+    /// #     ..Default::default()
+    /// # };
+    /// # CacheConfig::default().config_with_parameters(params.clone())?;
+    /// let paths = PathsConfig { data_dir: "/srv/app".to_string() };
+    /// # paths.config_with_parameters(params)?;
+    /// paths.save()?;
+    /// paths.expose_as("paths");
+    ///
+    /// CacheConfig { location: "${paths.data_dir}/cache".to_string() }.save()?;
+    /// let mut cache = CacheConfig::default();
+    /// cache.load()?;
+    /// assert_eq!(cache.location, "/srv/app/cache");
+    ///
+    /// // Still a reference in the file
+    /// cache.save()?;
+    /// let file = std::fs::read_to_string(dir.join("CacheConfig.json"))?;
+    /// assert!(file.contains("${paths.data_dir}/cache"));
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn expose_as(&self, name: &str) {
+        let resolve = || {
+            let params = registered_params::<Self>()?;
+            let value = match load_checked::<Self>(&params) {
+                Ok(value) => value,
+                Err(e) if is_not_found(&e) => Self::default(),
+                Err(e) => return Err(e),
+            };
+            Ok(serde_json::to_value(value)?)
         };
+        references::expose(name.to_owned(), std::sync::Arc::new(resolve));
+    }
+
+    /// Returns the parameters used to register the type automatically on first use.
+    ///
+    /// When `save` or `load` find no registration for the type, these parameters are
+    /// registered as if passed to [`config_with_parameters`](Self::config_with_parameters).
+    /// The default implementation returns `None`, meaning the type must be registered
+    /// explicitly. The `Persistent` derive implements it when registration keys such as
+    /// `#[persistent(save_format = "yaml")]` are present.
+    fn derived_parameters() -> Option<PersistentConfigParameters> {
+        None
+    }
+
+    /// Wipes sensitive values held by this instance.
+    ///
+    /// Called by `load` on the current instance right before it is overwritten, so that
+    /// secrets don't linger in freed memory. The default implementation does nothing,
+    /// the `Persistent` derive implements it for fields marked `#[persistent(zeroize)]`
+    /// (requires the `zeroize` feature).
+    fn zeroize_sensitive(&mut self) {}
+
+    /// Returns the `(field name, on-disk key)` pairs applied to the top level keys on save and load.
+    ///
+    /// The default implementation returns no pairs, the `Persistent` derive generates them
+    /// from `#[persistent(rename_all = "...")]`.
+    fn field_renames() -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    /// Overrides fields with the value of their environment variable, if set.
+    ///
+    /// Called by `load` once the file is read. The default implementation does nothing, the
+    /// `Persistent` derive implements it for fields marked `#[persistent(env = "VAR")]`.
+    fn apply_env_overrides(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the on-disk keys of the fields whose value can be read from a file.
+    ///
+    /// When loading, a `"file:<path>"` value of one of these keys is replaced by the content
+    /// of the file at `<path>`, relative to the config directory. When saving, the reference
+    /// found in the config file is kept, so the content of the file is never written to it.
+    /// The default implementation returns no keys, the `Persistent` derive generates them
+    /// from `#[persistent(from_file)]`.
+    fn file_fields() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Called on the value about to be saved, to normalize or clamp it before it is written.
+    ///
+    /// `save` and `save_fields` take the instance by reference, so the hook is called on a
+    /// copy made through its serde representation: the instance itself is left as it is.
+    /// `update` calls it on the updated value, which is returned as saved. The default
+    /// implementation does nothing, the `Persistent` derive calls the function set with
+    /// `#[persistent(before_save = "...")]`.
+    fn before_save(&mut self) {}
+
+    /// Called on the value read from the config file, once environment overrides are applied.
+    ///
+    /// Called by `load`, `load_cached` and `update`, but not on the default value used
+    /// when loading fails. The default implementation does nothing, the `Persistent` derive
+    /// calls the function set with `#[persistent(after_load = "...")]`.
+    fn after_load(&mut self) {}
+
+    /// Checks the values of the config, rejecting the ones that can't be used.
+    ///
+    /// Called by `load`, `load_cached` and `update` after
+    /// [`after_load`](Self::after_load), and by every save after
+    /// [`before_save`](Self::before_save): an invalid config fails to load, under the error
+    /// policy of `panic_on_error`, and is never written. The default implementation accepts
+    /// any value, the `Persistent` derive checks the `min`, `max`, `regex` and `one_of`
+    /// constraints of the fields.
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the fields asked for on the first run, as their serde name and the question.
+    ///
+    /// With the `dialoguer` feature, `load` asks for them in the terminal when the config
+    /// file does not exist yet, then saves the answers. The default implementation returns
+    /// no fields, the `Persistent` derive generates them from `#[persistent(prompt = "...")]`.
+    fn prompt_fields() -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    /// Describes the fields of the config: their key, type, doc comment, default value,
+    /// environment variable and constraints.
+    ///
+    /// Meant for a settings reference, such as the output of `myapp config --help`, and
+    /// serializable for tools. The default implementation only knows the keys and defaults
+    /// found in the default value, the `Persistent` derive describes every field.
+    fn describe() -> Vec<FieldDescription> {
+        describe::from_defaults::<Self>()
+    }
+
+    /// Returns the on-disk keys of the fields stored in the config file of their own type.
+    ///
+    /// These fields are left out of the config file: `load` reads them with the registration
+    /// of their type, and `save` and `update` save them the same way. The default
+    /// implementation returns no keys, the `Persistent` derive generates them from
+    /// `#[persistent(flatten_from = "...")]`, along with
+    /// [`load_delegated`](Self::load_delegated) and [`save_delegated`](Self::save_delegated).
+    fn delegated_fields() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Loads the fields listed by [`delegated_fields`](Self::delegated_fields) into
+    /// `document`, the on-disk layout read from the config file, before it is deserialized.
+    fn load_delegated(_document: &mut serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    /// Saves the fields listed by [`delegated_fields`](Self::delegated_fields) to the config
+    /// file of their own type.
+    fn save_delegated(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Sets the directory the [`Chunked`] and [`Lazy`] fields are read from, once the config
+    /// is loaded from a file.
+    ///
+    /// `dir` is the `<config file stem>.data` directory next to the config file, holding the
+    /// files of these fields under their on-disk key. The default implementation does
+    /// nothing, the `Persistent` derive implements it for the fields marked
+    /// `#[persistent(chunked)]` or `#[persistent(lazy)]`, along with
+    /// [`save_sidecars`](Self::save_sidecars) and [`share_sidecars`](Self::share_sidecars).
+    fn attach_sidecars(&mut self, _dir: &Path) {}
 
-        PERSISTENT_CONFIGS.add_config::<Self>(config_params.clone());
+    /// Writes the files of the [`Chunked`] and [`Lazy`] fields to `dir`, before the config
+    /// file is written.
+    fn save_sidecars(&self, _dir: &Path) -> Result<()> {
         Ok(())
     }
+
+    /// Makes the [`Chunked`] and [`Lazy`] fields share the values of `from`, whose copy made
+    /// through the serde representation is being saved.
+    fn share_sidecars(&mut self, _from: &Self) {}
+
+    /// Returns the default config embedded in the program, and its format.
+    ///
+    /// When the config file does not exist, `load` parses it, in the on-disk layout, instead
+    /// of using [`Default::default`], and the first run hooks start from it. The default
+    /// implementation returns `None`, the `Persistent` derive embeds the file given with
+    /// `#[persistent(embedded_default = "...")]`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(Debug, Default, Serialize, Deserialize)]
+    /// struct Theme {
+    ///     name: String,
+    /// }
+    ///
+    /// impl PersistentConfigBuilder for Theme {
+    ///     fn embedded_default() -> Option<(&'static [u8], SaveFormat)> {
+    ///         Some((b"name = \"solarized\"", SaveFormat::TOML))
+    ///     }
+    /// }
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut theme = Theme::default();
+    /// theme.config_with_parameters(PersistentConfigParameters {
+    ///     config_dir: std::env::temp_dir().join("persistent_config_doc_embedded").to_string_lossy().into_owned(),
+    ///     ..Default::default()
+    /// })?;
+    /// theme.load()?;
+    /// assert_eq!(theme.name, "solarized");
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn embedded_default() -> Option<(&'static [u8], SaveFormat)> {
+        None
+    }
+
+    /// Returns the on-disk keys of the fields whose value must not appear in logs.
+    ///
+    /// Their values are masked as `"***"` by [`dump`](PersistentConfig::dump) and in the
+    /// errors reported when loading. The default implementation returns no keys, the
+    /// `Persistent` derive generates them from `#[persistent(redact)]`.
+    fn redacted_fields() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Returns the `(old key, serde key)` pairs used to load values saved under a former name.
+    ///
+    /// When loading, a top level `old key` is moved to `serde key` if the latter is missing.
+    /// The default implementation returns no pairs, the `Persistent` derive generates them
+    /// from `#[persistent(alias = "...")]`.
+    fn field_aliases() -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    /// Returns the on-disk keys of the fields computed at runtime, never read from nor
+    /// written to the config file.
+    ///
+    /// `save` and [`dump`](PersistentConfig::dump) leave them out, and `load` ignores the
+    /// values found in the file: the fields keep their default value, for
+    /// [`after_load`](Self::after_load) to compute them. The default implementation returns
+    /// no keys, the `Persistent` derive generates them from `#[persistent(computed)]`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(Debug, Default, Serialize, Deserialize)]
+    /// struct Playlist {
+    ///     tracks: Vec<u32>,
+    ///     total_length: u32,
+    /// }
+    ///
+    /// impl PersistentConfigBuilder for Playlist {
+    ///     fn computed_fields() -> &'static [&'static str] {
+    ///         &["total_length"]
+    ///     }
+    ///
+    ///     fn after_load(&mut self) {
+    ///         self.total_length = self.tracks.iter().sum();
+    ///     }
+    /// }
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let dir = std::env::temp_dir().join("persistent_config_doc_computed");
+    /// # Playlist::default().config_with_parameters(PersistentConfigParameters {
+    /// #     config_dir: dir.to_string_lossy().into_owned(),
+    /// #     ..Default::default()
+    /// # })?;
+    /// let playlist = Playlist { tracks: vec![180, 240], total_length: 420 };
+    /// playlist.save()?;
+    /// assert_eq!(playlist.dump()?, "tracks = [180, 240]\n");
+    ///
+    /// let mut loaded = Playlist::default();
+    /// loaded.load()?;
+    /// assert_eq!(loaded.total_length, 420);
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn computed_fields() -> &'static [&'static str] {
+        &[]
+    }
 }
 
 /// Trait for saving and loading persistent configuration.
@@ -126,7 +919,15 @@ pub trait PersistentConfig: PersistentConfigBuilder {
     ///
     /// # Behavior
     ///
-    /// - If no configuration parameters have been registered, returns an error
+    /// - If no configuration parameters have been registered, registers the
+    ///   [`derived_parameters`](PersistentConfigBuilder::derived_parameters) or returns a
+    ///   [`PersistentConfigError::NotRegistered`] error
+    /// - Within [`testing::with_override`], replaces the overriding value instead
+    /// - If the registration was frozen with [`freeze`], returns a
+    ///   [`PersistentConfigError::Frozen`] error
+    /// - If `min_save_interval` is set and the file was written less than that ago, records
+    ///   the save as pending and returns, see [`flush`](PersistentConfig::flush) and
+    ///   [`flush_all`]
     /// - If saving succeeds, prints a success message
     /// - If saving fails and `panic_on_error` is true, logs the error but returns Ok
     /// - If saving fails and `panic_on_error` is false, returns an error
@@ -138,35 +939,119 @@ pub trait PersistentConfig: PersistentConfigBuilder {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig {}
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
     /// let my_config = MyConfig::default();
     /// my_config.default_save_config(false)?;
     /// my_config.save()?;
+    /// # Ok(())
+    /// # }
     /// ```
+    #[track_caller]
     fn save(&self) -> Result<()> {
-        let params = match PERSISTENT_CONFIGS.get_config::<Self>() {
-            Some(params) => params,
-            None => {
-                return Err(anyhow::anyhow!("No persistent config found for this type"));
-            }
+        self.save_report().map(|_| ())
+    }
+
+    /// Saves the current configuration like [`save`](PersistentConfig::save), and returns
+    /// what was written.
+    ///
+    /// The [`SaveReport`] holds the path and size of the config file and the time taken by
+    /// the save, so the caller can log them, or an autosave space its saves by their cost.
+    /// A save that deliberately left the file unchanged, such as one deferred by
+    /// `min_save_interval`, is reported as skipped. A failed save is returned as an error,
+    /// or with `panic_on_error` set, reported with its [`error`](SaveReport::error).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { volume: u8 }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let dir = std::env::temp_dir().join("persistent_config_doc_save_report");
+    /// let my_config = MyConfig { volume: 7 };
+    /// my_config.config_with_parameters(PersistentConfigParameters {
+    ///     config_dir: dir.to_string_lossy().into_owned(),
+    ///     ..Default::default()
+    /// })?;
+    ///
+    /// let report = my_config.save_report()?;
+    /// assert!(report.is_written());
+    /// assert_eq!(report.bytes, std::fs::metadata(&report.path)?.len());
+    /// println!("Saved {} bytes to {:?} in {:?}", report.bytes, report.path, report.duration);
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn save_report(&self) -> Result<SaveReport> {
+        let started = std::time::Instant::now();
+        let skipped = |path: PathBuf| SaveReport {
+            path,
+            bytes: 0,
+            duration: started.elapsed(),
+            skipped: true,
+            error: None,
         };
+        if testing::is_overridden::<Self>() {
+            testing::save_override(&prepare_save(self)?);
+            let path = PERSISTENT_CONFIGS.get_config::<Self>().map(|params| config_file_path(&params));
+            return Ok(skipped(path.unwrap_or_default()));
+        }
+        let params = registered_params::<Self>()?;
+        let file_path = config_file_path(&params);
+        ensure_not_frozen::<Self>()?;
+        if let Some(interval) = params.min_save_interval
+            && throttle::defer::<Self>(interval)
+        {
+            throttle::keep(&params, self)?;
+            return Ok(skipped(file_path));
+        }
 
-        match save_file(&params, self) {
-            Ok(_) => {
+        // The file is about to change, drop any cached copy of it
+        cache::invalidate::<Self>();
+
+        match prepare_save(self).and_then(|mut data| {
+            let saved = save_config(&params, &data);
+            data.zeroize_sensitive();
+            saved
+        }) {
+            Ok(bytes) => {
                 println!("File saved successfully");
+                hooks::field_changes(self);
+                Ok(SaveReport {
+                    path: file_path,
+                    bytes,
+                    duration: started.elapsed(),
+                    skipped: false,
+                    error: None,
+                })
             }
 
             Err(e) if params.panic_on_error => {
                 println!("Error saving file: {:?}", e);
                 println!("Using default configuration");
+                Ok(SaveReport {
+                    path: file_path,
+                    bytes: 0,
+                    duration: started.elapsed(),
+                    skipped: false,
+                    error: Some(format!("{:#}", e)),
+                })
             }
 
             Err(e) => {
                 println!("Error saving file: {:?}", e);
-                return Err(anyhow::anyhow!("Failed to save file"));
+                Err(anyhow::anyhow!("Failed to save file"))
             }
         }
-        Ok(())
     }
 
     /// Loads configuration from persistent storage into the current instance.
@@ -176,11 +1061,34 @@ pub trait PersistentConfig: PersistentConfigBuilder {
     ///
     /// # Behavior
     ///
-    /// - If no configuration parameters have been registered, returns an error
-    /// - If loading succeeds, replaces the current instance with the loaded data
+    /// - If no configuration parameters have been registered, registers the
+    ///   [`derived_parameters`](PersistentConfigBuilder::derived_parameters) or returns a
+    ///   [`PersistentConfigError::NotRegistered`] error
+    /// - Within [`testing::with_override`], replaces the current instance with the overriding
+    ///   value instead
+    /// - If loading succeeds, replaces the current instance with the loaded data, the top
+    ///   level fields missing from the file, such as in a file written by
+    ///   [`save_delta`](PersistentConfig::save_delta), taking their default value
+    /// - If the file does not exist and the type has an
+    ///   [`embedded_default`](PersistentConfigBuilder::embedded_default) config, or a hook was
+    ///   set with `on_first_run`, replaces the current instance with the embedded config, or
+    ///   else the default value, updated by the hook
+    /// - If the file does not exist, the type has
+    ///   [`prompt_fields`](PersistentConfigBuilder::prompt_fields) and stdin is a terminal,
+    ///   asks for their values and saves the answers (`dialoguer` feature)
+    /// - Fields overridden by an environment variable (see
+    ///   [`apply_env_overrides`](PersistentConfigBuilder::apply_env_overrides)) take its value,
+    ///   an invalid value is a loading failure
+    /// - If some values of the file don't deserialize and `repair_invalid` is set, uses the
+    ///   default values in their place, keeping the other values
+    /// - If the file can't be parsed and `quarantine_corrupt` is set, moves it to
+    ///   `<file name>.corrupt-<timestamp>` and saves the default values in its place
     /// - If loading fails and `panic_on_error` is false, logs the error and uses default values
     /// - If loading fails and `panic_on_error` is true, returns an error
     ///
+    /// [`load_with_outcome`](PersistentConfig::load_with_outcome) reports which of these
+    /// happened.
+    ///
     /// # Returns
     ///
     /// * `Ok(())` if the configuration was loaded successfully or if using defaults due to error with `panic_on_error` false
@@ -188,107 +1096,2342 @@ pub trait PersistentConfig: PersistentConfigBuilder {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig {}
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
     /// let mut my_config = MyConfig::default();
     /// my_config.default_save_config(false)?;
     /// my_config.load()?;
+    /// # Ok(())
+    /// # }
     /// ```
+    #[track_caller]
     fn load(&mut self) -> Result<()>
     where
         Self: for<'de> Deserialize<'de>,
     {
-        let params = match PERSISTENT_CONFIGS.get_config::<Self>() {
-            Some(params) => params,
-            None => {
-                return Err(anyhow::anyhow!("No persistent config found for this type"));
+        self.load_with_outcome().map(|_| ())
+    }
+
+    /// Loads configuration like [`load`](PersistentConfig::load), and reports how the config
+    /// was obtained: loaded, repaired, recovered from a corrupt file, or replaced by the default
+    /// values.
+    ///
+    /// Lets the application tell the user that their settings were reset, and where the
+    /// former file was moved to.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { volume: u8 }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let dir = std::env::temp_dir().join("persistent_config_doc_quarantine");
+    /// # std::fs::create_dir_all(&dir)?;
+    /// # std::fs::write(dir.join("MyConfig.toml"), "volume = \"loud\"")?;
+    /// let mut my_config = MyConfig::default();
+    /// my_config.config_with_parameters(PersistentConfigParameters {
+    ///     config_dir: dir.to_string_lossy().into_owned(),
+    ///     file_name: "MyConfig".to_string(),
+    ///     quarantine_corrupt: true,
+    ///     ..Default::default()
+    /// })?;
+    /// if let LoadOutcome::Recovered { quarantined_path, .. } = my_config.load_with_outcome()? {
+    ///     println!("Your settings were reset, the former ones are in {:?}", quarantined_path);
+    /// }
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn load_with_outcome(&mut self) -> Result<LoadOutcome> {
+        if let Some(value) = testing::overridden::<Self>() {
+            self.zeroize_sensitive();
+            *self = value;
+            return Ok(LoadOutcome::Loaded);
+        }
+        let params = registered_params::<Self>()?;
+        PERSISTENT_CONFIGS.record_read::<Self>(Location::caller());
+
+        let mut outcome = LoadOutcome::Loaded;
+        recovery::take_dropped();
+        let content = match load_file::<Self>(&params) {
+            Err(e) if is_not_found(&e) => first_run::<Self>(&params, e),
+            Err(e) if params.quarantine_corrupt && recovery::is_corrupt(&params, &e) => {
+                recovery::recover::<Self>(&params, e).map(|(content, recovered)| {
+                    outcome = recovered;
+                    content
+                })
             }
+            content => content,
         };
+        if let Some(dropped_fields) = recovery::take_dropped() {
+            outcome = LoadOutcome::Repaired { dropped_fields };
+        }
+        let content = content.and_then(|mut content| {
+            content.apply_env_overrides()?;
+            content.after_load();
+            content.validate()?;
+            Ok(content)
+        });
 
-        match load_file(&params) {
+        match content {
             Ok(content) => {
+                self.zeroize_sensitive();
                 *self = content;
-                return Ok(());
+                hooks::field_changes(self);
+                Ok(outcome)
             }
             Err(e) if !params.panic_on_error => {
                 eprintln!("Error loading file: {:?}", e);
                 eprintln!("Ephemeral mode selected, Returning default configuration, Attention values may be lost");
+                self.zeroize_sensitive();
                 *self = Self::default();
-                return Ok(());
+                hooks::field_changes(self);
+                Ok(LoadOutcome::Defaulted {
+                    error: format!("{:#}", e),
+                })
             }
             Err(e) => {
                 println!("Error loading file: {:?}", e);
-                return Err(anyhow::anyhow!("Failed to load file"));
+                Err(e.context("Failed to load file"))
             }
         }
     }
-}
-
-/// Loads configuration data from a file according to the given parameters.
-///
-/// Returns the deserialized configuration struct.
-fn load_file<T>(params: &PersistentConfigParameters) -> Result<T>
-where
-    T: for<'de> Deserialize<'de>,
-{
-    let mut file_path = PathBuf::new();
-    file_path.push(&params.config_dir);
-    file_path.push(&params.file_name);
-    file_path.set_extension(&params.save_format.ext());
-
-    let file = File::open(&file_path)?;
-    let ret_val = read_to_string(file)?;
 
-    match params.save_format {
-        SaveFormat::JSON => {
-            let config: T = serde_json::from_str(&ret_val)?;
-            Ok(config)
+    /// Loads configuration like [`load`](PersistentConfig::load), reusing the last loaded value
+    /// while the file on disk is unchanged.
+    ///
+    /// The value read from disk is cached in [`PERSISTENT_CONFIGS`] along with the file
+    /// modification time and size. Later calls return a clone of the cached value until the
+    /// file changes or the configuration is saved through [`save`](PersistentConfig::save).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the configuration was loaded from the cache or from disk
+    /// * `Err` under the same conditions as [`load`](PersistentConfig::load)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+    /// # struct MyConfig {}
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut my_config = MyConfig::default();
+    /// my_config.default_save_config(false)?;
+    /// my_config.load_cached()?; // Reads the file
+    /// my_config.load_cached()?; // Returns the cached value
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn load_cached(&mut self) -> Result<()>
+    where
+        Self: Clone + Send + Sync,
+    {
+        if testing::is_overridden::<Self>() {
+            return self.load();
         }
-        SaveFormat::TOML => {
-            let config: T = toml::de::from_str(&ret_val)?;
-            Ok(config)
+        let params = registered_params::<Self>()?;
+        let Some(stamp) = FileStamp::of(&config_file_path(&params)) else {
+            return self.load();
+        };
+
+        if let Some(value) = cache::get::<Self>(&stamp) {
+            PERSISTENT_CONFIGS.record_read::<Self>(Location::caller());
+            self.zeroize_sensitive();
+            *self = value;
+            hooks::field_changes(self);
+            return Ok(());
         }
-        SaveFormat::YAML => {
-            let config: T = serde_yaml::from_str(&ret_val)?;
-            Ok(config)
+
+        match load_checked::<Self>(&params) {
+            Ok(content) => {
+                PERSISTENT_CONFIGS.record_read::<Self>(Location::caller());
+                cache::store(stamp, content.clone());
+                self.zeroize_sensitive();
+                *self = content;
+                hooks::field_changes(self);
+                Ok(())
+            }
+            // Apply the regular error policy, without caching the fallback value
+            Err(_) => self.load(),
         }
     }
-}
 
-/// Saves configuration data to a file according to the given parameters.
-///
-/// Serializes the struct and writes it to disk.
-fn save_file<T>(params: &PersistentConfigParameters, data: T) -> Result<()>
-where
-    T: Serialize,
-{
-    let mut file_path = PathBuf::new();
-    file_path.push(&params.config_dir);
-    file_path.push(&params.file_name);
-    file_path.set_extension(&params.save_format.ext());
-
-    // Convert the data to the appropriate format
-    let data = match params.save_format {
-        SaveFormat::JSON => serde_json::to_string(&data)?,
-        SaveFormat::TOML => toml::to_string(&data)?,
-        SaveFormat::YAML => serde_yaml::to_string(&data)?,
-    };
+    /// Saves only the given fields, keeping the rest of the config file as it is.
+    ///
+    /// The existing file is read, the top level keys of `fields` are replaced by the current
+    /// values of these fields, and the file is written back. Keys the struct doesn't know
+    /// about, written by another program sharing the file, are preserved. If the file does
+    /// not exist, it is created with only these fields.
+    ///
+    /// `fields` are the names of the fields in the serialized struct; keys renamed with
+    /// `#[persistent(rename_all)]` are mapped to their on-disk names. The exclusive lock used
+    /// by [`update`](Self::update) is held during the operation.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the fields were saved, whatever `panic_on_error` is
+    /// * `Err` if a field is not part of the serialized struct, or the existing file could not
+    ///   be read or written
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { theme: String, window_size: (u32, u32) }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut my_config = MyConfig::default();
+    /// my_config.default_save_config(false)?;
+    /// my_config.theme = "dark".to_string();
+    /// my_config.save_fields(&["theme", "window_size"])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn save_fields(&self, fields: &[&str]) -> Result<()> {
+        if let Some(current) = testing::overridden::<Self>() {
+            let document = serde_json::to_value(prepare_save(self)?)?;
+            let patched = document::patch(serde_json::to_value(current)?, &document, fields)?;
+            testing::save_override::<Self>(&serde_json::from_value(patched)?);
+            return Ok(());
+        }
+        let params = registered_params::<Self>()?;
+        ensure_not_frozen::<Self>()?;
+        let file_path = config_file_path(&params);
+        let _lock = FileLock::acquire(&file_path)?;
+
+        cache::invalidate::<Self>();
+        let mut data = prepare_save(self)?;
+        let saved = telemetry::save(std::any::type_name::<Self>(), || {
+            write_config(&params, &data, WriteScope::Fields(fields))
+        });
+        data.zeroize_sensitive();
+        saved?;
+        cache::mark_synced::<Self>(FileStamp::of(&file_path));
+        Ok(())
+    }
+
+    /// Saves only the top level fields whose value differs from [`Default::default`], for
+    /// minimal config files holding only what the user changed.
+    ///
+    /// The fields are compared through their serde representation, and the file is written
+    /// from scratch: fields set back to their default value are removed from it. `load` gives
+    /// the missing fields their default value, so the file loads like a full one.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the config was saved, whatever `panic_on_error` is
+    /// * `Err` if the config could not be saved
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { theme: String, font_size: u32, plugins: Vec<String> }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let dir = std::env::temp_dir().join("persistent_config_doc_delta");
+    /// let mut my_config = MyConfig::default();
+    /// my_config.config_with_parameters(PersistentConfigParameters {
+    ///     config_dir: dir.to_string_lossy().into_owned(),
+    ///     file_name: "MyConfig".to_string(),
+    ///     ..Default::default()
+    /// })?;
+    /// my_config.theme = "dark".to_string();
+    /// my_config.save_delta()?;
+    /// assert_eq!(std::fs::read_to_string(dir.join("MyConfig.toml"))?, "theme = \"dark\"\n");
+    ///
+    /// let mut loaded = MyConfig::default();
+    /// loaded.load()?;
+    /// assert_eq!(loaded.theme, "dark");
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn save_delta(&self) -> Result<()> {
+        if testing::is_overridden::<Self>() {
+            testing::save_override(&prepare_save(self)?);
+            return Ok(());
+        }
+        let params = registered_params::<Self>()?;
+        ensure_not_frozen::<Self>()?;
+        let file_path = config_file_path(&params);
+
+        cache::invalidate::<Self>();
+        let mut data = prepare_save(self)?;
+        let saved = telemetry::save(std::any::type_name::<Self>(), || {
+            write_config(&params, &data, WriteScope::Delta)
+        })
+        .and_then(|_| data.save_delegated());
+        data.zeroize_sensitive();
+        saved?;
+        cache::mark_synced::<Self>(FileStamp::of(&file_path));
+        throttle::written::<Self>();
+        Ok(())
+    }
+
+    /// Writes the save deferred by `min_save_interval`, if any, without waiting for the
+    /// interval to be over.
+    ///
+    /// The current value is written, as by [`save`](PersistentConfig::save). A pending save is
+    /// also written by a background thread once the interval is over, call `flush` before
+    /// exiting so the last save isn't lost with that thread. Does nothing if no save is
+    /// pending.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # use std::time::Duration;
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct Window { width: u32 }
+    /// # impl PersistentConfigBuilder for Window {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let dir = std::env::temp_dir().join("persistent_config_doc_flush");
+    /// let mut window = Window::default();
+    /// window.config_with_parameters(PersistentConfigParameters {
+    ///     config_dir: dir.to_string_lossy().into_owned(),
+    ///     min_save_interval: Some(Duration::from_secs(1)),
+    ///     ..Default::default()
+    /// })?;
+    /// for width in 800..900 {
+    ///     window.width = width;
+    ///     window.save()?; // Only the first save writes the file
+    /// }
+    /// window.flush()?;
+    ///
+    /// let mut loaded = Window::default();
+    /// loaded.load()?;
+    /// assert_eq!(loaded.width, 899);
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn flush(&self) -> Result<()> {
+        if throttle::take_pending::<Self>() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Writes a template of the config to `path`, with placeholders instead of values.
+    ///
+    /// Every value is replaced by a `{{path}}` placeholder named after its on-disk keys, such
+    /// as `{{server.port}}`, for provisioning tools that fill in the blanks. Strings are
+    /// written as quoted placeholders and other values as bare ones, so the filled in file
+    /// has the right types. Lists are replaced as a whole, maps are kept with their current
+    /// keys, so the structure of the template follows the current value.
+    ///
+    /// The format is detected from the extension of `path`, and defaults to the registered
+    /// format (or TOML if the type is not registered). The missing parent directories of
+    /// `path` are created.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { name: String, port: u16 }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// // name = "{{name}}"
+    /// // port = {{port}}
+    /// MyConfig::default().write_template("./deploy/my_config.toml.j2")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn write_template(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let save_format = detect_format(path)
+            .or_else(|| PERSISTENT_CONFIGS.get_config::<Self>().map(|params| params.save_format))
+            .unwrap_or_default();
+
+        let mut document = document::rename_keys(serde_json::to_value(self)?, Self::field_renames(), Direction::ToDisk);
+        document::strip_computed(&mut document, Self::computed_fields());
+        let template = template::render(document, save_format)?;
+        // Create the directory of the template if necessary, as save does
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, template)?;
+        Ok(())
+    }
+
+    /// Renders the config for logs and bug reports, with the values of the redacted fields
+    /// replaced by `"***"`.
+    ///
+    /// The config is rendered with its on-disk keys in the registered format (TOML if the
+    /// type is not registered), or in JSON for the binary and load-only formats. Fields are
+    /// redacted with `#[persistent(redact)]`, see
+    /// [`redacted_fields`](PersistentConfigBuilder::redacted_fields).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { user: String, token: String }
+    /// # impl PersistentConfigBuilder for MyConfig {
+    /// #     fn redacted_fields() -> &'static [&'static str] {
+    /// #         &["token"]
+    /// #     }
+    /// # }
+    /// # fn main() -> anyhow::Result<()> {
+    /// let my_config = MyConfig {
+    ///     user: "alice".to_string(),
+    ///     token: "s3cr3t".to_string(),
+    /// };
+    /// assert_eq!(my_config.dump()?, "user = \"alice\"\ntoken = \"***\"\n");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn dump(&self) -> Result<String> {
+        let save_format =
+            registered_params::<Self>().map_or_else(|_| SaveFormat::default(), |params| params.save_format);
+
+        let mut document = document::rename_keys(serde_json::to_value(self)?, Self::field_renames(), Direction::ToDisk);
+        document::strip_computed(&mut document, Self::computed_fields());
+        document::redact(&mut document, Self::redacted_fields());
+        Ok(match save_format {
+            SaveFormat::TOML => {
+                document::strip_nulls(&mut document);
+                toml::to_string(&document)?
+            }
+            SaveFormat::YAML => serde_yaml::to_string(&document)?,
+            SaveFormat::Properties => properties::to_string(&document)?,
+            SaveFormat::JSON | SaveFormat::CBOR | SaveFormat::HCL => serde_json::to_string_pretty(&document)?,
+        })
+    }
+
+    /// Saves the config in `save_format` next to its config file, without changing the
+    /// registered format, and returns the path of the written file.
+    ///
+    /// The file is named like the config file, with the extension of `save_format`, and the
+    /// registration is left untouched: the next [`save`](PersistentConfig::save) still writes
+    /// the registered format. Meant for one-off exports, such as a JSON copy of the settings
+    /// attached to a bug report. The export is written even within
+    /// [`testing::with_override`], which only stands for the config file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { volume: u8 }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let dir = std::env::temp_dir().join("persistent_config_doc_save_as_format");
+    /// let mut my_config = MyConfig { volume: 7 };
+    /// my_config.config_with_parameters(PersistentConfigParameters {
+    ///     config_dir: dir.to_string_lossy().into_owned(),
+    ///     file_name: "MyConfig".to_string(),
+    ///     save_format: SaveFormat::TOML,
+    ///     ..Default::default()
+    /// })?;
+    /// let path = my_config.save_as_format(SaveFormat::JSON)?;
+    /// assert_eq!(path, dir.join("MyConfig.json"));
+    ///
+    /// let mut exported = MyConfig::default();
+    /// exported.load_as_format(SaveFormat::JSON)?;
+    /// assert_eq!(exported.volume, 7);
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn save_as_format(&self, save_format: SaveFormat) -> Result<PathBuf> {
+        let params = format_params(registered_params::<Self>()?, save_format);
+        let mut data = prepare_save(self)?;
+        let saved = write_config(&params, &data, WriteScope::All);
+        data.zeroize_sensitive();
+        saved?;
+        Ok(config_file_path(&params))
+    }
+
+    /// Loads the config from its file in `save_format`, without changing the registered
+    /// format.
+    ///
+    /// Reads the file written by [`save_as_format`](PersistentConfig::save_as_format), named
+    /// like the config file with the extension of `save_format`. The environment overrides,
+    /// the `after_load` hook and the validation apply as for [`load`](PersistentConfig::load),
+    /// but there is no fallback: a missing or invalid file is always an error, whatever
+    /// `panic_on_error`, and the current instance is then left unchanged. The file is read
+    /// even within [`testing::with_override`], which only stands for the config file.
+    #[track_caller]
+    fn load_as_format(&mut self, save_format: SaveFormat) -> Result<()> {
+        let params = format_params(registered_params::<Self>()?, save_format);
+        PERSISTENT_CONFIGS.record_read::<Self>(Location::caller());
+
+        let mut content: Self = read_config(&params, config_file_path(&params), save_format)?;
+        content.apply_env_overrides()?;
+        content.after_load();
+        content.validate()?;
+        self.zeroize_sensitive();
+        *self = content;
+        Ok(())
+    }
+
+    /// Returns what [`save`](PersistentConfig::save) would write, compared to the config file,
+    /// without writing anything.
+    ///
+    /// The [`SavePreview`] holds the content the save would write, the current content of the
+    /// file, the paths of the values that would change and a line diff, so a deployment tool
+    /// can show the change to an operator before saving it. The `before_save` hook and the
+    /// validation apply as for `save`, but `min_save_interval` and freezing are ignored.
+    /// Within [`testing::with_override`], the change is compared to the overriding value,
+    /// rendered as it would be saved, instead of the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct Server { host: String, port: u16 }
+    /// # impl PersistentConfigBuilder for Server {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let dir = std::env::temp_dir().join("persistent_config_doc_save_dry_run");
+    /// let mut server = Server { host: "localhost".to_string(), port: 8080 };
+    /// server.config_with_parameters(PersistentConfigParameters {
+    ///     config_dir: dir.to_string_lossy().into_owned(),
+    ///     ..Default::default()
+    /// })?;
+    /// server.save()?;
+    ///
+    /// server.port = 9090;
+    /// let preview = server.save_dry_run()?;
+    /// assert_eq!(preview.changed, ["port"]);
+    /// assert_eq!(preview.diff, " host = \"localhost\"\n-port = 8080\n+port = 9090\n");
+    /// println!("{}", preview);
+    ///
+    /// // Nothing was written
+    /// let mut saved = Server::default();
+    /// saved.load()?;
+    /// assert_eq!(saved.port, 8080);
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the type is not registered, the config is invalid, the config file
+    /// exists but can't be read, the save format is binary (CBOR), or the config file is
+    /// encrypted with SOPS, whatever `panic_on_error`.
+    #[track_caller]
+    fn save_dry_run(&self) -> Result<SavePreview> {
+        let params = registered_params::<Self>()?;
+        if params.save_format == SaveFormat::CBOR {
+            anyhow::bail!("Save previews are text, they can't be made in the CBOR format");
+        }
+        if params.sops {
+            anyhow::bail!("Save previews can't be made for SOPS encrypted config files");
+        }
+        let file_path = config_file_path(&params);
+        // Returns the document saved for `data` and its text
+        let render = |data: &Self| -> Result<(serde_json::Value, String)> {
+            let mut data = prepare_save(data)?;
+            let document = disk_document(&params, &data, WriteScope::All);
+            data.zeroize_sensitive();
+            let (document, _) = document?;
+
+            let mut serialized = Vec::new();
+            serialize_into(&mut serialized, &params, &document)?;
+            let mut contents = Vec::new();
+            let mut text =
+                serializer::TextWriter::new(&mut contents, &params.serializer, params.save_format, &file_path);
+            if let Some(header) = provenance::header(&params) {
+                text.write_all(header.as_bytes())?;
+            }
+            text.write_all(&serialized)?;
+            Ok((document, String::from_utf8(contents)?))
+        };
+        let (document, contents) = render(self)?;
+
+        let (current, previous) = if let Some(overriding) = testing::overridden::<Self>() {
+            let (previous, current) = render(&overriding)?;
+            (Some(current), envelope::payload(previous))
+        } else {
+            let current = match std::fs::read_to_string(&file_path) {
+                Ok(current) => Some(current),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(anyhow::Error::new(e).context(format!("Failed to read {:?}", file_path))),
+            };
+            // A file that can't be parsed is entirely replaced
+            let previous = current
+                .as_ref()
+                .and_then(|_| read_file::<serde_json::Value>(&params, file_path.clone(), params.save_format).ok())
+                .map_or_else(|| serde_json::Value::Object(Default::default()), envelope::payload);
+            (current, previous)
+        };
+
+        Ok(SavePreview {
+            changed: document::changed_paths(&previous, &envelope::payload(document)),
+            diff: preview::line_diff(current.as_deref().unwrap_or_default(), &contents),
+            file_path,
+            contents,
+            current,
+        })
+    }
+
+    /// Returns whether the config file changed since it was last loaded or saved.
+    ///
+    /// Compares the modification time and size of the file with the ones recorded by the
+    /// last `load`, `save` or `update` of the type in this process, so long-running
+    /// applications can offer to reload settings changed by another program. If the type
+    /// was never loaded nor saved, the file is stale as soon as it exists.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig {}
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut my_config = MyConfig::default();
+    /// my_config.default_save_config(false)?;
+    /// my_config.load()?;
+    /// // ...
+    /// if my_config.is_stale()? {
+    ///     println!("Settings changed externally, reloading");
+    ///     my_config.load()?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn is_stale(&self) -> Result<bool> {
+        let params = registered_params::<Self>()?;
+        Ok(cache::is_stale::<Self>(&config_file_path(&params)))
+    }
+
+    /// Watches the config file, calling `on_change` with the reloaded config whenever another
+    /// process saves it.
+    ///
+    /// Each process sharing the file can watch it, for a simple way to propagate settings
+    /// between an application and its helper daemons. The file is checked every `interval`
+    /// on a background thread, and reloaded like [`load`](PersistentConfig::load) when it
+    /// changed since this process last loaded or saved it: the saves of this process are
+    /// not reported to it. A file that can't be loaded is reported once as an error, until it
+    /// changes again.
+    ///
+    /// The watch stops when the returned handle is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # use std::time::Duration;
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { level: String }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut my_config = MyConfig::default();
+    /// my_config.default_save_config(false)?;
+    /// my_config.load()?;
+    /// let _watch = my_config.shared_watch(Duration::from_secs(1), |config| match config {
+    ///     Ok(config) => println!("New level: {}", config.level),
+    ///     Err(e) => eprintln!("Invalid settings: {:#}", e),
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn shared_watch<F>(&self, interval: Duration, on_change: F) -> Result<WatchHandle>
+    where
+        F: FnMut(Result<Self>) + Send + 'static,
+    {
+        let params = registered_params::<Self>()?;
+        Ok(watch::start::<Self, F>(params, interval, on_change))
+    }
+
+    /// Atomically updates the configuration stored on disk.
+    ///
+    /// Acquires an exclusive lock on the config file, loads its current content, applies `f`
+    /// and saves the result before releasing the lock. Concurrent updates from other threads
+    /// or processes are applied one after the other, so none of them is lost.
+    ///
+    /// The lock is advisory: only other calls to `update` wait for it, plain `save` calls
+    /// don't. It is held on a `<file>.lock` file next to the config file.
+    ///
+    /// # Behavior
+    ///
+    /// - If the config file does not exist, `f` is applied to the value returned by the
+    ///   [`on_first_run`](PersistentConfigBuilder::on_first_run) hook, or the default value
+    /// - If the config file can't be loaded, an error is returned whatever `panic_on_error`
+    ///   is, so a corrupt file is never overwritten with an updated default value
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(value)` with the updated configuration, as saved
+    /// * `Err` if the type is not registered, or the file could not be locked, loaded or saved
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { count: u32 }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// MyConfig::default().default_save_config(false)?;
+    /// let config = MyConfig::update(|config| config.count += 1)?;
+    /// println!("count: {}", config.count);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn update<F>(f: F) -> Result<Self>
+    where
+        F: FnOnce(&mut Self),
+    {
+        if let Some(mut value) = testing::overridden::<Self>() {
+            f(&mut value);
+            value.before_save();
+            value.validate()?;
+            testing::save_override(&value);
+            return Ok(value);
+        }
+        let params = registered_params::<Self>()?;
+        PERSISTENT_CONFIGS.record_read::<Self>(Location::caller());
+        let _lock = FileLock::acquire(&config_file_path(&params))?;
+
+        let mut value = match load_file::<Self>(&params) {
+            Err(e) if is_not_found(&e) => {
+                let mut value = embedded::default::<Self>(&params)?;
+                hooks::first_run(&mut value);
+                value
+            }
+            content => {
+                let mut value = content.context("Failed to load file")?;
+                value.after_load();
+                value.validate().context("Failed to load file")?;
+                value
+            }
+        };
+        f(&mut value);
+        value.before_save();
+        value.validate()?;
+
+        cache::invalidate::<Self>();
+        save_config(&params, &value)?;
+        hooks::field_changes(&value);
+        Ok(value)
+    }
+
+    /// Reads the envelope metadata of the config file, without loading it.
+    ///
+    /// Useful to branch on the `schema_version` before loading, or to display who saved the
+    /// file and when. The file is read whether or not an envelope is set in the parameters.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(metadata))` if the config file is wrapped in an envelope
+    /// * `Ok(None)` if the config file holds the bare struct
+    /// * `Err` if the type is not registered, or the file could not be read
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig {}
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut my_config = MyConfig::default();
+    /// my_config.config_with_parameters(PersistentConfigParameters {
+    ///     envelope: Some(EnvelopeOptions {
+    ///         schema_version: 2,
+    ///         app_version: env!("CARGO_PKG_VERSION").to_string(),
+    ///         accept_legacy: true,
+    ///     }),
+    ///     ..Default::default()
+    /// })?;
+    ///
+    /// match my_config.load_metadata()? {
+    ///     Some(metadata) if metadata.schema_version < 2 => { /* convert the old layout */ }
+    ///     _ => my_config.load()?,
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn load_metadata(&self) -> Result<Option<EnvelopeMetadata>> {
+        let params = registered_params::<Self>()?;
+        let document: serde_json::Value = read_file(&params, config_file_path(&params), params.save_format)?;
+        Ok(envelope::metadata(document))
+    }
+
+    /// Checks the config file and its directory, for support tooling.
+    ///
+    /// The report tells whether the directory exists and is writable, whether the file
+    /// exists and can be loaded, and its size, modification time and permissions. Nothing is
+    /// modified, apart from a probe file created and removed to check the directory.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(diagnostics)` with the report, problems found included
+    /// * `Err` if the type is not registered
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig {}
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// let my_config = MyConfig::default();
+    /// my_config.default_save_config(false)?;
+    /// let diagnostics = my_config.diagnose()?;
+    /// if !diagnostics.is_healthy() {
+    ///     eprintln!("{}", diagnostics);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn diagnose(&self) -> Result<Diagnostics> {
+        let params = registered_params::<Self>()?;
+        let file_path = config_file_path(&params);
+        Ok(diagnostics::diagnose(file_path, params.save_format, |path| {
+            read_config::<Self>(&params, path.to_owned(), params.save_format)
+                .map(|mut content| content.zeroize_sensitive())
+        }))
+    }
+
+    /// Moves the configuration from a legacy location to the registered one.
+    ///
+    /// If the registered config file does not exist, `old_paths` are searched in order and the
+    /// first existing file is loaded into the current instance, then saved to the registered
+    /// location. The format of the legacy file is detected from its extension, or by trying
+    /// every supported format if the extension is unknown.
+    ///
+    /// # Parameters
+    ///
+    /// * `old_paths` - Legacy file paths, in order of preference.
+    /// * `delete_old` - If true, the legacy file is deleted once migrated.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(path))` with the path of the migrated legacy file
+    /// * `Ok(None)` if the registered file already exists or no legacy file was found, or
+    ///   within [`testing::with_override`], where the overriding value stands for the file
+    /// * `Err` if a legacy file could not be read, or the migrated config could not be saved
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # use std::path::PathBuf;
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig {}
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut my_config = MyConfig::default();
+    /// my_config.default_save_config(false)?;
+    /// my_config.migrate_from(&[PathBuf::from("./my_app.json"), PathBuf::from("./my_app.conf")], true)?;
+    /// my_config.load()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    fn migrate_from(&mut self, old_paths: &[PathBuf], delete_old: bool) -> Result<Option<PathBuf>> {
+        if testing::is_overridden::<Self>() {
+            return Ok(None);
+        }
+        let params = registered_params::<Self>()?;
+        if config_file_path(&params).exists() {
+            return Ok(None);
+        }
+
+        let Some(old_path) = old_paths.iter().find(|path| path.is_file()) else {
+            return Ok(None);
+        };
+
+        let formats = match detect_format(old_path) {
+            Some(save_format) => vec![save_format],
+            None => {
+                let mut formats = vec![
+                    SaveFormat::JSON,
+                    SaveFormat::TOML,
+                    SaveFormat::YAML,
+                    SaveFormat::Properties,
+                ];
+                if cfg!(feature = "cbor") {
+                    formats.push(SaveFormat::CBOR);
+                }
+                if cfg!(feature = "hcl") {
+                    formats.push(SaveFormat::HCL);
+                }
+                formats
+            }
+        };
+        let mut errors = Vec::new();
+        let content = formats.into_iter().find_map(|save_format| {
+            read_config::<Self>(&params, old_path.clone(), save_format)
+                .map_err(|e| errors.push(format!("{:?}: {}", save_format, e)))
+                .ok()
+        });
+        let Some(content) = content else {
+            return Err(anyhow::anyhow!(
+                "Failed to migrate {:?}: {}",
+                old_path,
+                errors.join(", ")
+            ));
+        };
+
+        cache::invalidate::<Self>();
+        save_config(&params, &content)?;
+        self.zeroize_sensitive();
+        *self = content;
+
+        if delete_old {
+            std::fs::remove_file(old_path)?;
+        }
+        Ok(Some(old_path.clone()))
+    }
+
+    /// Stores a copy of the current configuration under `name`, in memory.
+    ///
+    /// The copy can be brought back with [`restore`](Self::restore), for example to undo a
+    /// bulk import that went wrong. A previous snapshot with the same name is replaced.
+    /// Snapshots are kept until the process exits, use
+    /// [`snapshot_to_disk`](Self::snapshot_to_disk) to keep them longer.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+    /// # struct MyConfig { servers: Vec<String> }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn import(_: &mut MyConfig) -> anyhow::Result<()> { Ok(()) }
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut my_config = MyConfig::default();
+    /// my_config.snapshot("before_import");
+    /// if let Err(e) = import(&mut my_config) {
+    ///     eprintln!("Import failed: {}", e);
+    ///     my_config.restore("before_import")?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn snapshot(&self, name: &str)
+    where
+        Self: Clone + Send,
+    {
+        snapshots::store(name, self.clone());
+    }
+
+    /// Stores a copy of the current configuration under `name`, in memory and on disk.
+    ///
+    /// The copy is saved in the `snapshots` subdirectory of the config directory, as
+    /// `<file_name>-<name>.<ext>`, with the registered format and layout, so
+    /// [`restore`](Self::restore) finds it after a restart. `name` may only contain ASCII
+    /// letters, digits, `-` and `_`. Within [`testing::with_override`], the snapshot is only
+    /// kept in memory.
+    ///
+    /// The copy on disk goes through [`before_save`](PersistentConfigBuilder::before_save)
+    /// and [`validate`](PersistentConfigBuilder::validate) as with [`save`](Self::save), so
+    /// an invalid config is never stored as a snapshot.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the snapshot was saved, whatever `panic_on_error` is
+    /// * `Err` if the type is not registered, the name is invalid, the config is invalid, or
+    ///   the file could not be written
+    #[track_caller]
+    fn snapshot_to_disk(&self, name: &str) -> Result<()>
+    where
+        Self: Clone + Send,
+    {
+        let params = snapshots::disk_parameters(&registered_params::<Self>()?, name)?;
+        let data = prepare_save(self)?;
+        if !testing::is_overridden::<Self>() {
+            write_config(&params, &data, WriteScope::All)
+                .with_context(|| format!("Failed to save snapshot {:?}", name))?;
+        }
+        self.snapshot(name);
+        Ok(())
+    }
+
+    /// Replaces the current configuration with the snapshot stored under `name`.
+    ///
+    /// Snapshots taken in memory by this process are used first, then the ones saved on disk
+    /// by [`snapshot_to_disk`](Self::snapshot_to_disk). The restored configuration is not
+    /// saved, call [`save`](Self::save) to write it to the config file.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the snapshot was restored
+    /// * `Err` if no snapshot is stored under `name`, or its file could not be read
+    #[track_caller]
+    fn restore(&mut self, name: &str) -> Result<()>
+    where
+        Self: Clone + Send,
+    {
+        let snapshot = match snapshots::get::<Self>(name) {
+            Some(snapshot) => snapshot,
+            None => {
+                let params = snapshots::disk_parameters(&registered_params::<Self>()?, name)?;
+                read_config::<Self>(&params, config_file_path(&params), params.save_format).map_err(|e| {
+                    if is_not_found(&e) {
+                        anyhow::anyhow!("No snapshot named {:?}", name)
+                    } else {
+                        e.context(format!("Failed to read snapshot {:?}", name))
+                    }
+                })?
+            }
+        };
+
+        self.zeroize_sensitive();
+        *self = snapshot;
+        Ok(())
+    }
+
+    /// Shows the fields of the configuration in `ui` as a settings panel, and saves the
+    /// configuration as soon as a field is changed.
+    ///
+    /// Each field gets a widget matching its value: a checkbox, a number bounded by its
+    /// type and its `min` and `max` constraints, a list of the values of its `one_of`
+    /// constraint, a text field, masked for the redacted fields, or a collapsible group for
+    /// a nested struct. Its doc comment is shown when its name is hovered. The constraints
+    /// and doc comments are the ones generated by the `Persistent` derive, see
+    /// [`describe`](PersistentConfigBuilder::describe).
+    ///
+    /// A change making the configuration invalid is kept in the panel with the error shown
+    /// below it, and is only applied and saved once fixed. Every valid change is saved, set
+    /// `min_save_interval` to limit the writes while a value is typed or dragged.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if nothing changed or the change was saved
+    /// * `Err` if the changed configuration could not be saved
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use persistent_config::prelude::*;
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Debug, Default, Serialize, Deserialize)]
+    /// # struct MyConfig { volume: u8, fullscreen: bool }
+    /// # impl PersistentConfigBuilder for MyConfig {}
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut my_config = MyConfig::default();
+    /// my_config.default_save_config(false)?;
+    /// # let ctx = egui::Context::default();
+    /// # let _ = ctx.run(Default::default(), |ctx| {
+    /// egui::Window::new("Preferences").show(ctx, |ui| {
+    ///     if let Err(e) = my_config.show_settings_ui(ui) {
+    ///         eprintln!("Failed to save the preferences: {:#}", e);
+    ///     }
+    /// });
+    /// # });
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "egui")]
+    fn show_settings_ui(&mut self, ui: &mut egui::Ui) -> Result<()> {
+        settings::show(self, ui)
+    }
+}
+
+/// Freezes the registration of `T` for the rest of the process: saving it, or registering
+/// it again with other parameters, returns a [`PersistentConfigError::Frozen`] error.
+///
+/// Meant for the settings that are only read at startup, such as security settings, once
+/// they have been consumed. Loading is still allowed. A type deriving `Persistent` with
+/// [`derived_parameters`](PersistentConfigBuilder::derived_parameters) is registered first.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct TlsConfig { verify_peer: bool }
+/// # impl PersistentConfigBuilder for TlsConfig {}
+/// # fn main() -> anyhow::Result<()> {
+/// # let dir = std::env::temp_dir().join("persistent_config_doc_freeze");
+/// let mut tls = TlsConfig::default();
+/// tls.config_with_parameters(PersistentConfigParameters {
+///     config_dir: dir.to_string_lossy().into_owned(),
+///     ..Default::default()
+/// })?;
+/// # TlsConfig { verify_peer: true }.save()?;
+/// tls.load()?;
+/// freeze::<TlsConfig>()?;
+///
+/// tls.verify_peer = false;
+/// assert!(tls.save().is_err());
+/// assert!(tls.config_with_parameters(PersistentConfigParameters::default()).is_err());
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns a [`PersistentConfigError::NotRegistered`] error if `T` is not registered and
+/// has no derived parameters.
+#[track_caller]
+pub fn freeze<T: PersistentConfigBuilder>() -> Result<()> {
+    registered_params::<T>()?;
+    PERSISTENT_CONFIGS.freeze::<T>()
+}
+
+/// Writes the saves deferred by `min_save_interval` of all the config types, without waiting
+/// for their interval to be over.
+///
+/// Unlike [`flush`](PersistentConfig::flush), it needs no instance of the types: the value of
+/// the last deferred save of each type is written. The types are written by increasing
+/// [`flush_order`](persistent_config_core::Category::flush_order) of their category, so the critical configs can be
+/// persisted before the others when exiting, and each save fails under the `panic_on_error`
+/// policy of its category.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # use std::time::Duration;
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct Window { width: u32 }
+/// # impl PersistentConfigBuilder for Window {}
+/// # fn main() -> anyhow::Result<()> {
+/// # let dir = std::env::temp_dir().join("persistent_config_doc_flush_all");
+/// PERSISTENT_CONFIGS.set_category("critical", Category { panic_on_error: true, flush_order: 0 });
+/// PERSISTENT_CONFIGS.set_category("ui", Category { panic_on_error: false, flush_order: 1 });
+///
+/// let mut window = Window::default();
+/// window.config_with_parameters(PersistentConfigParameters {
+///     config_dir: dir.to_string_lossy().into_owned(),
+///     min_save_interval: Some(Duration::from_secs(60)),
+///     category: Some("ui".to_string()),
+///     ..Default::default()
+/// })?;
+/// window.save()?;
+/// window.width = 1024;
+/// window.save()?; // Deferred
+///
+/// // Writes the pending saves before exiting, the critical configs first
+/// flush_all()?;
+/// let mut loaded = Window::default();
+/// loaded.load()?;
+/// assert_eq!(loaded.width, 1024);
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the first error of the saves made, once all of them were attempted.
+pub fn flush_all() -> Result<()> {
+    throttle::flush_all()
+}
+
+/// Loads the types declared with [`load_on_startup`](PersistentConfigBuilder::load_on_startup),
+/// each after the types it depends on.
+///
+/// The types without dependencies between them load in the order of their declaration, so
+/// the startup is the same from one run to the next. The dependencies are checked before
+/// any type is loaded.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # use std::sync::{Arc, Mutex};
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct License { key: String }
+/// # impl PersistentConfigBuilder for License {}
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct Features { beta: bool }
+/// # impl PersistentConfigBuilder for Features {}
+/// # fn main() -> anyhow::Result<()> {
+/// # let dir = std::env::temp_dir().join("persistent_config_doc_load_all_ordered");
+/// # let params = PersistentConfigParameters {
+/// #     config_dir: dir.to_string_lossy().into_owned(),
+/// #     panic_on_error: false,
+/// #     ..Default::default()
+/// # };
+/// # License::default().config_with_parameters(params.clone())?;
+/// # Features::default().config_with_parameters(params)?;
+/// let order = Arc::new(Mutex::new(Vec::new()));
+/// let loaded = Arc::clone(&order);
+/// Features::default().load_on_startup(&[ConfigType::of::<License>()], move |_| {
+///     loaded.lock().unwrap().push("features");
+/// });
+/// let loaded = Arc::clone(&order);
+/// License::default().load_on_startup(&[], move |_| loaded.lock().unwrap().push("license"));
+///
+/// load_all_ordered()?;
+/// assert_eq!(*order.lock().unwrap(), ["license", "features"]);
+///
+/// // A dependency cycle is reported before loading anything
+/// License::default().load_on_startup(&[ConfigType::of::<Features>()], |_| {});
+/// let error = load_all_ordered().unwrap_err();
+/// assert!(matches!(
+///     error.downcast_ref::<PersistentConfigError>(),
+///     Some(PersistentConfigError::DependencyCycle { .. })
+/// ));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// - Returns a [`PersistentConfigError::MissingDependency`] error if a type depends on a type
+///   not declared with `load_on_startup`
+/// - Returns a [`PersistentConfigError::DependencyCycle`] error if types depend on each other
+/// - Returns the error of the first type failing to load, the types after it being left
+///   unloaded
+pub fn load_all_ordered() -> Result<()> {
+    startup::load_all_ordered()
+}
+
+/// Writes the config files of all the registered types to a single archive at `path`, to
+/// back up the settings of an application or move them to another machine.
+///
+/// The archive is a tar file holding a `manifest.json`, see [`BundleManifest`], and the
+/// config file of each registered type, as it is on disk. Types whose config file does not
+/// exist are left out. An existing file at `path` is replaced.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct Theme { dark: bool }
+/// # impl PersistentConfigBuilder for Theme {}
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct Editor { tab_width: u8 }
+/// # impl PersistentConfigBuilder for Editor {}
+/// # fn main() -> anyhow::Result<()> {
+/// # let dir = std::env::temp_dir().join("persistent_config_doc_bundle");
+/// # let params = PersistentConfigParameters {
+/// #     config_dir: dir.join("configs").to_string_lossy().into_owned(),
+/// #     ..Default::default()
+/// # };
+/// # Theme::default().config_with_parameters(params.clone())?;
+/// # Editor::default().config_with_parameters(params)?;
+/// Theme { dark: true }.save()?;
+/// Editor { tab_width: 2 }.save()?;
+///
+/// let manifest = export_bundle(dir.join("settings.tar"))?;
+/// assert_eq!(manifest.configs.len(), 2);
+///
+/// // Restored on a new machine
+/// std::fs::remove_dir_all(dir.join("configs"))?;
+/// import_bundle(dir.join("settings.tar"))?;
+/// let mut editor = Editor::default();
+/// editor.load()?;
+/// assert_eq!(editor.tab_width, 2);
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if a config file exists but can't be read, or the archive can't be
+/// written.
+#[cfg(feature = "bundle")]
+pub fn export_bundle(path: impl AsRef<Path>) -> Result<BundleManifest> {
+    bundle::export(path.as_ref())
+}
+
+/// Restores the config files of a bundle written by [`export_bundle`], replacing the config
+/// files of the registered types.
+///
+/// Configs are matched by type name and written at the path their type is registered with,
+/// so the types must be registered before the import. The configs of the types that are not
+/// registered are skipped: the returned manifest lists the configs restored. The restored
+/// files are read by the next `load` of each type.
+///
+/// # Errors
+///
+/// Returns an error if the archive can't be read, is not a bundle, was written by a newer
+/// version of the crate, or lacks the file of a listed config. No config is replaced in
+/// these cases. Returns an error as well if a config file can't be written.
+#[cfg(feature = "bundle")]
+pub fn import_bundle(path: impl AsRef<Path>) -> Result<BundleManifest> {
+    bundle::import(path.as_ref())
+}
+
+/// Sets the resolver of the secret references of `scheme`, such as `vault`, replacing any
+/// previous one.
+///
+/// The string values of the config files made of a single `<scheme>://<path>#<key>` URI are
+/// replaced by the secret returned by the resolver when loading, the `#key` fragment being
+/// optional. Saving writes the URIs back as long as the secrets are unchanged, so the secrets
+/// never land in the config file while the struct holds them at runtime. URIs of the schemes
+/// without a resolver are left as they are.
+///
+/// Resolved secrets are kept in memory, so saving doesn't fetch them again. Setting a
+/// resolver clears them.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct Database { user: String, password: String }
+/// # impl PersistentConfigBuilder for Database {}
+/// struct Keyring;
+///
+/// impl SecretResolver for Keyring {
+///     fn resolve(&self, path: &str, key: Option<&str>) -> anyhow::Result<String> {
+///         match (path, key) {
+///             ("my_app/database", Some("password")) => Ok("s3cr3t".to_string()),
+///             _ => anyhow::bail!("No such secret"),
+///         }
+///     }
+/// }
+///
+/// # fn main() -> anyhow::Result<()> {
+/// # let dir = std::env::temp_dir().join("persistent_config_doc_set_secret_resolver");
+/// # Database::default().config_with_parameters(PersistentConfigParameters {
+/// #     config_dir: dir.to_string_lossy().into_owned(),
+/// #     ..Default::default()
+/// # })?;
+/// # std::fs::create_dir_all(&dir)?;
+/// # std::fs::write(dir.join("Database.toml"), "user = \"app\"\npassword = \"keyring://my_app/database#password\"\n")?;
+/// set_secret_resolver("keyring", Keyring);
+///
+/// let mut database = Database::default();
+/// database.load()?;
+/// assert_eq!(database.password, "s3cr3t");
+///
+/// // The secret is not written to the file
+/// database.user = "admin".to_string();
+/// database.save()?;
+/// let file = std::fs::read_to_string(dir.join("Database.toml"))?;
+/// assert!(file.contains("keyring://my_app/database#password"));
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn set_secret_resolver(scheme: &str, resolver: impl SecretResolver + 'static) {
+    secrets::set_resolver(scheme.to_owned(), std::sync::Arc::new(resolver));
+}
+
+/// Sets whether the process is offline, such as in airplane mode: the
+/// [`SecretResolver`]s are then never called, so a load doesn't hang on an unreachable
+/// secrets store.
+///
+/// While offline, the secret references resolve to the secrets resolved before by the
+/// process, the last copy kept in memory, and a reference never resolved fails the load.
+/// Saving writes the references back as usual. The local config files are read and written
+/// as when online.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # use std::sync::atomic::{AtomicUsize, Ordering};
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct Database { password: String }
+/// # impl PersistentConfigBuilder for Database {}
+/// static CALLS: AtomicUsize = AtomicUsize::new(0);
+///
+/// struct Remote;
+///
+/// impl SecretResolver for Remote {
+///     fn resolve(&self, _: &str, _: Option<&str>) -> anyhow::Result<String> {
+///         CALLS.fetch_add(1, Ordering::Relaxed);
+///         Ok("s3cr3t".to_string())
+///     }
+/// }
+///
+/// # fn main() -> anyhow::Result<()> {
+/// # let dir = std::env::temp_dir().join("persistent_config_doc_set_offline");
+/// # Database::default().config_with_parameters(PersistentConfigParameters {
+/// #     config_dir: dir.to_string_lossy().into_owned(),
+/// #     ..Default::default()
+/// # })?;
+/// # std::fs::create_dir_all(&dir)?;
+/// # std::fs::write(dir.join("Database.toml"), "password = \"remote://my_app/database\"\n")?;
+/// set_secret_resolver("remote", Remote);
+/// let mut database = Database::default();
+/// database.load()?;
+///
+/// set_offline(true);
+/// database.load()?; // Reuses the secret
+/// assert_eq!(database.password, "s3cr3t");
+/// assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+/// # set_offline(false);
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn set_offline(offline: bool) {
+    secrets::set_offline(offline);
+}
+
+/// Returns `true` if the process was set offline with [`set_offline`].
+pub fn is_offline() -> bool {
+    secrets::is_offline()
+}
+
+/// Sets the variable `name` of the templates rendered in the config files loaded with the
+/// `templates` parameter, replacing any previous value.
+///
+/// Besides these variables, the templates can use `env`, the environment variables, and
+/// `hostname`, the name of the host. Set the variables before loading the configs using them.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct Cluster { node_url: String }
+/// # impl PersistentConfigBuilder for Cluster {}
+/// # fn main() -> anyhow::Result<()> {
+/// # let dir = std::env::temp_dir().join("persistent_config_doc_set_template_var");
+/// # Cluster::default().config_with_parameters(PersistentConfigParameters {
+/// #     config_dir: dir.to_string_lossy().into_owned(),
+/// #     templates: true,
+/// #     ..Default::default()
+/// # })?;
+/// set_template_var("region", "eu-west")?;
+///
+/// Cluster { node_url: "https://{{ region }}.example.com".to_string() }.save()?;
+/// let mut cluster = Cluster::default();
+/// cluster.load()?;
+/// assert_eq!(cluster.node_url, "https://eu-west.example.com");
+///
+/// // Still a template in the file
+/// cluster.save()?;
+/// let file = std::fs::read_to_string(dir.join("Cluster.toml"))?;
+/// assert!(file.contains("{{ region }}"));
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if `value` can't be represented as JSON, such as a map with non-string
+/// keys, leaving any previous value of the variable.
+#[cfg(feature = "templates")]
+pub fn set_template_var(name: &str, value: impl Serialize) -> Result<()> {
+    let value = serde_json::to_value(value)
+        .with_context(|| format!("Failed to set the template variable {:?}", name))?;
+    templates::set_var(name.to_owned(), value);
+    Ok(())
+}
+
+/// Returns a [`PersistentConfigError::Frozen`] error if the registration of `T` is frozen.
+fn ensure_not_frozen<T: 'static>() -> Result<()> {
+    if PERSISTENT_CONFIGS.is_frozen::<T>() {
+        return Err(PersistentConfigError::Frozen {
+            type_name: std::any::type_name::<T>(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Detects the format of a file from its extension.
+fn detect_format(path: &Path) -> Option<SaveFormat> {
+    let ext = path.extension()?.to_str()?;
+    SaveFormat::try_from(ext).ok()
+}
+
+/// Returns whether the error was caused by a missing file.
+fn is_not_found(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// Returns the file name used for `T` when none is given.
+///
+/// Paths are shortened to their last segment and generic arguments are joined with `_`, so
+/// `app::Cache<alloc::string::String>` is saved as `Cache_String`.
+fn default_file_name<T>() -> String {
+    std::any::type_name::<T>()
+        .split(|c: char| !c.is_alphanumeric() && c != '_' && c != ':')
+        .filter_map(|path| path.rsplit("::").next())
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Returns the directory of the app defaults, or `./.config` if they are not set or have none.
+fn default_config_dir() -> String {
+    PERSISTENT_CONFIGS
+        .app_defaults()
+        .map(|defaults| defaults.config_dir)
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or_else(|| "./.config".to_string())
+}
+
+/// Fills the empty `config_dir` and `file_name` of `params` with the defaults for `T`.
+fn complete_parameters<T>(mut params: PersistentConfigParameters) -> PersistentConfigParameters {
+    if params.config_dir.is_empty() {
+        params.config_dir = default_config_dir();
+    }
+    if params.file_name.is_empty() {
+        params.file_name = default_file_name::<T>();
+    }
+    params
+}
+
+/// Returns the parameters registered for `T`.
+///
+/// If `T` is not registered yet, it is registered with its
+/// [`derived_parameters`](PersistentConfigBuilder::derived_parameters), or else with the app
+/// defaults, if any. The registration records the location of the public method called.
+#[track_caller]
+fn registered_params<T: PersistentConfigBuilder>() -> Result<PersistentConfigParameters> {
+    if let Some(params) = PERSISTENT_CONFIGS.get_config::<T>() {
+        return Ok(with_category(params));
+    }
+
+    if let Some(params) = T::derived_parameters().or_else(|| PERSISTENT_CONFIGS.app_defaults()) {
+        // Another thread may have registered the type in the meantime, its parameters are kept
+        _ = PERSISTENT_CONFIGS.try_add_config::<T>(complete_parameters::<T>(params));
+    }
+
+    PERSISTENT_CONFIGS.get_config::<T>().map(with_category).ok_or_else(|| {
+        PersistentConfigError::NotRegistered {
+            type_name: std::any::type_name::<T>(),
+        }
+        .into()
+    })
+}
+
+/// Applies the policy of the category of `params`, if one is set, to `params`.
+fn with_category(mut params: PersistentConfigParameters) -> PersistentConfigParameters {
+    if let Some(category) = params
+        .category
+        .as_deref()
+        .and_then(|name| PERSISTENT_CONFIGS.category(name))
+    {
+        params.panic_on_error = category.panic_on_error;
+    }
+    params
+}
+
+/// Returns `params` reading and writing the config in `save_format`, in the file named like
+/// the config file with the extension of that format.
+fn format_params(params: PersistentConfigParameters, save_format: SaveFormat) -> PersistentConfigParameters {
+    PersistentConfigParameters {
+        save_format,
+        extension: None,
+        full_path: params
+            .full_path
+            .as_ref()
+            .map(|full_path| full_path.with_extension(save_format.ext())),
+        fallback_formats: Vec::new(),
+        ..params
+    }
+}
+
+/// Builds the path of the config file from the given parameters.
+///
+/// Inside [`testing::with_temp_config`], the directory is resolved in the temporary directory.
+/// Otherwise a relative directory is resolved under the base directory, if one is set.
+/// The file is stored in the `namespace` subdirectory, if any. The extension is appended to
+/// the file name, so dots in the file name are kept.
+fn config_file_path(params: &PersistentConfigParameters) -> PathBuf {
+    if let Some(full_path) = &params.full_path {
+        return full_path.clone();
+    }
+    let config_dir = Path::new(&params.config_dir);
+    let mut file_path = testing::redirect(config_dir).unwrap_or_else(|| match PERSISTENT_CONFIGS.base_dir() {
+        Some(base_dir) if config_dir.is_relative() => base_dir.join(config_dir.strip_prefix(".").unwrap_or(config_dir)),
+        _ => config_dir.to_path_buf(),
+    });
+    if let Some(namespace) = &params.namespace {
+        file_path.push(namespace);
+    }
+    let mut file_name = params.file_name.clone();
+    if let Some(suffix) = params
+        .file_name_suffix
+        .map(|suffix| (suffix.0)().replace(['/', '\\'], "_"))
+        && !suffix.is_empty()
+    {
+        file_name = format!("{}-{}", file_name, suffix);
+    }
+    let extension = params.extension.as_deref().unwrap_or(params.save_format.ext());
+    if extension.is_empty() {
+        file_path.push(file_name);
+    } else {
+        file_path.push(format!("{}.{}", file_name, extension));
+    }
+    file_path
+}
+
+/// Returns the value loaded when the config file does not exist yet, or `error` if there is
+/// none.
+///
+/// The embedded default config, if any, is updated by the first run hook, then the prompt
+/// fields are asked for in a terminal, and the answers saved right away.
+fn first_run<T: PersistentConfigBuilder>(params: &PersistentConfigParameters, error: anyhow::Error) -> Result<T> {
+    let embedded = embedded::parse::<T>(params).transpose()?;
+    let found = embedded.is_some();
+    let mut value = embedded.unwrap_or_default();
+    let found = hooks::first_run(&mut value) || found;
+
+    #[cfg(feature = "dialoguer")]
+    if !T::prompt_fields().is_empty() && std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+        let value = prompt::ask(value)?;
+        save_config(params, &prepare_save(&value)?)?;
+        return Ok(value);
+    }
+    if found { Ok(value) } else { Err(error) }
+}
+
+/// Loads the config file like [`load_file`], then applies the environment overrides and the
+/// `after_load` hook, and validates the result.
+fn load_checked<T: PersistentConfigBuilder>(params: &PersistentConfigParameters) -> Result<T> {
+    let mut content = load_file::<T>(params)?;
+    content.apply_env_overrides()?;
+    content.after_load();
+    content.validate()?;
+    Ok(content)
+}
+
+/// Loads configuration data from a file according to the given parameters.
+///
+/// Returns the deserialized configuration struct.
+fn load_file<T: PersistentConfigBuilder>(params: &PersistentConfigParameters) -> Result<T> {
+    progress::observe(hooks::load_events::<T>(), || {
+        let file_path = config_file_path(params);
+        progress::emit(LoadEvent::Started {
+            path: file_path.clone(),
+            size: std::fs::metadata(&file_path).map(|metadata| metadata.len()).ok(),
+        });
+        let content = load_file_observed(params);
+        if content.is_ok() {
+            progress::emit(LoadEvent::Parsed);
+        }
+        progress::emit(LoadEvent::Done);
+        content
+    })
+}
+
+/// Loads the config file for [`load_file`], once the load events are observed.
+fn load_file_observed<T: PersistentConfigBuilder>(params: &PersistentConfigParameters) -> Result<T> {
+    let file_path = config_file_path(params);
+    // Taken before reading, so a change made while reading shows up as stale
+    let stamp = FileStamp::of(&file_path);
+    let content = telemetry::load(|| read_config(params, file_path, params.save_format));
+    // A file saved before the namespace was set is loaded from its former location
+    let content = match content {
+        Err(e) if is_not_found(&e) && params.namespace.is_some() => {
+            let legacy_path = config_file_path(&PersistentConfigParameters {
+                namespace: None,
+                ..params.clone()
+            });
+            if legacy_path.exists() {
+                telemetry::load(|| read_config(params, legacy_path, params.save_format))
+            } else {
+                Err(e)
+            }
+        }
+        content => content,
+    };
+    let content = match content {
+        Err(e) if is_not_found(&e) && !params.fallback_formats.is_empty() && params.full_path.is_none() => {
+            load_fallback(params).unwrap_or(Err(e))
+        }
+        content => content,
+    };
+    if content.as_ref().map_or_else(is_not_found, |_| true) {
+        cache::mark_synced::<T>(stamp);
+    }
+    content
+}
+
+/// Loads the config from the first existing file of the `fallback_formats` of `params`, and
+/// saves it in `save_format` if `resave_fallback` is set.
+///
+/// Returns `None` if none of the fallback files exist.
+fn load_fallback<T: PersistentConfigBuilder>(params: &PersistentConfigParameters) -> Option<Result<T>> {
+    let (save_format, file_path) = params.fallback_formats.iter().find_map(|&save_format| {
+        let file_path = config_file_path(&PersistentConfigParameters {
+            save_format,
+            extension: None,
+            ..params.clone()
+        });
+        file_path.is_file().then_some((save_format, file_path))
+    })?;
+
+    let content = telemetry::load(|| read_config::<T>(params, file_path.clone(), save_format))
+        .with_context(|| format!("Failed to load the fallback config file {:?}", file_path));
+    if !params.resave_fallback {
+        return Some(content);
+    }
+    Some(content.and_then(|content| {
+        save_config(params, &prepare_save(&content)?)
+            .with_context(|| format!("Failed to save the config loaded from {:?}", file_path))?;
+        Ok(content)
+    }))
+}
+
+/// Reads the config file at `file_path`, mapping the on-disk layout back to the type.
+fn read_config<T: PersistentConfigBuilder>(
+    params: &PersistentConfigParameters,
+    file_path: PathBuf,
+    save_format: SaveFormat,
+) -> Result<T> {
+    let renames = T::field_renames();
+    let aliases = T::field_aliases();
+    let file_fields = T::file_fields();
+    let redacted_fields = T::redacted_fields();
+    let delegated_fields = T::delegated_fields();
+    let computed_fields = T::computed_fields();
+    if renames.is_empty()
+        && aliases.is_empty()
+        && file_fields.is_empty()
+        && redacted_fields.is_empty()
+        && delegated_fields.is_empty()
+        && computed_fields.is_empty()
+        && params.envelope.is_none()
+        && !params.includes
+        && !params.repair_invalid
+        && references::is_empty()
+        && secrets::is_empty()
+        && !params.templates
+    {
+        match read_file::<T>(params, file_path.clone(), save_format) {
+            Ok(mut content) => {
+                content.attach_sidecars(&sidecar::sidecars_dir(&file_path));
+                return Ok(content);
+            }
+            // The file may lack fields, such as one written by `save_delta`, read it again
+            // through its document to give them their default value
+            Err(e) if recovery::is_parse_error(&e) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut document = read_file(params, file_path.clone(), save_format)?;
+    let mut metadata = None;
+    if let Some(options) = &params.envelope {
+        (document, metadata) = envelope::unwrap(document, options, &file_path)?;
+    }
+    let base_dir = file_path.parent().unwrap_or(Path::new(""));
+    if params.includes {
+        let mut stack = vec![std::fs::canonicalize(&file_path).unwrap_or_else(|_| file_path.clone())];
+        let read_included = |path: &Path| read_included(params, path);
+        document = include::extend(document, &file_path, &read_included, &params.merge, &mut stack)?;
+        document = include::resolve(document, base_dir, &read_included, &params.merge, &mut stack)?;
+    }
+    if !file_fields.is_empty() {
+        document = document::resolve_file_refs(document, file_fields, base_dir)?;
+    }
+    if !references::is_empty() {
+        document = references::resolve(document)?;
+    }
+    let mut resolved_secrets = Vec::new();
+    if !secrets::is_empty() {
+        document = secrets::resolve(document, &mut resolved_secrets)?;
+    }
+    if params.templates {
+        #[cfg(feature = "templates")]
+        {
+            document = templates::render(document)?;
+        }
+        #[cfg(not(feature = "templates"))]
+        anyhow::bail!("Rendering the templates of the config file requires the `templates` feature");
+    }
+    if !delegated_fields.is_empty() {
+        T::load_delegated(&mut document)?;
+    }
+    // Given their default value by `fill_missing`
+    document::strip_computed(&mut document, computed_fields);
+    // Deserialization errors may quote the values of the redacted fields
+    let mut secrets = document::redacted_strings(&document, redacted_fields);
+    secrets.extend(resolved_secrets);
+    let document = document::rename_keys(document, renames, Direction::FromDisk);
+    let mut document = document::resolve_aliases(document, aliases);
+    document::fill_missing(&mut document, serde_json::to_value(T::default())?);
+    let repairable = params.repair_invalid.then(|| document.clone());
+    let mut content: T = match (from_document(document, save_format), repairable) {
+        (Err(e), Some(document)) => recovery::repair(document, save_format, e),
+        (content, _) => content,
+    }
+    .map_err(|e| document::scrub(e, &secrets))?;
+    content.attach_sidecars(&sidecar::sidecars_dir(&file_path));
+
+    if let Some(options) = &params.envelope {
+        let old_version = metadata.map(|metadata| metadata.app_version);
+        if old_version.as_deref() != Some(options.app_version.as_str())
+            && hooks::upgrade(old_version.as_deref(), &options.app_version, &mut content)
+        {
+            // Saved with the current version, so the fix-ups run once
+            save_config(params, &prepare_save(&content)?).context("Failed to save the upgraded config")?;
+        }
+    }
+    Ok(content)
+}
+
+/// Deserializes `T` from a document read from a file in the given format.
+fn from_document<T>(document: serde_json::Value, save_format: SaveFormat) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    match save_format {
+        // The values of a properties file are all read as strings
+        SaveFormat::Properties => properties::from_value(document),
+        _ => Ok(serde_json::from_value(document)?),
+    }
+}
+
+/// Returns a copy of `data` updated by its [`before_save`](PersistentConfigBuilder::before_save)
+/// hook, made through its serde representation, and checks it.
+fn prepare_save<T: PersistentConfigBuilder>(data: &T) -> Result<T> {
+    let mut copy: T = serde_json::from_value(serde_json::to_value(data)?)?;
+    // The values of the sidecar fields are not part of the serde representation
+    copy.share_sidecars(data);
+    let mut data = copy;
+    data.before_save();
+    data.validate()?;
+    Ok(data)
+}
+
+/// Saves the config to the registered file, mapping the type to its on-disk layout, and the
+/// fields stored in the file of their own type.
+///
+/// Returns the size of the config file written.
+fn save_config<T: PersistentConfigBuilder>(params: &PersistentConfigParameters, data: &T) -> Result<u64> {
+    ensure_not_frozen::<T>()?;
+    let file_path = config_file_path(params);
+    let bytes = telemetry::save(std::any::type_name::<T>(), || {
+        write_config(params, data, WriteScope::All)
+    })?;
+    cache::mark_synced::<T>(FileStamp::of(&file_path));
+    throttle::written::<T>();
+    data.save_delegated()?;
+    Ok(bytes)
+}
+
+/// Part of the config written by [`write_config`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WriteScope<'a> {
+    /// The whole config.
+    All,
+    /// Only these fields, the rest of the existing file is kept.
+    Fields(&'a [&'a str]),
+    /// Only the top level fields differing from the default value.
+    Delta,
+}
+
+/// Saves the config to the file described by `params`, mapping the type to its on-disk layout.
+///
+/// Only the part of the config given by `scope` is written, and the size of the file written
+/// is returned. The file is not marked as synced, as it may not be the registered one.
+fn write_config<T: PersistentConfigBuilder>(
+    params: &PersistentConfigParameters,
+    data: &T,
+    scope: WriteScope,
+) -> Result<u64> {
+    let renames = T::field_renames();
+    let file_fields = T::file_fields();
+    let delegated_fields = T::delegated_fields();
+    let computed_fields = T::computed_fields();
+    let file_path = config_file_path(params);
+    data.save_sidecars(&sidecar::sidecars_dir(&file_path))?;
+    if renames.is_empty()
+        && file_fields.is_empty()
+        && delegated_fields.is_empty()
+        && computed_fields.is_empty()
+        && scope == WriteScope::All
+        && params.envelope.is_none()
+        && !params.audit_log
+        && !params.serializer.sort_keys
+        && !params.includes
+        && references::is_empty()
+        && secrets::is_empty()
+        && !params.templates
+    {
+        return save_file(params, data);
+    }
+
+    let (document, previous) = disk_document(params, data, scope)?;
+    let bytes = save_file(params, &document)?;
+    if params.audit_log {
+        audit::record::<T>(&file_path, previous, &document)?;
+    }
+    Ok(bytes)
+}
+
+/// Returns the document written by [`write_config`] for the part of `data` given by `scope`,
+/// and the document found in the file before, if it was needed to build it.
+fn disk_document<T: PersistentConfigBuilder>(
+    params: &PersistentConfigParameters,
+    data: &T,
+    scope: WriteScope,
+) -> Result<(serde_json::Value, Option<serde_json::Value>)> {
+    let fields = match scope {
+        WriteScope::Fields(fields) => Some(fields),
+        _ => None,
+    };
+    let renames = T::field_renames();
+    let file_fields = T::file_fields();
+    let delegated_fields = T::delegated_fields();
+    let computed_fields = T::computed_fields();
+    let file_path = config_file_path(params);
+    let previous = if params.audit_log
+        || !file_fields.is_empty()
+        || fields.is_some()
+        || params.includes
+        || !references::is_empty()
+        || !secrets::is_empty()
+        || params.templates
+    {
+        match read_file::<serde_json::Value>(params, file_path.clone(), params.save_format) {
+            Ok(previous) => Some(previous),
+            // A patch must not replace a file that could not be read
+            Err(e) if fields.is_some() && !is_not_found(&e) => {
+                return Err(e.context("Failed to read the config file to update"));
+            }
+            Err(_) => None,
+        }
+    } else {
+        None
+    };
+
+    let base_dir = file_path.parent().unwrap_or(Path::new(""));
+    let read_included = |path: &Path| read_included(params, path);
+    let mut document = document::rename_keys(serde_json::to_value(data)?, renames, Direction::ToDisk);
+    delegate::strip(&mut document, delegated_fields);
+    document::strip_computed(&mut document, computed_fields);
+    if scope == WriteScope::Delta {
+        let defaults = document::rename_keys(serde_json::to_value(T::default())?, renames, Direction::ToDisk);
+        document::strip_defaults(&mut document, &defaults);
+    }
+    if let Some(previous) = &previous {
+        let mut previous = envelope::payload(previous.clone());
+        if params.includes {
+            // File references may come from the included files
+            previous = include::extend(previous, &file_path, &read_included, &params.merge, &mut Vec::new())?;
+            previous = include::resolve(previous, base_dir, &read_included, &params.merge, &mut Vec::new())?;
+        }
+        document::keep_file_refs(&mut document, &previous, file_fields);
+        if !references::is_empty() {
+            references::keep(&mut document, &previous)?;
+        }
+        if !secrets::is_empty() {
+            secrets::keep(&mut document, &previous)?;
+        }
+        #[cfg(feature = "templates")]
+        if params.templates {
+            templates::keep(&mut document, &previous)?;
+        }
+    }
+    if let Some(fields) = fields {
+        let keys = fields
+            .iter()
+            .map(|field| {
+                renames
+                    .iter()
+                    .find(|(name, _)| name == field)
+                    .map_or(*field, |(_, key)| *key)
+            })
+            .collect::<Vec<_>>();
+        let existing = previous.clone().map_or(serde_json::Value::Null, envelope::payload);
+        document = document::patch(existing, &document, &keys)?;
+    }
+    if params.includes
+        && let Some(previous) = &previous
+    {
+        let previous = envelope::payload(previous.clone());
+        include::keep(&mut document, &previous, base_dir, &read_included, &params.merge)?;
+        include::keep_extends(&mut document, &previous, &file_path, &read_included, &params.merge)?;
+    }
+    if let Some(options) = &params.envelope {
+        document = envelope::wrap(document, options);
+    }
+    if params.save_format == SaveFormat::TOML {
+        document::strip_nulls(&mut document);
+    }
+    if params.serializer.sort_keys {
+        document::sort_keys(&mut document);
+    }
+
+    Ok((document, previous))
+}
+
+/// Reads a file included by the config file, in the format given by its extension or the
+/// format of the config file.
+fn read_included(params: &PersistentConfigParameters, path: &Path) -> Result<serde_json::Value> {
+    read_file(
+        params,
+        path.to_owned(),
+        detect_format(path).unwrap_or(params.save_format),
+    )
+}
+
+/// Reads and deserializes the file at `file_path`, stored in the given format.
+///
+/// The `max_file_size` and `timeout` limits of `params` apply.
+fn read_file<T>(params: &PersistentConfigParameters, file_path: PathBuf, save_format: SaveFormat) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let Some(timeout) = params.timeout else {
+        return read_file_blocking(params, file_path, save_format);
+    };
+
+    // `T` may not be sendable, the document is read as a `Value` on the IO thread
+    let (io_params, io_path) = (params.clone(), file_path.clone());
+    let observer = progress::current();
+    let document = timeout::run(timeout, &file_path, move || {
+        progress::observe(observer, || {
+            read_file_blocking::<serde_json::Value>(&io_params, io_path, save_format)
+        })
+    })?;
+    from_document(document, save_format)
+}
+
+/// Reads and deserializes the file at `file_path` on the current thread.
+///
+/// The `max_file_size` limit of `params` applies.
+fn read_file_blocking<T>(params: &PersistentConfigParameters, file_path: PathBuf, save_format: SaveFormat) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let file = File::open(&file_path)?;
+    let metadata = file.metadata()?;
+    links::check(params, &file_path, &metadata)?;
+    permissions::check(params, &file_path, &metadata)?;
+    signature::verify(params, &file_path)?;
+    let size = metadata.len();
+    if let Some(max_file_size) = params.max_file_size
+        && size > max_file_size
+    {
+        return Err(PersistentConfigError::FileTooLarge {
+            path: file_path,
+            size,
+            max_file_size,
+        }
+        .into());
+    }
+
+    if params.sops {
+        return sops::read(params, &file_path, save_format);
+    }
+
+    // Binary files are deserialized from the mapped file, sized as checked above
+    #[cfg(feature = "mmap")]
+    if save_format == SaveFormat::CBOR && size > 0 {
+        let map = map_file(&file, size)?;
+        progress::bytes_read(size);
+        return serializer::from_cbor(&map[..]);
+    }
+
+    // The reported size can't be trusted for special or growing files, so cap the read as well
+    let limit = params
+        .max_file_size
+        .map_or(u64::MAX, |max_file_size| max_file_size.saturating_add(1));
+
+    #[cfg(not(feature = "zeroize"))]
+    let (config, exhausted) = {
+        let mut reader = BufReader::new(ProgressReader(file)).take(limit);
+        if save_format != SaveFormat::CBOR {
+            serializer::skip_bom(&mut reader)?;
+        }
+        let config = match save_format {
+            SaveFormat::JSON => serde_json::from_reader(&mut reader).map_err(anyhow::Error::from),
+            // TOML has no streaming deserializer, the document must be read in full
+            SaveFormat::TOML => read_to_string(&mut reader)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(toml::de::from_str(&content)?)),
+            SaveFormat::YAML => {
+                serializer::from_yaml(serde_yaml::Deserializer::from_reader(&mut reader), &params.serializer)
+            }
+            SaveFormat::Properties => read_to_string(&mut reader)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| properties::from_value(properties::parse(&content)?)),
+            SaveFormat::CBOR => serializer::from_cbor(&mut reader),
+            SaveFormat::HCL => read_to_string(&mut reader)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| serializer::from_hcl(&content)),
+        };
+        (config, reader.limit() == 0)
+    };
+
+    // Read the document in a single buffer wiped on drop, instead of going through BufReader
+    #[cfg(feature = "zeroize")]
+    let (config, exhausted) = {
+        let mut reader = ProgressReader(file).take(limit);
+        let buffer = zeroizing::read_to_end(&mut reader, size)?;
+        let content = match save_format {
+            SaveFormat::CBOR => &buffer[..],
+            _ => serializer::strip_bom(&buffer),
+        };
+        let config = match save_format {
+            SaveFormat::JSON => serde_json::from_slice(content).map_err(anyhow::Error::from),
+            SaveFormat::TOML => std::str::from_utf8(content)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(toml::de::from_str(content)?)),
+            SaveFormat::YAML => {
+                serializer::from_yaml(serde_yaml::Deserializer::from_slice(content), &params.serializer)
+            }
+            SaveFormat::Properties => std::str::from_utf8(content)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| properties::from_value(properties::parse(content)?)),
+            SaveFormat::CBOR => serializer::from_cbor(content),
+            SaveFormat::HCL => std::str::from_utf8(content)
+                .map_err(anyhow::Error::from)
+                .and_then(serializer::from_hcl),
+        };
+        (config, reader.limit() == 0)
+    };
+
+    if let Some(max_file_size) = params.max_file_size
+        && exhausted
+    {
+        return Err(PersistentConfigError::FileTooLarge {
+            path: file_path,
+            size: limit,
+            max_file_size,
+        }
+        .into());
+    }
+
+    config
+}
+
+/// Maps the first `size` bytes of `file` in memory, read only.
+#[cfg(feature = "mmap")]
+fn map_file(file: &File, size: u64) -> Result<memmap2::Mmap> {
+    let len = usize::try_from(size)?;
+    // SAFETY: the mapping is only read while the file is deserialized. The saves replace the
+    // config file by renaming a new file over it, so they never modify a mapped file: only
+    // another program truncating the file in place could invalidate the mapping.
+    Ok(unsafe { memmap2::MmapOptions::new().len(len).map(file)? })
+}
+
+/// Saves configuration data to a file according to the given parameters, and returns the
+/// size of the file written.
+///
+/// The `timeout` limit of `params` applies.
+fn save_file<T>(params: &PersistentConfigParameters, data: T) -> Result<u64>
+where
+    T: Serialize,
+{
+    let file_path = config_file_path(params);
+    let Some(timeout) = params.timeout else {
+        return save_file_blocking(params, file_path, data);
+    };
+
+    // `T` may not be sendable, it is converted to a `Value` before moving to the IO thread
+    let mut document = serde_json::to_value(data)?;
+    if params.save_format == SaveFormat::TOML {
+        document::strip_nulls(&mut document);
+    }
+    let (io_params, io_path) = (params.clone(), file_path.clone());
+    timeout::run(timeout, &file_path, move || {
+        save_file_blocking(&io_params, io_path, document)
+    })
+}
 
+/// Saves configuration data to `file_path` on the current thread, and returns the size of
+/// the file written.
+///
+/// Serializes the struct into a temporary file next to the config file, then
+/// renames it over the config file, so a failed save never leaves a truncated file behind.
+fn save_file_blocking<T>(params: &PersistentConfigParameters, file_path: PathBuf, data: T) -> Result<u64>
+where
+    T: Serialize,
+{
     // Create a config directory if necessary
     if file_path.parent().is_some() && !file_path.parent().unwrap().exists() {
         // println!("Creating config directory: {:?}", file_path.parent().unwrap());
         std::fs::create_dir_all(file_path.parent().unwrap())?
     }
 
-    // Open the file for writing, truncating it if it exists
-    let mut file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .append(false)
-        .create(true)
-        .open(file_path)?;
+    let file_path = links::save_path(params, file_path)?;
+
+    let mut tmp_path = file_path.clone().into_os_string();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let written = write_file(params, &tmp_path, &file_path, data)
+        .and_then(|bytes| Ok((bytes, signature::sign(params, &tmp_path, &file_path)?)));
+    let (bytes, signature) = match written {
+        Ok(written) => written,
+        Err(e) => {
+            _ = std::fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+    };
+    std::fs::rename(&tmp_path, &file_path)?;
+    if let Some(signature) = signature {
+        std::fs::rename(signature, signature::signature_path(&file_path))?;
+    }
+
+    if params.durability == Durability::Fsync {
+        // Persist the directory entry as well, so the renamed file is not lost
+        #[cfg(unix)]
+        if let Some(parent) = file_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            File::open(parent)?.sync_all()?;
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Serializes the data into `tmp_path`, applying the durability policy, and returns the
+/// number of bytes written.
+///
+/// Permissions of an existing file at `file_path` are copied to the new file.
+fn write_file<T>(params: &PersistentConfigParameters, tmp_path: &Path, file_path: &Path, data: T) -> Result<u64>
+where
+    T: Serialize,
+{
+    // Create a new file, a stale one or a link left at its path is removed rather than followed
+    match std::fs::remove_file(tmp_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    // Create the file with its final mode, so it is never readable by others while written
+    #[cfg(unix)]
+    if let Some(mode) = params.mode {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+    let file = options.open(tmp_path)?;
+
+    // The creation mode is masked by the umask
+    #[cfg(unix)]
+    if let Some(mode) = params.mode {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(mode))?;
+    }
+    if params.mode.is_none()
+        && let Ok(metadata) = std::fs::metadata(file_path)
+    {
+        file.set_permissions(metadata.permissions())?;
+    }
+
+    #[cfg(all(unix, feature = "ownership"))]
+    ownership::apply(params, &file)?;
+    #[cfg(not(all(unix, feature = "ownership")))]
+    if params.owner.is_some() || params.group.is_some() {
+        anyhow::bail!("Setting the owner or group of the config file requires the `ownership` feature on Unix");
+    }
+
+    // Convert the data to the appropriate format, in a buffer written to the file at once
+    #[cfg(not(feature = "zeroize"))]
+    let file = serializer::with_buffer(|buffer| {
+        serialize_into(buffer, params, &data)?;
+        let mut file = file;
+        if params.sops {
+            file.write_all(&sops::encrypt(buffer, params.save_format, file_path)?)?;
+        } else {
+            let mut text = serializer::TextWriter::new(&mut file, &params.serializer, params.save_format, file_path);
+            if let Some(header) = provenance::header(params) {
+                text.write_all(header.as_bytes())?;
+            }
+            text.write_all(buffer)?;
+        }
+        Ok(file)
+    })?;
+
+    // Same as above, with every intermediate buffer wiped once written
+    #[cfg(feature = "zeroize")]
+    let file = if params.sops {
+        let mut buffer = zeroize::Zeroizing::new(Vec::new());
+        serialize_into(&mut buffer, params, &data)?;
+        let mut file = file;
+        file.write_all(&sops::encrypt(&buffer, params.save_format, file_path)?)?;
+        file
+    } else {
+        let mut writer = zeroizing::ZeroizingWriter::new(file);
+        let mut text = serializer::TextWriter::new(&mut writer, &params.serializer, params.save_format, file_path);
+        if let Some(header) = provenance::header(params) {
+            text.write_all(header.as_bytes())?;
+        }
+        match params.save_format {
+            SaveFormat::JSON => serializer::to_json_writer(&mut text, &data, &params.serializer)?,
+            SaveFormat::TOML => text.write_all(
+                zeroize::Zeroizing::new(serializer::to_toml_string(&data, &params.serializer)?).as_bytes(),
+            )?,
+            SaveFormat::YAML => serializer::to_yaml_writer(&mut text, &data, &params.serializer)?,
+            SaveFormat::Properties => text
+                .write_all(zeroize::Zeroizing::new(properties::to_string(&serde_json::to_value(&data)?)?).as_bytes())?,
+            SaveFormat::CBOR => serializer::to_cbor_writer(&mut text, &data)?,
+            SaveFormat::HCL => anyhow::bail!("The HCL format is load only, config files can't be saved in it"),
+        };
+        writer.into_inner()?
+    };
+
+    // The file was created empty, the writes all went to its end
+    let bytes = std::io::Seek::stream_position(&mut &file)?;
+    match params.durability {
+        Durability::None => {}
+        Durability::Flush => file.sync_data()?,
+        Durability::Fsync => file.sync_all()?,
+    }
 
-    file.write_all(&data.as_bytes())?;
+    Ok(bytes)
+}
 
+/// Appends `data` serialized in the save format of `params` to `buffer`.
+fn serialize_into<T>(buffer: &mut Vec<u8>, params: &PersistentConfigParameters, data: &T) -> Result<()>
+where
+    T: Serialize,
+{
+    match params.save_format {
+        SaveFormat::JSON => serializer::to_json_writer(&mut *buffer, data, &params.serializer)?,
+        // TOML has no streaming serializer, the document must be built in full
+        SaveFormat::TOML => buffer.extend_from_slice(serializer::to_toml_string(data, &params.serializer)?.as_bytes()),
+        SaveFormat::YAML => serializer::to_yaml_writer(&mut *buffer, data, &params.serializer)?,
+        SaveFormat::Properties => {
+            buffer.extend_from_slice(properties::to_string(&serde_json::to_value(data)?)?.as_bytes())
+        }
+        SaveFormat::CBOR => serializer::to_cbor_writer(&mut *buffer, data)?,
+        SaveFormat::HCL => anyhow::bail!("The HCL format is load only, config files can't be saved in it"),
+    };
     Ok(())
 }
 
@@ -0,0 +1,83 @@
+//! Handling of config files whose path is a symbolic link, or which have several hard links,
+//! with the `symlinks` parameter.
+//!
+//! Saves never write through a link: the config is written to a new temporary file, created
+//! exclusively so a link planted at its path is not followed, then renamed over the config
+//! file. The policy decides whether that rename replaces the target of a symlinked config
+//! file or the link itself, or whether such files are refused altogether.
+
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use persistent_config_core::{PersistentConfigError, PersistentConfigParameters, SymlinkPolicy};
+
+/// Returns the path the config file at `file_path` must be saved to, as required by `params`.
+pub(crate) fn save_path(params: &PersistentConfigParameters, file_path: PathBuf) -> Result<PathBuf> {
+    match params.symlinks {
+        // Update the target of a symlinked config file instead of replacing the link itself
+        SymlinkPolicy::Follow => Ok(std::fs::canonicalize(&file_path).unwrap_or(file_path)),
+        SymlinkPolicy::Replace => Ok(file_path),
+        SymlinkPolicy::Refuse => {
+            if let Ok(metadata) = std::fs::symlink_metadata(&file_path) {
+                refuse(&file_path, &metadata)?;
+            }
+            Ok(file_path)
+        }
+    }
+}
+
+/// Checks the config file at `file_path`, whose `metadata` was read from the opened file, as
+/// required by `params`.
+///
+/// The file is checked again without following links, and must still be the opened one, so
+/// a link swapped in between can't go unnoticed.
+pub(crate) fn check(params: &PersistentConfigParameters, file_path: &Path, metadata: &Metadata) -> Result<()> {
+    if params.symlinks != SymlinkPolicy::Refuse {
+        return Ok(());
+    }
+    let link_metadata = std::fs::symlink_metadata(file_path)?;
+    refuse(file_path, &link_metadata)?;
+    if !same_file(metadata, &link_metadata) {
+        return Err(unsafe_link(file_path, "was replaced while being opened"));
+    }
+    Ok(())
+}
+
+/// Returns an error if the file at `file_path`, whose `metadata` was read without following
+/// links, is a symbolic link or has several hard links.
+fn refuse(file_path: &Path, metadata: &Metadata) -> Result<()> {
+    if metadata.file_type().is_symlink() {
+        return Err(unsafe_link(file_path, "is a symbolic link"));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if metadata.is_file() && metadata.nlink() > 1 {
+            return Err(unsafe_link(file_path, &format!("has {} hard links", metadata.nlink())));
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` if both metadata were read from the same file.
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+/// Returns `true`, as files can't be told apart from their metadata on this platform.
+#[cfg(not(unix))]
+fn same_file(_a: &Metadata, _b: &Metadata) -> bool {
+    true
+}
+
+/// Returns the error refusing the file at `file_path` for `reason`.
+fn unsafe_link(file_path: &Path, reason: &str) -> anyhow::Error {
+    PersistentConfigError::UnsafeLink {
+        path: file_path.to_owned(),
+        reason: reason.to_owned(),
+    }
+    .into()
+}
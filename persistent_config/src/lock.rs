@@ -0,0 +1,42 @@
+//! Advisory lock serializing read-modify-write cycles across processes.
+//!
+//! Saves replace the config file by renaming a temporary file over it, so the lock is taken
+//! on a separate `<file>.lock` file which is never replaced.
+
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Exclusive lock on a config file, released when dropped.
+pub(crate) struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    /// Blocks until the exclusive lock of the config file at `file_path` is acquired.
+    ///
+    /// The config directory is created if necessary.
+    pub(crate) fn acquire(file_path: &Path) -> Result<Self> {
+        if let Some(parent) = file_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut lock_path = file_path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open lock file {:?}", lock_path))?;
+        file.lock().with_context(|| format!("Failed to lock {:?}", lock_path))?;
+        Ok(Self { file })
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        _ = self.file.unlock();
+    }
+}
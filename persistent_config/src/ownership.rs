@@ -0,0 +1,40 @@
+//! Ownership of the config file, applied when `owner` or `group` is set in the parameters,
+//! and checked on load with `permission_check`.
+
+use std::fs::File;
+
+use anyhow::{Result, anyhow};
+use nix::unistd::{Gid, Group, Uid, User, fchown};
+use persistent_config_core::PersistentConfigParameters;
+
+/// Changes the owner and group of `file` to the ones set in `params`.
+///
+/// Names are looked up in the user and group databases, numeric values are used as ids.
+pub(crate) fn apply(params: &PersistentConfigParameters, file: &File) -> Result<()> {
+    let uid = params.owner.as_deref().map(user_id).transpose()?;
+    let gid = params.group.as_deref().map(group_id).transpose()?;
+    if uid.is_some() || gid.is_some() {
+        fchown(file, uid, gid).map_err(|e| anyhow!("Failed to change the owner of the config file: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Resolves a user name or numeric id.
+pub(crate) fn user_id(owner: &str) -> Result<Uid> {
+    if let Ok(id) = owner.parse() {
+        return Ok(Uid::from_raw(id));
+    }
+    User::from_name(owner)?
+        .map(|user| user.uid)
+        .ok_or_else(|| anyhow!("Unknown user {:?}", owner))
+}
+
+/// Resolves a group name or numeric id.
+fn group_id(group: &str) -> Result<Gid> {
+    if let Ok(id) = group.parse() {
+        return Ok(Gid::from_raw(id));
+    }
+    Group::from_name(group)?
+        .map(|group| group.gid)
+        .ok_or_else(|| anyhow!("Unknown group {:?}", group))
+}
@@ -0,0 +1,81 @@
+//! Checks that the config files can't be tampered with by other users, with the
+//! `permission_check` parameter.
+//!
+//! Only Unix permissions are checked: on other platforms, every file passes.
+
+use std::fs::Metadata;
+use std::path::Path;
+
+use anyhow::Result;
+use persistent_config_core::{PermissionCheck, PersistentConfigError, PersistentConfigParameters};
+
+/// Checks the permissions of the config file at `file_path`, whose `metadata` was read from
+/// the opened file, as required by `params`.
+pub(crate) fn check(params: &PersistentConfigParameters, file_path: &Path, metadata: &Metadata) -> Result<()> {
+    if params.permission_check == PermissionCheck::Off {
+        return Ok(());
+    }
+    let problems = problems(params, file_path, metadata)?;
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    if params.permission_check == PermissionCheck::Deny {
+        return Err(PersistentConfigError::InsecurePermissions {
+            path: file_path.to_owned(),
+            problems,
+        }
+        .into());
+    }
+    for problem in problems {
+        eprintln!("Warning: config file {:?} {}", file_path, problem);
+    }
+    Ok(())
+}
+
+/// Returns the problems found with the permissions of the config file and its directory.
+#[cfg(unix)]
+#[cfg_attr(not(feature = "ownership"), allow(unused_variables))]
+fn problems(params: &PersistentConfigParameters, file_path: &Path, metadata: &Metadata) -> Result<Vec<String>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut problems = Vec::new();
+    let mode = metadata.mode();
+    if mode & 0o020 != 0 {
+        problems.push("is writable by its group".to_owned());
+    }
+    if mode & 0o002 != 0 {
+        problems.push("is writable by other users".to_owned());
+    }
+
+    // Without the sticky bit, whoever can write to the directory can replace the file
+    let dir = file_path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    if let Some(dir_mode) = dir.and_then(|dir| std::fs::metadata(dir).ok()).map(|dir| dir.mode())
+        && dir_mode & 0o1000 == 0
+    {
+        if dir_mode & 0o020 != 0 {
+            problems.push("is in a directory writable by its group".to_owned());
+        }
+        if dir_mode & 0o002 != 0 {
+            problems.push("is in a directory writable by other users".to_owned());
+        }
+    }
+
+    #[cfg(feature = "ownership")]
+    {
+        let owner = match params.owner.as_deref() {
+            Some(owner) => crate::ownership::user_id(owner)?,
+            None => nix::unistd::Uid::effective(),
+        };
+        if metadata.uid() != owner.as_raw() {
+            problems.push(format!("is owned by user {}, instead of {}", metadata.uid(), owner));
+        }
+    }
+    Ok(problems)
+}
+
+/// Returns no problem, as only Unix permissions are checked.
+#[cfg(not(unix))]
+fn problems(_params: &PersistentConfigParameters, _file_path: &Path, _metadata: &Metadata) -> Result<Vec<String>> {
+    Ok(Vec::new())
+}
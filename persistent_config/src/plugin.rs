@@ -0,0 +1,247 @@
+//! [Bevy](https://bevyengine.org) plugin keeping a config in a resource, with the `bevy`
+//! feature.
+
+use std::marker::PhantomData;
+
+use bevy_app::{App, AppExit, Last, Plugin};
+use bevy_ecs::component::Tick;
+use bevy_ecs::prelude::*;
+
+use crate::{LoadOutcome, PersistentConfig, is_not_found};
+
+/// Plugin loading the config `T` into a resource when added to the app, saving the resource
+/// whenever it is changed, and writing its deferred saves when the app exits.
+///
+/// The config is loaded with the parameters registered for `T`, or its
+/// [`derived_parameters`](crate::PersistentConfigBuilder::derived_parameters), with
+/// [`load_with_outcome`](PersistentConfig::load_with_outcome): `quarantine_corrupt` and
+/// `repair_invalid` apply as usual. A config that can't be loaded never stops the game: the
+/// error is logged and the default value used, as with `panic_on_error` unset, and the next
+/// change replaces the file. A missing config file also leaves the default value, as on the
+/// first run of a game. The [`ResourceLoadOutcome`] resource reports how the config was
+/// obtained, to tell the player that their settings were reset.
+///
+/// The changes are saved once per frame, in the [`Last`] schedule, including those made by
+/// the startup systems; set `min_save_interval` to write a value changed every frame less
+/// often, the last change being written when [`AppExit`] is sent.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use persistent_config::PersistentResourcePlugin;
+/// # use serde::{Deserialize, Serialize};
+/// use bevy_app::App;
+/// use bevy_ecs::prelude::*;
+///
+/// #[derive(Debug, Default, Serialize, Deserialize, Resource)]
+/// struct GameSettings {
+///     volume: u8,
+/// }
+/// # impl PersistentConfigBuilder for GameSettings {}
+///
+/// # fn main() -> anyhow::Result<()> {
+/// # let dir = std::env::temp_dir().join("persistent_config_doc_bevy");
+/// # GameSettings::default().config_with_parameters(PersistentConfigParameters {
+/// #     config_dir: dir.to_string_lossy().into_owned(),
+/// #     file_name: "GameSettings".to_string(),
+/// #     ..Default::default()
+/// # })?;
+/// let mut app = App::new();
+/// app.add_plugins(PersistentResourcePlugin::<GameSettings>::default());
+/// # app.update();
+///
+/// app.world_mut().resource_mut::<GameSettings>().volume = 8;
+/// app.update(); // Saves the settings
+/// # let mut saved = GameSettings::default();
+/// # saved.load()?;
+/// # assert_eq!(saved.volume, 8);
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct PersistentResourcePlugin<T>(PhantomData<fn() -> T>);
+
+impl<T> PersistentResourcePlugin<T> {
+    /// Returns the plugin of the config `T`.
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> Default for PersistentResourcePlugin<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resource reporting how [`PersistentResourcePlugin`] obtained the config `T`.
+#[derive(Resource)]
+pub struct ResourceLoadOutcome<T> {
+    /// How the config was obtained, [`LoadOutcome::Defaulted`] if it couldn't be loaded.
+    pub outcome: LoadOutcome,
+    /// Tick at which the loaded config was inserted, which doesn't need to be saved.
+    inserted: Tick,
+    config_type: PhantomData<fn() -> T>,
+}
+
+impl<T: PersistentConfig + Resource> Plugin for PersistentResourcePlugin<T> {
+    fn build(&self, app: &mut App) {
+        let mut config = T::default();
+        let outcome = match config.load_with_outcome() {
+            Ok(outcome) => outcome,
+            Err(e) if is_not_found(&e) => LoadOutcome::Loaded,
+            Err(e) => {
+                eprintln!(
+                    "Error loading {}, using the default values: {:#}",
+                    std::any::type_name::<T>(),
+                    e
+                );
+                config = T::default();
+                LoadOutcome::Defaulted {
+                    error: format!("{:#}", e),
+                }
+            }
+        };
+        app.insert_resource(config);
+        let inserted = app.world().resource_ref::<T>().last_changed();
+        // The first system run otherwise shares the tick of the insertion, hiding its changes
+        app.world_mut().increment_change_tick();
+        app.insert_resource(ResourceLoadOutcome::<T> {
+            outcome,
+            inserted,
+            config_type: PhantomData,
+        })
+        .add_systems(Last, (autosave::<T>, flush_on_exit::<T>).chain());
+    }
+}
+
+/// Saves the config `T` when its resource was changed since the last frame.
+///
+/// The loaded config, inserted by the plugin, is not saved again: a change is only saved
+/// once the resource was modified, or replaced, after the insertion.
+fn autosave<T: PersistentConfig + Resource>(config: Option<Res<T>>, loaded: Res<ResourceLoadOutcome<T>>) {
+    let Some(config) = config else {
+        return;
+    };
+    if config.is_changed()
+        && config.last_changed() != loaded.inserted
+        && let Err(e) = config.save()
+    {
+        eprintln!("Error saving {}: {:#}", std::any::type_name::<T>(), e);
+    }
+}
+
+/// Writes the deferred saves of the config `T` when the app exits.
+fn flush_on_exit<T: PersistentConfig + Resource>(mut exits: MessageReader<AppExit>, config: Option<Res<T>>) {
+    if exits.is_empty() {
+        return;
+    }
+    exits.clear();
+    if let Some(config) = config
+        && let Err(e) = config.flush()
+    {
+        eprintln!("Error saving {}: {:#}", std::any::type_name::<T>(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use bevy_app::Startup;
+    use persistent_config_core::PersistentConfigParameters;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::PersistentConfigBuilder;
+
+    macro_rules! settings {
+        ($name:ident) => {
+            #[derive(Debug, Default, PartialEq, Serialize, Deserialize, Resource)]
+            struct $name {
+                volume: u8,
+            }
+
+            impl PersistentConfigBuilder for $name {}
+        };
+    }
+
+    settings!(Corrupt);
+    settings!(Tuned);
+    settings!(Unchanged);
+
+    /// Registers `T` in a fresh directory named after the test, returning the config file.
+    fn register<T: PersistentConfig>(test: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("persistent_config_test_plugin_{test}"));
+        _ = std::fs::remove_dir_all(&dir);
+        T::default()
+            .config_with_parameters(PersistentConfigParameters {
+                config_dir: dir.to_string_lossy().into_owned(),
+                file_name: test.to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        let file = dir.join(format!("{test}.toml"));
+        (dir, file)
+    }
+
+    fn read(file: &Path) -> String {
+        std::fs::read_to_string(file).unwrap()
+    }
+
+    #[test]
+    fn corrupt_file_leaves_the_default_value() {
+        let (dir, file) = register::<Corrupt>("corrupt");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&file, "volume = \"loud\"").unwrap();
+
+        let mut app = App::new();
+        app.add_plugins(PersistentResourcePlugin::<Corrupt>::new());
+        app.update();
+
+        assert_eq!(*app.world().resource::<Corrupt>(), Corrupt::default());
+        let outcome = &app.world().resource::<ResourceLoadOutcome<Corrupt>>().outcome;
+        assert!(matches!(outcome, LoadOutcome::Defaulted { .. }), "{outcome:?}");
+        // The file is left for the user until the config changes
+        assert_eq!(read(&file), "volume = \"loud\"");
+
+        app.world_mut().resource_mut::<Corrupt>().volume = 3;
+        app.update();
+        assert_eq!(read(&file).trim(), "volume = 3");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saves_changes_made_by_startup_systems() {
+        let (dir, file) = register::<Tuned>("startup");
+
+        let mut app = App::new();
+        app.add_plugins(PersistentResourcePlugin::<Tuned>::new())
+            .add_systems(Startup, |mut settings: ResMut<Tuned>| settings.volume = 9);
+        app.update();
+
+        assert_eq!(read(&file).trim(), "volume = 9");
+        assert_eq!(
+            app.world().resource::<ResourceLoadOutcome<Tuned>>().outcome,
+            LoadOutcome::Loaded
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loaded_config_is_not_saved_again() {
+        let (dir, file) = register::<Unchanged>("unchanged");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&file, "# Set by the player\nvolume = 4\n").unwrap();
+
+        let mut app = App::new();
+        app.add_plugins(PersistentResourcePlugin::<Unchanged>::new());
+        app.update();
+        app.update();
+
+        assert_eq!(app.world().resource::<Unchanged>().volume, 4);
+        assert_eq!(read(&file), "# Set by the player\nvolume = 4\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+//! Preview of a save, returned by [`save_dry_run`](crate::PersistentConfig::save_dry_run).
+
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+
+/// What a save would write, compared to the config file on disk.
+///
+/// Its [`Display`] output is the line diff, meant to be shown to an operator before the
+/// change is saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavePreview {
+    /// Path of the config file.
+    pub file_path: PathBuf,
+    /// Content the save would write.
+    pub contents: String,
+    /// Content of the config file, `None` if it does not exist yet.
+    pub current: Option<String>,
+    /// Paths of the values that would change, keys joined with `.` (`server.port`).
+    pub changed: Vec<String>,
+    /// Line diff from the current content to the new one, each line prefixed with `-` if
+    /// removed, `+` if added, or a space if kept.
+    pub diff: String,
+}
+
+impl SavePreview {
+    /// Returns whether the save would leave the config file as it is.
+    pub fn is_unchanged(&self) -> bool {
+        self.current.as_deref() == Some(self.contents.as_str())
+    }
+}
+
+impl Display for SavePreview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- {}", self.file_path.display())?;
+        writeln!(f, "+++ {}", self.file_path.display())?;
+        f.write_str(&self.diff)
+    }
+}
+
+/// Returns the line diff from `old` to `new`, see [`SavePreview::diff`].
+///
+/// The lines are matched along their longest common subsequence, config files being small
+/// enough for the quadratic table.
+pub(crate) fn line_diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // common[i][j]: length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let mut push = |prefix: char, line: &str| {
+        diff.push(prefix);
+        diff.push_str(line);
+        diff.push('\n');
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            push(' ', old[i]);
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            push('-', old[i]);
+            i += 1;
+        } else {
+            push('+', new[j]);
+            j += 1;
+        }
+    }
+    diff
+}
@@ -0,0 +1,88 @@
+//! Progress events of the loads, reported to the callback set with
+//! [`on_load_event`](crate::PersistentConfigBuilder::on_load_event).
+//!
+//! The callback of the type being loaded is made current for the thread while its file is
+//! read, and handed over to the IO thread when a timeout is set, so the readers deep in the
+//! load report the bytes they read without knowing the type.
+
+use std::cell::RefCell;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Step of the load of a config, reported to the callback set with
+/// [`on_load_event`](crate::PersistentConfigBuilder::on_load_event).
+///
+/// A load reports `Started`, then `BytesRead` as the file is read, `Parsed` once the config
+/// is deserialized, and `Done` whatever the result. A file that doesn't exist or fails to
+/// parse reports no `Parsed`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LoadEvent {
+    /// The load started.
+    Started {
+        /// Path of the config file.
+        path: PathBuf,
+        /// Size of the config file, if it exists.
+        size: Option<u64>,
+    },
+    /// Total number of bytes read so far, including the included files.
+    BytesRead(u64),
+    /// The config was deserialized.
+    Parsed,
+    /// The load is over.
+    Done,
+}
+
+/// Callback receiving the load events of a type.
+pub(crate) type Observer = Arc<dyn Fn(&LoadEvent) + Send + Sync>;
+
+thread_local! {
+    /// Callback of the load in progress on the thread, and the number of bytes read so far.
+    static CURRENT: RefCell<Option<(Observer, u64)>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with `observer` receiving the events of the thread, if any.
+pub(crate) fn observe<R>(observer: Option<Observer>, f: impl FnOnce() -> R) -> R {
+    let previous = CURRENT.replace(observer.map(|observer| (observer, 0)));
+    let result = f();
+    CURRENT.set(previous);
+    result
+}
+
+/// Returns the callback receiving the events of the thread, to hand it over to another one.
+pub(crate) fn current() -> Option<Observer> {
+    CURRENT.with_borrow(|current| current.as_ref().map(|(observer, _)| observer.clone()))
+}
+
+/// Reports `event` to the callback of the thread, if any.
+pub(crate) fn emit(event: LoadEvent) {
+    if let Some(observer) = current() {
+        observer(&event);
+    }
+}
+
+/// Counts `read` bytes, and reports the total to the callback of the thread, if any.
+pub(crate) fn bytes_read(read: u64) {
+    let observer = CURRENT.with_borrow_mut(|current| {
+        let (observer, total) = current.as_mut()?;
+        *total += read;
+        Some((observer.clone(), *total))
+    });
+    if let Some((observer, total)) = observer {
+        observer(&LoadEvent::BytesRead(total));
+    }
+}
+
+/// Reader reporting the bytes it reads with [`bytes_read`].
+pub(crate) struct ProgressReader<R>(pub(crate) R);
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.0.read(buf)?;
+        if read > 0 {
+            bytes_read(read as u64);
+        }
+        Ok(read)
+    }
+}
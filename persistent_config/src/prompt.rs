@@ -0,0 +1,55 @@
+//! First-run wizard asking for the fields marked with `#[persistent(prompt = "...")]`, with
+//! the `dialoguer` feature.
+
+use anyhow::{Context, Result};
+use dialoguer::Input;
+use serde_json::Value;
+
+use crate::PersistentConfigBuilder;
+
+/// Asks for the value of every prompt field of `T`, starting from `value`.
+///
+/// Each question shows the current value as its default, and the answer must have the same
+/// type: a number, `true` or `false`, or any text. An empty answer to a field without a
+/// value leaves it unset.
+pub(crate) fn ask<T: PersistentConfigBuilder>(value: T) -> Result<T> {
+    let mut document = serde_json::to_value(&value)?;
+    let Value::Object(map) = &mut document else {
+        return Ok(value);
+    };
+
+    for (key, question) in T::prompt_fields() {
+        let current = map.get(*key).cloned().unwrap_or(Value::Null);
+        let mut input = Input::<String>::new().with_prompt(*question);
+        input = match &current {
+            Value::Null => input.allow_empty(true),
+            Value::String(text) => input.default(text.clone()),
+            other => input.default(other.to_string()),
+        };
+        let kind = current.clone();
+        let answer = input
+            .validate_with(move |answer: &String| parse(&kind, answer).map(|_| ()))
+            .interact_text()
+            .with_context(|| format!("Failed to ask for `{}`", key))?;
+        map.insert((*key).to_owned(), parse(&current, &answer).unwrap_or(current));
+    }
+    serde_json::from_value(document).context("Invalid answers to the first run questions")
+}
+
+/// Parses `answer` into a value of the same type as `current`.
+fn parse(current: &Value, answer: &str) -> Result<Value, String> {
+    let answer = answer.trim();
+    match current {
+        Value::Bool(_) => match answer.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "y" => Ok(Value::Bool(true)),
+            "false" | "no" | "n" => Ok(Value::Bool(false)),
+            _ => Err("Answer yes or no".to_owned()),
+        },
+        Value::Number(_) => match serde_json::from_str(answer) {
+            Ok(Value::Number(number)) => Ok(Value::Number(number)),
+            _ => Err("Answer a number".to_owned()),
+        },
+        Value::Null if answer.is_empty() => Ok(Value::Null),
+        _ => Ok(Value::String(answer.to_owned())),
+    }
+}
@@ -0,0 +1,677 @@
+//! Java properties format.
+//!
+//! Nested values are flattened to dotted keys, and list items are indexed the way Spring
+//! does it:
+//!
+//! ```properties
+//! name=example
+//! server.port=8080
+//! servers[0].host=a.example.org
+//! ```
+//!
+//! Every value is read back as a string, and parsed into the type of the field when
+//! deserializing, so the layout follows the shape of the struct. A value read as a list is
+//! split on commas. Empty lists and maps are written as an empty value, `null` values are
+//! not written. Map keys containing `.` or `[` can't be told apart from nested keys.
+
+use std::fmt::Write as _;
+
+use anyhow::{Result, anyhow, bail};
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, VariantAccess, Visitor};
+use serde::{Deserialize, forward_to_deserialize_any};
+use serde_json::{Map, Value};
+
+/// Largest list index accepted in a key, so a typo can't allocate a huge list.
+const MAX_INDEX: usize = 1 << 16;
+
+/// Serializes `document` to the properties format.
+pub(crate) fn to_string(document: &Value) -> Result<String> {
+    if !document.is_object() {
+        bail!("The properties format requires the config to be a map");
+    }
+
+    let mut properties = String::new();
+    write_value(&mut properties, &mut String::new(), document);
+    Ok(properties)
+}
+
+/// Parses a properties file into a document holding string values.
+pub(crate) fn parse(text: &str) -> Result<Value> {
+    let mut document = Value::Object(Map::new());
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let line = trim_start(line);
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            continue;
+        }
+
+        // A line ending with an odd number of backslashes continues on the next one
+        let mut logical = line.to_owned();
+        while logical.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1 {
+            logical.pop();
+            match lines.next() {
+                Some(next) => logical.push_str(trim_start(next)),
+                None => break,
+            }
+        }
+
+        let (key, value) = split_entry(&logical)?;
+        let path = parse_key(&key)?;
+        insert(&mut document, &path, value).map_err(|e| anyhow!("Invalid key `{}`: {}", key, e))?;
+    }
+    Ok(document)
+}
+
+/// Deserializes `T` from a document read from a properties file, parsing its strings into
+/// the types expected by `T`.
+pub(crate) fn from_value<T>(document: Value) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    Ok(T::deserialize(Lenient(document))?)
+}
+
+/// Appends the entries of `value`, found at `key`, to `properties`.
+fn write_value(properties: &mut String, key: &mut String, value: &Value) {
+    let len = key.len();
+    match value {
+        Value::Null => {}
+        Value::Object(map) if !map.is_empty() => {
+            for (name, value) in map {
+                if !key.is_empty() {
+                    key.push('.');
+                }
+                key.push_str(name);
+                write_value(properties, key, value);
+                key.truncate(len);
+            }
+        }
+        Value::Array(values) if !values.is_empty() => {
+            for (index, value) in values.iter().enumerate() {
+                _ = write!(key, "[{}]", index);
+                write_value(properties, key, value);
+                key.truncate(len);
+            }
+        }
+        Value::Object(_) | Value::Array(_) => write_entry(properties, key, ""),
+        Value::String(value) => write_entry(properties, key, value),
+        Value::Bool(value) => write_entry(properties, key, &value.to_string()),
+        Value::Number(value) => write_entry(properties, key, &value.to_string()),
+    }
+}
+
+/// Appends a `key=value` line to `properties`, escaping both sides.
+fn write_entry(properties: &mut String, key: &str, value: &str) {
+    for c in key.chars() {
+        match c {
+            ' ' | '=' | ':' | '#' | '!' => {
+                properties.push('\\');
+                properties.push(c);
+            }
+            _ => escape(properties, c),
+        }
+    }
+    properties.push('=');
+    for (index, c) in value.chars().enumerate() {
+        match c {
+            // Leading whitespace would be skipped when reading
+            ' ' if index == 0 => properties.push_str("\\ "),
+            _ => escape(properties, c),
+        }
+    }
+    properties.push('\n');
+}
+
+/// Appends `c` to `properties`, escaped if needed. Non-ASCII characters are written as
+/// `\uXXXX` escapes, as properties files are read as ISO-8859-1 by older JVMs.
+fn escape(properties: &mut String, c: char) {
+    match c {
+        '\\' => properties.push_str("\\\\"),
+        '\t' => properties.push_str("\\t"),
+        '\n' => properties.push_str("\\n"),
+        '\r' => properties.push_str("\\r"),
+        '\x0c' => properties.push_str("\\f"),
+        ' '..='~' => properties.push(c),
+        _ => {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                _ = write!(properties, "\\u{:04X}", unit);
+            }
+        }
+    }
+}
+
+/// Removes the whitespace at the start of a line, as defined by the format.
+fn trim_start(line: &str) -> &str {
+    line.trim_start_matches([' ', '\t', '\x0c'])
+}
+
+/// Splits a logical line into its unescaped key and value.
+///
+/// The key ends at the first unescaped `=`, `:` or whitespace.
+fn split_entry(line: &str) -> Result<(String, String)> {
+    let mut escaped = false;
+    let end = line
+        .char_indices()
+        .find(|(_, c)| {
+            let separator = !escaped && matches!(c, '=' | ':' | ' ' | '\t' | '\x0c');
+            escaped = !escaped && *c == '\\';
+            separator
+        })
+        .map_or(line.len(), |(index, _)| index);
+
+    let rest = trim_start(&line[end..]);
+    let rest = rest.strip_prefix(['=', ':']).map_or(rest, trim_start);
+    Ok((unescape(&line[..end])?, unescape(rest)?))
+}
+
+/// Replaces the escape sequences of `raw` by the characters they stand for.
+fn unescape(raw: &str) -> Result<String> {
+    let mut units = Vec::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('t') => '\t',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('f') => '\x0c',
+                Some('u') => {
+                    let hex = chars.by_ref().take(4).collect::<String>();
+                    let unit = u16::from_str_radix(&hex, 16).map_err(|_| anyhow!("Invalid escape `\\u{}`", hex))?;
+                    units.push(unit);
+                    continue;
+                }
+                Some(c) => c,
+                None => continue,
+            },
+            c => c,
+        };
+        units.extend(c.encode_utf16(&mut [0; 2]).iter());
+    }
+    String::from_utf16(&units).map_err(|_| anyhow!("Invalid UTF-16 escape sequence in `{}`", raw))
+}
+
+/// Segment of a key.
+enum Segment {
+    /// Map key, between dots.
+    Name(String),
+    /// List index, between brackets.
+    Index(usize),
+}
+
+/// Splits a key into its segments, such as `servers`, `0` and `host` for `servers[0].host`.
+fn parse_key(key: &str) -> Result<Vec<Segment>> {
+    let mut path = Vec::new();
+    for part in key.split('.') {
+        let (name, mut indices) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if name.is_empty() {
+            bail!("Invalid key `{}`: empty segment", key);
+        }
+        path.push(Segment::Name(name.to_owned()));
+
+        while !indices.is_empty() {
+            let index = indices
+                .strip_prefix('[')
+                .and_then(|rest| rest.split_once(']'))
+                .and_then(|(index, rest)| Some((index.parse::<usize>().ok()?, rest)));
+            let Some((index, rest)) = index else {
+                bail!("Invalid key `{}`: expected a `[index]`", key);
+            };
+            if index > MAX_INDEX {
+                bail!("Invalid key `{}`: index {} is over {}", key, index, MAX_INDEX);
+            }
+            path.push(Segment::Index(index));
+            indices = rest;
+        }
+    }
+    Ok(path)
+}
+
+/// Inserts `value` at `path` in `node`, creating the maps and lists on the way.
+///
+/// An empty string, written for an empty list or map, is replaced by the list or map.
+fn insert(node: &mut Value, path: &[Segment], value: String) -> Result<()> {
+    let Some((segment, rest)) = path.split_first() else {
+        if node.is_object() || node.is_array() {
+            bail!("it is also the prefix of other keys");
+        }
+        *node = Value::String(value);
+        return Ok(());
+    };
+
+    let empty = node.is_null() || node.as_str() == Some("");
+    match segment {
+        Segment::Name(name) => {
+            if empty {
+                *node = Value::Object(Map::new());
+            }
+            let Value::Object(map) = node else {
+                bail!("`{}` is not a map", name);
+            };
+            insert(map.entry(name.clone()).or_insert(Value::Null), rest, value)
+        }
+        Segment::Index(index) => {
+            if empty {
+                *node = Value::Array(Vec::new());
+            }
+            let Value::Array(values) = node else {
+                bail!("`[{}]` is not in a list", index);
+            };
+            if values.len() <= *index {
+                values.resize(index + 1, Value::Null);
+            }
+            insert(&mut values[*index], rest, value)
+        }
+    }
+}
+
+/// Deserializer parsing the strings of a document into the types they are read as.
+struct Lenient(Value);
+
+impl<'de> IntoDeserializer<'de, serde_json::Error> for Lenient {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Implements the deserialization of scalars parsed from strings.
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            match self.0 {
+                Value::String(value) => match value.trim().parse() {
+                    Ok(parsed) => visitor.$visit(parsed),
+                    Err(_) => Err(de::Error::invalid_value(de::Unexpected::Str(&value), &visitor)),
+                },
+                value => value.$method(visitor),
+            }
+        }
+    )*};
+}
+
+impl<'de> Deserializer<'de> for Lenient {
+    type Error = serde_json::Error;
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::String(value) => visitor.visit_string(value),
+            Value::Array(values) => visit_seq(values.into_iter().map(Lenient), visitor),
+            Value::Object(map) => visit_map(map, visitor),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::String(value) if value.is_empty() => visitor.visit_unit(),
+            value => value.deserialize_unit(visitor),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::String(value) if value.is_empty() => visit_seq(std::iter::empty(), visitor),
+            Value::String(value) => visit_seq(
+                value
+                    .split(',')
+                    .map(|item| Lenient(Value::String(item.trim().to_owned()))),
+                visitor,
+            ),
+            Value::Array(values) => visit_seq(values.into_iter().map(Lenient), visitor),
+            value => value.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::String(value) if value.is_empty() => visit_map(Map::new(), visitor),
+            Value::Object(map) => visit_map(map, visitor),
+            value => value.deserialize_map(visitor),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Value::Object(map) if map.len() == 1 => {
+                let (variant, value) = map.into_iter().next().expect("map has one entry");
+                visitor.visit_enum(Enum(variant, value))
+            }
+            value => value.deserialize_enum(name, variants, visitor),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct identifier
+    }
+}
+
+/// Visits the items of a list.
+fn visit_seq<'de, V: Visitor<'de>>(
+    items: impl Iterator<Item = Lenient>,
+    visitor: V,
+) -> Result<V::Value, serde_json::Error> {
+    let mut deserializer = SeqDeserializer::new(items);
+    let value = visitor.visit_seq(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Visits the entries of a map.
+fn visit_map<'de, V: Visitor<'de>>(map: Map<String, Value>, visitor: V) -> Result<V::Value, serde_json::Error> {
+    let mut deserializer = MapDeserializer::new(map.into_iter().map(|(key, value)| (key, Lenient(value))));
+    let value = visitor.visit_map(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Enum variant written as a map with a single key.
+struct Enum(String, Value);
+
+impl<'de> EnumAccess<'de> for Enum {
+    type Error = serde_json::Error;
+    type Variant = Lenient;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Lenient), Self::Error> {
+        let variant = seed.deserialize(IntoDeserializer::<serde_json::Error>::into_deserializer(self.0))?;
+        Ok((variant, Lenient(self.1)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Lenient {
+    type Error = serde_json::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    enum Mode {
+        #[default]
+        Fast,
+        Limited(u32),
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    struct Config {
+        name: String,
+        enabled: bool,
+        ratio: f64,
+        offset: i64,
+        tags: Vec<String>,
+        ports: Vec<u16>,
+        server: Server,
+        servers: Vec<Server>,
+        labels: BTreeMap<String, String>,
+        nickname: Option<String>,
+        mode: Mode,
+        limit: Mode,
+        letter: char,
+    }
+
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(value: &T) -> T {
+        let text = to_string(&serde_json::to_value(value).unwrap()).unwrap();
+        from_value(parse(&text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn round_trips_nested_values() {
+        let config = Config {
+            name: "example".to_owned(),
+            enabled: true,
+            ratio: 0.25,
+            offset: -3,
+            tags: vec!["a".to_owned(), "b".to_owned()],
+            ports: vec![80, 443],
+            server: Server {
+                host: "localhost".to_owned(),
+                port: 8080,
+            },
+            servers: vec![
+                Server {
+                    host: "a.example.org".to_owned(),
+                    port: 1,
+                },
+                Server {
+                    host: "b.example.org".to_owned(),
+                    port: 2,
+                },
+            ],
+            labels: BTreeMap::from([("team".to_owned(), "core".to_owned())]),
+            nickname: None,
+            mode: Mode::Fast,
+            limit: Mode::Limited(5),
+            letter: 'x',
+        };
+        assert_eq!(round_trip(&config), config);
+    }
+
+    #[test]
+    fn round_trips_empty_lists_and_maps() {
+        let config = Config::default();
+        let text = to_string(&serde_json::to_value(&config).unwrap()).unwrap();
+        assert!(text.contains("tags=\n"));
+        assert!(text.contains("labels=\n"));
+        assert!(!text.contains("nickname"));
+        assert_eq!(round_trip(&config), config);
+    }
+
+    #[test]
+    fn round_trips_escaped_characters() {
+        let name = " leading space, tab\t, newline\n, backslash\\, = : # ! é 🦀";
+        let labels = BTreeMap::from([("key with = and : and space".to_owned(), "value".to_owned())]);
+        let config = Config {
+            name: name.to_owned(),
+            labels,
+            ..Default::default()
+        };
+        let text = to_string(&serde_json::to_value(&config).unwrap()).unwrap();
+        assert!(text.is_ascii());
+        let escaped = "name=\\ leading space, tab\\t, newline\\n, backslash\\\\, = : # ! \\u00E9 \\uD83E\\uDD80\n";
+        assert!(text.contains(escaped));
+        assert!(text.contains("labels.key\\ with\\ \\=\\ and\\ \\:\\ and\\ space=value\n"));
+        assert_eq!(round_trip(&config), config);
+    }
+
+    #[test]
+    fn parses_separators() {
+        let text = "a=1\nb:2\nc 3\nd = 4\ne\t:\t5\nf\ng=\nh==x\n";
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({ "a": "1", "b": "2", "c": "3", "d": "4", "e": "5", "f": "", "g": "", "h": "=x" })
+        );
+    }
+
+    #[test]
+    fn parses_escapes() {
+        let text = "tab=a\\tb\nunicode=\\u00e9\\u00E9\nsurrogates=\\uD83E\\uDD80\n\
+                    other=\\q\\=\\\\\nkey\\ with\\:separators=1\n";
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({
+                "tab": "a\tb",
+                "unicode": "éé",
+                "surrogates": "🦀",
+                "other": "q=\\",
+                "key with:separators": "1",
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_escapes() {
+        assert!(parse("a=\\u12G4\n").is_err());
+        assert!(parse("a=\\uD83E\n").is_err());
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let text = "# comment\n! comment\n\n   \n  key = value\n";
+        assert_eq!(parse(text).unwrap(), json!({ "key": "value" }));
+    }
+
+    #[test]
+    fn joins_continued_lines() {
+        let text = "list=a,\\\n    b,\\\n    c\nescaped=ends with a backslash\\\\\nnext=1\n";
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({ "list": "a,b,c", "escaped": "ends with a backslash\\", "next": "1" })
+        );
+        // A continuation on the last line ends the value
+        assert_eq!(parse("key=value\\").unwrap(), json!({ "key": "value" }));
+    }
+
+    #[test]
+    fn nests_dotted_and_indexed_keys() {
+        let text = "server.host=localhost\nserver.port=8080\nservers[1].host=b\nservers[0].host=a\nmatrix[0][1]=x\n";
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({
+                "server": { "host": "localhost", "port": "8080" },
+                "servers": [{ "host": "a" }, { "host": "b" }],
+                "matrix": [[null, "x"]],
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        assert!(parse("a..b=1\n").is_err());
+        assert!(parse(".a=1\n").is_err());
+        assert!(parse("a[x]=1\n").is_err());
+        assert!(parse("a[1=1\n").is_err());
+        assert!(parse(&format!("a[{}]=1\n", MAX_INDEX + 1)).is_err());
+        // A key can't hold both a value and nested keys
+        assert!(parse("a.b=1\na=2\n").is_err());
+        assert!(parse("a=1\na.b=2\n").is_err());
+    }
+
+    #[test]
+    fn rejects_documents_other_than_maps() {
+        assert!(to_string(&json!([1, 2])).is_err());
+        assert!(to_string(&json!("text")).is_err());
+    }
+
+    #[test]
+    fn parses_strings_into_field_types() {
+        let text = "name=n\nenabled= true \nratio=1.5\noffset=-7\ntags=a, b ,c\nports=1,2\n\
+                    server.host=h\nserver.port=9\nlimit.Limited=3\nmode=Fast\nletter=z\n";
+        let config: Config = from_value(parse(text).unwrap()).unwrap();
+        assert_eq!(config.name, "n");
+        assert!(config.enabled);
+        assert_eq!(config.ratio, 1.5);
+        assert_eq!(config.offset, -7);
+        assert_eq!(config.tags, ["a", "b", "c"]);
+        assert_eq!(config.ports, [1, 2]);
+        assert_eq!(config.server.port, 9);
+        assert_eq!(config.limit, Mode::Limited(3));
+        assert_eq!(config.mode, Mode::Fast);
+        assert_eq!(config.letter, 'z');
+    }
+
+    #[test]
+    fn rejects_unparsable_values() {
+        assert!(from_value::<Server>(parse("host=h\nport=eighty\n").unwrap()).is_err());
+        assert!(from_value::<Server>(parse("host=h\nport=70000\n").unwrap()).is_err());
+        assert!(from_value::<Config>(parse("enabled=yes\n").unwrap()).is_err());
+    }
+}
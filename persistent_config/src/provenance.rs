@@ -0,0 +1,54 @@
+//! Comment header stamped at the top of the saved config files, with the `provenance`
+//! parameter.
+
+use std::time::SystemTime;
+
+use persistent_config_core::{PersistentConfigParameters, SaveFormat};
+
+use crate::envelope;
+
+/// Returns the comment header of the config file saved with `params`, or `None` if there is
+/// none or the save format has no comments.
+pub(crate) fn header(params: &PersistentConfigParameters) -> Option<String> {
+    let options = params.provenance.as_ref()?;
+    if !matches!(
+        params.save_format,
+        SaveFormat::TOML | SaveFormat::YAML | SaveFormat::Properties
+    ) {
+        return None;
+    }
+
+    let mut lines = vec![format!("Generated by {}", options.generator)];
+    if options.hostname {
+        lines.push(format!("Host: {}", hostname()));
+    }
+    if options.timestamp {
+        lines.push(format!("Saved at: {}", envelope::format_timestamp(SystemTime::now())));
+    }
+    lines.push("Machine-generated file, edits may be overwritten.".to_owned());
+
+    // A line break in the generator would end the comment
+    let mut header = String::new();
+    for line in lines.iter().flat_map(|line| line.lines()) {
+        header.push_str("# ");
+        header.push_str(line);
+        header.push('\n');
+    }
+    Some(header)
+}
+
+/// Returns the name of the host, or `unknown` if it can't be found.
+pub(crate) fn hostname() -> String {
+    #[cfg(unix)]
+    if let Ok(hostname) = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .map(|hostname| hostname.trim().to_owned())
+        && !hostname.is_empty()
+    {
+        return hostname;
+    }
+    ["COMPUTERNAME", "HOSTNAME"]
+        .into_iter()
+        .find_map(|name| std::env::var(name).ok().filter(|hostname| !hostname.is_empty()))
+        .unwrap_or_else(|| "unknown".to_owned())
+}
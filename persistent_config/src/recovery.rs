@@ -0,0 +1,231 @@
+//! Recovery from config files that can't be parsed, with the `quarantine_corrupt` parameter,
+//! and from files holding invalid values, with the `repair_invalid` parameter.
+//!
+//! The corrupt file is moved aside to `<file name>.corrupt-<timestamp>`, so the user can
+//! still fix it by hand, and a default config is saved in its place.
+//!
+//! A file that parses but holds values of the wrong type is repaired instead: its values are
+//! kept one by one while the config still deserializes, and the others take their default
+//! value. The file itself is left as it is until the next save.
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use persistent_config_core::{PersistentConfigError, PersistentConfigParameters, SaveFormat};
+use serde_json::{Map, Value};
+
+use crate::{PersistentConfigBuilder, config_file_path, from_document, prepare_save, save_config};
+
+thread_local! {
+    /// Paths of the values dropped by the last repair made on the thread.
+    static DROPPED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// How [`load_with_outcome`](crate::PersistentConfig::load_with_outcome) got the config.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+/// # #[persistent(config_dir = "/tmp/persistent_config_doc_repair", namespace = "")]
+/// #[persistent(repair_invalid = true)]
+/// struct Settings {
+///     theme: String,
+///     volume: u8,
+/// }
+///
+/// # fn main() -> anyhow::Result<()> {
+/// # std::fs::create_dir_all("/tmp/persistent_config_doc_repair")?;
+/// # std::fs::write("/tmp/persistent_config_doc_repair/Settings.toml", "theme = \"dark\"\nvolume = 300")?;
+/// let mut settings = Settings::default();
+/// let outcome = settings.load_with_outcome()?;
+/// assert_eq!(outcome, LoadOutcome::Repaired { dropped_fields: vec!["volume".to_owned()] });
+/// assert_eq!(settings.theme, "dark");
+/// assert_eq!(settings.volume, 0);
+/// # std::fs::remove_dir_all("/tmp/persistent_config_doc_repair")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LoadOutcome {
+    /// The config was loaded from its file, or from the first run hooks if there is none.
+    Loaded,
+    /// The config file holds values that don't deserialize, which were replaced by their
+    /// default value, as `repair_invalid` is set. The file is left as it is.
+    Repaired {
+        /// Paths of the dropped values, such as `server.port`, with the on-disk keys.
+        dropped_fields: Vec<String>,
+    },
+    /// The config file couldn't be parsed: it was moved to `quarantined_path`, and the default
+    /// config saved in its place.
+    Recovered {
+        /// New path of the corrupt file.
+        quarantined_path: PathBuf,
+        /// Why the file couldn't be parsed.
+        error: String,
+    },
+    /// The config couldn't be loaded, and the default value is used as `panic_on_error` is
+    /// unset. The config file is left as it is.
+    Defaulted {
+        /// Why the config couldn't be loaded.
+        error: String,
+    },
+}
+
+/// Returns `true` if `error` reports a config file that was read but couldn't be parsed.
+///
+/// IO errors and the errors of the crate, such as a file over `max_file_size`, don't make a
+/// file corrupt, nor does a format the crate can't save the default config in.
+pub(crate) fn is_corrupt(params: &PersistentConfigParameters, error: &anyhow::Error) -> bool {
+    // HCL files can't be saved, so the default config couldn't replace them
+    let supported =
+        params.save_format != SaveFormat::HCL && (params.save_format != SaveFormat::CBOR || cfg!(feature = "cbor"));
+    supported && is_parse_error(error)
+}
+
+/// Returns `true` if `error` reports a document that was read but couldn't be parsed or
+/// deserialized, rather than an IO error or an error of the crate.
+pub(crate) fn is_parse_error(error: &anyhow::Error) -> bool {
+    !error
+        .chain()
+        .any(|cause| cause.is::<std::io::Error>() || cause.is::<PersistentConfigError>())
+}
+
+/// Moves the corrupt config file of `params` aside, and saves the default value of `T` in
+/// its place.
+///
+/// Returns the default value and the outcome reporting the new path of the file.
+pub(crate) fn recover<T: PersistentConfigBuilder>(
+    params: &PersistentConfigParameters,
+    error: anyhow::Error,
+) -> Result<(T, LoadOutcome)> {
+    let quarantined_path = quarantine(&config_file_path(params))?;
+    eprintln!("Error loading file: {:?}", error);
+    eprintln!(
+        "Corrupt config file moved to {:?}, Saving default configuration",
+        quarantined_path
+    );
+
+    let content = T::default();
+    save_config(params, &prepare_save(&content)?).context("Failed to save the default configuration")?;
+    let outcome = LoadOutcome::Recovered {
+        quarantined_path,
+        error: format!("{:#}", error),
+    };
+    Ok((content, outcome))
+}
+
+/// Deserializes `T` from `document`, which failed to deserialize as a whole with `error`,
+/// keeping the values that deserialize and the default values of the others.
+///
+/// The values of the file are tried one at a time on top of the default value of `T`, and
+/// the maps are repaired key by key, so a broken value only drops itself. The paths of the
+/// dropped values are kept for [`take_dropped`]. Returns `error` if `document` is not a map,
+/// or if the default value itself doesn't deserialize.
+pub(crate) fn repair<T: PersistentConfigBuilder>(
+    document: Value,
+    save_format: SaveFormat,
+    error: anyhow::Error,
+) -> Result<T> {
+    let Value::Object(values) = document else {
+        return Err(error);
+    };
+    let Ok(mut repaired) = serde_json::to_value(T::default()) else {
+        return Err(error);
+    };
+    if !repaired.is_object() || from_document::<T>(repaired.clone(), save_format).is_err() {
+        return Err(error);
+    }
+
+    let mut dropped: Vec<Vec<String>> = Vec::new();
+    repair_map::<T>(&mut repaired, "", &mut Vec::new(), values, save_format, &mut dropped);
+    let content = from_document(repaired, save_format)?;
+
+    // Report the top level keys under their on-disk name
+    let renames = T::field_renames();
+    let dropped: Vec<String> = dropped
+        .into_iter()
+        .map(|mut path| {
+            let Some(name) = path.first_mut() else {
+                return String::new();
+            };
+            if let Some((_, key)) = renames.iter().find(|(field, _)| field == name) {
+                *name = (*key).to_owned();
+            }
+            path.join(".")
+        })
+        .collect();
+    eprintln!("Invalid values replaced by their default value: {}", dropped.join(", "));
+    DROPPED.set(Some(dropped));
+    Ok(content)
+}
+
+/// Returns the paths of the values dropped by the last repair made on the thread, if any,
+/// and forgets them.
+pub(crate) fn take_dropped() -> Option<Vec<String>> {
+    DROPPED.take()
+}
+
+/// Moves the `values` of the map at `pointer` in the file into the same map of `repaired`,
+/// keeping those with which `T` still deserializes, and adds the paths of the others to
+/// `dropped`.
+fn repair_map<T: PersistentConfigBuilder>(
+    repaired: &mut Value,
+    pointer: &str,
+    path: &mut Vec<String>,
+    values: Map<String, Value>,
+    save_format: SaveFormat,
+    dropped: &mut Vec<Vec<String>>,
+) {
+    for (key, value) in values {
+        let Some(Value::Object(map)) = repaired.pointer_mut(pointer) else {
+            return;
+        };
+        let previous = map.insert(key.clone(), value.clone());
+        if from_document::<T>(repaired.clone(), save_format).is_ok() {
+            continue;
+        }
+
+        let Some(Value::Object(map)) = repaired.pointer_mut(pointer) else {
+            return;
+        };
+        path.push(key.clone());
+        match (previous, value) {
+            // A map holding a broken value keeps its other values
+            (Some(previous @ Value::Object(_)), Value::Object(values)) => {
+                map.insert(key.clone(), previous);
+                let pointer = format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"));
+                repair_map::<T>(repaired, &pointer, path, values, save_format, dropped);
+            }
+            (Some(previous), _) => {
+                map.insert(key, previous);
+                dropped.push(path.clone());
+            }
+            (None, _) => {
+                map.remove(&key);
+                dropped.push(path.clone());
+            }
+        }
+        path.pop();
+    }
+}
+
+/// Moves the file at `file_path` to `<file name>.corrupt-<timestamp>`, the timestamp being in
+/// seconds since the Unix epoch, and returns its new path.
+fn quarantine(file_path: &Path) -> Result<PathBuf> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut quarantined_path = file_path.as_os_str().to_owned();
+    quarantined_path.push(format!(".corrupt-{}", timestamp));
+    let quarantined_path = PathBuf::from(quarantined_path);
+    std::fs::rename(file_path, &quarantined_path)
+        .with_context(|| format!("Failed to move the corrupt config file {:?} aside", file_path))?;
+    Ok(quarantined_path)
+}
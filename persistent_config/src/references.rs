@@ -0,0 +1,211 @@
+//! References to the values of other configs, `${name.field}`, resolved when loading.
+//!
+//! A type exposed under a name with
+//! [`expose_as`](crate::PersistentConfigBuilder::expose_as) can be referenced by the string
+//! values of the other configs. A value made of a single reference takes the referenced value, with
+//! its type, while references within a longer string are replaced by the referenced value:
+//!
+//! ```toml
+//! location = "${paths.data_dir}/cache"
+//! max_size = "${limits.cache_size}"
+//! ```
+//!
+//! Saving writes the references back as long as the values they resolve to are unchanged.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, PoisonError, RwLock};
+
+use anyhow::{Result, anyhow, bail};
+use serde_json::Value;
+
+/// Loads an exposed config and returns its serialized value.
+pub(crate) type ResolveFn = Arc<dyn Fn() -> Result<Value> + Send + Sync>;
+
+/// Exposed configs, by name.
+static EXPOSED: LazyLock<RwLock<HashMap<String, ResolveFn>>> = LazyLock::new(RwLock::default);
+
+thread_local! {
+    /// Names of the configs being loaded to resolve a reference, to detect cycles.
+    static RESOLVING: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Exposes the config loaded by `resolve` under `name`, replacing any config exposed under
+/// the same name.
+pub(crate) fn expose(name: String, resolve: ResolveFn) {
+    EXPOSED.write().unwrap_or_else(PoisonError::into_inner).insert(name, resolve);
+}
+
+/// Returns `true` if no config is exposed, so there are no references to resolve.
+pub(crate) fn is_empty() -> bool {
+    EXPOSED.read().unwrap_or_else(PoisonError::into_inner).is_empty()
+}
+
+/// Replaces the references of the string values of `document` by the referenced values.
+pub(crate) fn resolve(document: Value) -> Result<Value> {
+    Ok(match document {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| Ok((key, resolve(value)?)))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(resolve).collect::<Result<_>>()?),
+        Value::String(text) => resolve_string(text)?,
+        value => value,
+    })
+}
+
+/// Puts back the references of `previous` whose value is unchanged in `document`.
+///
+/// This way saving a loaded config never writes the values of the other configs in its file.
+pub(crate) fn keep(document: &mut Value, previous: &Value) -> Result<()> {
+    match (document, previous) {
+        (Value::Object(map), Value::Object(previous)) => {
+            for (key, value) in map {
+                if let Some(previous) = previous.get(key) {
+                    keep(value, previous)?;
+                }
+            }
+        }
+        (Value::Array(items), Value::Array(previous)) => {
+            for (value, previous) in items.iter_mut().zip(previous) {
+                keep(value, previous)?;
+            }
+        }
+        (value, Value::String(reference)) if *value != *previous && references(reference) => {
+            let unchanged = resolve_string(reference.clone())? == *value;
+            if unchanged {
+                *value = previous.clone();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns `true` if `text` holds a reference to an exposed config.
+fn references(text: &str) -> bool {
+    let exposed = EXPOSED.read().unwrap_or_else(PoisonError::into_inner);
+    split(text).any(|part| matches!(part, Part::Reference { name, .. } if exposed.contains_key(name)))
+}
+
+/// Replaces the references of `text` by the referenced values.
+///
+/// References to names that are not exposed are left as they are, so the `${VAR}` read by
+/// [`ExpandablePath`](crate::ExpandablePath) keep working.
+fn resolve_string(text: String) -> Result<Value> {
+    if !text.contains("${") {
+        return Ok(Value::String(text));
+    }
+
+    let mut parts: Vec<Value> = Vec::new();
+    for part in split(&text) {
+        match part {
+            Part::Reference { name, path, raw } => match lookup(name, path)? {
+                Some(value) => parts.push(value),
+                None => parts.push(Value::String(raw.to_owned())),
+            },
+            Part::Text(text) => parts.push(Value::String(text.to_owned())),
+        }
+    }
+    if let [value] = parts.as_slice() {
+        return Ok(value.clone());
+    }
+
+    let mut resolved = String::new();
+    for part in parts {
+        match part {
+            Value::String(text) => resolved.push_str(&text),
+            Value::Object(_) | Value::Array(_) => {
+                bail!("{:?} references a map or a list within a string", text)
+            }
+            value => resolved.push_str(&value.to_string()),
+        }
+    }
+    Ok(Value::String(resolved))
+}
+
+/// Returns the value at `path` of the config exposed as `name`, or `None` if no config is
+/// exposed under that name.
+fn lookup(name: &str, path: &str) -> Result<Option<Value>> {
+    let Some(resolve) = EXPOSED.read().unwrap_or_else(PoisonError::into_inner).get(name).cloned() else {
+        return Ok(None);
+    };
+
+    let cycle = RESOLVING.with_borrow_mut(|resolving| {
+        let cycle = resolving.iter().any(|resolved| resolved == name);
+        if !cycle {
+            resolving.push(name.to_owned());
+        }
+        cycle
+    });
+    if cycle {
+        bail!("Reference cycle: `{}` is already being loaded", name);
+    }
+    let config = resolve();
+    RESOLVING.with_borrow_mut(|resolving| resolving.pop());
+    let config = config.map_err(|e| anyhow!("Failed to load `{}` to resolve a reference: {:#}", name, e))?;
+
+    let mut value = &config;
+    for key in path.split('.') {
+        value = match value {
+            Value::Object(map) => map.get(key),
+            Value::Array(items) => key.parse().ok().and_then(|index: usize| items.get(index)),
+            _ => None,
+        }
+        .ok_or_else(|| anyhow!("The reference `${{{}.{}}}` matches no value", name, path))?;
+    }
+    Ok(Some(value.clone()))
+}
+
+/// Piece of a string value, see [`split`].
+enum Part<'a> {
+    /// Text around the references.
+    Text(&'a str),
+    /// A `${name.path}` reference.
+    Reference {
+        /// Name of the referenced config.
+        name: &'a str,
+        /// Path of the value in the referenced config, keys separated by dots.
+        path: &'a str,
+        /// The reference as written.
+        raw: &'a str,
+    },
+}
+
+/// Splits `text` into the text and the `${name.path}` references it is made of.
+///
+/// A `${...}` without a dot, or without its closing brace, is text.
+fn split(text: &str) -> impl Iterator<Item = Part<'_>> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let reference = rest.find("${").and_then(|start| {
+            let end = start + rest[start..].find('}')?;
+            let (name, path) = rest[start + 2..end].split_once('.')?;
+            Some((start, end, name, path))
+        });
+        let part = match reference {
+            Some((0, end, name, path)) => {
+                let raw = &rest[..=end];
+                rest = &rest[end + 1..];
+                Part::Reference { name, path, raw }
+            }
+            Some((start, ..)) => {
+                let text = &rest[..start];
+                rest = &rest[start..];
+                Part::Text(text)
+            }
+            None => {
+                // Past the `${` that is not a reference, if any
+                let end = rest.find("${").map_or(rest.len(), |start| start + 2);
+                let text = &rest[..end];
+                rest = &rest[end..];
+                Part::Text(text)
+            }
+        };
+        Some(part)
+    })
+}
@@ -0,0 +1,163 @@
+//! Optional handle of a registered config type.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use anyhow::Result;
+use persistent_config_core::PersistentConfigParameters;
+
+use crate::{PersistentConfig, registered_params};
+
+/// Handle of the registered config type `T`, returned by
+/// [`register`](crate::PersistentConfigBuilder::register) and
+/// [`registered`](crate::PersistentConfigBuilder::registered).
+///
+/// The handle is an optional helper: a function taking a `Registered<T>` documents in its
+/// signature that it expects `T` to be registered by its caller. It doesn't make an
+/// unregistered use a compile error, [`load`](PersistentConfig::load) and
+/// [`save`](PersistentConfig::save) can still be called on any config, and loading or saving
+/// through the handle reports the same errors as they do.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct MyConfig { volume: u8 }
+/// # impl PersistentConfigBuilder for MyConfig {}
+/// # fn main() -> anyhow::Result<()> {
+/// # let dir = std::env::temp_dir().join("persistent_config_doc_registered");
+/// let config = MyConfig::register(PersistentConfigParameters {
+///     config_dir: dir.to_string_lossy().into_owned(),
+///     ..Default::default()
+/// })?;
+/// set_volume(config, 7)?;
+/// assert_eq!(config.load()?.volume, 7);
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+///
+/// // Expects `MyConfig` to be registered by the caller
+/// fn set_volume(config: Registered<MyConfig>, volume: u8) -> anyhow::Result<()> {
+///     config.save(&MyConfig { volume })
+/// }
+/// ```
+pub struct Registered<T> {
+    /// The handle is `Send`, `Sync` and `Copy` whatever `T` is.
+    config_type: PhantomData<fn() -> T>,
+}
+
+impl<T> Registered<T> {
+    /// Returns the handle of `T`, which must be registered.
+    pub(crate) fn new() -> Self {
+        Self {
+            config_type: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for Registered<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Registered<T> {}
+
+impl<T> fmt::Debug for Registered<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Registered").field(&std::any::type_name::<T>()).finish()
+    }
+}
+
+impl<T: PersistentConfig> Registered<T> {
+    /// Returns the parameters currently registered for `T`, which
+    /// [`replace_config`](persistent_config_core::PersistentConfigDB::replace_config) may have
+    /// changed since the handle was returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PersistentConfigError::NotRegistered`] if `T` is no longer registered.
+    ///
+    /// [`PersistentConfigError::NotRegistered`]: persistent_config_core::PersistentConfigError::NotRegistered
+    pub fn parameters(&self) -> Result<PersistentConfigParameters> {
+        registered_params::<T>()
+    }
+
+    /// Returns the config loaded from persistent storage, see
+    /// [`load`](PersistentConfig::load).
+    #[track_caller]
+    pub fn load(&self) -> Result<T> {
+        let mut value = T::default();
+        value.load()?;
+        Ok(value)
+    }
+
+    /// Loads the config from persistent storage into `value`, see
+    /// [`load`](PersistentConfig::load).
+    #[track_caller]
+    pub fn load_into(&self, value: &mut T) -> Result<()> {
+        value.load()
+    }
+
+    /// Saves `value` to persistent storage, see [`save`](PersistentConfig::save).
+    #[track_caller]
+    pub fn save(&self, value: &T) -> Result<()> {
+        value.save()
+    }
+
+    /// Loads the config, applies `f` to it and saves it, holding the lock of the file, see
+    /// [`update`](PersistentConfig::update).
+    #[track_caller]
+    pub fn update<F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut T),
+    {
+        T::update(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::PersistentConfigBuilder;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Volume {
+        level: u8,
+    }
+
+    impl PersistentConfigBuilder for Volume {}
+
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    struct Unregistered;
+
+    impl PersistentConfigBuilder for Unregistered {}
+
+    #[test]
+    fn saves_and_loads_through_the_handle() -> Result<()> {
+        let dir = std::env::temp_dir().join("persistent_config_test_registered");
+        let config = Volume::register(PersistentConfigParameters {
+            config_dir: dir.to_string_lossy().into_owned(),
+            ..Default::default()
+        })?;
+        assert_eq!(config.parameters()?.config_dir, dir.to_string_lossy());
+
+        config.save(&Volume { level: 4 })?;
+        assert_eq!(config.load()?, Volume { level: 4 });
+        assert_eq!(config.update(|volume| volume.level += 1)?, Volume { level: 5 });
+        assert_eq!(config.load()?, Volume { level: 5 });
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn parameters_report_an_unregistered_type() {
+        let config = Registered::<Unregistered>::new();
+        let error = config.parameters().unwrap_err();
+        assert!(error.to_string().contains("Unregistered"), "{error}");
+    }
+}
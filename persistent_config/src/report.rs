@@ -0,0 +1,31 @@
+//! Outcome of a save, returned by [`save_report`](crate::PersistentConfig::save_report).
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// What a save wrote, so the caller can log it or adapt how often it saves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveReport {
+    /// Path of the config file.
+    pub path: PathBuf,
+    /// Number of bytes written to the config file, `0` if the save was skipped or failed.
+    pub bytes: u64,
+    /// Time taken by the save, from the serialization of the config to the rename of the
+    /// written file.
+    pub duration: Duration,
+    /// Whether the config file was deliberately left unchanged: the save was deferred by
+    /// `min_save_interval`, or replaced the value of [`testing::with_override`].
+    ///
+    /// [`testing::with_override`]: crate::testing::with_override
+    pub skipped: bool,
+    /// Why the save failed, when `panic_on_error` is set: the error is then logged and
+    /// reported here instead of being returned.
+    pub error: Option<String>,
+}
+
+impl SaveReport {
+    /// Returns whether the config file was written.
+    pub fn is_written(&self) -> bool {
+        !self.skipped && self.error.is_none()
+    }
+}
@@ -0,0 +1,228 @@
+//! Secrets referenced by the config file as `scheme://path#key`, resolved when loading.
+//!
+//! A string value made of a single URI whose scheme has a resolver, set with
+//! [`set_secret_resolver`](crate::set_secret_resolver), is replaced by the secret the
+//! resolver returns:
+//!
+//! ```toml
+//! password = "vault://secret/my_app/database#password"
+//! ```
+//!
+//! Saving writes the URIs back as long as the secrets they resolve to are unchanged, so the
+//! secrets never land in the config file. URIs of schemes without a resolver, such as
+//! `https://`, are left as they are.
+//!
+//! While offline, set with [`set_offline`](crate::set_offline), the resolvers are not called:
+//! the secrets resolved before are reused, and the other references fail the load.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError, RwLock};
+
+use anyhow::{Result, anyhow, bail};
+use serde_json::Value;
+
+/// Source of the secrets referenced by the config files, see
+/// [`set_secret_resolver`](crate::set_secret_resolver).
+pub trait SecretResolver: Send + Sync {
+    /// Returns the secret at `path`, or the value of `key` in it if the reference has a
+    /// `#key` fragment.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the secret doesn't exist or can't be fetched, the load then fails.
+    fn resolve(&self, path: &str, key: Option<&str>) -> Result<String>;
+}
+
+/// Resolvers, by scheme.
+static RESOLVERS: LazyLock<RwLock<HashMap<String, Arc<dyn SecretResolver>>>> = LazyLock::new(RwLock::default);
+
+/// Secrets resolved so far, by reference, so saving doesn't fetch them again.
+static RESOLVED: LazyLock<Mutex<HashMap<String, String>>> = LazyLock::new(Mutex::default);
+
+/// Whether the resolvers must not be called, see [`set_offline`](crate::set_offline).
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Serializes the tests setting resolvers, as it clears the secrets resolved by the others.
+#[cfg(test)]
+pub(crate) static TEST_SERIAL: Mutex<()> = Mutex::new(());
+
+/// Sets whether the resolvers must not be called.
+pub(crate) fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// Returns `true` if the resolvers must not be called.
+pub(crate) fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Sets the resolver of the references of `scheme`, replacing any previous one.
+pub(crate) fn set_resolver(scheme: String, resolver: Arc<dyn SecretResolver>) {
+    RESOLVERS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(scheme, resolver);
+    RESOLVED.lock().unwrap_or_else(PoisonError::into_inner).clear();
+}
+
+/// Returns `true` if no resolver is set, so there are no references to resolve.
+pub(crate) fn is_empty() -> bool {
+    RESOLVERS.read().unwrap_or_else(PoisonError::into_inner).is_empty()
+}
+
+/// Replaces the secret references of the string values of `document` by the secrets.
+///
+/// The secrets are pushed to `resolved` as well, to be scrubbed from the error messages.
+pub(crate) fn resolve(document: Value, resolved: &mut Vec<String>) -> Result<Value> {
+    Ok(match document {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| Ok((key, resolve(value, resolved)?)))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|value| resolve(value, resolved))
+                .collect::<Result<_>>()?,
+        ),
+        Value::String(text) => match fetch(&text, false)? {
+            Some(secret) => {
+                resolved.push(secret.clone());
+                Value::String(secret)
+            }
+            None => Value::String(text),
+        },
+        value => value,
+    })
+}
+
+/// Puts back the secret references of `previous` whose secret is unchanged in `document`.
+pub(crate) fn keep(document: &mut Value, previous: &Value) -> Result<()> {
+    match (document, previous) {
+        (Value::Object(map), Value::Object(previous)) => {
+            for (key, value) in map {
+                if let Some(previous) = previous.get(key) {
+                    keep(value, previous)?;
+                }
+            }
+        }
+        (Value::Array(items), Value::Array(previous)) => {
+            for (value, previous) in items.iter_mut().zip(previous) {
+                keep(value, previous)?;
+            }
+        }
+        (Value::String(value), Value::String(reference)) if value != reference => {
+            let unchanged = fetch(reference, true)?.is_some_and(|secret| secret == *value);
+            if unchanged {
+                value.clone_from(reference);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns the secret referenced by `text`, or `None` if it is not a reference of a scheme
+/// with a resolver.
+///
+/// With `cached`, or while offline, a secret resolved before is returned without asking the
+/// resolver again.
+fn fetch(text: &str, cached: bool) -> Result<Option<String>> {
+    let Some((scheme, rest)) = text.split_once("://") else {
+        return Ok(None);
+    };
+    let Some(resolver) = RESOLVERS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(scheme)
+        .cloned()
+    else {
+        return Ok(None);
+    };
+    let offline = is_offline();
+    if (cached || offline)
+        && let Some(secret) = RESOLVED.lock().unwrap_or_else(PoisonError::into_inner).get(text)
+    {
+        return Ok(Some(secret.clone()));
+    }
+    if offline {
+        bail!("Failed to resolve the secret `{}`: offline, and it was not resolved before", text);
+    }
+
+    let (path, key) = match rest.split_once('#') {
+        Some((path, key)) => (path, Some(key)),
+        None => (rest, None),
+    };
+    let secret = resolver
+        .resolve(path, key)
+        .map_err(|e| anyhow!("Failed to resolve the secret `{}`: {:#}", text, e))?;
+    RESOLVED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(text.to_owned(), secret.clone());
+    Ok(Some(secret))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Resolver counting its calls, returning the path of the secret reversed.
+    struct Counting(Arc<AtomicUsize>);
+
+    impl SecretResolver for Counting {
+        fn resolve(&self, path: &str, _key: Option<&str>) -> Result<String> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(path.chars().rev().collect())
+        }
+    }
+
+    #[test]
+    fn offline_reuses_the_resolved_secrets() -> Result<()> {
+        let _serial = TEST_SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        let calls = Arc::new(AtomicUsize::new(0));
+        set_resolver("test-offline".to_owned(), Arc::new(Counting(calls.clone())));
+
+        let mut resolved = Vec::new();
+        let document = json!({ "a": "test-offline://abc", "b": "test-offline://xyz" });
+        assert_eq!(resolve(json!({ "a": "test-offline://abc" }), &mut resolved)?, json!({ "a": "cba" }));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        set_offline(true);
+        let offline = resolve(json!({ "a": "test-offline://abc" }), &mut resolved);
+        let never_resolved = resolve(document, &mut resolved);
+        set_offline(false);
+
+        assert_eq!(offline?, json!({ "a": "cba" }));
+        let error = never_resolved.unwrap_err();
+        assert!(error.to_string().contains("offline"), "{error}");
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_the_references_of_unchanged_secrets() -> Result<()> {
+        let _serial = TEST_SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        let calls = Arc::new(AtomicUsize::new(0));
+        set_resolver("test-keep".to_owned(), Arc::new(Counting(calls.clone())));
+
+        let previous = json!({ "a": "test-keep://abc", "b": "test-keep://xyz", "c": "https://example.com" });
+        let mut resolved = Vec::new();
+        let mut document = resolve(previous.clone(), &mut resolved)?;
+        assert_eq!(document, json!({ "a": "cba", "b": "zyx", "c": "https://example.com" }));
+        assert_eq!(resolved, ["cba", "zyx"]);
+
+        document["b"] = json!("changed");
+        keep(&mut document, &previous)?;
+        assert_eq!(document, json!({ "a": "test-keep://abc", "b": "changed", "c": "https://example.com" }));
+        // The secrets are cached, saving doesn't resolve them again
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+//! Serializers configured with the [`SerializerOptions`] of the parameters.
+
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use persistent_config_core::{LineEnding, SaveFormat, SerializerOptions};
+use serde::{Deserialize, Serialize};
+use serde_json::ser::{CompactFormatter, PrettyFormatter};
+use toml_edit::{DocumentMut, Item, Table};
+
+/// Largest buffer kept by [`with_buffer`] between saves, 16 MiB.
+#[cfg(not(feature = "zeroize"))]
+const MAX_RETAINED_BUFFER: usize = 16 * 1024 * 1024;
+
+/// Calls `f` with an empty buffer, reused by the saves of the current thread.
+///
+/// A config saved every few seconds is serialized again and again: keeping the buffer avoids
+/// growing a new one to the size of the file on every save. A buffer larger than
+/// [`MAX_RETAINED_BUFFER`] is freed after use. Not used with the `zeroize` feature, as the
+/// buffer would keep the content of the config once the save is over.
+#[cfg(not(feature = "zeroize"))]
+pub(crate) fn with_buffer<R>(f: impl FnOnce(&mut Vec<u8>) -> Result<R>) -> Result<R> {
+    use std::cell::RefCell;
+
+    thread_local! {
+        static BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    }
+    BUFFER.with(|buffer| {
+        // A save made while serializing, from a `Serialize` impl, gets a buffer of its own
+        let Ok(mut buffer) = buffer.try_borrow_mut() else {
+            return f(&mut Vec::new());
+        };
+        buffer.clear();
+        let result = f(&mut buffer);
+        buffer.clear();
+        if buffer.capacity() > MAX_RETAINED_BUFFER {
+            *buffer = Vec::new();
+        }
+        result
+    })
+}
+
+/// UTF-8 byte order mark, see [`SerializerOptions::bom`].
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Returns `content` without its leading byte order mark, if any.
+pub(crate) fn strip_bom(content: &[u8]) -> &[u8] {
+    content.strip_prefix(BOM).unwrap_or(content)
+}
+
+/// Consumes the byte order mark at the start of `reader`, if any.
+#[cfg(not(feature = "zeroize"))]
+pub(crate) fn skip_bom(reader: &mut impl BufRead) -> io::Result<()> {
+    if reader.fill_buf()?.starts_with(BOM) {
+        reader.consume(BOM.len());
+    }
+    Ok(())
+}
+
+/// Writer applying the line ending and byte order mark of the [`SerializerOptions`] to the
+/// text formats written through it.
+///
+/// The serializers write `\n` line endings, each is turned into `\r\n` if needed. A `\n`
+/// already preceded by `\r` is kept as it is.
+pub(crate) struct TextWriter<W: Write> {
+    /// Writer of the file.
+    inner: W,
+    /// Whether `\n` is written as `\r\n`.
+    crlf: bool,
+    /// Whether the byte order mark is still to be written.
+    bom: bool,
+    /// Whether the last byte written is `\r`.
+    after_cr: bool,
+}
+
+impl<W: Write> TextWriter<W> {
+    /// Wraps `inner`, writing the config file at `file_path` in `save_format`.
+    ///
+    /// Binary formats are written as they are.
+    pub(crate) fn new(inner: W, options: &SerializerOptions, save_format: SaveFormat, file_path: &Path) -> Self {
+        let text = save_format != SaveFormat::CBOR;
+        let crlf = text
+            && match options.line_ending {
+                LineEnding::Lf => false,
+                LineEnding::CrLf => true,
+                LineEnding::Preserve => ends_lines_with_crlf(file_path),
+            };
+        Self {
+            inner,
+            crlf,
+            bom: text && options.bom,
+            after_cr: false,
+        }
+    }
+}
+
+impl<W: Write> Write for TextWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.bom {
+            self.inner.write_all(BOM)?;
+            self.bom = false;
+        }
+        if !self.crlf {
+            return self.inner.write(buf);
+        }
+
+        let mut rest = buf;
+        while let Some(end) = rest.iter().position(|byte| *byte == b'\n') {
+            let preceded_by_cr = if end == 0 { self.after_cr } else { rest[end - 1] == b'\r' };
+            self.inner.write_all(&rest[..end])?;
+            self.inner.write_all(if preceded_by_cr { b"\n" } else { b"\r\n" })?;
+            self.after_cr = false;
+            rest = &rest[end + 1..];
+        }
+        if let Some(last) = rest.last() {
+            self.inner.write_all(rest)?;
+            self.after_cr = *last == b'\r';
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Returns whether the first line of the file at `file_path` ends with `\r\n`, `false` if
+/// the file doesn't exist or has a single line.
+///
+/// Only the start of the file is read, a compact JSON file holding no line ending at all.
+fn ends_lines_with_crlf(file_path: &Path) -> bool {
+    const MAX_FIRST_LINE: u64 = 64 * 1024;
+
+    let Ok(file) = File::open(file_path) else {
+        return false;
+    };
+    let mut line = Vec::new();
+    match io::BufReader::new(file.take(MAX_FIRST_LINE)).read_until(b'\n', &mut line) {
+        Ok(_) => line.ends_with(b"\r\n"),
+        Err(_) => false,
+    }
+}
+
+/// Serializes `data` as JSON into `writer`, indented as set in `options`.
+pub(crate) fn to_json_writer<W: Write, T: Serialize>(writer: W, data: &T, options: &SerializerOptions) -> Result<()> {
+    match options.json_indent {
+        Some(indent) => {
+            let indent = vec![b' '; indent];
+            let mut serializer = serde_json::Serializer::with_formatter(writer, PrettyFormatter::with_indent(&indent));
+            data.serialize(&mut serializer)?;
+        }
+        None => data.serialize(&mut serde_json::Serializer::with_formatter(writer, CompactFormatter))?,
+    }
+    Ok(())
+}
+
+/// Serializes `data` as a TOML document, laid out as set in `options`.
+pub(crate) fn to_toml_string<T: Serialize>(data: &T, options: &SerializerOptions) -> Result<String> {
+    let mut document = String::new();
+    let serializer = if options.toml_multiline_arrays {
+        toml::Serializer::pretty(&mut document)
+    } else {
+        toml::Serializer::new(&mut document)
+    };
+    data.serialize(serializer)?;
+
+    if options.toml_inline_tables {
+        let mut parsed = document.parse::<DocumentMut>()?;
+        inline_arrays_of_tables(parsed.as_table_mut());
+        document = parsed.to_string();
+    }
+    Ok(document)
+}
+
+/// Serializes `data` as YAML into `writer`, as one document per item if set in `options`.
+pub(crate) fn to_yaml_writer<W: Write, T: Serialize>(writer: W, data: &T, options: &SerializerOptions) -> Result<()> {
+    let mut serializer = serde_yaml::Serializer::new(writer);
+    if !options.yaml_multi_document {
+        data.serialize(&mut serializer)?;
+        return Ok(());
+    }
+
+    let serde_yaml::Value::Sequence(documents) = serde_yaml::to_value(data)? else {
+        bail!("`yaml_multi_document` requires the config to be a list");
+    };
+    // Each value serialized after the first one starts a new `---` document
+    for document in documents {
+        document.serialize(&mut serializer)?;
+    }
+    Ok(())
+}
+
+/// Deserializes a YAML file, collecting its documents in a list if set in `options`.
+pub(crate) fn from_yaml<T>(deserializer: serde_yaml::Deserializer<'_>, options: &SerializerOptions) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    if !options.yaml_multi_document {
+        return Ok(T::deserialize(deserializer)?);
+    }
+
+    // An empty file is read as a single empty document, empty documents are not items
+    let documents = deserializer
+        .map(serde_yaml::Value::deserialize)
+        .filter(|document| !document.as_ref().is_ok_and(serde_yaml::Value::is_null))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(T::deserialize(serde_yaml::Value::Sequence(documents))?)
+}
+
+/// Serializes `data` as CBOR into `writer`.
+#[cfg(feature = "cbor")]
+pub(crate) fn to_cbor_writer<W: Write, T: Serialize>(writer: W, data: &T) -> Result<()> {
+    Ok(ciborium::into_writer(data, writer)?)
+}
+
+/// Fails, the CBOR format requires the `cbor` feature.
+#[cfg(not(feature = "cbor"))]
+pub(crate) fn to_cbor_writer<W: Write, T: Serialize>(_writer: W, _data: &T) -> Result<()> {
+    bail!("The CBOR format requires the `cbor` feature")
+}
+
+/// Deserializes a CBOR document from `reader`.
+#[cfg(feature = "cbor")]
+pub(crate) fn from_cbor<R: std::io::Read, T>(reader: R) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    Ok(ciborium::from_reader(reader)?)
+}
+
+/// Fails, the CBOR format requires the `cbor` feature.
+#[cfg(not(feature = "cbor"))]
+pub(crate) fn from_cbor<R: std::io::Read, T>(_reader: R) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    bail!("The CBOR format requires the `cbor` feature")
+}
+
+/// Deserializes an HCL document.
+#[cfg(feature = "hcl")]
+pub(crate) fn from_hcl<T>(content: &str) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    Ok(hcl::from_str(content)?)
+}
+
+/// Fails, the HCL format requires the `hcl` feature.
+#[cfg(not(feature = "hcl"))]
+pub(crate) fn from_hcl<T>(_content: &str) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    bail!("The HCL format requires the `hcl` feature")
+}
+
+/// Replaces the `[[section]]` arrays of tables in `table` by inline arrays, recursively.
+fn inline_arrays_of_tables(table: &mut Table) {
+    for (mut key, item) in table.iter_mut() {
+        match item {
+            Item::ArrayOfTables(tables) => {
+                let array = std::mem::take(tables).into_array();
+                *item = Item::Value(array.into());
+                // The key was formatted for a `[[section]]` header
+                key.leaf_decor_mut().clear();
+            }
+            Item::Table(table) => inline_arrays_of_tables(table),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Settings panel of a config drawn with [egui](https://docs.rs/egui), with the `egui`
+//! feature.
+//!
+//! The widgets are chosen from the serialized value of each field, their bounds and choices
+//! from its `min`, `max` and `one_of` constraints, and its doc comment is shown when the
+//! label is hovered, as described by [`describe`](crate::PersistentConfigBuilder::describe).
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use egui::{CollapsingHeader, ComboBox, DragValue, Grid, Id, TextEdit, Ui};
+use serde_json::{Map, Number, Value};
+
+use crate::document::{self, Direction};
+use crate::{FieldDescription, PersistentConfig};
+
+/// Edited values that don't make a valid config yet, kept by egui between the frames.
+#[derive(Clone)]
+struct Pending {
+    /// Document being edited, with the on-disk keys.
+    document: Value,
+    /// Why the document is not a valid config.
+    error: String,
+}
+
+/// Shows the fields of `config` in `ui`, and saves it as soon as a field is changed to a
+/// valid value.
+pub(crate) fn show<T: PersistentConfig>(config: &mut T, ui: &mut Ui) -> Result<()> {
+    let id = ui.id().with(("persistent_config_settings", std::any::type_name::<T>()));
+    let pending = ui.data(|data| data.get_temp::<Pending>(id));
+    let mut document = match &pending {
+        Some(pending) => pending.document.clone(),
+        None => document::rename_keys(serde_json::to_value(&*config)?, T::field_renames(), Direction::ToDisk),
+    };
+    let Value::Object(map) = &mut document else {
+        return Ok(());
+    };
+    let fields: HashMap<String, FieldDescription> =
+        T::describe().into_iter().map(|field| (field.key.clone(), field)).collect();
+
+    let mut changed = false;
+    Grid::new(id).num_columns(2).striped(true).show(ui, |ui| {
+        for (key, value) in map.iter_mut() {
+            let field = fields.get(key);
+            let label = ui.label(key.as_str());
+            if let Some(field) = field.filter(|field| !field.docs.is_empty()) {
+                label.on_hover_text(&field.docs);
+            }
+            let redacted = T::redacted_fields().contains(&key.as_str());
+            changed |= edit(ui, id.with(key), value, field, redacted);
+            ui.end_row();
+        }
+    });
+
+    if changed {
+        let updated = serde_json::from_value::<T>(document::rename_keys(
+            document.clone(),
+            T::field_renames(),
+            Direction::FromDisk,
+        ))
+        .map_err(anyhow::Error::from)
+        .and_then(|updated| updated.validate().map(|()| updated));
+        match updated {
+            Ok(updated) => {
+                ui.data_mut(|data| data.remove::<Pending>(id));
+                config.zeroize_sensitive();
+                *config = updated;
+                config.save()?;
+            }
+            Err(e) => {
+                let error = format!("{:#}", e);
+                ui.data_mut(|data| data.insert_temp(id, Pending { document, error }));
+            }
+        }
+    }
+
+    if let Some(pending) = ui.data(|data| data.get_temp::<Pending>(id)) {
+        ui.colored_label(ui.visuals().error_fg_color, pending.error);
+    }
+    Ok(())
+}
+
+/// Shows the widget editing `value`, the field described by `field`, and returns `true` if
+/// it was changed.
+///
+/// Arrays and `null` values are shown but can't be edited.
+fn edit(ui: &mut Ui, id: Id, value: &mut Value, field: Option<&FieldDescription>, redacted: bool) -> bool {
+    let constraints = field.map_or(&[][..], |field| field.constraints.as_slice());
+    if let Some(choices) = choices(constraints) {
+        let before = value.clone();
+        ComboBox::from_id_salt(id).selected_text(text(value)).show_ui(ui, |ui| {
+            for choice in choices {
+                let label = text(&choice);
+                ui.selectable_value(value, choice, label);
+            }
+        });
+        return *value != before;
+    }
+
+    match value {
+        Value::Bool(flag) => ui.checkbox(flag, "").changed(),
+        Value::Number(number) => edit_number(ui, number, field.map_or("", |field| &field.ty), constraints),
+        Value::String(text) => ui.add(TextEdit::singleline(text).password(redacted)).changed(),
+        Value::Object(map) => CollapsingHeader::new(format!("{} fields", map.len()))
+            .id_salt(id)
+            .show(ui, |ui| edit_map(ui, id, map))
+            .body_returned
+            .unwrap_or(false),
+        other => {
+            ui.label(other.to_string());
+            false
+        }
+    }
+}
+
+/// Shows the widgets editing the values of the nested struct or map `map`, and returns
+/// `true` if one was changed.
+fn edit_map(ui: &mut Ui, id: Id, map: &mut Map<String, Value>) -> bool {
+    let mut changed = false;
+    Grid::new(id).num_columns(2).show(ui, |ui| {
+        for (key, value) in map.iter_mut() {
+            ui.label(key.as_str());
+            changed |= edit(ui, id.with(key), value, None, false);
+            ui.end_row();
+        }
+    });
+    changed
+}
+
+/// Shows the widget editing `number`, a value of the Rust type `ty`, and returns `true` if
+/// it was changed.
+fn edit_number(ui: &mut Ui, number: &mut Number, ty: &str, constraints: &[String]) -> bool {
+    let (type_min, type_max) = match ty {
+        "u8" => (0.0, u8::MAX as f64),
+        "u16" => (0.0, u16::MAX as f64),
+        "u32" => (0.0, u32::MAX as f64),
+        "u64" | "u128" | "usize" => (0.0, f64::INFINITY),
+        "i8" => (i8::MIN as f64, i8::MAX as f64),
+        "i16" => (i16::MIN as f64, i16::MAX as f64),
+        "i32" => (i32::MIN as f64, i32::MAX as f64),
+        _ => (f64::NEG_INFINITY, f64::INFINITY),
+    };
+    let min = bound(constraints, "min").unwrap_or(type_min);
+    let max = bound(constraints, "max").unwrap_or(type_max);
+
+    if let Some(mut integer) = number.as_i64() {
+        let changed = ui.add(DragValue::new(&mut integer).range(min..=max)).changed();
+        *number = integer.into();
+        changed
+    } else if let Some(mut integer) = number.as_u64() {
+        let changed = ui.add(DragValue::new(&mut integer).range(min..=max)).changed();
+        *number = integer.into();
+        changed
+    } else {
+        let mut float = number.as_f64().unwrap_or_default();
+        let changed = ui.add(DragValue::new(&mut float).range(min..=max).speed(0.1)).changed();
+        if let Some(float) = Number::from_f64(float) {
+            *number = float;
+        }
+        changed
+    }
+}
+
+/// Returns the bound `name` (`min` or `max`) of the number constrained by `constraints`.
+fn bound(constraints: &[String], name: &str) -> Option<f64> {
+    constraints.iter().find_map(|constraint| {
+        let value = constraint.strip_prefix(name)?.trim_start().strip_prefix('=')?;
+        value.trim().replace(' ', "").parse().ok()
+    })
+}
+
+/// Returns the values allowed by the `one_of` constraint of `constraints`, if any.
+fn choices(constraints: &[String]) -> Option<Vec<Value>> {
+    constraints.iter().find_map(|constraint| {
+        let values = constraint.strip_prefix("one_of(")?.strip_suffix(')')?;
+        serde_json::from_str(&format!("[{}]", values)).ok()
+    })
+}
+
+/// Returns `value` as shown to the user, the strings without their quotes.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,35 @@
+//! Fields stored in files of their own next to the config file: the
+//! [`Chunked`](crate::Chunked) fields and the [`Lazy`](crate::Lazy) fields.
+//!
+//! Their files are kept in the `<config file stem>.data` directory, under the on-disk key of
+//! the field. They are read on first access, and written before the config file by every save.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Field types stored next to the config file.
+pub trait Sidecar: Clone {
+    /// Sets the path the field is read from, once the config is loaded from its file.
+    fn read_from(&mut self, path: PathBuf);
+
+    /// Writes the field to `path`.
+    fn write_to(&self, path: &Path) -> Result<()>;
+}
+
+/// Returns the directory holding the sidecar files of the config file at `file_path`.
+pub(crate) fn sidecars_dir(file_path: &Path) -> PathBuf {
+    file_path.with_extension("data")
+}
+
+/// Sets the path a field loaded from the config file is read from.
+pub fn attach<S: Sidecar>(field: &mut S, dir: &Path, key: &str) {
+    field.read_from(dir.join(key));
+}
+
+/// Writes a field saved to the config file.
+pub fn save<S: Sidecar>(field: &S, dir: &Path, key: &str) -> Result<()> {
+    field
+        .write_to(&dir.join(key))
+        .with_context(|| format!("Failed to save `{}` next to the config file", key))
+}
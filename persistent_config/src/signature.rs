@@ -0,0 +1,154 @@
+//! Detached GPG signatures of the config files, with the `signature` parameter.
+//!
+//! Files are signed and verified by the `gpg` command, found in the `PATH` or at the path set
+//! in the `GPG_BINARY` environment variable. The signature of a config file is stored next
+//! to it, as `<file name>.sig`.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use anyhow::{Context, Result, bail};
+use persistent_config_core::{PersistentConfigError, PersistentConfigParameters, SignatureOptions, SignaturePolicy};
+
+/// Returns the path of the signature of the config file at `file_path`.
+pub(crate) fn signature_path(file_path: &Path) -> PathBuf {
+    let mut path = file_path.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+/// Verifies the signature of the config file at `file_path`, as required by `params`.
+pub(crate) fn verify(params: &PersistentConfigParameters, file_path: &Path) -> Result<()> {
+    let Some(options) = &params.signature else {
+        return Ok(());
+    };
+    let Some(reason) = problem(options, file_path)? else {
+        return Ok(());
+    };
+
+    if options.policy == SignaturePolicy::Deny {
+        return Err(PersistentConfigError::InvalidSignature {
+            path: file_path.to_owned(),
+            reason,
+        }
+        .into());
+    }
+    eprintln!("Warning: config file {:?}: {}", file_path, reason);
+    Ok(())
+}
+
+/// Signs `tmp_path`, the new content of the config file at `file_path`, if required by
+/// `params`.
+///
+/// Returns the temporary path of the signature, to be renamed to the
+/// [`signature_path`] of `file_path` once the config file is in place.
+pub(crate) fn sign(params: &PersistentConfigParameters, tmp_path: &Path, file_path: &Path) -> Result<Option<PathBuf>> {
+    let Some(options) = &params.signature else {
+        return Ok(None);
+    };
+    // The file would fail the next load
+    trusted_fingerprints(options)?;
+    let Some(signing_key) = &options.signing_key else {
+        return Ok(None);
+    };
+    let mut signature_tmp = signature_path(file_path).into_os_string();
+    signature_tmp.push(".tmp");
+    let signature_tmp = PathBuf::from(signature_tmp);
+
+    let output = gpg()?
+        .args(["--batch", "--yes", "--detach-sign", "--local-user"])
+        .arg(signing_key)
+        .arg("--output")
+        .arg(&signature_tmp)
+        .arg(tmp_path)
+        .output()?;
+    if !output.status.success() {
+        _ = std::fs::remove_file(&signature_tmp);
+        bail!("Failed to sign {:?} with GPG: {}", file_path, last_line(&output));
+    }
+    Ok(Some(signature_tmp))
+}
+
+/// Returns what is wrong with the signature of the config file at `file_path`, or `None` if
+/// it is valid and made by a trusted key.
+fn problem(options: &SignatureOptions, file_path: &Path) -> Result<Option<String>> {
+    let trusted_keys = trusted_fingerprints(options)?;
+    let signature = signature_path(file_path);
+    if !signature.exists() {
+        return Ok(Some(format!("its signature {:?} is missing", signature)));
+    }
+
+    let mut command = gpg()?;
+    command.args(["--batch", "--status-fd", "1"]);
+    if let Some(keyring) = &options.keyring {
+        command.arg("--no-default-keyring").arg("--keyring").arg(keyring);
+    }
+    let output = command
+        .arg("--verify")
+        .arg(&signature)
+        .arg(file_path)
+        .output()
+        .context("Failed to verify the signature of the config file")?;
+
+    // `[GNUPG:] VALIDSIG <fingerprint> ... <primary key fingerprint>`
+    let status = String::from_utf8_lossy(&output.stdout);
+    let fingerprints: Vec<&str> = status
+        .lines()
+        .filter_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "))
+        .flat_map(|fields| {
+            let fields: Vec<&str> = fields.split_whitespace().collect();
+            [fields.first().copied(), fields.get(9).copied()]
+        })
+        .flatten()
+        .collect();
+    if !output.status.success() || fingerprints.is_empty() {
+        return Ok(Some(format!("its signature doesn't verify: {}", last_line(&output))));
+    }
+
+    let trusted = options.trust_keyring
+        || trusted_keys
+            .iter()
+            .any(|key| fingerprints.iter().any(|fingerprint| fingerprint.eq_ignore_ascii_case(key)));
+    if !trusted {
+        return Ok(Some(format!("it is signed by the untrusted key {}", fingerprints[0])));
+    }
+    Ok(None)
+}
+
+/// Returns the fingerprints of the trusted keys of `options`, without their spaces.
+///
+/// Fails if an entry is empty, or if there is none and the keyring is not trusted: any key
+/// would be accepted otherwise.
+fn trusted_fingerprints(options: &SignatureOptions) -> Result<Vec<String>> {
+    if options.trusted_keys.is_empty() && !options.trust_keyring {
+        bail!("Signed config files require at least one trusted key, or `trust_keyring`");
+    }
+    options
+        .trusted_keys
+        .iter()
+        .map(|key| {
+            let key: String = key.split_whitespace().collect();
+            if key.is_empty() {
+                bail!("The trusted keys of signed config files can't be empty");
+            }
+            Ok(key)
+        })
+        .collect()
+}
+
+/// Returns the `gpg` command, writing nothing to the terminal.
+fn gpg() -> Result<Command> {
+    if !cfg!(feature = "gpg") {
+        bail!("Signed config files require the `gpg` feature");
+    }
+    let program = std::env::var_os("GPG_BINARY").unwrap_or_else(|| "gpg".into());
+    let mut command = Command::new(program);
+    command.stdin(Stdio::null());
+    Ok(command)
+}
+
+/// Returns the last line written by gpg to its standard error, explaining its failure.
+fn last_line(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    stderr.lines().last().unwrap_or("gpg failed").trim().to_owned()
+}
@@ -0,0 +1,123 @@
+//! Named snapshots of configurations, kept in memory or spilled to disk.
+//!
+//! In-memory snapshots are stored in a [`PERSISTENT_CONFIGS`] extension slot of the type.
+//! Snapshots spilled to disk are saved in the `snapshots` subdirectory of the config
+//! directory, as `<file_name>-<snapshot name>.<ext>`.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{Result, bail};
+use persistent_config_core::{PERSISTENT_CONFIGS, PersistentConfigParameters};
+
+/// Extension slot holding the in-memory snapshots of a type.
+const SNAPSHOTS_SLOT: &str = "snapshots";
+
+/// In-memory snapshots of a type, by name.
+struct Snapshots<T>(Mutex<HashMap<String, T>>);
+
+/// Stores `value` as the snapshot `name` of `T`, replacing any previous one.
+pub(crate) fn store<T: Send + 'static>(name: &str, value: T) {
+    let snapshots = PERSISTENT_CONFIGS
+        .get_or_add_extension::<T, _>(SNAPSHOTS_SLOT, || Snapshots::<T>(Mutex::default()))
+        .expect("snapshots slot holds snapshots");
+    snapshots
+        .0
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(name.to_owned(), value);
+}
+
+/// Returns a clone of the in-memory snapshot `name` of `T`, if any.
+pub(crate) fn get<T: Clone + Send + 'static>(name: &str) -> Option<T> {
+    let snapshots = PERSISTENT_CONFIGS.get_extension::<T, Snapshots<T>>(SNAPSHOTS_SLOT)?;
+    let snapshots = snapshots.0.lock().unwrap_or_else(|e| e.into_inner());
+    snapshots.get(name).cloned()
+}
+
+/// Returns the parameters used to save the snapshot `name` on disk.
+///
+/// Snapshot names may only contain ASCII letters, digits, `-` and `_`, so they can be used
+/// in file names.
+pub(crate) fn disk_parameters(params: &PersistentConfigParameters, name: &str) -> Result<PersistentConfigParameters> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        bail!(
+            "Invalid snapshot name {:?}: use ASCII letters, digits, `-` and `_`",
+            name
+        );
+    }
+
+    // Snapshots of a config with a full path go next to it, and keep its extension
+    let (config_dir, file_name, namespace, extension) = match &params.full_path {
+        Some(full_path) => (
+            full_path.parent().unwrap_or(Path::new("")),
+            full_path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+            None,
+            Some(full_path.extension().unwrap_or_default().to_string_lossy().into_owned()),
+        ),
+        None => (
+            Path::new(&params.config_dir),
+            params.file_name.clone(),
+            params.namespace.clone(),
+            params.extension.clone(),
+        ),
+    };
+
+    Ok(PersistentConfigParameters {
+        config_dir: config_dir.join("snapshots").to_string_lossy().into_owned(),
+        file_name: format!("{}-{}", file_name, name),
+        namespace,
+        extension,
+        full_path: None,
+        audit_log: false,
+        ..params.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{PersistentConfig, PersistentConfigBuilder};
+
+    use super::*;
+
+    #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+    struct Limits {
+        max: u32,
+    }
+
+    impl PersistentConfigBuilder for Limits {
+        fn validate(&self) -> Result<()> {
+            if self.max > 100 {
+                bail!("max must be at most 100");
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn invalid_config_is_not_snapshot_to_disk() -> Result<()> {
+        let dir = std::env::temp_dir().join("persistent_config_test_snapshots");
+        _ = std::fs::remove_dir_all(&dir);
+        Limits::default().config_with_parameters(PersistentConfigParameters {
+            config_dir: dir.to_string_lossy().into_owned(),
+            file_name: "Limits".to_string(),
+            ..Default::default()
+        })?;
+
+        Limits { max: 10 }.snapshot_to_disk("valid")?;
+        let error = Limits { max: 1000 }.snapshot_to_disk("invalid").unwrap_err();
+        assert!(format!("{error:#}").contains("at most 100"), "{error:#}");
+        assert!(dir.join("snapshots/Limits-valid.toml").exists());
+        assert!(!dir.join("snapshots/Limits-invalid.toml").exists());
+
+        let mut restored = Limits::default();
+        assert!(restored.restore("invalid").is_err());
+        restored.restore("valid")?;
+        assert_eq!(restored, Limits { max: 10 });
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+//! Config files encrypted with [SOPS](https://github.com/getsops/sops), with the `sops`
+//! parameter.
+//!
+//! The files are decrypted and encrypted by the `sops` command, found in the `PATH` or at
+//! the path set in the `SOPS_BINARY` environment variable, so the keys and creation rules
+//! (`.sops.yaml`) are the ones the command uses. The plain document is passed through pipes
+//! and never written to disk.
+//!
+//! Saving is only supported on Unix, where `sops` reads the document to encrypt from
+//! `/dev/stdin`. Encrypted files can be loaded on every platform.
+
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result, bail};
+use persistent_config_core::{PersistentConfigParameters, SaveFormat};
+use serde::Deserialize;
+
+use crate::{embedded, from_document};
+
+/// Reads the encrypted config file at `file_path`.
+pub(crate) fn read<T>(params: &PersistentConfigParameters, file_path: &Path, save_format: SaveFormat) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let content = run(&[OsStr::new("--decrypt")], save_format, file_path.as_os_str(), None)
+        .with_context(|| format!("Failed to decrypt {:?} with SOPS", file_path))?;
+    from_document(embedded::parse_document(&content, save_format, params)?, save_format)
+}
+
+/// Returns `document`, a config file written in `save_format`, encrypted for `file_path`.
+///
+/// The creation rules matching `file_path` select the keys, as if the file was encrypted
+/// in place.
+pub(crate) fn encrypt(document: &[u8], save_format: SaveFormat, file_path: &Path) -> Result<Vec<u8>> {
+    if !cfg!(unix) {
+        bail!("Saving SOPS encrypted config files is only supported on Unix");
+    }
+    let args = [OsStr::new("--encrypt"), OsStr::new("--filename-override"), file_path.as_os_str()];
+    // The document is read from the standard input
+    run(&args, save_format, OsStr::new("/dev/stdin"), Some(document))
+        .with_context(|| format!("Failed to encrypt {:?} with SOPS", file_path))
+}
+
+/// Runs `sops` with `args` on `file`, feeding it `input`, and returns its output.
+fn run(args: &[&OsStr], save_format: SaveFormat, file: &OsStr, input: Option<&[u8]>) -> Result<Vec<u8>> {
+    if !cfg!(feature = "sops") {
+        bail!("SOPS encrypted config files require the `sops` feature");
+    }
+    let format = match save_format {
+        SaveFormat::JSON => "json",
+        SaveFormat::YAML => "yaml",
+        _ => bail!("SOPS only supports the JSON and YAML formats, not {:?}", save_format),
+    };
+    let program = std::env::var_os("SOPS_BINARY").unwrap_or_else(|| "sops".into());
+    let mut child = Command::new(&program)
+        .args(args)
+        .args(["--input-type", format, "--output-type", format])
+        .arg(file)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {:?}", program))?;
+
+    // Written from another thread, sops may fill the output pipe before reading all its input
+    let stdin = child.stdin.take();
+    let (output, written) = std::thread::scope(|scope| {
+        let writer = input.zip(stdin).map(|(input, mut stdin)| scope.spawn(move || stdin.write_all(input)));
+        let output = child.wait_with_output();
+        let written = writer.map_or(Ok(()), |writer| writer.join().expect("the writer thread doesn't panic"));
+        (output, written)
+    });
+    let output = output?;
+    // A failing sops may exit before reading its input, its message explains why
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    written?;
+    Ok(output.stdout)
+}
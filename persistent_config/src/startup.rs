@@ -0,0 +1,157 @@
+//! Loading of the configs at startup, in the order of their dependencies.
+//!
+//! The types declared with
+//! [`load_on_startup`](crate::PersistentConfigBuilder::load_on_startup) are kept with the
+//! types they depend on. [`load_all_ordered`](crate::load_all_ordered) sorts them so every
+//! type loads after its dependencies, the types without a dependency between them keeping
+//! the order of their declaration, and reports the cycles before loading anything.
+
+use std::any::TypeId;
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+
+use anyhow::{Context, Result};
+use persistent_config_core::PersistentConfigError;
+
+/// A config type, as a dependency of another one loaded at startup.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Default, Serialize, Deserialize)]
+/// # struct Database { url: String }
+/// let database = ConfigType::of::<Database>();
+/// assert!(database.name().ends_with("Database"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConfigType {
+    /// Identifier of the type.
+    id: TypeId,
+    /// Name of the type, as given by [`std::any::type_name`].
+    name: &'static str,
+}
+
+impl ConfigType {
+    /// Returns the config type `T`.
+    pub fn of<T: 'static>() -> Self {
+        Self {
+            id: TypeId::of::<T>(),
+            name: std::any::type_name::<T>(),
+        }
+    }
+
+    /// Returns the name of the type, as given by [`std::any::type_name`].
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Loads a type and hands the value over to its callback.
+type LoadFn = Arc<dyn Fn() -> Result<()> + Send + Sync>;
+
+/// A type loaded by [`load_all_ordered`].
+struct StartupEntry {
+    /// The type loaded.
+    config_type: ConfigType,
+    /// Types loaded before it.
+    depends_on: Vec<ConfigType>,
+    /// Loads the type.
+    load: LoadFn,
+}
+
+/// Types loaded at startup, in the order of their declaration.
+static STARTUP: LazyLock<Mutex<Vec<StartupEntry>>> = LazyLock::new(Mutex::default);
+
+/// Declares `config_type`, loaded by `load` after the types of `depends_on`.
+///
+/// A type declared again keeps its place in the declaration order, with the new dependencies
+/// and loader.
+pub(crate) fn declare(config_type: ConfigType, depends_on: &[ConfigType], load: LoadFn) {
+    let entry = StartupEntry {
+        config_type,
+        depends_on: depends_on.to_vec(),
+        load,
+    };
+    let mut startup = STARTUP.lock().unwrap_or_else(PoisonError::into_inner);
+    match startup.iter_mut().find(|declared| declared.config_type == config_type) {
+        Some(declared) => *declared = entry,
+        None => startup.push(entry),
+    }
+}
+
+/// Loads the declared types, each after its dependencies.
+///
+/// Stops at the first type failing to load, as the types depending on it couldn't load.
+pub(crate) fn load_all_ordered() -> Result<()> {
+    for (config_type, load) in ordered()? {
+        load().with_context(|| format!("Failed to load {} at startup", config_type.name))?;
+    }
+    Ok(())
+}
+
+/// Returns the loaders of the declared types, each after its dependencies.
+fn ordered() -> Result<Vec<(ConfigType, LoadFn)>> {
+    let startup = STARTUP.lock().unwrap_or_else(PoisonError::into_inner);
+    for entry in startup.iter() {
+        if let Some(dependency) = entry
+            .depends_on
+            .iter()
+            .find(|dependency| !startup.iter().any(|declared| declared.config_type == **dependency))
+        {
+            return Err(PersistentConfigError::MissingDependency {
+                type_name: entry.config_type.name,
+                dependency: dependency.name,
+            }
+            .into());
+        }
+    }
+
+    let mut loaded: Vec<TypeId> = Vec::with_capacity(startup.len());
+    let mut order = Vec::with_capacity(startup.len());
+    while order.len() < startup.len() {
+        // The first declared type whose dependencies are all loaded, a type depending on
+        // itself never being ready
+        let ready = startup.iter().find(|entry| {
+            !loaded.contains(&entry.config_type.id)
+                && entry
+                    .depends_on
+                    .iter()
+                    .all(|dependency| dependency.id != entry.config_type.id && loaded.contains(&dependency.id))
+        });
+        let Some(entry) = ready else {
+            return Err(PersistentConfigError::DependencyCycle {
+                cycle: cycle(&startup, &loaded),
+            }
+            .into());
+        };
+        loaded.push(entry.config_type.id);
+        order.push((entry.config_type, Arc::clone(&entry.load)));
+    }
+    Ok(order)
+}
+
+/// Returns the names of the types of a dependency cycle among the types not `loaded`, the
+/// first type being repeated at the end.
+///
+/// Every type not loaded depends on another one not loaded, so following the first of these
+/// dependencies from any of them ends up in a cycle.
+fn cycle(startup: &[StartupEntry], loaded: &[TypeId]) -> Vec<&'static str> {
+    let pending = |config_type: &ConfigType| {
+        startup
+            .iter()
+            .find(|entry| entry.config_type == *config_type && !loaded.contains(&config_type.id))
+    };
+    let mut path: Vec<ConfigType> = Vec::new();
+    let mut current = startup.iter().find(|entry| !loaded.contains(&entry.config_type.id));
+    while let Some(entry) = current {
+        if let Some(start) = path.iter().position(|visited| *visited == entry.config_type) {
+            let mut cycle: Vec<_> = path[start..].iter().map(|config_type| config_type.name).collect();
+            cycle.push(entry.config_type.name);
+            return cycle;
+        }
+        path.push(entry.config_type);
+        current = entry.depends_on.iter().find_map(pending);
+    }
+    path.iter().map(|config_type| config_type.name).collect()
+}
@@ -0,0 +1,63 @@
+//! Metrics of the saves and loads, emitted through the `metrics` facade with the `metrics`
+//! feature.
+//!
+//! Every metric has a `type` label holding the name of the config type:
+//!
+//! - `persistent_config_saves_total`: saves, with a `result` label (`ok` or `error`).
+//! - `persistent_config_save_duration_seconds`: histogram of the save durations.
+//! - `persistent_config_bytes_written_total`: size of the config files saved successfully.
+//! - `persistent_config_loads_total`: loads, with a `result` label (`ok`, `missing` when the
+//!   file does not exist, or `error`).
+//! - `persistent_config_load_duration_seconds`: histogram of the load durations.
+//!
+//! Without the feature, the operations are run as they are.
+
+use anyhow::Result;
+
+/// Runs `save`, which writes the config of the type `type_name` and returns the size of the
+/// file written, and records its metrics.
+#[cfg(feature = "metrics")]
+pub(crate) fn save(type_name: &'static str, save: impl FnOnce() -> Result<u64>) -> Result<u64> {
+    let started = std::time::Instant::now();
+    let result = save();
+
+    metrics::histogram!("persistent_config_save_duration_seconds", "type" => type_name)
+        .record(started.elapsed().as_secs_f64());
+    let outcome = if result.is_ok() { "ok" } else { "error" };
+    metrics::counter!("persistent_config_saves_total", "type" => type_name, "result" => outcome).increment(1);
+    if let Ok(bytes) = result {
+        metrics::counter!("persistent_config_bytes_written_total", "type" => type_name).increment(bytes);
+    }
+    result
+}
+
+/// Runs `save`, which writes the config of the type `type_name` and returns the size of the
+/// file written.
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn save(_type_name: &'static str, save: impl FnOnce() -> Result<u64>) -> Result<u64> {
+    save()
+}
+
+/// Runs `load`, which reads the config of `T`, and records its metrics.
+#[cfg(feature = "metrics")]
+pub(crate) fn load<T>(load: impl FnOnce() -> Result<T>) -> Result<T> {
+    let type_name = std::any::type_name::<T>();
+    let started = std::time::Instant::now();
+    let result = load();
+
+    metrics::histogram!("persistent_config_load_duration_seconds", "type" => type_name)
+        .record(started.elapsed().as_secs_f64());
+    let outcome = match &result {
+        Ok(_) => "ok",
+        Err(e) if crate::is_not_found(e) => "missing",
+        Err(_) => "error",
+    };
+    metrics::counter!("persistent_config_loads_total", "type" => type_name, "result" => outcome).increment(1);
+    result
+}
+
+/// Runs `load`, which reads the config of `T`.
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn load<T>(load: impl FnOnce() -> Result<T>) -> Result<T> {
+    load()
+}
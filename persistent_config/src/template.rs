@@ -0,0 +1,70 @@
+//! Config templates, with `{{placeholders}}` instead of values.
+//!
+//! Every value of the document is replaced by a placeholder named after its path, such as
+//! `{{server.port}}`. String placeholders are quoted like the strings they stand for, the
+//! others are written bare so the filled in file has the right types:
+//!
+//! ```toml
+//! name = "{{name}}"
+//!
+//! [server]
+//! port = {{server.port}}
+//! ```
+
+use anyhow::{Result, bail};
+use persistent_config_core::SaveFormat;
+use serde_json::Value;
+
+/// Marks the placeholders written without quotes, while the document is serialized.
+const RAW_MARKER: &str = "@@persistent_config_raw@@";
+
+/// Renders `document` as a template in the given format.
+pub(crate) fn render(document: Value, save_format: SaveFormat) -> Result<String> {
+    let mut raw = Vec::new();
+    let document = placeholders(document, &mut String::new(), &mut raw);
+
+    let mut template = match save_format {
+        SaveFormat::JSON => serde_json::to_string_pretty(&document)?,
+        SaveFormat::TOML => toml::to_string(&document)?,
+        SaveFormat::YAML => serde_yaml::to_string(&document)?,
+        SaveFormat::Properties => crate::properties::to_string(&document)?,
+        SaveFormat::CBOR => bail!("Templates are text files, they can't be written in the CBOR format"),
+        SaveFormat::HCL => bail!("The HCL format is load only, templates can't be written in it"),
+    };
+    for placeholder in raw {
+        let marked = format!("{}{}", RAW_MARKER, placeholder);
+        for quoted in [format!("\"{}\"", marked), format!("'{}'", marked), marked] {
+            template = template.replace(&quoted, &placeholder);
+        }
+    }
+    Ok(template)
+}
+
+/// Replaces the values of `document` below `path` by placeholders.
+///
+/// Placeholders to write without quotes are marked and pushed to `raw`.
+fn placeholders(document: Value, path: &mut String, raw: &mut Vec<String>) -> Value {
+    match document {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let len = path.len();
+                    if !path.is_empty() {
+                        path.push('.');
+                    }
+                    path.push_str(&key);
+                    let value = placeholders(value, path, raw);
+                    path.truncate(len);
+                    (key, value)
+                })
+                .collect(),
+        ),
+        Value::String(_) => Value::String(format!("{{{{{}}}}}", path)),
+        _ => {
+            let placeholder = format!("{{{{{}}}}}", path);
+            let marked = format!("{}{}", RAW_MARKER, placeholder);
+            raw.push(placeholder);
+            Value::String(marked)
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Templates in the string values of the config files, with the `templates` parameter.
+//!
+//! String values holding `{{` or `{%` are rendered by [MiniJinja](https://docs.rs/minijinja)
+//! when loading, with a context made of:
+//!
+//! - `env`: the environment variables of the process, such as `{{ env.HOME }}`
+//! - `hostname`: the name of the host
+//! - the variables set with [`set_template_var`](crate::set_template_var)
+//!
+//! An undefined variable fails the load, so a typo is not silently rendered as an empty
+//! string. Saving writes the templates back as long as the values they render to are
+//! unchanged.
+
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, PoisonError, RwLock};
+
+use anyhow::{Result, anyhow};
+use minijinja::{Environment, UndefinedBehavior};
+use serde_json::Value;
+
+use crate::provenance;
+
+/// Variables set by the application, by name.
+static VARS: LazyLock<RwLock<BTreeMap<String, Value>>> = LazyLock::new(RwLock::default);
+
+/// Sets the variable `name` of the templates, replacing any previous value.
+pub(crate) fn set_var(name: String, value: Value) {
+    VARS.write().unwrap_or_else(PoisonError::into_inner).insert(name, value);
+}
+
+/// Renders the templates of the string values of `document`.
+pub(crate) fn render(document: Value) -> Result<Value> {
+    Renderer::new().render_document(document)
+}
+
+/// Puts back the templates of `previous` whose rendered value is unchanged in `document`.
+pub(crate) fn keep(document: &mut Value, previous: &Value) -> Result<()> {
+    Renderer::new().keep(document, previous)
+}
+
+/// Returns `true` if `text` holds template syntax.
+fn is_template(text: &str) -> bool {
+    text.contains("{{") || text.contains("{%")
+}
+
+/// Renders templates with the context of the current process.
+struct Renderer {
+    /// Environment with the strict undefined behavior.
+    environment: Environment<'static>,
+    /// Variables of the templates.
+    context: BTreeMap<String, Value>,
+}
+
+impl Renderer {
+    /// Returns a renderer with the built-in and application variables.
+    fn new() -> Self {
+        let mut environment = Environment::new();
+        environment.set_undefined_behavior(UndefinedBehavior::Strict);
+        let mut context = BTreeMap::new();
+        context.insert("env".to_owned(), Value::Object(std::env::vars().map(|(k, v)| (k, v.into())).collect()));
+        context.insert("hostname".to_owned(), provenance::hostname().into());
+        context.extend(VARS.read().unwrap_or_else(PoisonError::into_inner).clone());
+        Self { environment, context }
+    }
+
+    /// Renders the templates of the string values of `document`.
+    fn render_document(&self, document: Value) -> Result<Value> {
+        Ok(match document {
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .map(|(key, value)| Ok((key, self.render_document(value)?)))
+                    .collect::<Result<_>>()?,
+            ),
+            Value::Array(items) => Value::Array(
+                items
+                    .into_iter()
+                    .map(|value| self.render_document(value))
+                    .collect::<Result<_>>()?,
+            ),
+            Value::String(text) if is_template(&text) => Value::String(self.render_string(&text)?),
+            value => value,
+        })
+    }
+
+    /// Puts back the templates of `previous` whose rendered value is unchanged in `document`.
+    fn keep(&self, document: &mut Value, previous: &Value) -> Result<()> {
+        match (document, previous) {
+            (Value::Object(map), Value::Object(previous)) => {
+                for (key, value) in map {
+                    if let Some(previous) = previous.get(key) {
+                        self.keep(value, previous)?;
+                    }
+                }
+            }
+            (Value::Array(items), Value::Array(previous)) => {
+                for (value, previous) in items.iter_mut().zip(previous) {
+                    self.keep(value, previous)?;
+                }
+            }
+            (Value::String(value), Value::String(template)) if value != template && is_template(template) => {
+                // A template that no longer renders is replaced by the value
+                let unchanged = self.render_string(template).is_ok_and(|rendered| rendered == *value);
+                if unchanged {
+                    value.clone_from(template);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Renders the template `text`.
+    fn render_string(&self, text: &str) -> Result<String> {
+        self.environment
+            .render_str(text, &self.context)
+            .map_err(|e| anyhow!("Failed to render the template {:?}: {}", text, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::set_template_var;
+
+    #[test]
+    fn renders_the_variables() -> Result<()> {
+        set_template_var("test_region", "eu-west")?;
+        let document = render(json!({ "url": "https://{{ test_region }}.example.com", "port": 80 }))?;
+        assert_eq!(document, json!({ "url": "https://eu-west.example.com", "port": 80 }));
+        Ok(())
+    }
+
+    #[test]
+    fn unrepresentable_variable_is_an_error() -> Result<()> {
+        set_template_var("test_zone", "a")?;
+        let error = set_template_var("test_zone", HashMap::from([((1, 2), "tuple key")])).unwrap_err();
+        assert!(format!("{error:#}").contains("test_zone"), "{error:#}");
+        assert_eq!(render(json!("{{ test_zone }}"))?, json!("a"));
+        Ok(())
+    }
+
+    #[test]
+    fn undefined_variable_fails() {
+        assert!(render(json!({ "name": "{{ test_undefined }}" })).is_err());
+    }
+
+    #[test]
+    fn keeps_the_templates_of_unchanged_values() -> Result<()> {
+        set_template_var("test_host", "db1")?;
+        let previous = json!({ "host": "{{ test_host }}", "other": "{{ test_host }}" });
+        let mut document = json!({ "host": "db1", "other": "db2" });
+        keep(&mut document, &previous)?;
+        assert_eq!(document, json!({ "host": "{{ test_host }}", "other": "db2" }));
+        Ok(())
+    }
+}
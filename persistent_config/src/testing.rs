@@ -0,0 +1,194 @@
+//! Helpers to isolate persistent configurations in tests.
+//!
+//! [`with_temp_config`] redirects every config file accessed from the current thread to a
+//! unique temporary directory, so tests running in parallel never touch each other's files
+//! nor the real configuration of the developer.
+//!
+//! [`with_override`] replaces the config of a single type by a value held in memory, for
+//! focused tests and A/B experiments that must not touch the files at all.
+
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::PersistentConfigBuilder;
+
+thread_local! {
+    /// Temporary directory config files are redirected to, for the current thread.
+    static TEMP_DIR: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    /// Values overriding the config of their type on the current thread, by type.
+    static OVERRIDES: RefCell<HashMap<TypeId, Box<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// Value overriding the config of `T`, with the function cloning it, as the loads of `T`
+/// don't require `T: Clone`.
+struct Override<T> {
+    value: T,
+    clone: fn(&T) -> T,
+}
+
+/// Counter making temporary directories unique within the process.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs `f` with all config files redirected to a fresh temporary directory.
+///
+/// Registrations are kept as they are, but their `config_dir` is resolved inside the
+/// temporary directory (`./.config` becomes `<tmp>/.config`, `/etc/app` becomes `<tmp>/etc/app`).
+/// The directory is removed once `f` returns, even if it panics.
+///
+/// The redirection only applies to the calling thread: threads spawned inside `f` use the
+/// registered paths.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+/// # struct MyConfig { count: u32 }
+/// # impl PersistentConfigBuilder for MyConfig {}
+/// use persistent_config::testing::with_temp_config;
+///
+/// with_temp_config(|| {
+///     let config = MyConfig { count: 3 };
+///     config.default_save_config(false).unwrap();
+///     config.save().unwrap();
+///
+///     let mut loaded = MyConfig::default();
+///     loaded.load().unwrap();
+///     assert_eq!(loaded, config);
+/// });
+/// ```
+pub fn with_temp_config<R>(f: impl FnOnce() -> R) -> R {
+    let dir = std::env::temp_dir().join(format!(
+        "persistent_config-{}-{}",
+        std::process::id(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let previous = TEMP_DIR.with(|temp_dir| temp_dir.replace(Some(dir.clone())));
+    let _guard = TempDirGuard { dir, previous };
+    f()
+}
+
+/// Runs `f` with `value` standing for the config of `T`, in place of its file.
+///
+/// On the calling thread, loading `T` returns a copy of `value` as it is, without reading
+/// the file nor running the load hooks, and saving `T` replaces `value` without writing the
+/// file, so the value saved by `f` is the one loaded next. The config is back to its file
+/// once `f` returns, even if it panics. `T` doesn't need to be registered.
+///
+/// The other methods reading or writing the config file follow: `save_dry_run` compares
+/// with `value`, `migrate_from` migrates nothing and `snapshot_to_disk` only keeps the
+/// snapshot in memory. The exports of `save_as_format` and `load_as_format` are separate
+/// files, still written and read.
+///
+/// Overrides nest, and only apply to the calling thread. Within [`with_temp_config`], the
+/// files of the other types are redirected as usual.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+/// # struct Features { new_checkout: bool }
+/// # impl PersistentConfigBuilder for Features {}
+/// use persistent_config::testing::with_override;
+///
+/// with_override(Features { new_checkout: true }, || {
+///     let mut features = Features::default();
+///     features.load().unwrap();
+///     assert!(features.new_checkout);
+///
+///     features.new_checkout = false;
+///     features.save().unwrap();
+///     features.load().unwrap();
+///     assert!(!features.new_checkout);
+/// });
+/// ```
+pub fn with_override<T, R>(value: T, f: impl FnOnce() -> R) -> R
+where
+    T: PersistentConfigBuilder + Clone,
+{
+    let type_id = TypeId::of::<T>();
+    let value = Override { value, clone: T::clone };
+    let previous = OVERRIDES.with_borrow_mut(|overrides| overrides.insert(type_id, Box::new(value)));
+    let _guard = OverrideGuard { type_id, previous };
+    f()
+}
+
+/// Returns a copy of the value overriding the config of `T` on this thread, if any.
+pub(crate) fn overridden<T: 'static>() -> Option<T> {
+    OVERRIDES.with_borrow(|overrides| {
+        let value = overrides.get(&TypeId::of::<T>())?.downcast_ref::<Override<T>>()?;
+        Some((value.clone)(&value.value))
+    })
+}
+
+/// Returns `true` if the config of `T` is overridden on this thread.
+pub(crate) fn is_overridden<T: 'static>() -> bool {
+    OVERRIDES.with_borrow(|overrides| overrides.contains_key(&TypeId::of::<T>()))
+}
+
+/// Replaces the value overriding the config of `T` on this thread by a copy of `value`.
+///
+/// Returns `false` if the config of `T` is not overridden.
+pub(crate) fn save_override<T: 'static>(value: &T) -> bool {
+    OVERRIDES.with_borrow_mut(|overrides| {
+        let Some(current) = overrides
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut::<Override<T>>())
+        else {
+            return false;
+        };
+        current.value = (current.clone)(value);
+        true
+    })
+}
+
+/// Returns the temporary directory config files are currently redirected to, if any.
+pub fn temp_config_dir() -> Option<PathBuf> {
+    TEMP_DIR.with(|temp_dir| temp_dir.borrow().clone())
+}
+
+/// Resolves `config_dir` inside the temporary directory, if one is active on this thread.
+pub(crate) fn redirect(config_dir: &Path) -> Option<PathBuf> {
+    let mut dir = temp_config_dir()?;
+    dir.extend(
+        config_dir
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_))),
+    );
+    Some(dir)
+}
+
+/// Restores the previous redirection and removes the temporary directory on drop.
+struct TempDirGuard {
+    dir: PathBuf,
+    previous: Option<PathBuf>,
+}
+
+impl Drop for TempDirGuard {
+    fn drop(&mut self) {
+        TEMP_DIR.with(|temp_dir| temp_dir.replace(self.previous.take()));
+        _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Restores the previous override of a type on drop.
+struct OverrideGuard {
+    type_id: TypeId,
+    previous: Option<Box<dyn Any>>,
+}
+
+impl Drop for OverrideGuard {
+    fn drop(&mut self) {
+        OVERRIDES.with_borrow_mut(|overrides| match self.previous.take() {
+            Some(previous) => overrides.insert(self.type_id, previous),
+            None => overrides.remove(&self.type_id),
+        });
+    }
+}
@@ -0,0 +1,250 @@
+//! Coalescing of bursts of saves, with the `min_save_interval` parameter.
+//!
+//! The time of the last write of each type is kept in a [`PERSISTENT_CONFIGS`] extension
+//! slot. A save made sooner than the interval after it is only recorded as pending: the
+//! next save once the interval is over, or [`flush`](crate::PersistentConfig::flush), writes
+//! the value of the moment, so a burst of saves ends up in a single write.
+//!
+//! The value of the last deferred save of each type is also kept, serialized, so
+//! [`flush_all`](crate::flush_all) can write every pending save at once, category by category.
+//! A timer thread writes it as well once the interval is over, so the last save of a burst
+//! reaches the disk even if nothing is saved or flushed after it.
+
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use std::{mem, thread};
+
+use anyhow::Result;
+use persistent_config_core::{PERSISTENT_CONFIGS, PersistentConfigParameters};
+
+use crate::{PersistentConfigBuilder, prepare_save, save_config};
+
+/// Extension slot holding the save throttle of a type.
+const THROTTLE_SLOT: &str = "save_throttle";
+
+/// Last write of a type, whether a save was deferred since, and whether a timer thread
+/// will write it.
+#[derive(Default)]
+struct Throttle {
+    last_write: Option<Instant>,
+    pending: bool,
+    scheduled: bool,
+}
+
+/// Write of the last deferred save of a type.
+struct PendingSave {
+    /// Category of the type, giving the flush order.
+    category: Option<String>,
+    /// Writes the deferred value, returning an error under the policy of `save`.
+    write: Box<dyn FnOnce() -> Result<()> + Send>,
+}
+
+/// Last deferred save of each type, by type.
+static PENDING: LazyLock<Mutex<HashMap<TypeId, PendingSave>>> = LazyLock::new(Mutex::default);
+
+/// Runs `f` on the save throttle of `T`.
+fn with_throttle<T: 'static, R>(f: impl FnOnce(&mut Throttle) -> R) -> R {
+    let throttle = PERSISTENT_CONFIGS
+        .get_or_add_extension::<T, Mutex<Throttle>>(THROTTLE_SLOT, Mutex::default)
+        .expect("the slot only holds throttles");
+    let mut throttle = throttle.lock().unwrap_or_else(PoisonError::into_inner);
+    f(&mut throttle)
+}
+
+/// Returns `true` if a save of `T` must be deferred, as the last write is less than
+/// `interval` old, and records it as pending.
+pub(crate) fn defer<T: 'static>(interval: Duration) -> bool {
+    with_throttle::<T, _>(|throttle| {
+        let defer = throttle.last_write.is_some_and(|last| last.elapsed() < interval);
+        throttle.pending |= defer;
+        defer
+    })
+}
+
+/// Keeps `data`, whose save was deferred, to be written with `params` by [`flush_all`], or by
+/// a timer thread once `min_save_interval` is over.
+pub(crate) fn keep<T: PersistentConfigBuilder>(params: &PersistentConfigParameters, data: &T) -> Result<()> {
+    let document = serde_json::to_value(prepare_save(data)?)?;
+    let interval = params.min_save_interval;
+    let params = params.clone();
+    let pending = PendingSave {
+        category: params.category.clone(),
+        write: Box::new(move || {
+            let data: T = serde_json::from_value(document)?;
+            match save_config(&params, &data) {
+                Ok(_) => Ok(()),
+                Err(e) if params.panic_on_error => {
+                    println!("Error saving file: {:?}", e);
+                    Ok(())
+                }
+                Err(e) => Err(e.context(format!("Failed to save {}", std::any::type_name::<T>()))),
+            }
+        }),
+    };
+    lock_pending().insert(TypeId::of::<T>(), pending);
+    if let Some(interval) = interval {
+        schedule::<T>(interval);
+    }
+    Ok(())
+}
+
+/// Starts a thread writing the deferred save of `T` once `interval` is over since the last
+/// write, unless one is already waiting.
+fn schedule<T: 'static>(interval: Duration) {
+    if with_throttle::<T, _>(|throttle| mem::replace(&mut throttle.scheduled, true)) {
+        return;
+    }
+    thread::spawn(move || {
+        // A write made meanwhile moves the end of the interval
+        let due = loop {
+            let wait = with_throttle::<T, _>(|throttle| {
+                throttle
+                    .last_write
+                    .map(|last| interval.saturating_sub(last.elapsed()))
+            });
+            match wait {
+                Some(wait) if !wait.is_zero() => thread::sleep(wait),
+                // Without a last write, a flush is writing the pending save
+                wait => break wait.is_some(),
+            }
+        };
+        with_throttle::<T, _>(|throttle| throttle.scheduled = false);
+        let pending = if due { lock_pending().remove(&TypeId::of::<T>()) } else { None };
+        if let Some(pending) = pending
+            && let Err(e) = (pending.write)()
+        {
+            eprintln!("Error saving {}: {:#}", std::any::type_name::<T>(), e);
+        }
+    });
+}
+
+/// Records a write of the whole config of `T`, which makes any deferred save moot.
+pub(crate) fn written<T: 'static>() {
+    with_throttle::<T, _>(|throttle| {
+        throttle.last_write = Some(Instant::now());
+        throttle.pending = false;
+    });
+    lock_pending().remove(&TypeId::of::<T>());
+}
+
+/// Returns `true` if a save of `T` was deferred since its last write, and lets the next save
+/// write at once.
+pub(crate) fn take_pending<T: 'static>() -> bool {
+    with_throttle::<T, _>(|throttle| {
+        let pending = throttle.pending;
+        if pending {
+            throttle.last_write = None;
+        }
+        pending
+    })
+}
+
+/// Writes the deferred saves of all types, by increasing flush order of their category, and
+/// returns the first error.
+///
+/// Every save is attempted, whatever the errors of the others.
+pub(crate) fn flush_all() -> Result<()> {
+    let mut pending: Vec<PendingSave> = lock_pending().drain().map(|(_, pending)| pending).collect();
+    pending.sort_by_key(|pending| {
+        pending
+            .category
+            .as_deref()
+            .and_then(|name| PERSISTENT_CONFIGS.category(name))
+            .map_or(0, |category| category.flush_order)
+    });
+
+    let mut result = Ok(());
+    for pending in pending {
+        if let Err(e) = (pending.write)()
+            && result.is_ok()
+        {
+            result = Err(e);
+        }
+    }
+    result
+}
+
+/// Locks the deferred saves, recovering them if a thread panicked while holding the lock.
+fn lock_pending() -> std::sync::MutexGuard<'static, HashMap<TypeId, PendingSave>> {
+    PENDING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::PersistentConfig;
+
+    macro_rules! window {
+        ($name:ident) => {
+            #[derive(Debug, Default, Serialize, Deserialize)]
+            struct $name {
+                width: u32,
+            }
+
+            impl PersistentConfigBuilder for $name {}
+        };
+    }
+
+    window!(Trailing);
+    window!(Flushed);
+
+    /// Registers `T` with `interval` in a fresh directory, returning its config file.
+    fn register<T: PersistentConfigBuilder>(name: &str, interval: Duration) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("persistent_config_test_throttle_{name}"));
+        _ = std::fs::remove_dir_all(&dir);
+        T::default()
+            .config_with_parameters(PersistentConfigParameters {
+                config_dir: dir.to_string_lossy().into_owned(),
+                file_name: name.to_string(),
+                min_save_interval: Some(interval),
+                ..Default::default()
+            })
+            .unwrap();
+        dir.join(format!("{name}.toml"))
+    }
+
+    fn width(file: &Path) -> String {
+        std::fs::read_to_string(file).unwrap().trim().to_string()
+    }
+
+    #[test]
+    fn last_save_of_a_burst_is_written_once_the_interval_is_over() -> Result<()> {
+        let file = register::<Trailing>("trailing", Duration::from_millis(500));
+        for width in 800..900 {
+            Trailing { width }.save()?;
+        }
+        // Only the first save was written
+        assert_eq!(width(&file), "width = 800");
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while width(&file) != "width = 899" && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(width(&file), "width = 899");
+        std::fs::remove_dir_all(file.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn flush_writes_the_pending_save_at_once() -> Result<()> {
+        let file = register::<Flushed>("flushed", Duration::from_secs(3600));
+        Flushed { width: 1 }.save()?;
+        Flushed { width: 2 }.save()?;
+        Flushed { width: 3 }.save()?;
+        assert_eq!(width(&file), "width = 1");
+
+        Flushed { width: 3 }.flush()?;
+        assert_eq!(width(&file), "width = 3");
+        // Nothing is pending anymore
+        Flushed { width: 4 }.flush()?;
+        assert_eq!(width(&file), "width = 3");
+        std::fs::remove_dir_all(file.parent().unwrap())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+//! Timeout around the file IO of load and save.
+//!
+//! The IO runs on a separate thread, the caller waits for its result at most for the
+//! configured timeout. A thread stuck on a hung file system can't be cancelled, it is left
+//! behind and its result is dropped once it completes.
+
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use persistent_config_core::PersistentConfigError;
+
+/// Runs `io` on a separate thread, failing with [`PersistentConfigError::Timeout`] if it
+/// does not complete within `timeout`.
+pub(crate) fn run<R, F>(timeout: Duration, path: &Path, io: F) -> Result<R>
+where
+    R: Send + 'static,
+    F: FnOnce() -> Result<R> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::sync_channel(1);
+    std::thread::Builder::new()
+        .name("persistent_config-io".to_string())
+        .spawn(move || {
+            // The receiver is gone if the caller timed out
+            _ = sender.send(io());
+        })?;
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => Err(PersistentConfigError::Timeout {
+            path: path.to_owned(),
+            timeout,
+        }
+        .into()),
+        Err(RecvTimeoutError::Disconnected) => Err(anyhow!("The IO thread for {:?} panicked", path)),
+    }
+}
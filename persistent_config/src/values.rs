@@ -0,0 +1,443 @@
+//! Field types read from human-readable values, such as `"30s"`, `"512MB"` or `"~/data"`.
+//!
+//! Each type is written back to the config file the way it is read, so a saved config stays
+//! readable:
+//!
+//! ```
+//! # use persistent_config::{ByteSize, ExpandablePath, HumanDuration};
+//! # use serde::{Deserialize, Serialize};
+//! #[derive(Debug, Serialize, Deserialize)]
+//! struct Cache {
+//!     ttl: HumanDuration,
+//!     max_size: ByteSize,
+//!     dir: ExpandablePath,
+//! }
+//!
+//! let cache: Cache = toml::from_str("ttl = \"1h30m\"\nmax_size = \"512MiB\"\ndir = \"/var/cache\"").unwrap();
+//! assert_eq!(cache.ttl.as_secs(), 5400);
+//! assert_eq!(cache.max_size.0, 512 * 1024 * 1024);
+//! assert_eq!(toml::to_string(&cache).unwrap(), "ttl = \"1h30m\"\nmax_size = \"512MiB\"\ndir = \"/var/cache\"\n");
+//! ```
+
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Error returned when a value can't be parsed into one of the field types of this module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseValueError {
+    /// Kind of value expected, such as `"duration"`.
+    pub kind: &'static str,
+    /// Value that could not be parsed.
+    pub input: String,
+    /// What went wrong.
+    pub reason: String,
+}
+
+impl ParseValueError {
+    fn new(kind: &'static str, input: &str, reason: impl Into<String>) -> Self {
+        Self {
+            kind,
+            input: input.to_owned(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid {} {:?}: {}", self.kind, self.input, self.reason)
+    }
+}
+
+impl std::error::Error for ParseValueError {}
+
+/// Value of a config file given as a number or as a string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Raw {
+    Number(u64),
+    Text(String),
+}
+
+/// Splits `input` into its numbers and their units, such as `[("1", "h"), ("30", "m")]` for
+/// `"1h30m"`.
+fn quantities(input: &str) -> Option<Vec<(&str, &str)>> {
+    let mut quantities = Vec::new();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(number_len);
+        let tail = tail.trim_start();
+        let unit_len = tail.find(|c: char| !c.is_alphabetic()).unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        if number.is_empty() {
+            return None;
+        }
+        quantities.push((number, unit));
+        rest = tail.trim_start();
+    }
+    (!quantities.is_empty()).then_some(quantities)
+}
+
+/// Returns `number` times `factor`, rounded for decimal numbers.
+fn scale(number: &str, factor: u128) -> Option<u128> {
+    match number.parse::<u128>() {
+        Ok(number) => number.checked_mul(factor),
+        Err(_) => {
+            let scaled = number.parse::<f64>().ok()? * factor as f64;
+            (scaled.is_finite() && scaled < u128::MAX as f64).then_some(scaled.round() as u128)
+        }
+    }
+}
+
+/// A [`Duration`] written as `"30s"`, `"1h30m"` or `"250ms"`.
+///
+/// The units are `ns`, `us` (or `µs`), `ms`, `s`, `m` (or `min`), `h` and `d`, and numbers
+/// may have decimals (`"1.5h"`). A bare number, in the file or in a string, is a number of
+/// seconds.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::HumanDuration;
+/// # use std::time::Duration;
+/// let timeout: HumanDuration = "1m 30s".parse().unwrap();
+/// assert_eq!(*timeout, Duration::from_secs(90));
+/// assert_eq!(timeout.to_string(), "1m30s");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HumanDuration(pub Duration);
+
+impl HumanDuration {
+    /// Units of the durations, with their length in nanoseconds.
+    const UNITS: [(&'static str, u128); 7] = [
+        ("d", 86_400_000_000_000),
+        ("h", 3_600_000_000_000),
+        ("m", 60_000_000_000),
+        ("s", 1_000_000_000),
+        ("ms", 1_000_000),
+        ("us", 1_000),
+        ("ns", 1),
+    ];
+}
+
+impl Deref for HumanDuration {
+    type Target = Duration;
+
+    fn deref(&self) -> &Duration {
+        &self.0
+    }
+}
+
+impl From<Duration> for HumanDuration {
+    fn from(duration: Duration) -> Self {
+        Self(duration)
+    }
+}
+
+impl From<HumanDuration> for Duration {
+    fn from(duration: HumanDuration) -> Self {
+        duration.0
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = ParseValueError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let error = |reason: &str| ParseValueError::new("duration", input, reason);
+        let quantities =
+            quantities(input).ok_or_else(|| error("expected a number and a unit, such as \"30s\" or \"1h30m\""))?;
+
+        let mut nanos: u128 = 0;
+        for (number, unit) in quantities {
+            let factor = match unit {
+                "" | "s" | "sec" | "secs" => 1_000_000_000,
+                "ns" => 1,
+                "us" | "µs" => 1_000,
+                "ms" => 1_000_000,
+                "m" | "min" | "mins" => 60_000_000_000,
+                "h" | "hr" | "hrs" => 3_600_000_000_000,
+                "d" | "day" | "days" => 86_400_000_000_000,
+                _ => return Err(error("unknown unit, use ns, us, ms, s, m, h or d")),
+            };
+            nanos = scale(number, factor)
+                .and_then(|scaled| nanos.checked_add(scaled))
+                .ok_or_else(|| error("expected a number and a unit, such as \"30s\" or \"1h30m\""))?;
+        }
+
+        let secs = u64::try_from(nanos / 1_000_000_000).map_err(|_| error("the duration is too long"))?;
+        Ok(Self(Duration::new(secs, (nanos % 1_000_000_000) as u32)))
+    }
+}
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut nanos = self.0.as_nanos();
+        if nanos == 0 {
+            return f.write_str("0s");
+        }
+        for (unit, length) in Self::UNITS {
+            if nanos >= length {
+                write!(f, "{}{}", nanos / length, unit)?;
+                nanos %= length;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for HumanDuration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(secs) => Ok(Self(Duration::from_secs(secs))),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// A number of bytes written as `"512MB"`, `"1.5GiB"` or `"64k"`.
+///
+/// The SI units (`KB`, `MB`, `GB`, `TB`, `PB`) are powers of 1000. The binary units (`KiB`,
+/// `MiB`, ...) and the single letters (`K`, `M`, `G`, `T`, `P`) are powers of 1024. Units
+/// ignore case, and a bare number is a number of bytes.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::ByteSize;
+/// assert_eq!("512MB".parse::<ByteSize>().unwrap().0, 512_000_000);
+/// assert_eq!("64k".parse::<ByteSize>().unwrap().0, 65_536);
+/// assert_eq!(ByteSize(3 * 1024 * 1024).to_string(), "3MiB");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    /// Binary units, from the largest.
+    const BINARY_UNITS: [(&'static str, u64); 5] = [
+        ("PiB", 1 << 50),
+        ("TiB", 1 << 40),
+        ("GiB", 1 << 30),
+        ("MiB", 1 << 20),
+        ("KiB", 1 << 10),
+    ];
+
+    /// SI units, from the largest.
+    const SI_UNITS: [(&'static str, u64); 5] = [
+        ("PB", 1_000_000_000_000_000),
+        ("TB", 1_000_000_000_000),
+        ("GB", 1_000_000_000),
+        ("MB", 1_000_000),
+        ("KB", 1_000),
+    ];
+}
+
+impl From<u64> for ByteSize {
+    fn from(bytes: u64) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<ByteSize> for u64 {
+    fn from(size: ByteSize) -> Self {
+        size.0
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = ParseValueError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let error = |reason: &str| ParseValueError::new("byte size", input, reason);
+        let quantities = quantities(input).unwrap_or_default();
+        let [(number, unit)] = quantities[..] else {
+            return Err(error("expected a number and a unit, such as \"512MB\" or \"1GiB\""));
+        };
+
+        let unit = unit.to_ascii_lowercase();
+        let factor = match unit.as_str() {
+            "" | "b" => 1,
+            "kb" => 1_000,
+            "mb" => 1_000_000,
+            "gb" => 1_000_000_000,
+            "tb" => 1_000_000_000_000,
+            "pb" => 1_000_000_000_000_000,
+            "k" | "kib" => 1 << 10,
+            "m" | "mib" => 1 << 20,
+            "g" | "gib" => 1 << 30,
+            "t" | "tib" => 1 << 40,
+            "p" | "pib" => 1 << 50,
+            _ => {
+                return Err(error(
+                    "unknown unit, use B, KB, MB, GB, TB, PB or KiB, MiB, GiB, TiB, PiB",
+                ));
+            }
+        };
+        scale(number, factor)
+            .and_then(|bytes| u64::try_from(bytes).ok())
+            .map(Self)
+            .ok_or_else(|| error("the size is too large"))
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = Self::BINARY_UNITS
+            .iter()
+            .chain(&Self::SI_UNITS)
+            .find(|(_, length)| self.0 != 0 && self.0.is_multiple_of(*length));
+        match unit {
+            Some((unit, length)) => write!(f, "{}{}", self.0 / length, unit),
+            None => write!(f, "{}B", self.0),
+        }
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(bytes) => Ok(Self(bytes)),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// A path with a leading `~` and environment variables (`$VAR` or `${VAR}`) expanded, such
+/// as `"~/data"` or `"${XDG_CACHE_HOME}/app"`.
+///
+/// The path is expanded when it is read, and saved as it was written. Reading fails if a
+/// variable is not set, or the home directory is unknown.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::ExpandablePath;
+/// # use std::path::Path;
+/// let dir: ExpandablePath = "~/data".parse().unwrap();
+/// let home = std::env::var("HOME").unwrap();
+/// assert_eq!(dir.as_path(), Path::new(&format!("{}/data", home)));
+/// assert_eq!(dir.to_string(), "~/data");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ExpandablePath {
+    /// The path as written in the config file.
+    raw: String,
+    /// The expanded path.
+    path: PathBuf,
+}
+
+impl ExpandablePath {
+    /// Returns the expanded path.
+    pub fn as_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path as written in the config file.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+}
+
+impl Deref for ExpandablePath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for ExpandablePath {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl FromStr for ExpandablePath {
+    type Err = ParseValueError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let error = |reason: String| ParseValueError::new("path", input, reason);
+        let var = |name: &str| std::env::var(name).map_err(|_| error(format!("`{}` is not set", name)));
+
+        let mut expanded = String::new();
+        let mut rest = input;
+        if let Some(tail) = input.strip_prefix('~')
+            && (tail.is_empty() || tail.starts_with(['/', '\\']))
+        {
+            let home = var("HOME")
+                .or_else(|e| if cfg!(windows) { var("USERPROFILE") } else { Err(e) })
+                .map_err(|_| error("the home directory is unknown".to_owned()))?;
+            expanded.push_str(&home);
+            rest = tail;
+        }
+
+        while let Some(start) = rest.find('$') {
+            expanded.push_str(&rest[..start]);
+            let tail = &rest[start + 1..];
+            let (name, after) = match tail.strip_prefix('{') {
+                Some(braced) => {
+                    let end = braced.find('}').ok_or_else(|| error("unclosed `${`".to_owned()))?;
+                    (&braced[..end], &braced[end + 1..])
+                }
+                None => {
+                    let end = tail
+                        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                        .unwrap_or(tail.len());
+                    (&tail[..end], &tail[end..])
+                }
+            };
+            if name.is_empty() {
+                expanded.push('$');
+            } else {
+                expanded.push_str(&var(name)?);
+            }
+            rest = after;
+        }
+        expanded.push_str(rest);
+
+        Ok(Self {
+            raw: input.to_owned(),
+            path: PathBuf::from(expanded),
+        })
+    }
+}
+
+impl fmt::Display for ExpandablePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+impl Serialize for ExpandablePath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExpandablePath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
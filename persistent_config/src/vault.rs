@@ -0,0 +1,234 @@
+//! Resolver of the secrets stored in the KV version 2 secrets engine of HashiCorp Vault.
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::Value;
+
+use crate::SecretResolver;
+
+/// Sends a request to Vault and returns the body of the response, see [`VaultResolver`].
+type FetchFn = Box<dyn Fn(&VaultRequest) -> Result<String> + Send + Sync>;
+
+/// A `GET` request to the Vault HTTP API, sent by the function given to [`VaultResolver`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultRequest {
+    /// URL of the secret, such as `https://vault:8200/v1/secret/data/my_app/database`.
+    pub url: String,
+    /// Headers of the request, the `X-Vault-Token` and the optional `X-Vault-Namespace`.
+    pub headers: Vec<(&'static str, String)>,
+}
+
+/// [`SecretResolver`] of the `vault://<mount>/<path>#<key>` references, reading the secrets
+/// of a KV version 2 secrets engine.
+///
+/// The crate never opens a network connection itself: the requests are sent by the `fetch`
+/// function, with the HTTP client and TLS settings of the application, and return the body
+/// of the response. A request failing or answered with an error status must return an error.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use persistent_config::{VaultRequest, VaultResolver};
+/// # fn main() -> anyhow::Result<()> {
+/// let vault = VaultResolver::new("https://vault:8200", "s.token", |request: &VaultRequest| {
+///     assert_eq!(request.url, "https://vault:8200/v1/secret/data/my_app/database");
+///     // Sent with the HTTP client of the application
+///     Ok(r#"{ "data": { "data": { "password": "s3cr3t" } } }"#.to_string())
+/// });
+/// assert_eq!(vault.resolve("secret/my_app/database", Some("password"))?, "s3cr3t");
+/// set_secret_resolver("vault", vault);
+/// # Ok(())
+/// # }
+/// ```
+pub struct VaultResolver {
+    /// Address of the Vault server, without the trailing `/`.
+    address: String,
+    /// Token the requests are authenticated with.
+    token: String,
+    /// Namespace of the secrets, Vault Enterprise only.
+    namespace: Option<String>,
+    /// Sends the requests.
+    fetch: FetchFn,
+}
+
+impl VaultResolver {
+    /// Returns a resolver reading the secrets from the Vault server at `address`, with the
+    /// requests sent by `fetch`.
+    pub fn new<F>(address: impl Into<String>, token: impl Into<String>, fetch: F) -> Self
+    where
+        F: Fn(&VaultRequest) -> Result<String> + Send + Sync + 'static,
+    {
+        let mut address = address.into();
+        while address.ends_with('/') {
+            address.pop();
+        }
+        Self {
+            address,
+            token: token.into(),
+            namespace: None,
+            fetch: Box::new(fetch),
+        }
+    }
+
+    /// Returns a resolver configured by the `VAULT_ADDR`, `VAULT_TOKEN` and optional
+    /// `VAULT_NAMESPACE` environment variables, as the Vault CLI.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `VAULT_ADDR` or `VAULT_TOKEN` is not set.
+    pub fn from_env<F>(fetch: F) -> Result<Self>
+    where
+        F: Fn(&VaultRequest) -> Result<String> + Send + Sync + 'static,
+    {
+        let address = std::env::var("VAULT_ADDR").context("VAULT_ADDR is not set")?;
+        let token = std::env::var("VAULT_TOKEN").context("VAULT_TOKEN is not set")?;
+        let resolver = Self::new(address, token, fetch);
+        Ok(match std::env::var("VAULT_NAMESPACE") {
+            Ok(namespace) if !namespace.is_empty() => resolver.with_namespace(namespace),
+            _ => resolver,
+        })
+    }
+
+    /// Reads the secrets in `namespace`, Vault Enterprise only.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+}
+
+impl std::fmt::Debug for VaultResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VaultResolver")
+            .field("address", &self.address)
+            .field("token", &"***")
+            .field("namespace", &self.namespace)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SecretResolver for VaultResolver {
+    /// Reads the secret at `path`, its first segment being the mount of the secrets engine,
+    /// and returns the value of `key` in it.
+    fn resolve(&self, path: &str, key: Option<&str>) -> Result<String> {
+        let Some(key) = key else {
+            bail!("A Vault reference needs the `#key` of the value in the secret");
+        };
+        let Some((mount, secret)) = path.trim_start_matches('/').split_once('/') else {
+            bail!("The Vault path {:?} has no mount, such as `secret/{}`", path, path);
+        };
+
+        let mut headers = vec![("X-Vault-Token", self.token.clone())];
+        if let Some(namespace) = &self.namespace {
+            headers.push(("X-Vault-Namespace", namespace.clone()));
+        }
+        let request = VaultRequest {
+            url: format!("{}/v1/{}/data/{}", self.address, mount, secret),
+            headers,
+        };
+        let body = (self.fetch)(&request)?;
+        let response: Value = serde_json::from_str(&body).context("Invalid response from Vault")?;
+        match response.pointer("/data/data").and_then(|data| data.get(key)) {
+            Some(Value::String(value)) => Ok(value.clone()),
+            Some(value) => Ok(value.to_string()),
+            None => Err(anyhow!("The Vault secret {:?} has no key `{}`", path, key)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex, PoisonError};
+
+    use persistent_config_core::PersistentConfigParameters;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::secrets::TEST_SERIAL;
+    use crate::{PersistentConfig, PersistentConfigBuilder, set_secret_resolver};
+
+    const SECRET: &str = r#"{ "data": { "data": { "password": "s3cr3t", "port": 5432 } } }"#;
+
+    #[test]
+    fn sends_the_request_of_the_secret() -> Result<()> {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let sent = requests.clone();
+        let vault = VaultResolver::new("https://vault:8200/", "s.token", move |request: &VaultRequest| {
+            sent.lock().unwrap().push(request.clone());
+            Ok(SECRET.to_string())
+        })
+        .with_namespace("team");
+
+        assert_eq!(vault.resolve("/secret/app/db", Some("password"))?, "s3cr3t");
+        assert_eq!(vault.resolve("secret/app/db", Some("port"))?, "5432");
+        let expected = VaultRequest {
+            url: "https://vault:8200/v1/secret/data/app/db".to_string(),
+            headers: vec![("X-Vault-Token", "s.token".to_string()), ("X-Vault-Namespace", "team".to_string())],
+        };
+        assert_eq!(*requests.lock().unwrap(), [expected.clone(), expected]);
+        Ok(())
+    }
+
+    #[test]
+    fn reports_invalid_references_and_responses() {
+        let vault = VaultResolver::new("https://vault:8200", "s.token", |request: &VaultRequest| {
+            match request.url.as_str() {
+                "https://vault:8200/v1/secret/data/down" => bail!("connection refused"),
+                "https://vault:8200/v1/secret/data/html" => Ok("<html>".to_string()),
+                _ => Ok(SECRET.to_string()),
+            }
+        });
+
+        let error = |path, key| vault.resolve(path, key).unwrap_err().to_string();
+        assert!(error("secret/app/db", None).contains("#key"));
+        assert!(error("db", Some("password")).contains("no mount"));
+        assert!(error("secret/app/db", Some("user")).contains("no key `user`"));
+        assert!(error("secret/down", Some("password")).contains("connection refused"));
+        assert!(error("secret/html", Some("password")).contains("Invalid response"));
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    struct Database {
+        user: String,
+        password: String,
+    }
+
+    impl PersistentConfigBuilder for Database {}
+
+    #[test]
+    fn saving_reuses_the_resolved_secret() -> Result<()> {
+        let _serial = TEST_SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        let dir = std::env::temp_dir().join("persistent_config_test_vault");
+        _ = std::fs::remove_dir_all(&dir);
+        let file = dir.join("Database.toml");
+        Database::default().config_with_parameters(PersistentConfigParameters {
+            config_dir: dir.to_string_lossy().into_owned(),
+            file_name: "Database".to_string(),
+            ..Default::default()
+        })?;
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(&file, "user = \"app\"\npassword = \"vault://secret/app/db#password\"\n")?;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        set_secret_resolver(
+            "vault",
+            VaultResolver::new("https://vault:8200", "s.token", move |_: &VaultRequest| {
+                counted.fetch_add(1, Ordering::Relaxed);
+                Ok(SECRET.to_string())
+            }),
+        );
+
+        let mut database = Database::default();
+        database.load()?;
+        assert_eq!(database.password, "s3cr3t");
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        database.user = "admin".to_string();
+        database.save()?;
+        assert!(std::fs::read_to_string(&file)?.contains("vault://secret/app/db#password"));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
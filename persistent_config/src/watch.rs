@@ -0,0 +1,75 @@
+//! Reload notifications when another process saves the config file.
+//!
+//! A background thread checks the stamp of the config file at a fixed interval, the same way
+//! as [`is_stale`](crate::PersistentConfig::is_stale). Every process using the file can
+//! watch it, and is notified of the saves made by the others: its own saves mark the file as
+//! synced, so they are not reported back to it.
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::Result;
+use persistent_config_core::PersistentConfigParameters;
+
+use crate::cache::FileStamp;
+use crate::{PersistentConfigBuilder, cache, config_file_path, hooks, load_checked};
+
+/// Handle of a watch started by [`shared_watch`](crate::PersistentConfig::shared_watch).
+///
+/// The watch runs until the handle is dropped or [`stop`](Self::stop) is called.
+#[derive(Debug)]
+pub struct WatchHandle {
+    /// Dropped to stop the watching thread.
+    stop: Option<Sender<()>>,
+    /// Watching thread.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WatchHandle {
+    /// Stops watching, and waits for a notification in progress to complete.
+    pub fn stop(self) {}
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take()
+            && thread.thread().id() != std::thread::current().id()
+        {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Starts a thread calling `on_change` with the reloaded config whenever the file of `T`
+/// changed since it was last loaded or saved in this process.
+pub(crate) fn start<T, F>(params: PersistentConfigParameters, interval: Duration, mut on_change: F) -> WatchHandle
+where
+    T: PersistentConfigBuilder,
+    F: FnMut(Result<T>) + Send + 'static,
+{
+    let (stop, stopped) = mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || {
+        let file_path = config_file_path(&params);
+        // A file that failed to load is reported once, until it changes again
+        let mut failed = None;
+        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+            let stamp = FileStamp::of(&file_path);
+            if stamp.is_none() || stamp == failed || !cache::is_stale::<T>(&file_path) {
+                continue;
+            }
+            cache::invalidate::<T>();
+            let loaded = load_checked::<T>(&params);
+            failed = loaded.is_err().then_some(stamp).flatten();
+            if let Ok(value) = &loaded {
+                hooks::field_changes(value);
+            }
+            on_change(loaded);
+        }
+    });
+    WatchHandle {
+        stop: Some(stop),
+        thread: Some(thread),
+    }
+}
@@ -0,0 +1,151 @@
+//! Integration with the [axum](https://docs.rs/axum) web framework, with the `axum` feature.
+//!
+//! A [`PersistentCell`] is the state of the router, or a part of it through [`FromRef`]:
+//! the handlers extract a copy of the current config with [`ConfigExtractor`], and the
+//! [`refresh_config`] handler reloads it from disk, so the config can be changed without
+//! restarting the service.
+
+use std::convert::Infallible;
+
+use axum::extract::{FromRef, FromRequestParts, State};
+use axum::http::StatusCode;
+use axum::http::request::Parts;
+
+use crate::{PersistentCell, PersistentConfig};
+
+/// Extractor of a copy of the config held by the [`PersistentCell`] state of the router.
+///
+/// The copy is taken when the request is received, a reload while the request is handled
+/// doesn't change it.
+///
+/// # Example
+///
+/// ```
+/// # use persistent_config::prelude::*;
+/// # use persistent_config::{ConfigExtractor, refresh_config};
+/// # use serde::{Deserialize, Serialize};
+/// use axum::Router;
+/// use axum::routing::{get, post};
+///
+/// # #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+/// # struct MyConfig { greeting: String }
+/// # impl PersistentConfigBuilder for MyConfig {}
+/// async fn greet(ConfigExtractor(config): ConfigExtractor<MyConfig>) -> String {
+///     config.greeting
+/// }
+///
+/// # fn main() -> anyhow::Result<()> {
+/// # let mut my_config = MyConfig::default();
+/// # my_config.default_save_config(false)?;
+/// let config = PersistentCell::new(my_config);
+/// let app: Router = Router::new()
+///     .route("/", get(greet))
+///     .route("/admin/config/refresh", post(refresh_config::<MyConfig>))
+///     .with_state(config);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConfigExtractor<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for ConfigExtractor<T>
+where
+    T: PersistentConfig + Clone + Send + Sync,
+    PersistentCell<T>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(PersistentCell::<T>::from_ref(state).read().clone()))
+    }
+}
+
+/// Handler reloading the config held by the [`PersistentCell`] state of the router from
+/// disk, see [`ConfigExtractor`].
+///
+/// Answers `204 No Content` once reloaded, or `500 Internal Server Error` with the error if
+/// the config can't be loaded. The file is read on the blocking thread pool of the Tokio
+/// runtime serving the router, not to stall the other requests. The handler should be
+/// mounted on a route only the administrators of the service can reach.
+pub async fn refresh_config<T>(State(config): State<PersistentCell<T>>) -> Result<StatusCode, (StatusCode, String)>
+where
+    T: PersistentConfig + Send + Sync,
+{
+    tokio::task::spawn_blocking(move || config.reload())
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|reloaded| reloaded)
+        .map(|()| StatusCode::NO_CONTENT)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::Router;
+    use axum::body::{Body, to_bytes};
+    use axum::http::Request;
+    use axum::routing::{get, post};
+    use persistent_config_core::PersistentConfigParameters;
+    use serde::{Deserialize, Serialize};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::PersistentConfigBuilder;
+
+    #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+    struct Greeting {
+        text: String,
+    }
+
+    impl PersistentConfigBuilder for Greeting {}
+
+    async fn greet(ConfigExtractor(config): ConfigExtractor<Greeting>) -> String {
+        config.text
+    }
+
+    /// Sends a request to `app` and returns the status and body of the response.
+    async fn send(app: &Router, method: &str, uri: &str) -> (StatusCode, String) {
+        let request = Request::builder().method(method).uri(uri).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn refresh_reloads_the_config() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join("persistent_config_test_web");
+        _ = std::fs::remove_dir_all(&dir);
+        let file = dir.join("Greeting.toml");
+        let greeting = Greeting { text: "hello".to_string() };
+        greeting.config_with_parameters(PersistentConfigParameters {
+            config_dir: dir.to_string_lossy().into_owned(),
+            file_name: "Greeting".to_string(),
+            ..Default::default()
+        })?;
+        greeting.save()?;
+
+        let app = Router::new()
+            .route("/", get(greet))
+            .route("/refresh", post(refresh_config::<Greeting>))
+            .with_state(PersistentCell::<Greeting>::load()?);
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        runtime.block_on(async {
+            assert_eq!(send(&app, "GET", "/").await, (StatusCode::OK, "hello".to_string()));
+
+            std::fs::write(&file, "text = \"bonjour\"\n").unwrap();
+            assert_eq!(send(&app, "GET", "/").await.1, "hello");
+            assert_eq!(send(&app, "POST", "/refresh").await.0, StatusCode::NO_CONTENT);
+            assert_eq!(send(&app, "GET", "/").await.1, "bonjour");
+
+            std::fs::write(&file, "text = [").unwrap();
+            let (status, error) = send(&app, "POST", "/refresh").await;
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+            assert!(error.contains("Failed to load"), "{error}");
+            assert_eq!(send(&app, "GET", "/").await.1, "bonjour");
+        });
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
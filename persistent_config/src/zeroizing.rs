@@ -0,0 +1,67 @@
+//! Buffers that wipe their contents, used when the `zeroize` feature is enabled.
+//!
+//! The regular save and load paths go through `BufWriter`/`BufReader`, whose internal
+//! buffers are freed without being cleared. With the `zeroize` feature, serialized data
+//! only transits through the buffers below, which are zeroized once they are dropped.
+
+use std::io::{self, Read, Write};
+
+use zeroize::Zeroizing;
+
+/// Capacity of the [`ZeroizingWriter`] buffer.
+const BUFFER_SIZE: usize = 8 * 1024;
+
+/// Buffered writer whose buffer never reallocates and is wiped on drop.
+pub(crate) struct ZeroizingWriter<W: Write> {
+    inner: W,
+    buffer: Zeroizing<Vec<u8>>,
+}
+
+impl<W: Write> ZeroizingWriter<W> {
+    /// Creates a new writer around `inner`.
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buffer: Zeroizing::new(Vec::with_capacity(BUFFER_SIZE)),
+        }
+    }
+
+    /// Flushes the buffer and returns the inner writer.
+    pub(crate) fn into_inner(mut self) -> io::Result<W> {
+        self.flush_buffer()?;
+        Ok(self.inner)
+    }
+
+    /// Writes the buffered data to the inner writer and clears the buffer.
+    fn flush_buffer(&mut self) -> io::Result<()> {
+        self.inner.write_all(&self.buffer)?;
+        zeroize::Zeroize::zeroize(&mut *self.buffer);
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ZeroizingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buffer.len() == BUFFER_SIZE {
+            self.flush_buffer()?;
+        }
+        // Never write past the capacity, so the buffer is never moved to a new allocation
+        let len = buf.len().min(BUFFER_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_buffer()?;
+        self.inner.flush()
+    }
+}
+
+/// Reads `reader` to the end into a buffer wiped on drop.
+///
+/// `size_hint` should be the expected size in bytes, so the buffer is allocated once.
+pub(crate) fn read_to_end(mut reader: impl Read, size_hint: u64) -> io::Result<Zeroizing<Vec<u8>>> {
+    let mut content = Zeroizing::new(Vec::with_capacity(size_hint.saturating_add(1) as usize));
+    reader.read_to_end(&mut content)?;
+    Ok(content)
+}
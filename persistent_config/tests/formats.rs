@@ -0,0 +1,224 @@
+//! Saving and loading the config files in each format.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+struct Server {
+    host: String,
+    port: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    name: String,
+    enabled: bool,
+    ratio: f64,
+    tags: Vec<String>,
+    server: Server,
+    servers: Vec<Server>,
+    labels: BTreeMap<String, String>,
+    nickname: Option<String>,
+}
+
+impl Settings {
+    fn example() -> Self {
+        Settings {
+            name: "example\twith = escapes: é".to_owned(),
+            enabled: true,
+            ratio: 0.5,
+            tags: vec!["a".to_owned(), "b".to_owned()],
+            server: Server {
+                host: "localhost".to_owned(),
+                port: 8080,
+            },
+            servers: vec![Server {
+                host: "a.example.org".to_owned(),
+                port: 443,
+            }],
+            labels: BTreeMap::from([("team".to_owned(), "core".to_owned())]),
+            nickname: None,
+        }
+    }
+}
+
+/// Declares a config type wrapping [`Settings`], registered on its own.
+macro_rules! config_type {
+    ($name:ident) => {
+        #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+        struct $name(Settings);
+
+        impl PersistentConfigBuilder for $name {}
+    };
+}
+
+/// Returns an empty directory named after `test`.
+fn test_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("persistent_config_test_{}", test));
+    _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Returns the parameters saving in `save_format` to `dir`, returning the errors.
+fn parameters(dir: &Path, save_format: SaveFormat) -> PersistentConfigParameters {
+    PersistentConfigParameters {
+        config_dir: dir.to_string_lossy().into_owned(),
+        file_name: "settings".to_owned(),
+        save_format,
+        panic_on_error: false,
+        ..Default::default()
+    }
+}
+
+config_type!(PropertiesSettings);
+
+#[test]
+fn properties_round_trip() -> anyhow::Result<()> {
+    let dir = test_dir("properties_round_trip");
+    let saved = PropertiesSettings(Settings::example());
+    saved.config_with_parameters(parameters(&dir, SaveFormat::Properties))?;
+    saved.save()?;
+
+    let text = std::fs::read_to_string(dir.join("settings.properties"))?;
+    assert!(text.contains("name=example\\twith = escapes: \\u00E9\n"));
+    assert!(text.contains("server.port=8080\n"));
+    assert!(text.contains("servers[0].host=a.example.org\n"));
+
+    let mut loaded = PropertiesSettings::default();
+    loaded.load()?;
+    assert_eq!(loaded, saved);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+config_type!(PropertiesFile);
+
+#[test]
+fn properties_load_written_by_hand() -> anyhow::Result<()> {
+    let dir = test_dir("properties_load_written_by_hand");
+    PropertiesFile::default().config_with_parameters(parameters(&dir, SaveFormat::Properties))?;
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(
+        dir.join("settings.properties"),
+        "# Written by hand\n\
+         ! with both comment styles\n\
+         name : caf\\u00e9\n\
+         enabled true\n\
+         ratio=0.25\n\
+         tags = a, \\\n    b\n\
+         server.host=localhost\n\
+         server.port=\\ 80\n",
+    )?;
+
+    let mut loaded = PropertiesFile::default();
+    loaded.load()?;
+    assert_eq!(loaded.0.name, "café");
+    assert!(loaded.0.enabled);
+    assert_eq!(loaded.0.ratio, 0.25);
+    assert_eq!(loaded.0.tags, ["a", "b"]);
+    assert_eq!(loaded.0.server, Server { host: "localhost".to_owned(), port: 80 });
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[cfg(feature = "cbor")]
+config_type!(CborSettings);
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_round_trip() -> anyhow::Result<()> {
+    let dir = test_dir("cbor_round_trip");
+    let saved = CborSettings(Settings::example());
+    saved.config_with_parameters(parameters(&dir, SaveFormat::CBOR))?;
+    saved.save()?;
+
+    let bytes = std::fs::read(dir.join("settings.cbor"))?;
+    assert!(!bytes.starts_with(b"{"));
+
+    let mut loaded = CborSettings::default();
+    loaded.load()?;
+    assert_eq!(loaded, saved);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[cfg(feature = "cbor")]
+config_type!(CborCorrupt);
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_rejects_truncated_file() -> anyhow::Result<()> {
+    let dir = test_dir("cbor_rejects_truncated_file");
+    let saved = CborCorrupt(Settings::example());
+    saved.config_with_parameters(parameters(&dir, SaveFormat::CBOR))?;
+    saved.save()?;
+    let path = dir.join("settings.cbor");
+    let bytes = std::fs::read(&path)?;
+    std::fs::write(&path, &bytes[..bytes.len() / 2])?;
+
+    let mut loaded = CborCorrupt::default();
+    let outcome = loaded.load_with_outcome();
+    assert!(!matches!(outcome, Ok(LoadOutcome::Loaded)), "{:?}", outcome);
+    assert_eq!(loaded, CborCorrupt::default());
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[cfg(feature = "hcl")]
+config_type!(HclSettings);
+
+#[cfg(feature = "hcl")]
+#[test]
+fn hcl_load() -> anyhow::Result<()> {
+    let dir = test_dir("hcl_load");
+    HclSettings::default().config_with_parameters(parameters(&dir, SaveFormat::HCL))?;
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(
+        dir.join("settings.hcl"),
+        r#"
+        # Written by hand
+        name    = "example"
+        enabled = true
+        ratio   = 0.5
+        tags    = ["a", "b"]
+
+        server {
+          host = "localhost"
+          port = 8080
+        }
+
+        servers = [{ host = "a.example.org", port = 443 }]
+        labels = { team = "core" }
+        "#,
+    )?;
+
+    let mut loaded = HclSettings::default();
+    loaded.load()?;
+    let expected = Settings {
+        name: "example".to_owned(),
+        ..Settings::example()
+    };
+    assert_eq!(loaded.0, expected);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[cfg(feature = "hcl")]
+config_type!(HclReadOnly);
+
+#[cfg(feature = "hcl")]
+#[test]
+fn hcl_save_is_refused() -> anyhow::Result<()> {
+    let dir = test_dir("hcl_save_is_refused");
+    let settings = HclReadOnly(Settings::example());
+    settings.config_with_parameters(parameters(&dir, SaveFormat::HCL))?;
+
+    assert!(settings.save().is_err());
+    assert!(!dir.join("settings.hcl").exists());
+    _ = std::fs::remove_dir_all(&dir);
+    Ok(())
+}
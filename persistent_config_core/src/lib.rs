@@ -5,19 +5,222 @@
 //! This module provides the [`PersistentConfigDB`] for storing configuration parameters
 //! for different types, as well as the [`SaveFormat`] enum and related helpers.
 
-use std::any::TypeId;
+use std::any::{Any, TypeId};
 use std::collections::HashMap;
-use std::fmt::Debug;
-use std::sync::{LazyLock, RwLock};
+use std::fmt::{self, Debug, Display};
+use std::panic::Location;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, SystemTime};
 
 /// Re-exported error and result types from `anyhow`.
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
 /// Global static database for persistent configuration parameters.
-pub static PERSISTENT_CONFIGS: LazyLock<PersistentConfigDB> = LazyLock::new(|| PersistentConfigDB::default());
+pub static PERSISTENT_CONFIGS: LazyLock<PersistentConfigDB> = LazyLock::new(PersistentConfigDB::default);
+
+/// Errors reported by persistent configuration operations.
+///
+/// These are returned wrapped in an [`anyhow::Error`], use `downcast_ref` to match on them.
+#[derive(Debug)]
+pub enum PersistentConfigError {
+    /// The config file is bigger than the configured `max_file_size`.
+    FileTooLarge {
+        /// Path of the offending file.
+        path: PathBuf,
+        /// Size of the file in bytes (at least `max_file_size + 1` if it could not be determined upfront).
+        size: u64,
+        /// Configured maximum size in bytes.
+        max_file_size: u64,
+    },
+    /// The type is already registered with different parameters.
+    RegistrationConflict {
+        /// Name of the registered type.
+        type_name: &'static str,
+        /// Parameters currently registered.
+        existing: Box<PersistentConfigParameters>,
+        /// Source location of the current registration.
+        location: &'static Location<'static>,
+        /// Parameters that were rejected.
+        requested: Box<PersistentConfigParameters>,
+    },
+    /// The type was saved or loaded before being registered.
+    NotRegistered {
+        /// Name of the unregistered type.
+        type_name: &'static str,
+    },
+    /// The config file has no envelope, while the parameters require one.
+    MissingEnvelope {
+        /// Path of the offending file.
+        path: PathBuf,
+    },
+    /// Reading or writing the config file took longer than the configured `timeout`.
+    Timeout {
+        /// Path of the config file.
+        path: PathBuf,
+        /// Configured timeout.
+        timeout: Duration,
+    },
+    /// A field holds a value rejected by its constraints.
+    InvalidValue {
+        /// On-disk key of the field.
+        field: String,
+        /// Why the value was rejected.
+        message: String,
+    },
+    /// The config file can be tampered with by other users, and `permission_check` is
+    /// [`PermissionCheck::Deny`].
+    InsecurePermissions {
+        /// Path of the offending file.
+        path: PathBuf,
+        /// Problems found, in plain words.
+        problems: Vec<String>,
+    },
+    /// The config file is a symbolic link, or has several hard links, and `symlinks` is
+    /// [`SymlinkPolicy::Refuse`].
+    UnsafeLink {
+        /// Path of the offending file.
+        path: PathBuf,
+        /// What is wrong with the file, in plain words.
+        reason: String,
+    },
+    /// The type was saved or registered again after its registration was frozen.
+    Frozen {
+        /// Name of the frozen type.
+        type_name: &'static str,
+    },
+    /// A type loaded at startup depends on a type that isn't.
+    MissingDependency {
+        /// Name of the type loaded at startup.
+        type_name: &'static str,
+        /// Name of the dependency not loaded at startup.
+        dependency: &'static str,
+    },
+    /// The types loaded at startup depend on each other.
+    DependencyCycle {
+        /// Names of the types of the cycle, each depending on the next one, the first type
+        /// being repeated at the end.
+        cycle: Vec<&'static str>,
+    },
+    /// The signature of the config file is missing or doesn't verify, and the signature
+    /// policy is [`SignaturePolicy::Deny`].
+    InvalidSignature {
+        /// Path of the offending file.
+        path: PathBuf,
+        /// What is wrong with the signature, in plain words.
+        reason: String,
+    },
+}
+
+impl Display for PersistentConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistentConfigError::FileTooLarge {
+                path,
+                size,
+                max_file_size,
+            } => write!(
+                f,
+                "Config file {:?} is too large: {} bytes, the maximum is {} bytes",
+                path, size, max_file_size
+            ),
+            PersistentConfigError::RegistrationConflict {
+                type_name,
+                existing,
+                location,
+                requested,
+            } => write!(
+                f,
+                "Type {} is already registered at {} with different parameters: existing {:?}, requested {:?}",
+                type_name, location, existing, requested
+            ),
+            PersistentConfigError::NotRegistered { type_name } => write!(
+                f,
+                "No persistent config found for type {}: register it first with `default_save_config()`, \
+                 `config_builder()` or `config_with_parameters()`, or derive `Persistent` with \
+                 `#[persistent(config_dir = \"...\")]` to register it automatically",
+                type_name
+            ),
+            PersistentConfigError::MissingEnvelope { path } => write!(
+                f,
+                "Config file {:?} has no envelope, set `accept_legacy` to load it as a bare payload",
+                path
+            ),
+            PersistentConfigError::Timeout { path, timeout } => {
+                write!(f, "Timed out after {:?} accessing config file {:?}", timeout, path)
+            }
+            PersistentConfigError::InvalidValue { field, message } => {
+                write!(f, "Invalid value of `{}`: {}", field, message)
+            }
+            PersistentConfigError::InsecurePermissions { path, problems } => write!(
+                f,
+                "Config file {:?} can be tampered with by other users: {}",
+                path,
+                problems.join(", ")
+            ),
+            PersistentConfigError::UnsafeLink { path, reason } => {
+                write!(f, "Refusing to use config file {:?}: it {}", path, reason)
+            }
+            PersistentConfigError::Frozen { type_name } => write!(
+                f,
+                "The persistent config of type {} is frozen: it can't be saved or registered again",
+                type_name
+            ),
+            PersistentConfigError::MissingDependency { type_name, dependency } => write!(
+                f,
+                "Type {} depends on type {}, which is not loaded at startup: declare it with `load_on_startup()`",
+                type_name, dependency
+            ),
+            PersistentConfigError::DependencyCycle { cycle } => {
+                write!(f, "The configs loaded at startup depend on each other: {}", cycle.join(" -> "))
+            }
+            PersistentConfigError::InvalidSignature { path, reason } => {
+                write!(f, "Refusing to load config file {:?}: {}", path, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PersistentConfigError {}
+
+/// Error returned when parsing an unknown [`SaveFormat`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsupportedFormatError {
+    /// The string that could not be parsed.
+    pub format: String,
+}
+
+impl Display for UnsupportedFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unsupported format {:?}: use 'json', 'toml', 'yaml', 'properties', 'cbor' or 'hcl'",
+            self.format
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedFormatError {}
 
 /// Supported formats for saving configuration files.
-#[derive(Debug, Clone, Copy, Default, PartialEq)]
+///
+/// Formats are serialized and displayed as their lowercase name, which is also their file
+/// extension. Parsing ignores case, a leading `.` and accepts common aliases (`yml`, `tml`,
+/// `props`):
+///
+/// ```
+/// # use persistent_config_core::*;
+/// assert_eq!(SaveFormat::YAML.to_string(), "yaml");
+/// assert_eq!("properties".parse(), Ok(SaveFormat::Properties));
+/// assert_eq!("YML".parse(), Ok(SaveFormat::YAML));
+/// assert_eq!(SaveFormat::try_from(".Json"), Ok(SaveFormat::JSON));
+/// assert!(SaveFormat::try_from("xml").is_err());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", try_from = "String")]
 pub enum SaveFormat {
     /// JSON format (`.json`)
     JSON,
@@ -26,6 +229,14 @@ pub enum SaveFormat {
     TOML,
     /// YAML format (`.yaml`)
     YAML,
+    /// Java properties format (`.properties`), with flat `key=value` lines and dotted keys
+    /// for nested values
+    Properties,
+    /// CBOR binary format (`.cbor`), requires the `cbor` feature of `persistent_config`
+    CBOR,
+    /// HashiCorp configuration language (`.hcl`), load only, requires the `hcl` feature of
+    /// `persistent_config`
+    HCL,
 }
 
 impl SaveFormat {
@@ -35,10 +246,28 @@ impl SaveFormat {
             SaveFormat::JSON => "json",
             SaveFormat::TOML => "toml",
             SaveFormat::YAML => "yaml",
+            SaveFormat::Properties => "properties",
+            SaveFormat::CBOR => "cbor",
+            SaveFormat::HCL => "hcl",
         }
     }
 }
 
+impl Display for SaveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.ext())
+    }
+}
+
+/// Parses a [`SaveFormat`] from its name or an alias, ignoring case.
+impl FromStr for SaveFormat {
+    type Err = UnsupportedFormatError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        SaveFormat::try_from(value)
+    }
+}
+
 /// Converts a [`SaveFormat`] to its string representation.
 impl TryFrom<SaveFormat> for String {
     type Error = &'static str;
@@ -48,57 +277,351 @@ impl TryFrom<SaveFormat> for String {
             SaveFormat::JSON => Ok("json".to_string()),
             SaveFormat::TOML => Ok("toml".to_string()),
             SaveFormat::YAML => Ok("yaml".to_string()),
+            SaveFormat::Properties => Ok("properties".to_string()),
+            SaveFormat::CBOR => Ok("cbor".to_string()),
+            SaveFormat::HCL => Ok("hcl".to_string()),
         }
     }
 }
 
-/// Parses a [`SaveFormat`] from a string slice.
+/// Parses a [`SaveFormat`] from a string slice, see [`SaveFormat`] for the accepted names.
 impl TryFrom<&'_ str> for SaveFormat {
-    type Error = &'static str;
+    type Error = UnsupportedFormatError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value {
+        let name = value.strip_prefix('.').unwrap_or(value).to_ascii_lowercase();
+        match name.as_str() {
             "json" => Ok(SaveFormat::JSON),
-            "toml" => Ok(SaveFormat::TOML),
-            "yaml" => Ok(SaveFormat::YAML),
-            _ => Err("Unsupported format: use 'json', 'toml', or 'yaml'"),
+            "toml" | "tml" => Ok(SaveFormat::TOML),
+            "yaml" | "yml" => Ok(SaveFormat::YAML),
+            "properties" | "props" => Ok(SaveFormat::Properties),
+            "cbor" => Ok(SaveFormat::CBOR),
+            "hcl" => Ok(SaveFormat::HCL),
+            _ => Err(UnsupportedFormatError {
+                format: value.to_owned(),
+            }),
         }
     }
 }
 
-/// Parses a [`SaveFormat`] from a [`String`].
+/// Parses a [`SaveFormat`] from a [`String`], see [`SaveFormat`] for the accepted names.
 impl TryFrom<String> for SaveFormat {
-    type Error = &'static str;
+    type Error = UnsupportedFormatError;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        match value.as_str() {
-            "json" => Ok(SaveFormat::JSON),
-            "toml" => Ok(SaveFormat::TOML),
-            "yaml" => Ok(SaveFormat::YAML),
-            _ => Err("Unsupported format: use 'json', 'toml', or 'yaml'"),
+        SaveFormat::try_from(value.as_str())
+    }
+}
+
+/// How hard a save tries to make sure the written data reaches the disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Durability {
+    /// No explicit sync, the OS decides when the data is written (fastest).
+    #[default]
+    None,
+    /// Syncs the file contents, without metadata (`File::sync_data`).
+    Flush,
+    /// Syncs the file contents and metadata (`File::sync_all`), and on Unix also the
+    /// containing directory, so the config survives a power loss.
+    Fsync,
+}
+
+/// What load does when the config file can be tampered with by other users, see
+/// [`PersistentConfigParameters::permission_check`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionCheck {
+    /// The permissions are not checked.
+    #[default]
+    Off,
+    /// The problems found are printed as warnings, and the file is loaded.
+    Warn,
+    /// The problems found fail the load with [`PersistentConfigError::InsecurePermissions`].
+    Deny,
+}
+
+/// How the config file is handled when its path is a symbolic link, see
+/// [`PersistentConfigParameters::symlinks`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// The link is followed: loads read its target, saves replace its target.
+    #[default]
+    Follow,
+    /// Loads read the target of the link, saves replace the link itself by a regular file.
+    Replace,
+    /// Config files that are symbolic links, or have several hard links, are refused with
+    /// [`PersistentConfigError::UnsafeLink`], by loads and saves.
+    Refuse,
+}
+
+/// Detached GPG signature of the config file, see [`PersistentConfigParameters::signature`].
+///
+/// The signature is stored next to the config file, as `<file name>.sig`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SignatureOptions {
+    /// Key signing the config file on save, as given to `gpg --local-user`.
+    ///
+    /// `None` for the files signed by whoever distributes them: saving then leaves the
+    /// signature as it is, so a saved file fails the next verification.
+    pub signing_key: Option<String>,
+    /// Full fingerprints of the keys whose signatures are accepted by load, spaces ignored.
+    ///
+    /// Key IDs are not accepted, as they can collide. Load fails if the list is empty, unless
+    /// `trust_keyring` is set, or if an entry is empty.
+    pub trusted_keys: Vec<String>,
+    /// Whether a valid signature of any key of the keyring is accepted, in addition to the
+    /// `trusted_keys`.
+    ///
+    /// Only meant for a dedicated `keyring` holding nothing but the trusted keys.
+    pub trust_keyring: bool,
+    /// Keyring holding the public keys, instead of the default keyring of gpg.
+    pub keyring: Option<PathBuf>,
+    /// What load does when the signature is missing or doesn't verify.
+    pub policy: SignaturePolicy,
+}
+
+/// What load does when the signature of the config file is missing or doesn't verify, see
+/// [`SignatureOptions::policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignaturePolicy {
+    /// The problem is printed as a warning, and the file is loaded.
+    Warn,
+    /// The load fails with [`PersistentConfigError::InvalidSignature`].
+    #[default]
+    Deny,
+}
+
+/// Comment header stamped at the top of the saved config files, see
+/// [`PersistentConfigParameters::provenance`].
+///
+/// It tells which build of which application wrote a file, and warns that manual edits may
+/// be overwritten.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProvenanceOptions {
+    /// Name and version of the application writing the file, such as
+    /// `concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"))`.
+    pub generator: String,
+    /// Whether the name of the host writing the file is included.
+    pub hostname: bool,
+    /// Whether the time of the save is included. Re-saving an unchanged config then changes
+    /// the file.
+    pub timestamp: bool,
+}
+
+/// Policy shared by the configs of a category, see [`PersistentConfigParameters::category`]
+/// and [`PersistentConfigDB::set_category`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Category {
+    /// Replaces the `panic_on_error` parameter of the configs of the category.
+    pub panic_on_error: bool,
+    /// Rank of the category when the pending saves are flushed all at once, lower ranks are
+    /// written first. Configs without a category are ranked `0`.
+    pub flush_order: i32,
+}
+
+impl Default for Category {
+    /// Returns the policy of a config without a category: `panic_on_error` and rank `0`.
+    fn default() -> Self {
+        Category {
+            panic_on_error: true,
+            flush_order: 0,
         }
     }
 }
 
+/// How the layers of a config, its included files and base profiles, are merged.
+///
+/// The default merges maps key by key and replaces lists.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MergeStrategy {
+    /// How a map is merged over the map of a lower layer.
+    pub maps: MapMerge,
+    /// How a list is merged over the list of a lower layer.
+    pub arrays: ArrayMerge,
+}
+
+/// How a map is merged over the map of a lower layer, see [`MergeStrategy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapMerge {
+    /// The maps are merged key by key, recursively.
+    #[default]
+    Recursive,
+    /// The map replaces the one of the lower layer.
+    Replace,
+}
+
+/// How a list is merged over the list of a lower layer, see [`MergeStrategy`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrayMerge {
+    /// The list replaces the one of the lower layer.
+    #[default]
+    Replace,
+    /// The items of the list are added after the ones of the lower layer.
+    Append,
+    /// Items are maps identified by the given key: an item with the same key as an item of
+    /// the lower layer is merged over it, the others are added after them.
+    Keyed(String),
+}
+
+/// Metadata wrapped around the saved payload.
+///
+/// When set in [`PersistentConfigParameters::envelope`], the config file is written as
+/// `{ schema_version, app_version, saved_at, payload }` instead of the bare struct.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvelopeOptions {
+    /// Version of the config schema, bump it when the struct changes incompatibly.
+    pub schema_version: u32,
+    /// Version of the application writing the file, usually `env!("CARGO_PKG_VERSION")`.
+    pub app_version: String,
+    /// Whether files without envelope, saved before it was enabled, are loaded as the bare
+    /// payload. They are wrapped on the next save.
+    pub accept_legacy: bool,
+}
+
+/// Options passed to the serializer (and for some, the deserializer) of the save format.
+///
+/// The defaults write compact JSON, the standard TOML layout and single document YAML.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SerializerOptions {
+    /// Number of spaces JSON is indented with, `None` writes it on a single line.
+    pub json_indent: Option<usize>,
+    /// Whether TOML arrays are written one item per line.
+    pub toml_multiline_arrays: bool,
+    /// Whether TOML arrays of tables are written inline, as `servers = [{ ... }]`, instead of
+    /// `[[servers]]` sections.
+    ///
+    /// The document goes through an extra parsing step, whose buffers are not wiped by the
+    /// `zeroize` feature.
+    pub toml_inline_tables: bool,
+    /// Whether map keys are written in alphabetical order instead of the declaration order
+    /// of the fields, so re-saving an unchanged config always gives the same file.
+    pub sort_keys: bool,
+    /// Whether a list is saved in YAML as one `---` separated document per item, and loaded
+    /// back from such a file, as done by Kubernetes-style tooling.
+    ///
+    /// Saving anything else than a list fails. The documents go through an extra conversion,
+    /// whose buffers are not wiped by the `zeroize` feature.
+    pub yaml_multi_document: bool,
+    /// Line ending of the text formats. Files are loaded whatever their line ending.
+    pub line_ending: LineEnding,
+    /// Whether the text formats are written with a leading UTF-8 byte order mark, expected by
+    /// some Windows editors. A byte order mark is skipped when loading, whatever this option.
+    pub bom: bool,
+}
+
+/// Line ending of the text formats, see [`SerializerOptions::line_ending`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// `\n`, as written on Unix.
+    #[default]
+    Lf,
+    /// `\r\n`, as written on Windows.
+    CrLf,
+    /// The line ending of the existing config file, `\n` for a new file, so a file re-saved
+    /// by a Windows editor doesn't show as fully changed on the next save.
+    Preserve,
+}
+
+/// Function returning the suffix of a config file name, see
+/// [`PersistentConfigParameters::file_name_suffix`].
+///
+/// Two suffixes are equal when they hold the same function.
+#[derive(Debug, Clone, Copy)]
+pub struct FileNameSuffix(pub fn() -> String);
+
+impl PartialEq for FileNameSuffix {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::fn_addr_eq(self.0, other.0)
+    }
+}
+
 /// Parameters for a persistent configuration instance.
 ///
 /// # Default Values
-/// - `config_dir`: `"./config"`
+/// - `config_dir`: `""` (empty string)
 /// - `file_name`: `""` (empty string)
 /// - `save_format`: [`SaveFormat::TOML`] (default format)
 /// - `panic_on_error`: `true`
+/// - `durability`: [`Durability::None`]
+/// - `max_file_size`: `None` (no limit)
+/// - `envelope`: `None` (the bare struct is saved)
+/// - `audit_log`: `false`
+/// - `mode`, `owner`, `group`: `None` (the defaults of the system)
+/// - `timeout`: `None` (no timeout)
+/// - `serializer`: [`SerializerOptions::default()`] (compact JSON, standard TOML layout)
+/// - `includes`: `false`
+/// - `namespace`: `None` (the file is stored directly in `config_dir`)
+/// - `extension`: `None` (the extension of the save format)
+/// - `full_path`: `None` (the path is built from the other parameters)
+/// - `fallback_formats`: empty (only the file of `save_format` is loaded)
+/// - `resave_fallback`: `false`
+/// - `file_name_suffix`: `None` (the file name is used as it is)
+/// - `merge`: [`MergeStrategy::default()`] (maps merged key by key, lists replaced)
+/// - `quarantine_corrupt`: `false` (a file that can't be parsed fails the load)
+/// - `repair_invalid`: `false` (an invalid value fails the load)
+/// - `min_save_interval`: `None` (every save writes the file)
+/// - `permission_check`: [`PermissionCheck::Off`]
+/// - `symlinks`: [`SymlinkPolicy::Follow`] (the target of a symlinked config file is updated)
+/// - `category`: `None` (the config follows its own `panic_on_error`)
+/// - `sops`: `false` (the config file is plain text)
+/// - `signature`: `None` (the config file is not signed)
+/// - `provenance`: `None` (no comment header)
+/// - `templates`: `false` (string values are loaded as they are)
 ///
 /// Use [`PersistentConfigParameters::default()`] to get these defaults.
 ///
+/// The parameters implement the serde traits, so they can be read from a bootstrap config
+/// file or sent to another process. Keys missing when deserializing take their default
+/// value, formats and durabilities are written in lowercase (`"yaml"`, `"fsync"`).
+///
 /// # Example
 /// ```
+/// # use persistent_config_core::*;
 /// let params = PersistentConfigParameters::default();
-/// assert_eq!(params.config_dir, "./config");
+/// assert_eq!(params.config_dir, "");
 /// assert_eq!(params.file_name, "");
 /// assert_eq!(params.save_format, SaveFormat::TOML);
 /// assert!(params.panic_on_error);
+/// assert_eq!(params.durability, Durability::None);
+/// assert_eq!(params.max_file_size, None);
+/// assert_eq!(params.envelope, None);
+/// assert!(!params.audit_log);
+/// assert_eq!(params.mode, None);
+/// assert_eq!(params.timeout, None);
+/// assert_eq!(params.serializer, SerializerOptions::default());
+/// assert!(!params.includes);
+/// assert_eq!(params.namespace, None);
+/// assert_eq!(params.extension, None);
+/// assert_eq!(params.full_path, None);
+/// assert!(params.fallback_formats.is_empty());
+/// assert!(!params.resave_fallback);
+/// assert!(params.file_name_suffix.is_none());
+/// assert_eq!(params.merge, MergeStrategy::default());
+/// assert!(!params.quarantine_corrupt);
+/// assert!(!params.repair_invalid);
+/// assert_eq!(params.min_save_interval, None);
+/// assert_eq!(params.permission_check, PermissionCheck::Off);
+/// assert_eq!(params.symlinks, SymlinkPolicy::Follow);
+/// assert_eq!(params.category, None);
+/// assert!(!params.sops);
+/// assert_eq!(params.signature, None);
+/// assert_eq!(params.provenance, None);
+/// assert!(!params.templates);
 /// ```
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PersistentConfigParameters {
     /// Directory where the config file is stored.
     pub config_dir: String,
@@ -108,42 +631,433 @@ pub struct PersistentConfigParameters {
     pub save_format: SaveFormat,
     /// Whether to panic on error.
     pub panic_on_error: bool,
+    /// Sync policy applied after writing the config file.
+    pub durability: Durability,
+    /// Maximum size in bytes of a config file accepted by load, `None` means no limit.
+    pub max_file_size: Option<u64>,
+    /// Envelope wrapped around the saved struct, `None` saves the bare struct.
+    pub envelope: Option<EnvelopeOptions>,
+    /// Whether every save appends the changed fields, the process and the user to
+    /// `audit.log` in the config directory.
+    pub audit_log: bool,
+    /// Unix permission bits of the config file, such as `0o600`. Ignored on other platforms.
+    ///
+    /// When `None`, the permissions of an existing file are kept.
+    pub mode: Option<u32>,
+    /// User owning the config file, as a name or a numeric id (Unix, `ownership` feature).
+    pub owner: Option<String>,
+    /// Group owning the config file, as a name or a numeric id (Unix, `ownership` feature).
+    pub group: Option<String>,
+    /// Maximum time spent reading or writing the config file, `None` means no limit.
+    ///
+    /// When set, file IO runs on a separate thread, so a hung network mount fails with
+    /// [`PersistentConfigError::Timeout`] instead of blocking forever. The thread is left
+    /// behind until the IO completes.
+    pub timeout: Option<Duration>,
+    /// Options of the serializer, such as the JSON indentation.
+    pub serializer: SerializerOptions,
+    /// Whether `@include` directives of the config file splice other files into it, and its
+    /// top-level `extends` key names a base profile merged under it.
+    pub includes: bool,
+    /// Subdirectory of `config_dir` holding the config file, so the configs of different
+    /// crates don't collide when their types share a name.
+    ///
+    /// The `Persistent` derive sets it to the name of the crate defining the type. A file
+    /// saved before the namespace was set is still loaded, until a save writes the config
+    /// in the namespace. The former file is left in place.
+    pub namespace: Option<String>,
+    /// Extension of the config file, without the leading `.`, `None` uses the one of the
+    /// save format.
+    ///
+    /// The content is still written in the save format, so `extension: Some("conf")` writes
+    /// TOML to `<file_name>.conf`. An empty extension writes the file without one.
+    pub extension: Option<String>,
+    /// Path of the config file, used as it is instead of the one built from `config_dir`,
+    /// `namespace`, `file_name` and `extension`.
+    ///
+    /// For paths set from outside the application, such as a systemd credential or a file
+    /// mounted in a container. The base directory and the temporary directories of
+    /// `testing` don't apply to it.
+    pub full_path: Option<PathBuf>,
+    /// Formats tried in order by load when the file of `save_format` does not exist, such as
+    /// `[YAML, JSON]` to load `config.yaml`, then `config.json`, while migrating to
+    /// `config.toml`.
+    ///
+    /// The fallback files are looked up with the extension of their format. Saves always
+    /// write `save_format`. Ignored with a `full_path`.
+    pub fallback_formats: Vec<SaveFormat>,
+    /// Whether a config loaded from one of the `fallback_formats` is saved right away in
+    /// `save_format`. The fallback file is left in place.
+    pub resave_fallback: bool,
+    /// Function returning a suffix appended to the file name on every save and load, such as
+    /// the user name or an instance id, so the users or instances sharing a machine each get
+    /// their own file: `settings-alice.toml`.
+    ///
+    /// Path separators in the suffix are replaced by `_`, an empty suffix leaves the file name
+    /// as it is. Ignored with a `full_path`, and not serialized.
+    #[serde(skip)]
+    pub file_name_suffix: Option<FileNameSuffix>,
+    /// How the included files and base profiles are merged with the config file, when
+    /// `includes` is set.
+    pub merge: MergeStrategy,
+    /// Whether a config file that can't be parsed is moved to
+    /// `<file name>.corrupt-<timestamp>` by load, and replaced by the default config, instead
+    /// of failing under the `panic_on_error` policy.
+    pub quarantine_corrupt: bool,
+    /// Whether the values of the config file that don't deserialize, such as a string where a
+    /// number is expected, are replaced by their default value by load, keeping the other
+    /// values, instead of failing the whole load.
+    pub repair_invalid: bool,
+    /// Minimum time between two writes of the config file by `save`, `None` means no limit.
+    ///
+    /// A save made sooner is only recorded as pending, and written by the next save once the
+    /// interval is over, by `flush`, or by a background thread at the end of the interval,
+    /// so bursts of saves, such as the ones of a window resize handler, are coalesced into
+    /// one write per interval.
+    pub min_save_interval: Option<Duration>,
+    /// Whether load checks that the config file, and its included files, can't be changed by
+    /// other users (Unix only).
+    ///
+    /// A file writable by its group or by other users, or in a directory they can write to
+    /// without the sticky bit, is reported. With the `ownership` feature, a file not owned by
+    /// `owner`, or else by the current user, is reported as well.
+    pub permission_check: PermissionCheck,
+    /// How a config file whose path is a symbolic link is loaded and saved.
+    ///
+    /// Whatever the policy, saves write a new file renamed over the config file, so the
+    /// other hard links of the file are never written through.
+    pub symlinks: SymlinkPolicy,
+    /// Category of the config, such as `"critical"` or `"ui"`, whose policy set with
+    /// [`PersistentConfigDB::set_category`] replaces the error policy of the config.
+    ///
+    /// A category without a policy leaves the parameters as they are.
+    pub category: Option<String>,
+    /// Whether the config file is encrypted with [SOPS](https://github.com/getsops/sops),
+    /// decrypted by load and encrypted again by save with the `sops` command.
+    ///
+    /// Only JSON and YAML files are supported, and they can only be saved on Unix. Requires
+    /// the `sops` feature of `persistent_config`.
+    pub sops: bool,
+    /// Detached GPG signature written by save and verified by load, `None` if the config
+    /// file is not signed.
+    ///
+    /// Files loaded with these parameters, such as the included files, are verified as well.
+    /// Requires the `gpg` feature of `persistent_config`.
+    pub signature: Option<SignatureOptions>,
+    /// Comment header written at the top of the config file, `None` for no header.
+    ///
+    /// Only written in the formats with comments (TOML, YAML and Java properties), it is
+    /// skipped when loading like any comment.
+    pub provenance: Option<ProvenanceOptions>,
+    /// Whether the string values of the config file are rendered as templates by load, such
+    /// as `"{{ env.HOME }}/data"` or `"{{ hostname }}.example.com"`.
+    ///
+    /// Saving writes the templates back as long as the values they render to are unchanged.
+    /// Requires the `templates` feature of `persistent_config`.
+    pub templates: bool,
 }
 
 impl Default for PersistentConfigParameters {
     /// Returns the default parameters:
-    /// - `config_dir`: `"./config"`
+    /// - `config_dir`: `""`
     /// - `file_name`: `""`
     /// - `save_format`: [`SaveFormat::TOML`]
     /// - `panic_on_error`: `true`
+    /// - `durability`: [`Durability::None`]
+    /// - `max_file_size`: `None`
+    /// - `envelope`: `None`
+    /// - `audit_log`: `false`
+    /// - `mode`, `owner`, `group`: `None`
+    /// - `timeout`: `None`
+    /// - `serializer`: [`SerializerOptions::default()`]
+    /// - `includes`: `false`
+    /// - `namespace`: `None`
+    /// - `extension`: `None`
+    /// - `full_path`: `None`
+    /// - `fallback_formats`: empty
+    /// - `resave_fallback`: `false`
+    /// - `file_name_suffix`: `None`
+    /// - `merge`: [`MergeStrategy::default()`]
+    /// - `quarantine_corrupt`: `false`
+    /// - `repair_invalid`: `false`
+    /// - `min_save_interval`: `None`
+    /// - `permission_check`: [`PermissionCheck::Off`]
+    /// - `symlinks`: [`SymlinkPolicy::Follow`]
+    /// - `category`: `None`
+    /// - `sops`: `false`
+    /// - `signature`: `None`
+    /// - `provenance`: `None`
+    /// - `templates`: `false`
     fn default() -> Self {
         Self {
             config_dir: String::new(),
             file_name: String::new(),
             save_format: SaveFormat::default(),
             panic_on_error: true,
+            durability: Durability::default(),
+            max_file_size: None,
+            envelope: None,
+            audit_log: false,
+            mode: None,
+            owner: None,
+            group: None,
+            timeout: None,
+            serializer: SerializerOptions::default(),
+            includes: false,
+            namespace: None,
+            extension: None,
+            full_path: None,
+            fallback_formats: Vec::new(),
+            resave_fallback: false,
+            file_name_suffix: None,
+            merge: MergeStrategy::default(),
+            quarantine_corrupt: false,
+            repair_invalid: false,
+            min_save_interval: None,
+            permission_check: PermissionCheck::Off,
+            symlinks: SymlinkPolicy::Follow,
+            category: None,
+            sops: false,
+            signature: None,
+            provenance: None,
+            templates: false,
         }
     }
 }
 
+/// A registered type, as returned by [`PersistentConfigDB::registration`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registration {
+    /// Name of the registered type, as given by [`std::any::type_name`].
+    pub type_name: &'static str,
+    /// Registered parameters.
+    pub params: PersistentConfigParameters,
+    /// Source location of the call that registered the type.
+    ///
+    /// For types registered on first use, such as types deriving `Persistent`, it is the
+    /// location of the first save or load.
+    pub location: &'static Location<'static>,
+    /// Time of the registration.
+    pub registered_at: SystemTime,
+    /// Whether the registration was frozen with [`PersistentConfigDB::freeze`].
+    pub frozen: bool,
+    /// Call sites that loaded the config since its registration, in the order of their first
+    /// load, while [`PersistentConfigDB::set_track_readers`] is enabled.
+    pub readers: Vec<ReadSite>,
+}
+
+/// A call site that loaded a config, see [`Registration::readers`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadSite {
+    /// Source location of the load.
+    pub location: &'static Location<'static>,
+    /// Number of loads made from this location.
+    pub count: u64,
+    /// Time of the first load made from this location.
+    pub first_read_at: SystemTime,
+    /// Time of the last load made from this location.
+    pub last_read_at: SystemTime,
+}
+
+impl Registration {
+    /// Creates the registration of `T` with `params`, made by the caller.
+    #[track_caller]
+    fn new<T>(params: PersistentConfigParameters) -> Self {
+        Self {
+            type_name: std::any::type_name::<T>(),
+            params,
+            location: Location::caller(),
+            registered_at: SystemTime::now(),
+            frozen: false,
+            readers: Vec::new(),
+        }
+    }
+}
+
+/// Value attached to a type in [`PersistentConfigDB`], see [`PersistentConfigDB::add_extension`].
+type Extension = Arc<dyn Any + Send + Sync>;
+
+/// Acquires `lock` for reading, recovering it if a thread panicked while holding it.
+///
+/// Every update of [`PersistentConfigDB`] is a single assignment, insertion or removal, so a
+/// panic can't leave it half updated.
+fn read_lock<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Acquires `lock` for writing, recovering it if a thread panicked while holding it.
+fn write_lock<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
 /// Database for storing persistent configuration parameters for different types.
+///
+/// Lookups only take a shared lock, and a thread panicking while holding a lock doesn't
+/// make the database unusable for the others.
 #[derive(Debug, Default)]
 pub struct PersistentConfigDB {
-    /// Internal map from type ID to configuration parameters.
-    map: RwLock<HashMap<TypeId, PersistentConfigParameters>>,
+    /// Internal map from type ID to registration.
+    map: RwLock<HashMap<TypeId, Registration>>,
+    /// Internal map from type ID and slot name to per-type values, such as caches or hooks.
+    extensions: RwLock<HashMap<(TypeId, &'static str), Extension>>,
+    /// Parameters used as a base by the registrations that don't set every parameter.
+    app_defaults: RwLock<Option<PersistentConfigParameters>>,
+    /// Root under which the relative config directories are resolved.
+    base_dir: RwLock<Option<PathBuf>>,
+    /// Policies of the categories, by name.
+    categories: RwLock<HashMap<String, Category>>,
+    /// Whether the call sites loading the configs are recorded.
+    track_readers: AtomicBool,
 }
 
 impl PersistentConfigDB {
     /// Add configuration parameters for a type.
     ///
+    /// Parameters previously registered for the type are silently replaced, use
+    /// [`try_add_config`](Self::try_add_config) to detect conflicting registrations.
+    ///
+    /// # Errors
+    /// Returns [`PersistentConfigError::Frozen`] if the registration of the type is frozen,
+    /// which is left as it is.
+    ///
     /// # Type Parameters
     /// * `T`: The type for which to store the configuration.
-    pub fn add_config<T: 'static>(&self, config: PersistentConfigParameters) {
+    #[track_caller]
+    pub fn add_config<T: 'static>(&self, config: PersistentConfigParameters) -> Result<()> {
+        let type_id = TypeId::of::<T>();
+        let mut map = write_lock(&self.map);
+        if map.get(&type_id).is_some_and(|registration| registration.frozen) {
+            return Err(PersistentConfigError::Frozen {
+                type_name: std::any::type_name::<T>(),
+            }
+            .into());
+        }
+        map.insert(type_id, Registration::new::<T>(config));
+        Ok(())
+    }
+
+    /// Add configuration parameters for a type, refusing to overwrite different parameters.
+    ///
+    /// Registering the same parameters again is a no-op.
+    ///
+    /// # Example
+    /// ```
+    /// # use persistent_config_core::*;
+    /// struct LogConfig;
+    ///
+    /// let params = PersistentConfigParameters {
+    ///     config_dir: "./logs".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// PERSISTENT_CONFIGS.try_add_config::<LogConfig>(params.clone()).unwrap();
+    /// PERSISTENT_CONFIGS.try_add_config::<LogConfig>(params).unwrap();
+    ///
+    /// let other = PersistentConfigParameters {
+    ///     config_dir: "/var/log".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// assert!(PERSISTENT_CONFIGS.try_add_config::<LogConfig>(other.clone()).is_err());
+    /// assert!(PERSISTENT_CONFIGS.replace_config::<LogConfig>(other).is_some());
+    /// ```
+    ///
+    /// # Errors
+    /// Returns [`PersistentConfigError::RegistrationConflict`] if the type is already
+    /// registered with different parameters, or [`PersistentConfigError::Frozen`] if that
+    /// registration is frozen.
+    ///
+    /// # Type Parameters
+    /// * `T`: The type for which to store the configuration.
+    #[track_caller]
+    pub fn try_add_config<T: 'static>(&self, config: PersistentConfigParameters) -> Result<()> {
+        let type_id = TypeId::of::<T>();
+        let mut map = write_lock(&self.map);
+        match map.get(&type_id) {
+            Some(existing) if existing.params != config && existing.frozen => Err(PersistentConfigError::Frozen {
+                type_name: std::any::type_name::<T>(),
+            }
+            .into()),
+            Some(existing) if existing.params != config => Err(PersistentConfigError::RegistrationConflict {
+                type_name: std::any::type_name::<T>(),
+                existing: Box::new(existing.params.clone()),
+                location: existing.location,
+                requested: Box::new(config),
+            }
+            .into()),
+            Some(_) => Ok(()),
+            None => {
+                map.insert(type_id, Registration::new::<T>(config));
+                Ok(())
+            }
+        }
+    }
+
+    /// Replace the configuration parameters of a type, deliberately overriding any previous registration.
+    ///
+    /// Returns the previously registered parameters, if any. A frozen registration is left as
+    /// it is, and `None` is returned.
+    ///
+    /// # Type Parameters
+    /// * `T`: The type for which to store the configuration.
+    #[track_caller]
+    pub fn replace_config<T: 'static>(&self, config: PersistentConfigParameters) -> Option<PersistentConfigParameters> {
+        let type_id = TypeId::of::<T>();
+        let mut map = write_lock(&self.map);
+        if map.get(&type_id).is_some_and(|registration| registration.frozen) {
+            return None;
+        }
+        map.insert(type_id, Registration::new::<T>(config))
+            .map(|registration| registration.params)
+    }
+
+    /// Freeze the registration of a type for the rest of the process: its parameters can't
+    /// be replaced anymore, and the `persistent_config` crate refuses to save it.
+    ///
+    /// # Example
+    /// ```
+    /// # use persistent_config_core::*;
+    /// struct SecurityConfig;
+    ///
+    /// PERSISTENT_CONFIGS.add_config::<SecurityConfig>(PersistentConfigParameters::default()).unwrap();
+    /// PERSISTENT_CONFIGS.freeze::<SecurityConfig>().unwrap();
+    /// assert!(PERSISTENT_CONFIGS.is_frozen::<SecurityConfig>());
+    ///
+    /// let other = PersistentConfigParameters {
+    ///     config_dir: "/tmp".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// assert!(PERSISTENT_CONFIGS.add_config::<SecurityConfig>(other.clone()).is_err());
+    /// assert!(PERSISTENT_CONFIGS.try_add_config::<SecurityConfig>(other.clone()).is_err());
+    /// assert!(PERSISTENT_CONFIGS.replace_config::<SecurityConfig>(other).is_none());
+    /// ```
+    ///
+    /// # Errors
+    /// Returns [`PersistentConfigError::NotRegistered`] if the type is not registered.
+    ///
+    /// # Type Parameters
+    /// * `T`: The type whose registration to freeze.
+    pub fn freeze<T: 'static>(&self) -> Result<()> {
         let type_id = TypeId::of::<T>();
-        self.map
-            .write()
-            .expect("Unable to lock, for adding config.")
-            .insert(type_id, config);
+        match write_lock(&self.map).get_mut(&type_id) {
+            Some(registration) => {
+                registration.frozen = true;
+                Ok(())
+            }
+            None => Err(PersistentConfigError::NotRegistered {
+                type_name: std::any::type_name::<T>(),
+            }
+            .into()),
+        }
+    }
+
+    /// Returns `true` if the registration of a type is frozen.
+    ///
+    /// # Type Parameters
+    /// * `T`: The type whose registration to check.
+    pub fn is_frozen<T: 'static>(&self) -> bool {
+        let type_id = TypeId::of::<T>();
+        read_lock(&self.map)
+            .get(&type_id)
+            .is_some_and(|registration| registration.frozen)
     }
 
     /// Get configuration parameters for a type.
@@ -152,10 +1066,274 @@ impl PersistentConfigDB {
     /// * `T`: The type for which to retrieve the configuration.
     pub fn get_config<T: 'static>(&self) -> Option<PersistentConfigParameters> {
         let type_id = TypeId::of::<T>();
-        self.map
-            .write()
-            .expect("Unable to lock, for getting config.")
+        read_lock(&self.map)
             .get(&type_id)
-            .cloned()
+            .map(|registration| registration.params.clone())
+    }
+
+    /// Get the registration of a type: its parameters, along with its name, and where and
+    /// when it was registered.
+    ///
+    /// # Example
+    /// ```
+    /// # use persistent_config_core::*;
+    /// struct CacheConfig;
+    ///
+    /// PERSISTENT_CONFIGS.add_config::<CacheConfig>(PersistentConfigParameters::default()).unwrap();
+    /// let registration = PERSISTENT_CONFIGS.registration::<CacheConfig>().unwrap();
+    /// assert!(registration.type_name.ends_with("CacheConfig"));
+    /// assert_eq!(registration.location.line(), line!() - 3);
+    /// ```
+    ///
+    /// # Type Parameters
+    /// * `T`: The type for which to retrieve the registration.
+    pub fn registration<T: 'static>(&self) -> Option<Registration> {
+        let type_id = TypeId::of::<T>();
+        read_lock(&self.map).get(&type_id).cloned()
+    }
+
+    /// Get the registrations of all types, sorted by type name.
+    pub fn registrations(&self) -> Vec<Registration> {
+        let mut registrations = read_lock(&self.map).values().cloned().collect::<Vec<_>>();
+        registrations.sort_by_key(|registration| registration.type_name);
+        registrations
+    }
+
+    /// Set the parameters used as a base by subsequent registrations, replacing previous ones.
+    ///
+    /// Types registered by the `Persistent` derive take the parameters they don't set from
+    /// these defaults, and types without any registration are registered with them on first
+    /// use. An empty `file_name` is filled with the type name.
+    ///
+    /// # Example
+    /// ```
+    /// # use persistent_config_core::*;
+    /// PERSISTENT_CONFIGS.set_app_defaults(PersistentConfigParameters {
+    ///     config_dir: "/etc/my_app".to_string(),
+    ///     save_format: SaveFormat::YAML,
+    ///     ..Default::default()
+    /// });
+    /// assert_eq!(PERSISTENT_CONFIGS.app_defaults().unwrap().save_format, SaveFormat::YAML);
+    /// ```
+    pub fn set_app_defaults(&self, defaults: PersistentConfigParameters) {
+        *write_lock(&self.app_defaults) = Some(defaults);
+    }
+
+    /// Get the parameters set with [`set_app_defaults`](Self::set_app_defaults), if any.
+    pub fn app_defaults(&self) -> Option<PersistentConfigParameters> {
+        read_lock(&self.app_defaults).clone()
+    }
+
+    /// Resolve the relative config directories of all types under `path`, replacing any
+    /// previous base directory.
+    ///
+    /// Registrations are left untouched: the base directory is applied when building the path
+    /// of a config file, so it also affects types registered before the call. Absolute config
+    /// directories are not rerouted.
+    ///
+    /// # Example
+    /// ```
+    /// # use persistent_config_core::*;
+    /// // For example from a `--config-dir` command line flag
+    /// PERSISTENT_CONFIGS.set_base_dir("/tmp/my_app");
+    /// assert_eq!(PERSISTENT_CONFIGS.base_dir(), Some("/tmp/my_app".into()));
+    ///
+    /// PERSISTENT_CONFIGS.clear_base_dir();
+    /// assert_eq!(PERSISTENT_CONFIGS.base_dir(), None);
+    /// ```
+    pub fn set_base_dir(&self, path: impl Into<PathBuf>) {
+        *write_lock(&self.base_dir) = Some(path.into());
+    }
+
+    /// Remove the base directory, relative config directories are resolved from the current
+    /// directory again.
+    pub fn clear_base_dir(&self) {
+        *write_lock(&self.base_dir) = None;
+    }
+
+    /// Get the base directory set with [`set_base_dir`](Self::set_base_dir), if any.
+    pub fn base_dir(&self) -> Option<PathBuf> {
+        read_lock(&self.base_dir).clone()
+    }
+
+    /// Set the policy of the configs of the category `name`, replacing any previous one.
+    ///
+    /// The policy applies to the configs registered with this
+    /// [`category`](PersistentConfigParameters::category), before or after the call, so a
+    /// corrupt file of a lenient category falls back to the default values while one of a
+    /// strict category fails.
+    ///
+    /// # Example
+    /// ```
+    /// # use persistent_config_core::*;
+    /// PERSISTENT_CONFIGS.set_category("ui", Category { panic_on_error: false, flush_order: 1 });
+    /// PERSISTENT_CONFIGS.set_category("critical", Category { panic_on_error: true, flush_order: 0 });
+    /// assert_eq!(PERSISTENT_CONFIGS.category("ui").unwrap().flush_order, 1);
+    /// assert_eq!(PERSISTENT_CONFIGS.category("cache"), None);
+    /// ```
+    pub fn set_category(&self, name: impl Into<String>, category: Category) {
+        write_lock(&self.categories).insert(name.into(), category);
+    }
+
+    /// Get the policy set for the category `name` with [`set_category`](Self::set_category),
+    /// if any.
+    pub fn category(&self, name: &str) -> Option<Category> {
+        read_lock(&self.categories).get(name).cloned()
+    }
+
+    /// Record, from now on, the call sites loading each registered type in its
+    /// [`readers`](Registration::readers), or stop recording them.
+    ///
+    /// Meant to find the config types, or the parts of a program, that no longer read their
+    /// config. It is disabled by default, as every load then takes the write lock.
+    ///
+    /// # Example
+    /// ```
+    /// # use persistent_config_core::*;
+    /// struct LegacyConfig;
+    ///
+    /// PERSISTENT_CONFIGS.set_track_readers(true);
+    /// PERSISTENT_CONFIGS.add_config::<LegacyConfig>(PersistentConfigParameters::default()).unwrap();
+    /// PERSISTENT_CONFIGS.record_read::<LegacyConfig>(std::panic::Location::caller());
+    ///
+    /// let registration = PERSISTENT_CONFIGS.registration::<LegacyConfig>().unwrap();
+    /// assert_eq!(registration.readers.len(), 1);
+    /// assert_eq!(registration.readers[0].count, 1);
+    /// ```
+    pub fn set_track_readers(&self, enabled: bool) {
+        self.track_readers.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Get whether the call sites loading the configs are recorded, see
+    /// [`set_track_readers`](Self::set_track_readers).
+    pub fn tracks_readers(&self) -> bool {
+        self.track_readers.load(Ordering::Relaxed)
+    }
+
+    /// Record a load of a type made from `location`, if
+    /// [`set_track_readers`](Self::set_track_readers) is enabled and the type is registered.
+    ///
+    /// # Type Parameters
+    /// * `T`: The type that was loaded.
+    pub fn record_read<T: 'static>(&self, location: &'static Location<'static>) {
+        if !self.tracks_readers() {
+            return;
+        }
+        let type_id = TypeId::of::<T>();
+        let mut map = write_lock(&self.map);
+        let Some(registration) = map.get_mut(&type_id) else {
+            return;
+        };
+        let now = SystemTime::now();
+        match registration
+            .readers
+            .iter_mut()
+            .find(|reader| reader.location == location)
+        {
+            Some(reader) => {
+                reader.count += 1;
+                reader.last_read_at = now;
+            }
+            None => registration.readers.push(ReadSite {
+                location,
+                count: 1,
+                first_read_at: now,
+                last_read_at: now,
+            }),
+        }
+    }
+
+    /// Attach a value to a type under the given slot name, replacing any previous value.
+    ///
+    /// Extensions let helpers store per-type state, such as cached values or hooks,
+    /// alongside the configuration parameters.
+    ///
+    /// # Type Parameters
+    /// * `T`: The type to which the value is attached.
+    /// * `V`: The type of the stored value.
+    pub fn add_extension<T: 'static, V: Any + Send + Sync>(&self, slot: &'static str, value: V) {
+        let type_id = TypeId::of::<T>();
+        write_lock(&self.extensions).insert((type_id, slot), Arc::new(value));
+    }
+
+    /// Get the value attached to a type under the given slot name.
+    ///
+    /// Returns `None` if the slot is empty or holds a value of another type than `V`.
+    ///
+    /// # Type Parameters
+    /// * `T`: The type to which the value is attached.
+    /// * `V`: The type of the stored value.
+    pub fn get_extension<T: 'static, V: Any + Send + Sync>(&self, slot: &'static str) -> Option<Arc<V>> {
+        let type_id = TypeId::of::<T>();
+        let value = read_lock(&self.extensions).get(&(type_id, slot)).cloned()?;
+        value.downcast().ok()
+    }
+
+    /// Get the value attached to a type under the given slot name, attaching the value
+    /// returned by `default` first if the slot is empty.
+    ///
+    /// Returns `None` if the slot holds a value of another type than `V`.
+    ///
+    /// # Type Parameters
+    /// * `T`: The type to which the value is attached.
+    /// * `V`: The type of the stored value.
+    pub fn get_or_add_extension<T: 'static, V: Any + Send + Sync>(
+        &self,
+        slot: &'static str,
+        default: impl FnOnce() -> V,
+    ) -> Option<Arc<V>> {
+        let type_id = TypeId::of::<T>();
+        // Most calls find the slot filled, the write lock is only taken to fill it
+        let existing = read_lock(&self.extensions).get(&(type_id, slot)).cloned();
+        let value = match existing {
+            Some(value) => value,
+            None => write_lock(&self.extensions)
+                .entry((type_id, slot))
+                .or_insert_with(|| Arc::new(default()))
+                .clone(),
+        };
+        value.downcast().ok()
+    }
+
+    /// Remove the value attached to a type under the given slot name.
+    ///
+    /// # Type Parameters
+    /// * `T`: The type to which the value is attached.
+    pub fn remove_extension<T: 'static>(&self, slot: &'static str) {
+        let type_id = TypeId::of::<T>();
+        write_lock(&self.extensions).remove(&(type_id, slot));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Frozen;
+
+    fn params(config_dir: &str) -> PersistentConfigParameters {
+        PersistentConfigParameters {
+            config_dir: config_dir.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn frozen_registration_refuses_new_parameters() {
+        let db = PersistentConfigDB::default();
+        assert!(db.freeze::<Frozen>().is_err());
+        db.add_config::<Frozen>(params("./first")).unwrap();
+        db.add_config::<Frozen>(params("./second")).unwrap();
+        db.freeze::<Frozen>().unwrap();
+
+        let error = db.add_config::<Frozen>(params("./third")).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PersistentConfigError>(),
+            Some(PersistentConfigError::Frozen { .. })
+        ));
+        assert!(db.try_add_config::<Frozen>(params("./third")).is_err());
+        assert_eq!(db.replace_config::<Frozen>(params("./third")), None);
+        db.try_add_config::<Frozen>(params("./second")).unwrap();
+        assert_eq!(db.get_config::<Frozen>().unwrap().config_dir, "./second");
     }
 }
@@ -0,0 +1,832 @@
+//! Parsing of the `#[persistent(...)]` attributes.
+
+use persistent_config_core::{ArrayMerge, MapMerge, PermissionCheck, SaveFormat, SymlinkPolicy};
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::meta::ParseNestedMeta;
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Expr, ExprLit, Field, Lit, LitBool, LitInt, LitStr, Meta, MetaNameValue, Token, token};
+
+/// Keys accepted in `#[persistent(...)]` on the struct.
+const CONTAINER_KEYS: &[&str] = &[
+    "rename_all",
+    "config_dir",
+    "linux_dir",
+    "macos_dir",
+    "windows_dir",
+    "file_name",
+    "save_format",
+    "extension",
+    "file_name_suffix",
+    "fallback_formats",
+    "resave_fallback",
+    "quarantine_corrupt",
+    "permission_check",
+    "symlinks",
+    "category",
+    "embedded_default",
+    "repair_invalid",
+    "sops",
+    "merge_maps",
+    "merge_arrays",
+    "panic_on_error",
+    "mode",
+    "owner",
+    "group",
+    "namespace",
+    "before_save",
+    "after_load",
+];
+
+/// Keys accepted in `#[persistent_config(...)]`.
+const MACRO_KEYS: &[&str] = &["dir", "file_name", "format", "panic_on_error"];
+
+/// Keys accepted in `#[persistent(...)]` on a field.
+const FIELD_KEYS: &[&str] = &[
+    "zeroize",
+    "alias",
+    "env",
+    "from_file",
+    "redact",
+    "computed",
+    "flatten_from",
+    "base",
+    "min",
+    "max",
+    "regex",
+    "one_of",
+    "prompt",
+    "chunked",
+    "lazy",
+];
+
+/// Keys that can be set more than once.
+const REPEATABLE_KEYS: &[&str] = &["alias"];
+
+/// Attributes set on the struct itself.
+#[derive(Default)]
+pub(crate) struct ContainerAttrs {
+    /// `#[persistent(rename_all = "...")]`
+    pub(crate) rename_all: Option<RenameRule>,
+    /// `#[persistent(config_dir = "...")]`
+    pub(crate) config_dir: Option<LitStr>,
+    /// `#[persistent(linux_dir = "...")]`
+    pub(crate) linux_dir: Option<LitStr>,
+    /// `#[persistent(macos_dir = "...")]`
+    pub(crate) macos_dir: Option<LitStr>,
+    /// `#[persistent(windows_dir = "...")]`
+    pub(crate) windows_dir: Option<LitStr>,
+    /// `#[persistent(file_name = "...")]`
+    pub(crate) file_name: Option<LitStr>,
+    /// `#[persistent(save_format = "...")]`
+    pub(crate) save_format: Option<SaveFormat>,
+    /// `#[persistent(extension = "...")]`
+    pub(crate) extension: Option<LitStr>,
+    /// `#[persistent(file_name_suffix = "...")]`
+    pub(crate) file_name_suffix: Option<syn::ExprPath>,
+    /// `#[persistent(fallback_formats = "...")]`, a comma-separated list
+    pub(crate) fallback_formats: Vec<SaveFormat>,
+    /// `#[persistent(resave_fallback = ...)]`
+    pub(crate) resave_fallback: Option<LitBool>,
+    /// `#[persistent(quarantine_corrupt = ...)]`
+    pub(crate) quarantine_corrupt: Option<LitBool>,
+    /// `#[persistent(repair_invalid = ...)]`
+    pub(crate) repair_invalid: Option<LitBool>,
+    /// `#[persistent(sops = ...)]`
+    pub(crate) sops: Option<LitBool>,
+    /// `#[persistent(permission_check = "...")]`
+    pub(crate) permission_check: Option<PermissionCheck>,
+    /// `#[persistent(symlinks = "...")]`
+    pub(crate) symlinks: Option<SymlinkPolicy>,
+    /// `#[persistent(category = "...")]`
+    pub(crate) category: Option<LitStr>,
+    /// `#[persistent(merge_maps = "...")]`
+    pub(crate) merge_maps: Option<MapMerge>,
+    /// `#[persistent(merge_arrays = "...")]`
+    pub(crate) merge_arrays: Option<ArrayMerge>,
+    /// `#[persistent(panic_on_error = ...)]`
+    pub(crate) panic_on_error: Option<LitBool>,
+    /// `#[persistent(mode = 0o...)]`
+    pub(crate) mode: Option<LitInt>,
+    /// `#[persistent(owner = "...")]`
+    pub(crate) owner: Option<LitStr>,
+    /// `#[persistent(group = "...")]`
+    pub(crate) group: Option<LitStr>,
+    /// `#[persistent(namespace = "...")]`
+    pub(crate) namespace: Option<LitStr>,
+    /// `#[persistent(before_save = "...")]`
+    pub(crate) before_save: Option<syn::ExprPath>,
+    /// `#[persistent(after_load = "...")]`
+    pub(crate) after_load: Option<syn::ExprPath>,
+    /// `#[persistent(embedded_default = "...")]`, with the format of the file
+    pub(crate) embedded_default: Option<(LitStr, SaveFormat)>,
+}
+
+impl ContainerAttrs {
+    /// Parses the `#[persistent(...)]` attributes of a struct.
+    pub(crate) fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut container = Self::default();
+        parse_keys(attrs, CONTAINER_KEYS, |key, meta| match key {
+            "rename_all" => {
+                container.rename_all = Some(RenameRule::from_lit(&string_value(key, &meta)?)?);
+                Ok(())
+            }
+            "config_dir" => {
+                container.config_dir = Some(string_value(key, &meta)?);
+                Ok(())
+            }
+            "linux_dir" => {
+                container.linux_dir = Some(string_value(key, &meta)?);
+                Ok(())
+            }
+            "macos_dir" => {
+                container.macos_dir = Some(string_value(key, &meta)?);
+                Ok(())
+            }
+            "windows_dir" => {
+                container.windows_dir = Some(string_value(key, &meta)?);
+                Ok(())
+            }
+            "file_name" => {
+                container.file_name = Some(string_value(key, &meta)?);
+                Ok(())
+            }
+            "save_format" => {
+                let lit = string_value(key, &meta)?;
+                let save_format =
+                    SaveFormat::try_from(lit.value().as_str()).map_err(|e| syn::Error::new_spanned(&lit, e))?;
+                container.save_format = Some(save_format);
+                Ok(())
+            }
+            "extension" => {
+                let lit = string_value(key, &meta)?;
+                if lit.value().starts_with('.') || lit.value().contains(['/', '\\']) {
+                    return Err(syn::Error::new_spanned(
+                        lit,
+                        "expected an extension without the leading `.`, such as \"conf\"",
+                    ));
+                }
+                container.extension = Some(lit);
+                Ok(())
+            }
+            "file_name_suffix" => {
+                container.file_name_suffix = Some(string_value(key, &meta)?.parse()?);
+                Ok(())
+            }
+            "fallback_formats" => {
+                let lit = string_value(key, &meta)?;
+                container.fallback_formats = lit
+                    .value()
+                    .split(',')
+                    .map(|format| SaveFormat::try_from(format.trim()).map_err(|e| syn::Error::new_spanned(&lit, e)))
+                    .collect::<syn::Result<_>>()?;
+                Ok(())
+            }
+            "resave_fallback" => {
+                container.resave_fallback = Some(bool_value(key, &meta)?);
+                Ok(())
+            }
+            "embedded_default" => {
+                let lit = string_value(key, &meta)?;
+                let path = lit.value();
+                let extension = std::path::Path::new(&path)
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .ok_or_else(|| syn::Error::new_spanned(&lit, "The embedded default config needs an extension"))?;
+                let format = SaveFormat::try_from(extension).map_err(|e| syn::Error::new_spanned(&lit, e))?;
+                container.embedded_default = Some((lit, format));
+                Ok(())
+            }
+            "quarantine_corrupt" => {
+                container.quarantine_corrupt = Some(bool_value(key, &meta)?);
+                Ok(())
+            }
+            "permission_check" => {
+                let lit = string_value(key, &meta)?;
+                container.permission_check = Some(match lit.value().as_str() {
+                    "off" => PermissionCheck::Off,
+                    "warn" => PermissionCheck::Warn,
+                    "deny" => PermissionCheck::Deny,
+                    _ => return Err(syn::Error::new_spanned(lit, "expected \"off\", \"warn\" or \"deny\"")),
+                });
+                Ok(())
+            }
+            "symlinks" => {
+                let lit = string_value(key, &meta)?;
+                container.symlinks = Some(match lit.value().as_str() {
+                    "follow" => SymlinkPolicy::Follow,
+                    "replace" => SymlinkPolicy::Replace,
+                    "refuse" => SymlinkPolicy::Refuse,
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            lit,
+                            "expected \"follow\", \"replace\" or \"refuse\"",
+                        ));
+                    }
+                });
+                Ok(())
+            }
+            "category" => {
+                container.category = Some(string_value(key, &meta)?);
+                Ok(())
+            }
+            "repair_invalid" => {
+                container.repair_invalid = Some(bool_value(key, &meta)?);
+                Ok(())
+            }
+            "sops" => {
+                container.sops = Some(bool_value(key, &meta)?);
+                Ok(())
+            }
+            "merge_maps" => {
+                let lit = string_value(key, &meta)?;
+                container.merge_maps = Some(match lit.value().as_str() {
+                    "recursive" => MapMerge::Recursive,
+                    "replace" => MapMerge::Replace,
+                    _ => return Err(syn::Error::new_spanned(lit, "expected \"recursive\" or \"replace\"")),
+                });
+                Ok(())
+            }
+            "merge_arrays" => {
+                let lit = string_value(key, &meta)?;
+                let value = lit.value();
+                container.merge_arrays = Some(match value.as_str() {
+                    "replace" => ArrayMerge::Replace,
+                    "append" => ArrayMerge::Append,
+                    _ => match value.strip_prefix("keyed(").and_then(|key| key.strip_suffix(')')) {
+                        Some(key) if !key.trim().is_empty() => ArrayMerge::Keyed(key.trim().to_owned()),
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                lit,
+                                "expected \"replace\", \"append\" or \"keyed(<key>)\"",
+                            ));
+                        }
+                    },
+                });
+                Ok(())
+            }
+            "panic_on_error" => {
+                container.panic_on_error = Some(bool_value(key, &meta)?);
+                Ok(())
+            }
+            "mode" => {
+                let lit = int_value(key, &meta)?;
+                if lit.base10_parse::<u32>().map_or(true, |mode| mode > 0o7777) {
+                    return Err(syn::Error::new_spanned(
+                        lit,
+                        "expected Unix permission bits, such as `0o600`",
+                    ));
+                }
+                container.mode = Some(lit);
+                Ok(())
+            }
+            "owner" => {
+                container.owner = Some(string_value(key, &meta)?);
+                Ok(())
+            }
+            "group" => {
+                container.group = Some(string_value(key, &meta)?);
+                Ok(())
+            }
+            "namespace" => {
+                let lit = string_value(key, &meta)?;
+                if lit.value().contains(['/', '\\']) || lit.value() == "." || lit.value() == ".." {
+                    return Err(syn::Error::new_spanned(
+                        lit,
+                        "expected a directory name, without separators",
+                    ));
+                }
+                container.namespace = Some(lit);
+                Ok(())
+            }
+            "before_save" => {
+                container.before_save = Some(string_value(key, &meta)?.parse()?);
+                Ok(())
+            }
+            "after_load" => {
+                container.after_load = Some(string_value(key, &meta)?.parse()?);
+                Ok(())
+            }
+            _ => unreachable!("key validated by parse_keys"),
+        })?;
+        Ok(container)
+    }
+
+    /// Returns whether any of the registration keys is set.
+    pub(crate) fn has_registration(&self) -> bool {
+        self.config_dir.is_some()
+            || !self.os_dirs().is_empty()
+            || self.file_name.is_some()
+            || self.save_format.is_some()
+            || self.extension.is_some()
+            || self.file_name_suffix.is_some()
+            || !self.fallback_formats.is_empty()
+            || self.resave_fallback.is_some()
+            || self.quarantine_corrupt.is_some()
+            || self.repair_invalid.is_some()
+            || self.sops.is_some()
+            || self.permission_check.is_some()
+            || self.symlinks.is_some()
+            || self.category.is_some()
+            || self.merge_maps.is_some()
+            || self.merge_arrays.is_some()
+            || self.panic_on_error.is_some()
+            || self.mode.is_some()
+            || self.owner.is_some()
+            || self.group.is_some()
+            || self.namespace.is_some()
+    }
+
+    /// Returns the `(target_os, dir)` pairs of the per-OS config directories that are set.
+    pub(crate) fn os_dirs(&self) -> Vec<(&'static str, &LitStr)> {
+        [
+            ("linux", &self.linux_dir),
+            ("macos", &self.macos_dir),
+            ("windows", &self.windows_dir),
+        ]
+        .into_iter()
+        .filter_map(|(os, dir)| Some((os, dir.as_ref()?)))
+        .collect()
+    }
+}
+
+/// Arguments of the `#[persistent_config(...)]` attribute macro.
+///
+/// They are forwarded to the `Persistent` derive as the matching `#[persistent(...)]` keys.
+#[derive(Default)]
+pub(crate) struct MacroArgs {
+    /// `dir = "..."`, forwarded as `config_dir`
+    dir: Option<LitStr>,
+    /// `file_name = "..."`
+    file_name: Option<LitStr>,
+    /// `format = "..."`, forwarded as `save_format`
+    format: Option<LitStr>,
+    /// `panic_on_error = ...`
+    panic_on_error: Option<LitBool>,
+}
+
+impl MacroArgs {
+    /// Parses the arguments of `#[persistent_config(...)]`.
+    pub(crate) fn parse(args: proc_macro::TokenStream) -> syn::Result<Self> {
+        let mut macro_args = Self::default();
+        let mut seen = Vec::new();
+        let parser = syn::meta::parser(|meta| match known_key(&meta, MACRO_KEYS, &mut seen)? {
+            key @ "dir" => {
+                macro_args.dir = Some(string_value(key, &meta)?);
+                Ok(())
+            }
+            key @ "file_name" => {
+                macro_args.file_name = Some(string_value(key, &meta)?);
+                Ok(())
+            }
+            key @ "format" => {
+                let lit = string_value(key, &meta)?;
+                SaveFormat::try_from(lit.value().as_str()).map_err(|e| syn::Error::new_spanned(&lit, e))?;
+                macro_args.format = Some(lit);
+                Ok(())
+            }
+            key @ "panic_on_error" => {
+                macro_args.panic_on_error = Some(bool_value(key, &meta)?);
+                Ok(())
+            }
+            _ => unreachable!("key validated by known_key"),
+        });
+        syn::parse::Parser::parse(parser, args)?;
+        Ok(macro_args)
+    }
+
+    /// Returns the `#[persistent(...)]` attribute equivalent to the arguments, if any is set.
+    pub(crate) fn to_persistent_attr(&self) -> Option<proc_macro2::TokenStream> {
+        let keys = [
+            self.dir.as_ref().map(|dir| quote! { config_dir = #dir }),
+            self.file_name
+                .as_ref()
+                .map(|file_name| quote! { file_name = #file_name }),
+            self.format.as_ref().map(|format| quote! { save_format = #format }),
+            self.panic_on_error
+                .as_ref()
+                .map(|panic_on_error| quote! { panic_on_error = #panic_on_error }),
+        ];
+        let keys = keys.into_iter().flatten().collect::<Vec<_>>();
+        (!keys.is_empty()).then(|| quote! { #[persistent(#(#keys),*)] })
+    }
+}
+
+/// Attributes set on a struct field.
+#[derive(Default)]
+pub(crate) struct FieldAttrs {
+    /// `#[persistent(zeroize)]`
+    pub(crate) zeroize: bool,
+    /// `#[persistent(alias = "...")]`, in declaration order
+    pub(crate) aliases: Vec<LitStr>,
+    /// `#[persistent(env = "...")]`
+    pub(crate) env: Option<LitStr>,
+    /// `#[persistent(from_file)]`
+    pub(crate) from_file: bool,
+    /// `#[persistent(redact)]`
+    pub(crate) redact: bool,
+    /// `#[persistent(computed)]`
+    pub(crate) computed: bool,
+    /// `#[persistent(flatten_from = "...")]`
+    pub(crate) flatten_from: Option<syn::Type>,
+    /// `#[persistent(base)]`
+    pub(crate) base: bool,
+    /// `#[persistent(min = ...)]`
+    pub(crate) min: Option<Expr>,
+    /// `#[persistent(max = ...)]`
+    pub(crate) max: Option<Expr>,
+    /// `#[persistent(regex = "...")]`
+    pub(crate) regex: Option<LitStr>,
+    /// `#[persistent(one_of(...))]`
+    pub(crate) one_of: Vec<Expr>,
+    /// `#[persistent(prompt = "...")]`
+    pub(crate) prompt: Option<LitStr>,
+    /// `#[persistent(chunked)]`
+    pub(crate) chunked: bool,
+    /// `#[persistent(lazy)]`
+    pub(crate) lazy: bool,
+}
+
+impl FieldAttrs {
+    /// Parses the `#[persistent(...)]` attributes of a field.
+    pub(crate) fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut field = Self::default();
+        parse_keys(attrs, FIELD_KEYS, |key, meta| match key {
+            "zeroize" => {
+                flag(key, &meta)?;
+                field.zeroize = true;
+                Ok(())
+            }
+            "alias" => {
+                field.aliases.push(string_value(key, &meta)?);
+                Ok(())
+            }
+            "from_file" => {
+                flag(key, &meta)?;
+                field.from_file = true;
+                Ok(())
+            }
+            "redact" => {
+                flag(key, &meta)?;
+                field.redact = true;
+                Ok(())
+            }
+            "computed" => {
+                flag(key, &meta)?;
+                field.computed = true;
+                Ok(())
+            }
+            "flatten_from" => {
+                field.flatten_from = Some(string_value(key, &meta)?.parse()?);
+                Ok(())
+            }
+            "base" => {
+                flag(key, &meta)?;
+                field.base = true;
+                Ok(())
+            }
+            "min" | "max" => {
+                if !meta.input.peek(Token![=]) {
+                    return Err(meta.error(format!("`{}` expects a value, use `{} = 1`", key, key)));
+                }
+                let bound = meta.value()?.parse()?;
+                if key == "min" {
+                    field.min = Some(bound);
+                } else {
+                    field.max = Some(bound);
+                }
+                Ok(())
+            }
+            "regex" => {
+                field.regex = Some(string_value(key, &meta)?);
+                Ok(())
+            }
+            "prompt" => {
+                field.prompt = Some(string_value(key, &meta)?);
+                Ok(())
+            }
+            "chunked" => {
+                flag(key, &meta)?;
+                field.chunked = true;
+                Ok(())
+            }
+            "lazy" => {
+                flag(key, &meta)?;
+                field.lazy = true;
+                Ok(())
+            }
+            "one_of" => {
+                if !meta.input.peek(token::Paren) {
+                    return Err(meta.error("`one_of` expects a list of values, use `one_of(\"a\", \"b\")`"));
+                }
+                let content;
+                syn::parenthesized!(content in meta.input);
+                field.one_of = Punctuated::<Expr, Token![,]>::parse_terminated(&content)?
+                    .into_iter()
+                    .collect();
+                if field.one_of.is_empty() {
+                    return Err(meta.error("`one_of` expects at least one value"));
+                }
+                Ok(())
+            }
+            "env" => {
+                let var = string_value(key, &meta)?;
+                if var.value().is_empty() || var.value().contains(['=', '\0']) {
+                    return Err(syn::Error::new_spanned(var, "invalid environment variable name"));
+                }
+                field.env = Some(var);
+                Ok(())
+            }
+            _ => unreachable!("key validated by parse_keys"),
+        })?;
+        Ok(field)
+    }
+}
+
+/// Calls `f` for every key of the `#[persistent(...)]` attributes in `attrs`.
+///
+/// Malformed attributes, unknown keys and keys set more than once are reported as errors
+/// spanning the offending tokens.
+fn parse_keys(
+    attrs: &[Attribute],
+    known: &[&'static str],
+    mut f: impl FnMut(&str, ParseNestedMeta) -> syn::Result<()>,
+) -> syn::Result<()> {
+    let mut seen = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("persistent")) {
+        if !matches!(attr.meta, Meta::List(_)) {
+            return Err(syn::Error::new_spanned(
+                attr,
+                format!("expected `#[persistent(...)]` with one of: {}", known.join(", ")),
+            ));
+        }
+
+        attr.parse_nested_meta(|meta| {
+            let key = known_key(&meta, known, &mut seen)?;
+            f(key, meta)
+        })?;
+    }
+    Ok(())
+}
+
+/// Returns the key of `meta` if it is one of `known` and wasn't already `seen`.
+fn known_key(
+    meta: &ParseNestedMeta,
+    known: &[&'static str],
+    seen: &mut Vec<&'static str>,
+) -> syn::Result<&'static str> {
+    let key = meta.path.get_ident().map(ToString::to_string).unwrap_or_default();
+    let Some(key) = known.iter().find(|known| **known == key) else {
+        return Err(meta.error(unknown_key_message(&key, known)));
+    };
+    if seen.contains(key) && !REPEATABLE_KEYS.contains(key) {
+        return Err(meta.error(format!("duplicate persistent attribute `{}`", key)));
+    }
+    seen.push(key);
+    Ok(key)
+}
+
+/// Builds the error message for an unknown key, suggesting the closest known key.
+fn unknown_key_message(key: &str, known: &[&str]) -> String {
+    let suggestion = known
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance);
+
+    match suggestion {
+        Some((_, candidate)) => format!("unknown persistent attribute `{}`, did you mean `{}`?", key, candidate),
+        None => format!(
+            "unknown persistent attribute `{}`, expected one of: {}",
+            key,
+            known.join(", ")
+        ),
+    }
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Checks that `key` is used as a bare flag, without a value.
+fn flag(key: &str, meta: &ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(Token![=]) || meta.input.peek(token::Paren) {
+        return Err(meta.error(format!("`{}` does not take a value, use `#[persistent({})]`", key, key)));
+    }
+    Ok(())
+}
+
+/// Parses the boolean literal value of `key = true|false`.
+fn bool_value(key: &str, meta: &ParseNestedMeta) -> syn::Result<LitBool> {
+    if !meta.input.peek(Token![=]) {
+        return Err(meta.error(format!(
+            "`{}` expects a value, use `{} = true` or `{} = false`",
+            key, key, key
+        )));
+    }
+    match meta.value()?.parse()? {
+        Expr::Lit(ExprLit {
+            lit: Lit::Bool(lit), ..
+        }) => Ok(lit),
+        expr => Err(syn::Error::new_spanned(
+            expr,
+            format!("expected `true` or `false` for `{}`", key),
+        )),
+    }
+}
+
+/// Parses the integer literal value of `key = ...`.
+fn int_value(key: &str, meta: &ParseNestedMeta) -> syn::Result<LitInt> {
+    if !meta.input.peek(Token![=]) {
+        return Err(meta.error(format!("`{}` expects a value, use `{} = 0o600`", key, key)));
+    }
+    match meta.value()?.parse()? {
+        Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }) => Ok(lit),
+        expr => Err(syn::Error::new_spanned(
+            expr,
+            format!("expected an integer literal for `{}`", key),
+        )),
+    }
+}
+
+/// Parses the string literal value of `key = "..."`.
+fn string_value(key: &str, meta: &ParseNestedMeta) -> syn::Result<LitStr> {
+    if !meta.input.peek(Token![=]) {
+        return Err(meta.error(format!("`{}` expects a value, use `{} = \"...\"`", key, key)));
+    }
+    match meta.value()?.parse()? {
+        Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }) => Ok(lit),
+        expr => Err(syn::Error::new_spanned(
+            expr,
+            format!("expected a string literal for `{}`", key),
+        )),
+    }
+}
+
+/// Returns the first `#[serde(...)]` attribute setting one of `keys`, if any.
+///
+/// Serde attributes that can't be parsed as a list of metas are ignored.
+pub(crate) fn find_serde_attr<'a>(attrs: &'a [Attribute], keys: &[&str]) -> Option<&'a Attribute> {
+    attrs.iter().filter(|attr| attr.path().is_ident("serde")).find(|attr| {
+        attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+            .is_ok_and(|metas| {
+                metas
+                    .iter()
+                    .any(|meta| keys.iter().any(|key| meta.path().is_ident(key)))
+            })
+    })
+}
+
+/// Returns whether the on-disk key of the field is decided by serde itself.
+pub(crate) fn has_serde_key(field: &Field) -> bool {
+    find_serde_attr(
+        &field.attrs,
+        &["rename", "flatten", "skip", "skip_serializing", "skip_deserializing"],
+    )
+    .is_some()
+}
+
+/// Returns the key serde uses for `field` when deserializing.
+///
+/// Follows `#[serde(rename)]` on the field and `#[serde(rename_all)]` on the container
+/// (`container_attrs`), including their `deserialize = "..."` forms.
+pub(crate) fn serde_key(container_attrs: &[Attribute], field: &Field) -> syn::Result<String> {
+    if let Some(rename) = serde_string(&field.attrs, "rename") {
+        return Ok(rename.value());
+    }
+
+    let field_name = field
+        .ident
+        .as_ref()
+        .map(|ident| ident.unraw().to_string())
+        .unwrap_or_default();
+    match serde_string(container_attrs, "rename_all") {
+        Some(rule) => Ok(RenameRule::from_lit(&rule)?.apply(&field_name)),
+        None => Ok(field_name),
+    }
+}
+
+/// Returns the key of `field` in the config file, or `index`, its position, in a tuple
+/// struct.
+///
+/// Follows `#[persistent(rename_all)]` of `container`, unless the field sets its own key
+/// with serde, then the serde attributes of the field and of the container
+/// (`container_attrs`), see [`serde_key`].
+pub(crate) fn disk_key(
+    container: &ContainerAttrs,
+    container_attrs: &[Attribute],
+    field: &Field,
+    index: usize,
+) -> syn::Result<String> {
+    let Some(ident) = &field.ident else {
+        return Ok(index.to_string());
+    };
+    match container.rename_all {
+        Some(rule) if !has_serde_key(field) => Ok(rule.apply(&ident.unraw().to_string())),
+        _ => serde_key(container_attrs, field),
+    }
+}
+
+/// Returns the string set for `key` in the `#[serde(...)]` attributes, as `key = "..."` or
+/// `key(deserialize = "...")`.
+fn serde_string(attrs: &[Attribute], key: &str) -> Option<LitStr> {
+    let metas = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("serde"))
+        .filter_map(|attr| {
+            attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .ok()
+        })
+        .flatten()
+        .filter(|meta| meta.path().is_ident(key))
+        .collect::<Vec<_>>();
+
+    metas.into_iter().find_map(|meta| match meta {
+        Meta::NameValue(MetaNameValue {
+            value: Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }),
+            ..
+        }) => Some(lit),
+        Meta::List(list) => list
+            .parse_args_with(Punctuated::<MetaNameValue, Token![,]>::parse_terminated)
+            .ok()?
+            .into_iter()
+            .find_map(|name_value| match name_value.value {
+                Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }) if name_value.path.is_ident("deserialize") => Some(lit),
+                _ => None,
+            }),
+        _ => None,
+    })
+}
+
+/// Case conversion applied to field names, following serde's `rename_all` rules.
+#[derive(Clone, Copy)]
+pub(crate) enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    /// Parses a rule from its serde name.
+    fn from_lit(lit: &LitStr) -> syn::Result<Self> {
+        match lit.value().as_str() {
+            "lowercase" => Ok(Self::Lower),
+            "UPPERCASE" => Ok(Self::Upper),
+            "PascalCase" => Ok(Self::Pascal),
+            "camelCase" => Ok(Self::Camel),
+            "snake_case" => Ok(Self::Snake),
+            "SCREAMING_SNAKE_CASE" => Ok(Self::ScreamingSnake),
+            "kebab-case" => Ok(Self::Kebab),
+            "SCREAMING-KEBAB-CASE" => Ok(Self::ScreamingKebab),
+            _ => Err(syn::Error::new_spanned(
+                lit,
+                "unsupported rename_all rule, expected one of \"lowercase\", \"UPPERCASE\", \"PascalCase\", \
+                 \"camelCase\", \"snake_case\", \"SCREAMING_SNAKE_CASE\", \"kebab-case\", \"SCREAMING-KEBAB-CASE\"",
+            )),
+        }
+    }
+
+    /// Applies the rule to a `snake_case` field name.
+    pub(crate) fn apply(self, field: &str) -> String {
+        match self {
+            Self::Lower | Self::Snake => field.to_owned(),
+            Self::Upper | Self::ScreamingSnake => field.to_ascii_uppercase(),
+            Self::Pascal => field
+                .split('_')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars
+                        .next()
+                        .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                        .unwrap_or_default()
+                })
+                .collect(),
+            Self::Camel => {
+                let pascal = Self::Pascal.apply(field);
+                let mut chars = pascal.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_ascii_lowercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            }
+            Self::Kebab => field.replace('_', "-"),
+            Self::ScreamingKebab => field.replace('_', "-").to_ascii_uppercase(),
+        }
+    }
+}
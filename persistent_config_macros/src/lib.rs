@@ -15,40 +15,836 @@
 //! use persistent_config_macros::Persistent;
 //! use serde::{Deserialize, Serialize};
 //!
-//! #[derive(Serialize, Deserialize, Persistent)]
+//! #[derive(Debug, Default, Serialize, Deserialize, Persistent)]
 //! struct MyConfig {/* ... */}
 //! ```
 //!
-//! You can also use the `#[persistent(...)]` attribute for future customization.
+//! The generated `describe` lists the fields saved in the config file, with their key, type,
+//! doc comment, default value, environment variable and constraints.
+//!
+//! The struct can be customized with the `#[persistent(...)]` attribute:
+//!
+//! - `#[persistent(rename_all = "kebab-case")]`: renames the keys of the fields in the config
+//!   file, using the same rules as serde's `rename_all`. Fields with their own
+//!   `#[serde(rename)]`, `#[serde(flatten)]` or `#[serde(skip)]` are left untouched.
+//!
+//! - `#[persistent(config_dir = "...", file_name = "...", save_format = "yaml", panic_on_error = false)]`:
+//!   registers the type automatically the first time it is saved or loaded, so `config_builder`
+//!   or `default_save_config` don't need to be called. Unset keys use the same defaults as
+//!   `config_builder`, and an explicit registration always takes precedence.
+//!
+//! - `#[persistent(extension = "conf")]`: extension of the config file, instead of the one
+//!   of the save format. The content is still written in the save format.
+//!
+//! - `#[persistent(file_name_suffix = "my_crate::user_name")]`: function returning a
+//!   `String` appended to the file name on every save and load, such as the user name, so
+//!   the users of a machine don't share the same file.
+//!
+//! - `#[persistent(fallback_formats = "yaml, json", resave_fallback = true)]`: formats tried
+//!   in order when the file of the save format does not exist, and whether a config loaded
+//!   from one of them is saved again in the save format.
+//!
+//! - `#[persistent(embedded_default = "defaults/app.toml")]`: a default config file embedded
+//!   in the program, parsed by load instead of using `Default::default` when the config file
+//!   does not exist. The path is relative to the crate root, the format is given by the
+//!   extension.
+//!
+//! - `#[persistent(quarantine_corrupt = true)]`: a config file that can't be parsed is moved
+//!   to `<file name>.corrupt-<timestamp>` by load, and replaced by the default config.
+//!
+//! - `#[persistent(repair_invalid = true)]`: the values of the config file that don't
+//!   deserialize are replaced by their default value by load, keeping the other values.
+//!
+//! - `#[persistent(sops = true)]`: the config file is encrypted with SOPS, decrypted by load
+//!   and encrypted by save (JSON and YAML only, requires the `sops` feature).
+//!
+//! - `#[persistent(permission_check = "deny")]`: whether load checks that the config file
+//!   can't be changed by other users, `"off"`, `"warn"` or `"deny"` (Unix only).
+//!
+//! - `#[persistent(symlinks = "refuse")]`: how a config file that is a symbolic link is
+//!   handled, `"follow"`, `"replace"` or `"refuse"`, see `SymlinkPolicy`.
+//!
+//! - `#[persistent(category = "ui")]`: category of the config, whose policy set with
+//!   `PERSISTENT_CONFIGS.set_category` replaces its `panic_on_error` and orders `flush_all`.
+//!
+//! - `#[persistent(merge_maps = "replace", merge_arrays = "keyed(name)")]`: how the included
+//!   files and base profiles are merged, see `MergeStrategy`. Maps are `"recursive"` or
+//!   `"replace"`, lists `"replace"`, `"append"` or `"keyed(<key>)"`.
+//!
+//! - `#[persistent(namespace = "...")]`: subdirectory of the config directory holding the
+//!   file, so two crates defining a `Settings` type don't share `Settings.toml`. Registered
+//!   types default to the name of the crate defining them, an empty namespace stores the
+//!   file directly in the config directory.
+//!
+//! - `#[persistent(linux_dir = "...", macos_dir = "...", windows_dir = "...")]`: config
+//!   directory used on the given platform instead of `config_dir`, resolved when the type is
+//!   registered. Platforms without their own directory use `config_dir`.
+//!
+//! - `#[persistent(mode = 0o600, owner = "app", group = "app")]`: permissions and ownership of
+//!   the config file, also registration keys. `owner` and `group` require the `ownership`
+//!   feature of `persistent_config`.
+//!
+//! - `#[persistent(before_save = "Self::normalize", after_load = "Self::clamp")]`: functions
+//!   taking `&mut Self`, called by the `before_save` and `after_load` hooks of
+//!   `PersistentConfigBuilder`.
+//!
+//! And so can its fields:
+//!
+//! - `#[persistent(zeroize)]`: wipes the field before the value is overwritten by `load`
+//!   (requires the `zeroize` feature of `persistent_config`).
+//!
+//! - `#[persistent(alias = "old_name")]`: loads the field from `old_name` when its own key is
+//!   missing from the config file, so renaming a field doesn't lose the saved value. The next
+//!   save writes the value under the new key. Can be repeated for several old names.
+//!
+//! - `#[persistent(env = "PORT")]`: overrides the field with the `PORT` environment variable
+//!   when loading, if it is set. The value is parsed as JSON when possible (numbers, booleans,
+//!   arrays) and used as a string otherwise. The overridden value is written by the next save.
+//!
+//! - `#[persistent(from_file)]`: a `"file:/run/secrets/db_password"` value in the config file
+//!   is replaced by the content of that file when loading, the way Docker and Kubernetes
+//!   deliver secrets. Saving keeps the reference, the secret is never written to the config.
+//!
+//! - `#[persistent(min = 1, max = 65535)]`, `#[persistent(regex = "^[a-z]+$")]`,
+//!   `#[persistent(one_of("debug", "info"))]`: constraints on the value of the field, checked
+//!   by the generated `validate` method when loading and before saving. An `Option` field is
+//!   checked when it holds a value. `regex` needs the `regex` feature of `persistent_config`.
+//!
+//! - `#[persistent(prompt = "Your user name?")]`: with the `dialoguer` feature of
+//!   `persistent_config`, the question asked in the terminal when the config file does not
+//!   exist yet. The answers are saved right away.
+//!
+//! - `#[persistent(redact)]`: masks the value of the field as `"***"` in the output of `dump`
+//!   and in the errors reported by the crate, so tokens don't leak into logs.
+//!
+//! - `#[persistent(computed)]`: the field is computed at runtime, such as a cache derived from
+//!   the other fields. It is never written to the config file, and the value found in the
+//!   file is ignored by load: the field keeps its default value, for the `after_load` hook to
+//!   compute it. It is left out of `describe`.
+//!
+//! - `#[persistent(flatten_from = "LibConfig")]`: the field, of the persistent type
+//!   `LibConfig`, is stored in the config file of that type instead of this one. It is loaded
+//!   and saved with the registration and error policy of `LibConfig`, so a library crate can
+//!   own its section of an app-level struct.
+//!
+//! - `#[persistent(base)]`: the field, marked `#[serde(flatten)]`, is a base struct shared by
+//!   several configs, such as the `CommonSettings` of every service. Its type must implement
+//!   `PersistentConfigBuilder`, usually by deriving `Persistent` without registration keys:
+//!   the attributes of its fields (`env`, `redact`, constraints, ...) and its hooks apply to
+//!   the config, and `describe` lists its fields with their doc comments and the defaults of
+//!   the config. The type of the base can't depend on the generic parameters of the config.
+//!
+//! - `#[persistent(chunked)]`: the field, a `Chunked<T>` list, is stored in chunk files next
+//!   to the config file, read when their items are first accessed. The config file only
+//!   holds its length and chunk size, so a huge list doesn't slow down loading.
+//!
+//! - `#[persistent(lazy)]`: the field, a `Lazy<T>` value, is stored in a sidecar file next to
+//!   the config file, read the first time the value is accessed.
+//!
+//! Generic structs are supported: the generated impl requires the parameters to be `'static`
+//! and the struct to implement the serde traits, `Default` and `Debug` for the given
+//! parameters. Each instantiation is registered separately, and by default saved in its own
+//! file (`Cache<String>` in `Cache_String.toml`).
 
+use attrs::{ContainerAttrs, FieldAttrs, MacroArgs};
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{DeriveInput, parse_macro_input};
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::punctuated::Punctuated;
+use syn::{
+    Data, DeriveInput, Expr, ExprLit, Fields, GenericParam, Generics, Ident, Index, Lit, Member, Path, Token,
+    WherePredicate, parse_macro_input, parse_quote, parse_quote_spanned,
+};
+
+mod attrs;
 
-/// Derive macro for [`PersistentConfigBuilder`](persistent_config::PersistentConfigBuilder).
+/// Derive macro for [`PersistentConfigBuilder`](https://docs.rs/persistent_config/latest/persistent_config/trait.PersistentConfigBuilder.html).
 ///
 /// This macro automatically implements the trait for your struct, enabling
 /// persistent configuration save/load functionality.
 ///
+/// The struct must implement `Serialize`, `Deserialize`, `Default` and `Debug`; a missing
+/// implementation is reported on the struct name, with the derive or impl to add.
+///
 /// # Example
 /// ```rust
 /// use persistent_config::prelude::*;
 /// use persistent_config_macros::Persistent;
 /// use serde::{Deserialize, Serialize};
 ///
-/// #[derive(Serialize, Deserialize, Persistent)]
+/// #[derive(Debug, Default, Serialize, Deserialize, Persistent)]
 /// struct MyConfig {/* ... */}
 /// ```
 #[proc_macro_derive(Persistent, attributes(persistent))]
 pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Attribute macro making a struct persistent in one step.
+///
+/// `#[persistent_config(...)]` derives `Serialize`, `Deserialize` and
+/// [`Persistent`](macro@Persistent), which checks that the type implements `Default`, and
+/// registers it with the given parameters:
+///
+/// - `dir = "..."`: the `config_dir` of the registration
+/// - `file_name = "..."`: the name of the file, without extension
+/// - `format = "toml"`: the save format, one of `"json"`, `"toml"`, `"yaml"`, `"properties"`,
+///   `"cbor"` or `"hcl"` (load only)
+/// - `panic_on_error = false`: whether errors are logged instead of returned
+///
+/// The other `#[persistent(...)]` and `#[serde(...)]` attributes of the struct keep working.
+/// `Default` and `Debug` are left to the user, so they can be implemented by hand.
+///
+/// # Example
+/// ```rust
+/// use persistent_config::prelude::*;
+///
+/// #[persistent_config(dir = "./.config", format = "yaml")]
+/// #[derive(Debug, Default)]
+/// struct MyConfig {
+///     name: String,
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn persistent_config(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = match MacroArgs::parse(args) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_attribute(args, input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Derives injected by `#[persistent_config]`.
+const INJECTED_DERIVES: &[&str] = &["Serialize", "Deserialize", "Persistent"];
+
+/// Adds the derives and the registration of `#[persistent_config]` to `input`.
+fn expand_attribute(args: MacroArgs, input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("derive")) {
+        let paths = attr.parse_args_with(Punctuated::<Path, Token![,]>::parse_terminated)?;
+        if let Some(path) = paths.iter().find(|path| {
+            path.segments
+                .last()
+                .is_some_and(|segment| INJECTED_DERIVES.iter().any(|derive| segment.ident == derive))
+        }) {
+            return Err(syn::Error::new_spanned(
+                path,
+                "this derive is already added by #[persistent_config], remove it",
+            ));
+        }
+    }
+
+    let persistent_attr = args.to_persistent_attr();
+
+    Ok(quote! {
+        #[derive(
+            persistent_config::serde::Serialize,
+            persistent_config::serde::Deserialize,
+            persistent_config::prelude::Persistent
+        )]
+        #[serde(crate = "persistent_config::serde")]
+        #persistent_attr
+        #input
+    })
+}
+
+/// Adds the bounds required by `PersistentConfigBuilder` to the generics of `name`.
+///
+/// Registrations are keyed by `TypeId`, so every parameter must be `'static`, and the
+/// serde, `Default` and `Debug` bounds are required on the type itself rather than on its
+/// parameters, since the derives of the struct decide which bounds they need. They are
+/// required through the traits of `__private`, whose messages say what to derive: a type
+/// missing one gets a single error on its name, rather than the errors of every use of the
+/// trait in the impl.
+fn with_builder_bounds(mut generics: Generics, name: &Ident) -> Generics {
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let self_bound: WherePredicate = parse_quote_spanned! {name.span()=>
+        #name #ty_generics: persistent_config::__private::PersistentSerialize
+            + persistent_config::__private::PersistentDeserialize
+            + persistent_config::__private::PersistentDefault
+            + persistent_config::__private::PersistentDebug
+    };
+    let static_bounds = generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(param) => {
+                let ident = &param.ident;
+                Some(parse_quote! { #ident: 'static })
+            }
+            GenericParam::Lifetime(param) => {
+                let lifetime = &param.lifetime;
+                Some(parse_quote! { #lifetime: 'static })
+            }
+            GenericParam::Const(_) => None,
+        })
+        .collect::<Vec<WherePredicate>>();
+
+    let where_clause = generics.make_where_clause();
+    where_clause.predicates.extend(static_bounds);
+    where_clause.predicates.push(self_bound);
+    generics
+}
+
+/// Generates the `FieldDescription` of a field, returned by `describe`.
+fn describe_field(
+    container_attrs: &[syn::Attribute],
+    container: &ContainerAttrs,
+    field_attrs: &FieldAttrs,
+    field: &syn::Field,
+    index: usize,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let key = attrs::disk_key(container, container_attrs, field, index)?;
+    let lookup = match &field.ident {
+        Some(_) => quote! { #key },
+        None => quote! { #index },
+    };
+    let ty = type_name(&field.ty);
+    let docs = field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                value: Expr::Lit(ExprLit { lit: Lit::Str(doc), .. }),
+                ..
+            }) => Some(doc.value()),
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').map(str::to_owned).unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let env = match &field_attrs.env {
+        Some(var) => quote! { ::core::option::Option::Some(#var.to_string()) },
+        None => quote! { ::core::option::Option::None },
+    };
+
+    let mut constraints = Vec::new();
+    if let Some(min) = &field_attrs.min {
+        constraints.push(format!("min = {}", quote! { #min }));
+    }
+    if let Some(max) = &field_attrs.max {
+        constraints.push(format!("max = {}", quote! { #max }));
+    }
+    if let Some(regex) = &field_attrs.regex {
+        constraints.push(format!("regex = {:?}", regex.value()));
+    }
+    if !field_attrs.one_of.is_empty() {
+        let values = field_attrs.one_of.iter().map(|value| quote! { #value }.to_string());
+        constraints.push(format!("one_of({})", values.collect::<Vec<_>>().join(", ")));
+    }
+
+    Ok(quote! {
+        persistent_config::FieldDescription {
+            key: #key.to_string(),
+            ty: #ty.to_string(),
+            docs: #docs.to_string(),
+            default: defaults.get(#lookup).cloned().unwrap_or_default(),
+            env: #env,
+            constraints: ::std::vec![#(#constraints.to_string()),*],
+        }
+    })
+}
+
+/// Returns `keys` as string literals.
+fn quoted(keys: &[String]) -> Vec<proc_macro2::TokenStream> {
+    keys.iter().map(|key| quote! { #key }).collect()
+}
+
+/// Generates the method `name` returning the slice of `items`, followed by the items returned
+/// by the same method of the `bases`, or `None` if there are neither.
+///
+/// The slice of a config with bases is built on first use, as the methods of the bases are
+/// not `const`.
+fn merged_slice(
+    name: &str,
+    item: &proc_macro2::TokenStream,
+    items: &[proc_macro2::TokenStream],
+    bases: &[syn::Type],
+) -> Option<proc_macro2::TokenStream> {
+    let name = format_ident!("{}", name);
+    if bases.is_empty() {
+        return (!items.is_empty()).then(|| {
+            quote! {
+                fn #name() -> &'static [#item] {
+                    &[#(#items),*]
+                }
+            }
+        });
+    }
+    Some(quote! {
+        fn #name() -> &'static [#item] {
+            static ITEMS: ::std::sync::LazyLock<::std::vec::Vec<#item>> = ::std::sync::LazyLock::new(|| {
+                #[allow(unused_mut)]
+                let mut items: ::std::vec::Vec<#item> = ::std::vec![#(#items),*];
+                #( items.extend_from_slice(<#bases as persistent_config::PersistentConfigBuilder>::#name()); )*
+                items
+            });
+            &ITEMS
+        }
+    })
+}
+
+/// Returns the type as written in the source, such as `Option<Vec<String>>`.
+fn type_name(ty: &syn::Type) -> String {
+    let tokens = quote! { #ty }.to_string();
+    let chars = tokens.chars().collect::<Vec<_>>();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    // Only the spaces between two words are meaningful, such as in `dyn Trait`
+    let mut name = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if *c == ' ' && !(i > 0 && is_word(chars[i - 1]) && chars.get(i + 1).is_some_and(|next| is_word(*next))) {
+            continue;
+        }
+        name.push(*c);
+        if *c == ',' {
+            name.push(' ');
+        }
+    }
+    name
+}
+
+/// Generates the `PersistentConfigBuilder` implementation.
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let container = ContainerAttrs::parse(&input.attrs)?;
     let name = input.ident;
 
-    let generics = input.generics;
+    let mut zeroize_fields = Vec::new();
+    let mut renames = Vec::new();
+    let mut aliases = Vec::new();
+    let mut env_overrides = Vec::new();
+    let mut file_fields = Vec::new();
+    let mut redacted_fields = Vec::new();
+    let mut computed_fields = Vec::new();
+    let mut constraints = Vec::new();
+    let mut prompts = Vec::new();
+    let mut descriptions = Vec::new();
+    let (mut delegated_keys, mut delegated_types, mut delegated_members) = (Vec::new(), Vec::new(), Vec::new());
+    let (mut sidecar_keys, mut sidecar_members) = (Vec::new(), Vec::new());
+    let (mut base_types, mut base_members) = (Vec::new(), Vec::new());
+    match &input.data {
+        Data::Struct(data) => {
+            if let Some(rule) = container.rename_all {
+                if !matches!(data.fields, Fields::Named(_)) {
+                    return Err(syn::Error::new_spanned(
+                        &name,
+                        "rename_all is only supported on structs with named fields",
+                    ));
+                }
+                if let Some(attr) = attrs::find_serde_attr(&input.attrs, &["rename_all"]) {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "use either #[serde(rename_all)] or #[persistent(rename_all)], not both",
+                    ));
+                }
+                for field in data.fields.iter().filter(|field| !attrs::has_serde_key(field)) {
+                    let field_name = field.ident.as_ref().unwrap().unraw().to_string();
+                    let key = rule.apply(&field_name);
+                    if key != field_name {
+                        renames.push(quote! { (#field_name, #key) });
+                    }
+                }
+            }
+
+            for (index, field) in data.fields.iter().enumerate() {
+                let field_attrs = FieldAttrs::parse(&field.attrs)?;
+                let member = match &field.ident {
+                    Some(ident) => Member::Named(ident.clone()),
+                    None => Member::Unnamed(Index::from(index)),
+                };
+                if field_attrs.zeroize {
+                    zeroize_fields.push(member.clone());
+                }
+                if let Some(var) = &field_attrs.env {
+                    let ty = &field.ty;
+                    let redact = field_attrs.redact;
+                    env_overrides.push(quote! {
+                        if let Some(value) = persistent_config::__private::env_override::<#ty>(#var, #redact)? {
+                            self.#member = value;
+                        }
+                    });
+                }
+
+                if let Some(alias) = field_attrs.aliases.first()
+                    && field.ident.is_none()
+                {
+                    return Err(syn::Error::new_spanned(
+                        alias,
+                        "alias is only supported on structs with named fields",
+                    ));
+                }
+                if field_attrs.from_file {
+                    if field.ident.is_none() {
+                        return Err(syn::Error::new_spanned(
+                            field,
+                            "from_file is only supported on structs with named fields",
+                        ));
+                    }
+                    let key = attrs::disk_key(&container, &input.attrs, field, index)?;
+                    file_fields.push(key);
+                }
+                if field_attrs.redact {
+                    if field.ident.is_none() {
+                        return Err(syn::Error::new_spanned(
+                            field,
+                            "redact is only supported on structs with named fields",
+                        ));
+                    }
+                    let key = attrs::disk_key(&container, &input.attrs, field, index)?;
+                    redacted_fields.push(key);
+                }
+                if field_attrs.computed {
+                    let key = attrs::disk_key(&container, &input.attrs, field, index)?;
+                    computed_fields.push(key);
+                }
+                if let Some(ty) = &field_attrs.flatten_from {
+                    if field.ident.is_none() {
+                        return Err(syn::Error::new_spanned(
+                            field,
+                            "flatten_from is only supported on structs with named fields",
+                        ));
+                    }
+                    let key = attrs::disk_key(&container, &input.attrs, field, index)?;
+                    delegated_keys.push(key);
+                    delegated_types.push(ty.clone());
+                    delegated_members.push(member.clone());
+                }
+                if field_attrs.base {
+                    if field.ident.is_none() || attrs::find_serde_attr(&field.attrs, &["flatten"]).is_none() {
+                        return Err(syn::Error::new_spanned(
+                            field,
+                            "base is only supported on named fields marked #[serde(flatten)]",
+                        ));
+                    }
+                    base_types.push(field.ty.clone());
+                    base_members.push(member.clone());
+                }
+                if field_attrs.chunked || field_attrs.lazy {
+                    let key = attrs::disk_key(&container, &input.attrs, field, index)?;
+                    sidecar_keys.push(key);
+                    sidecar_members.push(member.clone());
+                }
+                if field_attrs.min.is_some()
+                    || field_attrs.max.is_some()
+                    || field_attrs.regex.is_some()
+                    || !field_attrs.one_of.is_empty()
+                {
+                    let key = attrs::disk_key(&container, &input.attrs, field, index)?;
+                    let checks = quote! { persistent_config::__private::constraints };
+                    if let Some(min) = &field_attrs.min {
+                        constraints.push(quote! { #checks::min(#key, &self.#member, #min)?; });
+                    }
+                    if let Some(max) = &field_attrs.max {
+                        constraints.push(quote! { #checks::max(#key, &self.#member, #max)?; });
+                    }
+                    if let Some(regex) = &field_attrs.regex {
+                        constraints.push(quote! { #checks::regex(#key, &self.#member, #regex)?; });
+                    }
+                    if !field_attrs.one_of.is_empty() {
+                        // String literals are already references, to `str`
+                        let allowed = field_attrs.one_of.iter().map(|value| match value {
+                            Expr::Lit(ExprLit { lit: Lit::Str(_), .. }) => quote! { #value },
+                            _ => quote! { &#value },
+                        });
+                        constraints.push(quote! { #checks::one_of(#key, &self.#member, &[#(#allowed),*])?; });
+                    }
+                }
+                if let Some(question) = &field_attrs.prompt {
+                    if field.ident.is_none() {
+                        return Err(syn::Error::new_spanned(
+                            question,
+                            "prompt is only supported on structs with named fields",
+                        ));
+                    }
+                    let key = attrs::serde_key(&input.attrs, field)?;
+                    prompts.push(quote! { (#key, #question) });
+                }
+                if !field_attrs.aliases.is_empty() {
+                    let key = attrs::serde_key(&input.attrs, field)?;
+                    aliases.extend(field_attrs.aliases.iter().map(|alias| quote! { (#alias, #key) }));
+                }
+                if !field_attrs.computed
+                    && attrs::find_serde_attr(&field.attrs, &["skip", "skip_serializing", "flatten"]).is_none()
+                {
+                    descriptions.push(describe_field(&input.attrs, &container, &field_attrs, field, index)?);
+                }
+            }
+        }
+        Data::Enum(data) => {
+            if container.rename_all.is_some() {
+                return Err(syn::Error::new_spanned(
+                    &name,
+                    "rename_all is only supported on structs with named fields",
+                ));
+            }
+            for field in data.variants.iter().flat_map(|variant| variant.fields.iter()) {
+                if let Some(attr) = field.attrs.iter().find(|attr| attr.path().is_ident("persistent")) {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "persistent field attributes are only supported on structs",
+                    ));
+                }
+            }
+        }
+        Data::Union(_) => {}
+    }
+
+    let generics = with_builder_bounds(input.generics, &name);
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
-    let expanded = quote! {
-        impl #impl_generics persistent_config::PersistentConfigBuilder for #name #ty_generics #where_clause {}
-    };
-    TokenStream::from(expanded).into()
+    let builder = quote! { persistent_config::PersistentConfigBuilder };
+    let zeroize_sensitive = (!zeroize_fields.is_empty() || !base_types.is_empty()).then(|| {
+        quote! {
+            fn zeroize_sensitive(&mut self) {
+                #( persistent_config::zeroize::Zeroize::zeroize(&mut self.#zeroize_fields); )*
+                #( #builder::zeroize_sensitive(&mut self.#base_members); )*
+            }
+        }
+    });
+
+    let derived_parameters = container.has_registration().then(|| {
+        let config_dir = container.config_dir.iter();
+        let file_name = container.file_name.iter();
+        let save_format = container
+            .save_format
+            .iter()
+            .map(|save_format| format_ident!("{}", format!("{:?}", save_format)));
+        let extension = container.extension.iter();
+        let file_name_suffix = container.file_name_suffix.iter();
+        let fallback_formats = (!container.fallback_formats.is_empty()).then(|| {
+            let formats = container
+                .fallback_formats
+                .iter()
+                .map(|save_format| format_ident!("{}", format!("{:?}", save_format)));
+            quote! { fallback_formats: ::std::vec![#( persistent_config::prelude::SaveFormat::#formats ),*], }
+        });
+        let resave_fallback = container.resave_fallback.iter();
+        let quarantine_corrupt = container.quarantine_corrupt.iter();
+        let repair_invalid = container.repair_invalid.iter();
+        let sops = container.sops.iter();
+        let permission_check = container
+            .permission_check
+            .iter()
+            .map(|check| format_ident!("{}", format!("{:?}", check)));
+        let symlinks = container
+            .symlinks
+            .iter()
+            .map(|policy| format_ident!("{}", format!("{:?}", policy)));
+        let merge = (container.merge_maps.is_some() || container.merge_arrays.is_some()).then(|| {
+            let maps = format_ident!("{}", format!("{:?}", container.merge_maps.unwrap_or_default()));
+            let arrays = match container.merge_arrays.clone().unwrap_or_default() {
+                persistent_config_core::ArrayMerge::Replace => quote! { Replace },
+                persistent_config_core::ArrayMerge::Append => quote! { Append },
+                persistent_config_core::ArrayMerge::Keyed(key) => quote! { Keyed(#key.to_string()) },
+            };
+            quote! {
+                merge: persistent_config::prelude::MergeStrategy {
+                    maps: persistent_config::prelude::MapMerge::#maps,
+                    arrays: persistent_config::prelude::ArrayMerge::#arrays,
+                },
+            }
+        });
+        let panic_on_error = container.panic_on_error.iter();
+        let mode = container.mode.iter();
+        let owner = container.owner.iter();
+        let category = container.category.iter();
+        let group = container.group.iter();
+        let (os, os_dir): (Vec<_>, Vec<_>) = container.os_dirs().into_iter().unzip();
+        let namespace = match &container.namespace {
+            Some(namespace) if namespace.value().is_empty() => quote! { None },
+            Some(namespace) => quote! { Some(#namespace.to_string()) },
+            None => quote! { Some(::core::env!("CARGO_CRATE_NAME").to_string()) },
+        };
+        quote! {
+            fn derived_parameters() -> Option<persistent_config::prelude::PersistentConfigParameters> {
+                #[allow(unused_mut)]
+                let mut params = persistent_config::prelude::PersistentConfigParameters {
+                    #( config_dir: #config_dir.to_string(), )*
+                    #( file_name: #file_name.to_string(), )*
+                    #( save_format: persistent_config::prelude::SaveFormat::#save_format, )*
+                    #( extension: Some(#extension.to_string()), )*
+                    #( file_name_suffix: Some(persistent_config::prelude::FileNameSuffix(#file_name_suffix)), )*
+                    #fallback_formats
+                    #( resave_fallback: #resave_fallback, )*
+                    #( quarantine_corrupt: #quarantine_corrupt, )*
+                    #( repair_invalid: #repair_invalid, )*
+                    #( sops: #sops, )*
+                    #( permission_check: persistent_config::prelude::PermissionCheck::#permission_check, )*
+                    #( symlinks: persistent_config::prelude::SymlinkPolicy::#symlinks, )*
+                    #merge
+                    #( panic_on_error: #panic_on_error, )*
+                    #( mode: Some(#mode), )*
+                    #( owner: Some(#owner.to_string()), )*
+                    #( category: Some(#category.to_string()), )*
+                    #( group: Some(#group.to_string()), )*
+                    namespace: #namespace,
+                    ..persistent_config::prelude::PERSISTENT_CONFIGS.app_defaults().unwrap_or_default()
+                };
+                #(
+                    if cfg!(target_os = #os) {
+                        params.config_dir = #os_dir.to_string();
+                    }
+                )*
+                Some(params)
+            }
+        }
+    });
+
+    let pair = quote! { (&'static str, &'static str) };
+    let key = quote! { &'static str };
+    let field_renames = merged_slice("field_renames", &pair, &renames, &base_types);
+
+    let apply_env_overrides = (!env_overrides.is_empty() || !base_types.is_empty()).then(|| {
+        quote! {
+            fn apply_env_overrides(&mut self) -> persistent_config::__private::anyhow::Result<()> {
+                #( #builder::apply_env_overrides(&mut self.#base_members)?; )*
+                #(#env_overrides)*
+                Ok(())
+            }
+        }
+    });
+
+    let file_fields = merged_slice("file_fields", &key, &quoted(&file_fields), &base_types);
+
+    let delegated_fields = (!delegated_keys.is_empty() || !base_types.is_empty()).then(|| {
+        let fields = merged_slice("delegated_fields", &key, &quoted(&delegated_keys), &base_types);
+        quote! {
+            #fields
+
+            fn load_delegated(
+                document: &mut persistent_config::__private::serde_json::Value,
+            ) -> persistent_config::__private::anyhow::Result<()> {
+                #( persistent_config::__private::load_field::<#delegated_types>(document, #delegated_keys)?; )*
+                #( <#base_types as #builder>::load_delegated(document)?; )*
+                Ok(())
+            }
+
+            fn save_delegated(&self) -> persistent_config::__private::anyhow::Result<()> {
+                #( persistent_config::__private::save_field::<#delegated_types>(&self.#delegated_members, #delegated_keys)?; )*
+                #( #builder::save_delegated(&self.#base_members)?; )*
+                Ok(())
+            }
+        }
+    });
+
+    let sidecar_fields = (!sidecar_keys.is_empty() || !base_types.is_empty()).then(|| {
+        quote! {
+            fn attach_sidecars(&mut self, dir: &::std::path::Path) {
+                #( persistent_config::__private::attach_sidecar(&mut self.#sidecar_members, dir, #sidecar_keys); )*
+                #( #builder::attach_sidecars(&mut self.#base_members, dir); )*
+            }
+
+            fn save_sidecars(&self, dir: &::std::path::Path) -> persistent_config::__private::anyhow::Result<()> {
+                #( persistent_config::__private::save_sidecar(&self.#sidecar_members, dir, #sidecar_keys)?; )*
+                #( #builder::save_sidecars(&self.#base_members, dir)?; )*
+                Ok(())
+            }
+
+            fn share_sidecars(&mut self, from: &Self) {
+                #( self.#sidecar_members = ::std::clone::Clone::clone(&from.#sidecar_members); )*
+                #( #builder::share_sidecars(&mut self.#base_members, &from.#base_members); )*
+            }
+        }
+    });
+
+    // The hooks of the bases run first, so the config sees the values they set
+    let before_save = (container.before_save.is_some() || !base_types.is_empty()).then(|| {
+        let path = container.before_save.iter();
+        quote! {
+            fn before_save(&mut self) {
+                #( #builder::before_save(&mut self.#base_members); )*
+                #( #path(self); )*
+            }
+        }
+    });
+
+    let after_load = (container.after_load.is_some() || !base_types.is_empty()).then(|| {
+        let path = container.after_load.iter();
+        quote! {
+            fn after_load(&mut self) {
+                #( #builder::after_load(&mut self.#base_members); )*
+                #( #path(self); )*
+            }
+        }
+    });
+
+    let embedded_default = container.embedded_default.as_ref().map(|(path, format)| {
+        let format = format_ident!("{}", format!("{:?}", format));
+        let path = if std::path::Path::new(&path.value()).is_absolute() {
+            quote! { #path }
+        } else {
+            quote! { ::core::concat!(::core::env!("CARGO_MANIFEST_DIR"), "/", #path) }
+        };
+        quote! {
+            fn embedded_default() -> Option<(&'static [u8], persistent_config::prelude::SaveFormat)> {
+                Some((::core::include_bytes!(#path), persistent_config::prelude::SaveFormat::#format))
+            }
+        }
+    });
+
+    let redacted_fields = merged_slice("redacted_fields", &key, &quoted(&redacted_fields), &base_types);
+
+    let validate = (!constraints.is_empty() || !base_types.is_empty()).then(|| {
+        quote! {
+            fn validate(&self) -> persistent_config::__private::anyhow::Result<()> {
+                #( #builder::validate(&self.#base_members)?; )*
+                #(#constraints)*
+                Ok(())
+            }
+        }
+    });
+
+    let prompt_fields = merged_slice("prompt_fields", &pair, &prompts, &base_types);
+
+    let describe = (!descriptions.is_empty() || !base_types.is_empty()).then(|| {
+        quote! {
+            fn describe() -> ::std::vec::Vec<persistent_config::FieldDescription> {
+                let defaults = persistent_config::__private::defaults::<Self>();
+                #[allow(unused_mut)]
+                let mut fields = ::std::vec![#(#descriptions),*];
+                // The default value of the config may differ from the one of the base
+                #(
+                    fields.extend(<#base_types as #builder>::describe().into_iter().map(|mut field| {
+                        if let Some(default) = defaults.get(&field.key) {
+                            field.default = default.clone();
+                        }
+                        field
+                    }));
+                )*
+                fields
+            }
+        }
+    });
+
+    let field_aliases = merged_slice("field_aliases", &pair, &aliases, &base_types);
+
+    let computed_fields = merged_slice("computed_fields", &key, &quoted(&computed_fields), &base_types);
+
+    Ok(quote! {
+        impl #impl_generics persistent_config::PersistentConfigBuilder for #name #ty_generics #where_clause {
+            #zeroize_sensitive
+            #field_renames
+            #field_aliases
+            #apply_env_overrides
+            #file_fields
+            #redacted_fields
+            #computed_fields
+            #delegated_fields
+            #sidecar_fields
+            #before_save
+            #after_load
+            #embedded_default
+            #validate
+            #prompt_fields
+            #describe
+            #derived_parameters
+        }
+    })
 }
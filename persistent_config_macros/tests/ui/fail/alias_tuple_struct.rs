@@ -0,0 +1,7 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+struct MyConfig(#[persistent(alias = "name")] String);
+
+fn main() {}
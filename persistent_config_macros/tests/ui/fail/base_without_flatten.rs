@@ -0,0 +1,15 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+struct CommonSettings {
+    log_level: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+struct ServiceConfig {
+    #[persistent(base)]
+    common: CommonSettings,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use persistent_config::prelude::*;
+
+#[derive(Default, Persistent)]
+struct MyConfig {
+    name: String,
+}
+
+fn main() {}
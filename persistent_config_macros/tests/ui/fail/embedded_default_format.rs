@@ -0,0 +1,10 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+#[persistent(embedded_default = "defaults/app.xml")]
+struct MyConfig {
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default)]
+struct NotSerializable;
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+struct Cache<T> {
+    entries: Vec<T>,
+}
+
+fn main() {
+    let cache = Cache::<NotSerializable>::default();
+    cache.save().unwrap();
+}
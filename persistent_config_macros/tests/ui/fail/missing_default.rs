@@ -0,0 +1,9 @@
+use persistent_config::prelude::*;
+
+#[persistent_config(format = "yaml")]
+#[derive(Debug)]
+struct MyConfig {
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+struct Borrowed<'a> {
+    name: &'a str,
+}
+
+fn main() {
+    let name = String::from("local");
+    let borrowed = Borrowed { name: &name };
+    borrowed.save().unwrap();
+}
@@ -0,0 +1,10 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+#[persistent(rename_al = "camelCase")]
+struct MyConfig {
+    name: String,
+}
+
+fn main() {}
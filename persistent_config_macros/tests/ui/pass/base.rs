@@ -0,0 +1,70 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Persistent)]
+#[persistent(after_load = "CommonSettings::clamp")]
+struct CommonSettings {
+    /// Verbosity of the logs.
+    #[persistent(one_of("debug", "info", "warn"))]
+    log_level: String,
+    #[persistent(redact)]
+    api_token: String,
+    max_connections: u32,
+}
+
+impl CommonSettings {
+    fn clamp(&mut self) {
+        self.max_connections = self.max_connections.min(100);
+    }
+}
+
+impl Default for CommonSettings {
+    fn default() -> Self {
+        Self {
+            log_level: "info".to_string(),
+            api_token: String::new(),
+            max_connections: 10,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Persistent)]
+struct BillingConfig {
+    /// Currency of the invoices.
+    currency: String,
+    #[serde(flatten)]
+    #[persistent(base)]
+    common: CommonSettings,
+}
+
+impl Default for BillingConfig {
+    fn default() -> Self {
+        Self {
+            currency: "EUR".to_string(),
+            common: CommonSettings {
+                log_level: "debug".to_string(),
+                ..CommonSettings::default()
+            },
+        }
+    }
+}
+
+fn main() {
+    let fields = BillingConfig::describe();
+    assert_eq!(fields.len(), 4);
+    assert_eq!(fields[0].key, "currency");
+    assert_eq!(fields[1].key, "log_level");
+    assert_eq!(fields[1].docs, "Verbosity of the logs.");
+    assert_eq!(fields[1].default, "debug");
+    assert_eq!(fields[2].default, "***");
+
+    assert_eq!(BillingConfig::redacted_fields(), ["api_token"]);
+
+    let mut config = BillingConfig::default();
+    config.common.max_connections = 500;
+    config.after_load();
+    assert_eq!(config.common.max_connections, 100);
+
+    config.common.log_level = "trace".to_string();
+    assert!(config.validate().is_err());
+}
@@ -0,0 +1,25 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+#[persistent(rename_all = "camelCase", after_load = "Self::index")]
+struct Catalog {
+    item_names: Vec<String>,
+    /// Lookup table rebuilt after every load.
+    #[persistent(computed)]
+    name_index: Vec<usize>,
+}
+
+impl Catalog {
+    fn index(&mut self) {
+        self.name_index = (0..self.item_names.len()).collect();
+    }
+}
+
+fn main() {
+    assert_eq!(Catalog::computed_fields(), ["nameIndex"]);
+
+    let fields = Catalog::describe();
+    assert_eq!(fields.len(), 1);
+    assert_eq!(fields[0].key, "itemNames");
+}
@@ -0,0 +1,38 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+#[persistent(rename_all = "kebab-case")]
+struct ServerConfig {
+    #[persistent(min = 1, max = 65535)]
+    port: u32,
+    #[persistent(one_of("debug", "info", "warn"))]
+    log_level: String,
+    #[persistent(min = 0.5)]
+    ratio: Option<f64>,
+}
+
+fn main() {
+    let mut config = ServerConfig {
+        port: 8080,
+        log_level: "info".to_string(),
+        ratio: None,
+    };
+    assert!(config.validate().is_ok());
+
+    config.port = 70000;
+    let error = config.validate().unwrap_err().to_string();
+    assert_eq!(error, "Invalid value of `port`: 70000 is greater than the maximum 65535");
+
+    config.port = 80;
+    config.log_level = "trace".to_string();
+    let error = config.validate().unwrap_err().to_string();
+    assert_eq!(
+        error,
+        r#"Invalid value of `log-level`: "trace" is not one of "debug", "info", "warn""#
+    );
+
+    config.log_level = "warn".to_string();
+    config.ratio = Some(0.1);
+    assert!(config.validate().is_err());
+}
@@ -0,0 +1,46 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Persistent)]
+#[persistent(rename_all = "kebab-case")]
+struct ServerConfig {
+    /// Port the server listens on.
+    #[persistent(env = "APP_PORT", min = 1)]
+    listen_port: u16,
+    /// Hosts allowed to connect,
+    /// as names or addresses.
+    allowed_hosts: Option<Vec<String>>,
+    #[persistent(redact)]
+    token: String,
+    #[serde(skip)]
+    connections: u32,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen_port: 8080,
+            allowed_hosts: None,
+            token: "secret".to_string(),
+            connections: 0,
+        }
+    }
+}
+
+fn main() {
+    let fields = ServerConfig::describe();
+    assert_eq!(fields.len(), 3);
+
+    assert_eq!(fields[0].key, "listen-port");
+    assert_eq!(fields[0].ty, "u16");
+    assert_eq!(fields[0].docs, "Port the server listens on.");
+    assert_eq!(fields[0].default, 8080);
+    assert_eq!(fields[0].env.as_deref(), Some("APP_PORT"));
+    assert_eq!(fields[0].constraints, ["min = 1"]);
+
+    assert_eq!(fields[1].ty, "Option<Vec<String>>");
+    assert_eq!(fields[1].docs, "Hosts allowed to connect,\nas names or addresses.");
+    assert!(fields[1].default.is_null());
+
+    assert_eq!(fields[2].default, "***");
+}
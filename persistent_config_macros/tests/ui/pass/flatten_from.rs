@@ -0,0 +1,21 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+#[persistent(file_name = "network")]
+struct NetworkConfig {
+    timeout_ms: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+#[persistent(rename_all = "kebab-case")]
+struct AppConfig {
+    name: String,
+    #[persistent(flatten_from = "NetworkConfig")]
+    network_config: NetworkConfig,
+}
+
+fn main() {
+    assert_eq!(AppConfig::delegated_fields(), ["network-config"]);
+    assert!(NetworkConfig::delegated_fields().is_empty());
+}
@@ -0,0 +1,49 @@
+use persistent_config::prelude::*;
+use persistent_config::testing::{temp_config_dir, with_temp_config};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Persistent)]
+#[serde(bound = "T: Serialize + DeserializeOwned")]
+struct Cache<T: Serialize + DeserializeOwned> {
+    entries: Vec<T>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Persistent)]
+#[persistent(save_format = "json")]
+struct Pair<K, V>
+where
+    K: Ord,
+{
+    key: K,
+    value: V,
+}
+
+fn main() {
+    with_temp_config(|| {
+        let strings = Cache {
+            entries: vec!["a".to_string()],
+        };
+        let numbers = Cache { entries: vec![1u32, 2] };
+        strings.default_save_config(false).unwrap();
+        numbers.default_save_config(false).unwrap();
+        strings.save().unwrap();
+        numbers.save().unwrap();
+
+        let mut loaded = Cache::<String>::default();
+        loaded.load().unwrap();
+        assert_eq!(loaded, strings);
+        let mut loaded = Cache::<u32>::default();
+        loaded.load().unwrap();
+        assert_eq!(loaded, numbers);
+
+        let pair = Pair { key: 1u8, value: true };
+        pair.save().unwrap();
+
+        let dir = temp_config_dir().unwrap().join(".config");
+        assert!(dir.join("Cache_String.toml").exists());
+        assert!(dir.join("Cache_u32.toml").exists());
+        // Registered by the derive, in the namespace of the crate
+        assert!(dir.join(env!("CARGO_CRATE_NAME")).join("Pair_u8_bool.json").exists());
+    });
+}
@@ -0,0 +1,30 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+#[persistent(before_save = "Self::normalize", after_load = "clamp")]
+struct ServerConfig {
+    host: String,
+    port: u16,
+}
+
+impl ServerConfig {
+    fn normalize(&mut self) {
+        self.host = self.host.trim().to_lowercase();
+    }
+}
+
+fn clamp(config: &mut ServerConfig) {
+    config.port = config.port.max(1024);
+}
+
+fn main() {
+    let mut config = ServerConfig {
+        host: " Example.COM ".to_string(),
+        port: 80,
+    };
+    config.before_save();
+    assert_eq!(config.host, "example.com");
+    config.after_load();
+    assert_eq!(config.port, 1024);
+}
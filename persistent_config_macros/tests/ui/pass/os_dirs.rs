@@ -0,0 +1,36 @@
+use persistent_config::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+#[persistent(
+    config_dir = "./fallback",
+    linux_dir = "./linux",
+    macos_dir = "./macos",
+    windows_dir = "./windows"
+)]
+struct AppConfig {
+    name: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Persistent)]
+#[persistent(linux_dir = "./linux")]
+struct LinuxOnly {
+    name: String,
+}
+
+fn main() {
+    let params = AppConfig::derived_parameters().unwrap();
+    let expected = if cfg!(target_os = "linux") {
+        "./linux"
+    } else if cfg!(target_os = "macos") {
+        "./macos"
+    } else if cfg!(target_os = "windows") {
+        "./windows"
+    } else {
+        "./fallback"
+    };
+    assert_eq!(params.config_dir, expected);
+
+    let params = LinuxOnly::derived_parameters().unwrap();
+    assert_eq!(params.config_dir == "./linux", cfg!(target_os = "linux"));
+}